@@ -0,0 +1,234 @@
+//! Types shared by the swap contract and the WASI worker
+//!
+//! The contract sends a [`SwapInput`] as OutLayer `input_data` and parses the
+//! worker's stdout as a [`SwapResponse`]. Both sides depend on this crate so
+//! a field renamed on one side fails to compile on the other instead of
+//! silently deserializing as `None`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the worker output JSON (v1 had no schema_version field; v3
+/// echoes the token pair)
+pub const OUTPUT_SCHEMA_VERSION: u32 = 3;
+
+/// First output version that echoes token_in/token_out; the swap contract
+/// requires the echo from it on
+pub const PAIR_ECHO_SCHEMA_VERSION: u32 = 3;
+
+/// Parameters of a single swap, as sent by the swap contract
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SwapInput {
+    pub sender_id: String,
+    /// Defuse asset id, e.g. "nep141:wrap.near"
+    pub token_in: String,
+    pub token_out: String,
+    /// Amount the worker swaps (after the contract's fee)
+    pub amount_in: String,
+    pub min_amount_out: String,
+    pub swap_contract_id: String,
+    /// Extra margin (basis points) the quote must beat min_amount_out by, so small
+    /// price moves before settlement don't trip the contract's min-amount check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slippage_buffer_bps: Option<u32>,
+    /// Referral account forwarded to the relay and the token_diff intent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
+    /// Free-form metadata forwarded to the relay for flow attribution
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// OutLayer's max_execution_seconds for this run (sets the wall-clock budget)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_execution_seconds: Option<u64>,
+    /// Register an unregistered sender with token_out (paid by the swap contract)
+    /// instead of refusing the swap
+    #[serde(default)]
+    pub register_storage: bool,
+}
+
+/// Swap request sent as the `msg` of `ft_transfer_call` to the swap contract
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SwapMessage {
+    /// Output token contract (must be whitelisted)
+    pub token_out: String,
+    /// Smallest acceptable output, as a decimal string; no minimum if absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_amount_out: Option<String>,
+    /// Referral account forwarded to the solver relay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
+    /// Free-form attribution data forwarded to the solver relay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Gas (TGas) for the output ft_transfer, for tokens whose transfer
+    /// needs more than the default 10; the contract accepts 10..=50
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_gas_tgas: Option<u64>,
+    /// Extra fee (bps of the amount, up to 100) for the priority lane, on
+    /// top of the protocol fee
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_fee_bps: Option<u16>,
+}
+
+impl SwapMessage {
+    pub fn new(token_out: &str) -> Self {
+        Self {
+            token_out: token_out.to_string(),
+            min_amount_out: None,
+            referral: None,
+            metadata: None,
+            callback_gas_tgas: None,
+            priority_fee_bps: None,
+        }
+    }
+
+    pub fn min_amount_out(mut self, min_amount_out: u128) -> Self {
+        self.min_amount_out = Some(min_amount_out.to_string());
+        self
+    }
+
+    pub fn referral(mut self, referral: &str) -> Self {
+        self.referral = Some(referral.to_string());
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn callback_gas_tgas(mut self, callback_gas_tgas: u64) -> Self {
+        self.callback_gas_tgas = Some(callback_gas_tgas);
+        self
+    }
+
+    pub fn priority_fee_bps(mut self, priority_fee_bps: u16) -> Self {
+        self.priority_fee_bps = Some(priority_fee_bps);
+        self
+    }
+
+    /// The `msg` string: `{"Swap": {...}}`
+    pub fn to_msg(&self) -> String {
+        serde_json::json!({ "Swap": self }).to_string()
+    }
+}
+
+/// The part of the worker output the swap contract acts on. The worker
+/// reports more (steps, metrics), which the contract ignores.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SwapResponse {
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+    pub success: bool,
+    pub amount_out: Option<String>,
+    pub error_message: Option<String>,
+    pub intent_hash: Option<String>,
+    /// Machine-readable failure reason (None on success)
+    #[serde(default)]
+    pub error_code: Option<ErrorCode>,
+    /// Relay quote(s) the swap intent was built on, comma-separated for split swaps
+    #[serde(default)]
+    pub quote_hash: Option<String>,
+    /// Transaction depositing amount_in into intents.near
+    #[serde(default)]
+    pub deposit_tx_hash: Option<String>,
+    /// Intent withdrawing amount_out from intents.near back to the swap contract
+    #[serde(default)]
+    pub withdraw_intent_hash: Option<String>,
+    /// Defuse asset ids of the input's token_in / token_out, echoed so the
+    /// contract can tell the result belongs to its request
+    #[serde(default)]
+    pub token_in: Option<String>,
+    #[serde(default)]
+    pub token_out: Option<String>,
+}
+
+/// Failure reasons the swap contract can branch on (refund vs retry)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// Relay returned no usable quote
+    QuoteUnavailable,
+    /// Best quote is below min_amount_out
+    InsufficientLiquidity,
+    /// Quoted rate is too far below the reference price
+    PriceImpactTooHigh,
+    /// Quote expires before the swap could typically settle; nothing was moved, safe to retry
+    QuoteTooShort,
+    /// ft_transfer_call to intents.near failed; tokens are still on the swap contract
+    DepositFailed,
+    /// Swap intent was rejected or didn't settle; tokens are in intents.near
+    IntentNotSettled,
+    /// Swap settled but output tokens are still in intents.near
+    WithdrawFailed,
+    /// Swap settled but the budget ran out before the withdrawal finished;
+    /// the withdraw intent, if any, may still settle
+    WithdrawPending,
+    /// Recipient isn't registered with the output token
+    NoStorage,
+    /// No operator key configured, or none of them is usable on-chain
+    KeyMissing,
+    /// NEAR RPC returned an error or an unexpected response
+    RpcError,
+    /// Swap input is malformed (token format, amounts)
+    InvalidInput,
+    /// Not enough execution time left to run the next step safely
+    BudgetExhausted,
+    /// Unexpected worker error
+    InternalError,
+    /// A code this build doesn't know (sent by a newer worker)
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::QuoteUnavailable => "QUOTE_UNAVAILABLE",
+            ErrorCode::InsufficientLiquidity => "INSUFFICIENT_LIQUIDITY",
+            ErrorCode::PriceImpactTooHigh => "PRICE_IMPACT_TOO_HIGH",
+            ErrorCode::QuoteTooShort => "QUOTE_TOO_SHORT",
+            ErrorCode::DepositFailed => "DEPOSIT_FAILED",
+            ErrorCode::IntentNotSettled => "INTENT_NOT_SETTLED",
+            ErrorCode::WithdrawFailed => "WITHDRAW_FAILED",
+            ErrorCode::WithdrawPending => "WITHDRAW_PENDING",
+            ErrorCode::NoStorage => "NO_STORAGE",
+            ErrorCode::KeyMissing => "KEY_MISSING",
+            ErrorCode::RpcError => "RPC_ERROR",
+            ErrorCode::InvalidInput => "INVALID_INPUT",
+            ErrorCode::BudgetExhausted => "BUDGET_EXHAUSTED",
+            ErrorCode::InternalError => "INTERNAL_ERROR",
+            ErrorCode::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_serialize_as_their_str() {
+        for code in [ErrorCode::QuoteUnavailable, ErrorCode::PriceImpactTooHigh, ErrorCode::RpcError, ErrorCode::Unknown] {
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+        }
+        let code: ErrorCode = serde_json::from_str(r#""SOMETHING_NEW""#).unwrap();
+        assert_eq!(code, ErrorCode::Unknown);
+
+        // v1 output: no schema_version, no artifacts
+        let v1: SwapResponse =
+            serde_json::from_str(r#"{"success":true,"amount_out":"5","error_message":null,"intent_hash":"h"}"#).unwrap();
+        assert_eq!(v1.schema_version, None);
+        assert_eq!(v1.error_code, None);
+    }
+}
@@ -4,10 +4,16 @@ set -e
 cd $(dirname $0)
 mkdir -p res/local
 
-echo "Building contract..."
+# `./build.sh testnet` builds against testnet accounts
+FEATURES=""
+if [ "$1" = "testnet" ]; then
+    FEATURES="--features testnet"
+fi
+
+echo "Building contract${FEATURES:+ ($1)}..."
 
 # Build the contract
-cargo near build non-reproducible-wasm
+cargo near build non-reproducible-wasm $FEATURES
 
 # Copy the WASM file to res/local/ (created in parent dir due to workspace)
 cp ../target/near/intents_contract/intents_contract.wasm res/local/
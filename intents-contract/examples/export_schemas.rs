@@ -0,0 +1,7 @@
+//! Print the JSON Schemas of the swap contract's off-chain JSON
+//!
+//! cargo run -p intents-contract --example export_schemas --features schema > intents-contract/schemas.json
+
+fn main() {
+    println!("{}", serde_json::to_string_pretty(&intents_contract::json_schemas()).unwrap());
+}
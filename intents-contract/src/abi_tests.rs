@@ -0,0 +1,71 @@
+//! The ABI `cargo near abi` generates: built with `--features abi`, each
+//! `#[near]` impl block exports its chunk of the ABI from a symbol named
+//! after its first method, and cargo-near collects them from the compiled
+//! library. These tests collect them the same way, so a method or type the
+//! ABI can't describe fails here rather than in `cargo near abi`.
+//!
+//! A new `#[near]` impl block adds a symbol to `abi_chunks` below.
+
+use near_sdk::serde_json::Value;
+use std::collections::BTreeSet;
+
+macro_rules! load_chunks {
+    ($($symbol:ident),* $(,)?) => {{
+        #[allow(improper_ctypes)]
+        extern "C" {
+            $(fn $symbol() -> (*const u8, usize);)*
+        }
+        vec![$({
+            let (ptr, len) = unsafe { $symbol() };
+            near_sdk::serde_json::from_slice::<Value>(unsafe { std::slice::from_raw_parts(ptr, len) }).unwrap()
+        }),*]
+    }};
+}
+
+fn abi_chunks() -> Vec<Value> {
+    load_chunks!(
+        __near_abi_contract_source_metadata,
+        __near_abi_new,
+        __near_abi_set_owner,
+        __near_abi_approve_operator_swaps,
+        __near_abi_set_memo_templates,
+        __near_abi_migrate,
+        __near_abi_prune_swap_history,
+        __near_abi_get_user_receipts,
+        __near_abi_reconcile_fees,
+        __near_abi_is_request_processed,
+        __near_abi_set_secrets_ref_template,
+        __near_abi_on_output_transferred,
+        __near_abi_register_worker_version,
+    )
+}
+
+fn method_names() -> Vec<String> {
+    abi_chunks()
+        .iter()
+        .flat_map(|chunk| chunk["functions"].as_array().unwrap().clone())
+        .map(|function| function["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn test_abi_describes_every_method_once() {
+    let names = method_names();
+    let unique: BTreeSet<_> = names.iter().collect();
+    assert_eq!(unique.len(), names.len(), "methods listed twice: {:?}", names);
+    for method in [
+        "new",
+        "migrate",
+        "ft_on_transfer",
+        "on_execution_response",
+        "swap_from_balance",
+        "withdraw",
+        "get_config",
+        "get_swap_status",
+        "whitelist_token",
+        "set_secrets_ref_template",
+        "reconcile_fees",
+    ] {
+        assert!(unique.contains(&method.to_string()), "{} missing from the ABI", method);
+    }
+}
@@ -13,6 +13,29 @@ impl Contract {
         );
     }
 
+    /// Config of a token swaps may use; `side` ("in" or "out") names it in
+    /// the panic for unlisted or expired tokens
+    pub(crate) fn listed_token(&self, token_id: &TokenId, side: &str) -> TokenConfig {
+        let config = self
+            .whitelist
+            .get(token_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Token {} not whitelisted", side)));
+        if let Some(expires_at) = config.expires_at {
+            if env::block_timestamp() >= expires_at {
+                env::panic_str(&format!("Token {} listing of {} expired at {}", side, token_id, expires_at));
+            }
+        }
+        config
+    }
+
+    pub(crate) fn assert_owner_or_operator(&self) {
+        let caller = env::predecessor_account_id();
+        assert!(
+            caller == self.owner_id || caller == self.operator_id,
+            "Only owner or operator can call this method"
+        );
+    }
+
     pub(crate) fn assert_not_paused(&self) {
         assert!(!self.paused, "Contract is paused");
     }
@@ -20,6 +43,59 @@ impl Contract {
     pub(crate) fn assert_swaps_not_paused(&self) {
         assert!(!self.swap_paused, "Swaps are paused");
     }
+
+    /// Err (refunding the swap) if the result came in later than
+    /// `max_callback_age_seconds` after the request
+    pub(crate) fn check_result_fresh(&self, swap_request: &SwapRequest) -> Result<(), String> {
+        let age_seconds = env::block_timestamp().saturating_sub(swap_request.timestamp) / 1_000_000_000;
+        if age_seconds > self.max_callback_age_seconds {
+            return Err(format!(
+                "Swap #{} result arrived {}s after the request (max {}s), refunding",
+                swap_request.request_id, age_seconds, self.max_callback_age_seconds
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Contract {
+    /// Whether the callback's promise args describe the stored request
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn callback_matches(
+        &self,
+        swap_request: &SwapRequest,
+        sender_id: &AccountId,
+        token_in: &TokenId,
+        token_out: &TokenId,
+        amount_in: Balance,
+        min_amount_out: Balance,
+        fee_amount: Balance,
+    ) -> bool {
+        swap_request.sender_id == *sender_id
+            && swap_request.token_in == *token_in
+            && swap_request.token_out == *token_out
+            && swap_request.amount_in == amount_in
+            && swap_request.min_amount_out == min_amount_out
+            && swap_request.fee_amount == fee_amount
+            && Some(swap_request.amount_after_fee)
+                == amount_in
+                    .checked_sub(fee_amount)
+                    .and_then(|amount| amount.checked_sub(swap_request.priority_fee_amount))
+    }
+}
+
+/// A limit below the execution time would refund swaps that ran normally
+pub(crate) fn assert_callback_age_covers_execution(max_callback_age_seconds: u64) {
+    assert!(
+        max_callback_age_seconds >= MAX_EXECUTION_SECONDS,
+        "Max callback age must be at least {}s",
+        MAX_EXECUTION_SECONDS
+    );
+}
+
+/// A listing must not be born expired
+fn assert_expiry_in_future(expires_at: u64) {
+    assert!(expires_at > env::block_timestamp(), "expires_at must be in the future");
 }
 
 // ============================================================================
@@ -58,29 +134,53 @@ impl Contract {
         log!("Secrets profile set to {}", profile);
     }
 
+    pub fn set_auto_register_storage(&mut self, enabled: bool) {
+        self.assert_owner();
+        self.auto_register_storage = enabled;
+        log!("Storage auto-registration {}", if enabled { "enabled" } else { "disabled" });
+    }
+
+    pub fn set_max_callback_age(&mut self, max_callback_age_seconds: u64) {
+        self.assert_owner();
+        assert_callback_age_covers_execution(max_callback_age_seconds);
+        self.max_callback_age_seconds = max_callback_age_seconds;
+        log!("Max callback age set to {}s", max_callback_age_seconds);
+    }
+
     pub fn whitelist_token(
         &mut self,
         token_id: TokenId,
         defuse_asset_id: Option<String>,
         min_swap_amount: U128,
+        expires_at: Option<u64>,
     ) {
         self.assert_owner();
 
         // Generate defuse_asset_id if not provided: "nep141:{token_id}"
         let asset_id = defuse_asset_id.unwrap_or_else(|| format!("nep141:{}", token_id));
+        crate::types::validate_defuse_asset_id(&asset_id).unwrap_or_else(|e| env::panic_str(&e));
+        if let Some(expires_at) = expires_at {
+            assert_expiry_in_future(expires_at);
+        }
 
         let config = TokenConfig {
             defuse_asset_id: asset_id.clone(),
             min_swap_amount: min_swap_amount.0,
+            whitelisted_at: env::block_timestamp(),
+            expires_at,
+            transfer_fee_bps: 0,
+            dust_threshold: 0,
         };
 
         self.whitelist.insert(&token_id, &config);
+        self.whitelisted_tokens.insert(&token_id);
 
         log!(
-            "Token {} whitelisted with defuse_asset_id: {}, min_swap_amount: {}",
+            "Token {} whitelisted with defuse_asset_id: {}, min_swap_amount: {}, expires_at: {:?}",
             token_id,
             asset_id,
-            min_swap_amount.0
+            min_swap_amount.0,
+            expires_at
         );
     }
 
@@ -89,6 +189,9 @@ impl Contract {
         token_id: TokenId,
         defuse_asset_id: Option<String>,
         min_swap_amount: Option<U128>,
+        expires_at: Option<u64>,
+        transfer_fee_bps: Option<u16>,
+        dust_threshold: Option<U128>,
     ) {
         self.assert_owner();
 
@@ -97,6 +200,7 @@ impl Contract {
             .expect("Token not in whitelist");
 
         if let Some(asset_id) = defuse_asset_id {
+            crate::types::validate_defuse_asset_id(&asset_id).unwrap_or_else(|e| env::panic_str(&e));
             config.defuse_asset_id = asset_id;
         }
 
@@ -104,19 +208,40 @@ impl Contract {
             config.min_swap_amount = min_amount.0;
         }
 
+        if let Some(expires_at) = expires_at {
+            assert_expiry_in_future(expires_at);
+            config.expires_at = Some(expires_at);
+        }
+
+        if let Some(transfer_fee_bps) = transfer_fee_bps {
+            assert!(
+                transfer_fee_bps <= fees::MAX_TRANSFER_FEE_BASIS_POINTS,
+                "Transfer fee cannot exceed {} basis points",
+                fees::MAX_TRANSFER_FEE_BASIS_POINTS
+            );
+            config.transfer_fee_bps = transfer_fee_bps;
+        }
+
+        if let Some(dust_threshold) = dust_threshold {
+            config.dust_threshold = dust_threshold.0;
+        }
+
         self.whitelist.insert(&token_id, &config);
 
         log!(
-            "Token {} config updated: defuse_asset_id={}, min_swap_amount={}",
+            "Token {} config updated: defuse_asset_id={}, min_swap_amount={}, expires_at={:?}, transfer_fee_bps={}, dust_threshold={}",
             token_id,
             config.defuse_asset_id,
-            config.min_swap_amount
+            config.min_swap_amount,
+            config.expires_at,
+            config.transfer_fee_bps,
+            config.dust_threshold
         );
     }
 
     pub fn set_fee_percentage(&mut self, fee_basis_points: u16) {
         self.assert_owner();
-        assert!(fee_basis_points <= 1000, "Fee cannot exceed 10%");
+        assert!(fee_basis_points <= fees::MAX_FEE_BASIS_POINTS, "Fee cannot exceed 10%");
 
         self.fee_basis_points = fee_basis_points;
         log!("Fee set to {} basis points ({}%)", fee_basis_points, fee_basis_points as f64 / 100.0);
@@ -151,8 +276,9 @@ impl Contract {
             .ft_transfer(
                 self.owner_id.clone(),
                 near_sdk::json_types::U128(withdraw_amount),
-                Some(format!("Fee withdrawal")),
-            );
+                Some(self.fee_withdrawal_memo(&token_id, withdraw_amount)),
+            )
+            .detach();
 
         log!(
             "Withdrew {} {} in fees to {} (remaining: {})",
@@ -163,9 +289,81 @@ impl Contract {
         );
     }
 
+    /// Transfer collected priority lane fees (all by default) to the operator
+    pub fn withdraw_priority_fees(&mut self, token_id: TokenId, amount: Option<U128>) {
+        assert_eq!(env::predecessor_account_id(), self.operator_id, "Only operator can call this method");
+
+        let available = self.collected_priority_fees.get(&token_id).unwrap_or(0);
+        let withdraw_amount = amount.map_or(available, |amount| amount.0);
+        assert!(withdraw_amount > 0, "No priority fees collected for this token");
+        assert!(
+            withdraw_amount <= available,
+            "Cannot withdraw {} - only {} available",
+            withdraw_amount,
+            available
+        );
+
+        let remaining = available - withdraw_amount;
+        if remaining > 0 {
+            self.collected_priority_fees.insert(&token_id, &remaining);
+        } else {
+            self.collected_priority_fees.remove(&token_id);
+        }
+
+        ext_ft::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(
+                self.operator_id.clone(),
+                U128(withdraw_amount),
+                Some(self.fee_withdrawal_memo(&token_id, withdraw_amount)),
+            )
+            .detach();
+
+        log!(
+            "Withdrew {} {} in priority fees to {} (remaining: {})",
+            withdraw_amount,
+            token_id,
+            self.operator_id,
+            remaining
+        );
+    }
+
+    /// Drop pending swap records whose callback failed. Refused swaps are
+    /// removed by the callback itself, but a callback that panics or runs
+    /// out of gas refunds the tokens and reverts its removal of the record
+    /// (and its status updates). Balance-funded swaps get no token refund,
+    /// so their input is credited back to the sender's balance here. Ids
+    /// younger than `STALE_SWAP_SECONDS` or already gone are skipped;
+    /// returns the ids removed.
+    pub fn cleanup_stale_swaps(&mut self, request_ids: Vec<u64>) -> Vec<u64> {
+        self.assert_owner();
+
+        let cutoff = env::block_timestamp().saturating_sub(STALE_SWAP_SECONDS * 1_000_000_000);
+        let removed: Vec<u64> = request_ids
+            .into_iter()
+            .filter(|request_id| match self.pending_swaps.get(request_id) {
+                Some(swap) if swap.timestamp <= cutoff => {
+                    self.remove_pending_swap(*request_id);
+                    self.mark_processed(*request_id);
+                    if swap.from_balance {
+                        self.internal_deposit(&swap.sender_id, &swap.token_in, swap.amount_in);
+                    }
+                    self.set_swap_status(*request_id, SwapStatus::Refunded, Some("Callback failed, removed by cleanup"));
+                    true
+                }
+                _ => false,
+            })
+            .collect();
+
+        log!("Removed {} stale pending swaps: {:?}", removed.len(), removed);
+        removed
+    }
+
     pub fn remove_token_from_whitelist(&mut self, token_id: TokenId) {
         self.assert_owner();
         self.whitelist.remove(&token_id);
+        self.whitelisted_tokens.remove(&token_id);
         log!("Token {} removed from whitelist", token_id);
     }
 
@@ -176,9 +374,13 @@ impl Contract {
             "paused": self.paused,
             "swap_paused": self.swap_paused,
             "secrets_profile": self.secrets_profile,
+            "secrets_ref_template": self.get_secrets_ref_template(),
             "next_request_id": self.next_request_id,
             "fee_basis_points": self.fee_basis_points,
             "fee_percentage": format!("{}%", self.fee_basis_points as f64 / 100.0),
+            "auto_register_storage": self.auto_register_storage,
+            "max_callback_age_seconds": self.max_callback_age_seconds,
+            "pruned_below": self.pruned_below,
         })
     }
 
@@ -190,10 +392,18 @@ impl Contract {
         self.collected_fees.get(&token_id).unwrap_or(0)
     }
 
+    pub fn get_collected_priority_fees(&self, token_id: TokenId) -> U128 {
+        U128(self.collected_priority_fees.get(&token_id).unwrap_or(0))
+    }
+
     pub fn is_swap_paused(&self) -> bool {
         self.swap_paused
     }
 
+    pub fn get_whitelisted_tokens(&self) -> Vec<TokenId> {
+        self.whitelisted_tokens.to_vec()
+    }
+
     pub fn is_token_whitelisted(&self, token_id: TokenId) -> bool {
         self.whitelist.get(&token_id).is_some()
     }
@@ -201,4 +411,94 @@ impl Contract {
     pub fn get_pending_swap(&self, request_id: u64) -> Option<crate::types::SwapRequest> {
         self.pending_swaps.get(&request_id)
     }
+
+    /// What a pending swap sent to OutLayer, to reproduce a stuck execution
+    /// by hand. Built from the stored request (asset ids included) and the
+    /// current config, so it reflects later changes to the secrets profile,
+    /// operator or auto registration. None if the swap isn't pending.
+    pub fn get_request_execution_payload(&self, request_id: u64) -> Option<crate::types::ExecutionPayload> {
+        let swap_request = self.pending_swaps.get(&request_id)?;
+        Some(self.execution_payload(&swap_request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    const HOUR_NS: u64 = 3_600_000_000_000;
+
+    fn context(predecessor: &str, timestamp: u64) -> near_sdk::VMContext {
+        VMContextBuilder::new()
+            .predecessor_account_id(predecessor.parse().unwrap())
+            .block_timestamp(timestamp)
+            .build()
+    }
+
+    fn pending(contract: &mut Contract, request_id: u64, timestamp: u64) {
+        contract.pending_swaps.insert(
+            &request_id,
+            &SwapRequest {
+                request_id,
+                sender_id: "alice.near".parse().unwrap(),
+                token_in: "wrap.near".parse().unwrap(),
+                token_out: "usdc.near".parse().unwrap(),
+                token_in_asset_id: "nep141:wrap.near".to_string(),
+                token_out_asset_id: "nep141:usdc.near".to_string(),
+                amount_in: 1_000,
+                min_amount_out: 0,
+                fee_amount: 1,
+                priority_fee_amount: 0,
+                amount_after_fee: 999,
+                referral: None,
+                metadata: None,
+                worker_version: None,
+                pair_echo_required: false,
+                callback_gas_tgas: None,
+                from_balance: false,
+                timestamp,
+            },
+        );
+    }
+
+    #[test]
+    fn test_cleanup_removes_only_stale_swaps() {
+        testing_env!(context("owner.near", 0));
+        let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+        pending(&mut contract, 0, HOUR_NS);
+        pending(&mut contract, 1, 2 * HOUR_NS + 1);
+
+        testing_env!(context("owner.near", 2 * HOUR_NS));
+        assert_eq!(contract.cleanup_stale_swaps(vec![0, 1, 7]), vec![0]);
+        assert!(contract.get_pending_swap(0).is_none());
+        assert!(contract.get_pending_swap(1).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid defuse_asset_id nep141:wrap near: not a valid account id")]
+    fn test_whitelist_rejects_malformed_asset_id() {
+        testing_env!(context("owner.near", 0));
+        let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+        contract.whitelist_token("wrap.near".parse().unwrap(), Some("nep141:wrap near".to_string()), U128(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid defuse_asset_id nep245:mt.near: expected nep245:<account>:<token id>")]
+    fn test_token_config_update_rejects_malformed_asset_id() {
+        testing_env!(context("owner.near", 0));
+        let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+        contract.whitelist_token("mt.near".parse().unwrap(), None, U128(1), None);
+        contract.update_token_config("mt.near".parse().unwrap(), Some("nep245:mt.near".to_string()), None, None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn test_cleanup_is_owner_only() {
+        testing_env!(context("owner.near", 0));
+        let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+        testing_env!(context("alice.near", 2 * HOUR_NS));
+        contract.cleanup_stale_swaps(vec![0]);
+    }
 }
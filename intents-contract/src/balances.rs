@@ -0,0 +1,199 @@
+use crate::*;
+
+// ============================================================================
+// Deposited Balances
+// ============================================================================
+//
+// Users can deposit whitelisted tokens (`{"Deposit":{}}` as the
+// ft_transfer_call msg), then swap from the balance with a plain function
+// call (no FT transfer per swap) or let the operator swap up to an approved
+// allowance of it, so scheduled or triggered swaps need no transaction from
+// the user. A balance-funded swap takes its input from the balance and a
+// refused one credits it back; the output is transferred as usual, unless
+// it is below the output token's dust threshold, which credits it to the
+// sender's balance for one later withdrawal of all such outputs.
+
+/// Gas for on_withdraw, after the withdrawal ft_transfer
+const GAS_FOR_WITHDRAW_CALLBACK: Gas = Gas::from_gas(5 * TGAS);
+
+impl Contract {
+    pub(crate) fn internal_deposit(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let key = (account_id.clone(), token_id.clone());
+        let balance = self.balances.get(&key).unwrap_or(0);
+        self.balances.insert(&key, &balance.checked_add(amount).expect("Balance overflow"));
+        reconcile::add_to_total(&mut self.deposit_totals, token_id, amount);
+        if balance == 0 {
+            let mut token_ids = self.account_tokens.get(account_id).unwrap_or_default();
+            token_ids.push(token_id.clone());
+            self.account_tokens.insert(account_id, &token_ids);
+        }
+    }
+
+    pub(crate) fn internal_withdraw_balance(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let key = (account_id.clone(), token_id.clone());
+        let balance = self.balances.get(&key).unwrap_or(0);
+        assert!(
+            amount <= balance,
+            "Cannot use {} {} - only {} deposited",
+            amount,
+            token_id,
+            balance
+        );
+        if balance > amount {
+            self.balances.insert(&key, &(balance - amount));
+        } else {
+            self.balances.remove(&key);
+            let mut token_ids = self.account_tokens.get(account_id).unwrap_or_default();
+            token_ids.retain(|held| held != token_id);
+            if token_ids.is_empty() {
+                self.account_tokens.remove(account_id);
+            } else {
+                self.account_tokens.insert(account_id, &token_ids);
+            }
+        }
+        reconcile::sub_from_total(&mut self.deposit_totals, token_id, amount);
+    }
+
+    /// Start a swap of `amount_in` from `account_id`'s balance; `msg` is the
+    /// Swap message an ft_transfer_call would carry
+    pub(crate) fn internal_swap_from_balance(
+        &mut self,
+        account_id: AccountId,
+        token_in: TokenId,
+        amount_in: Balance,
+        msg: &str,
+    ) -> Promise {
+        self.assert_not_paused();
+        self.assert_swaps_not_paused();
+
+        let token_in_config = self.listed_token(&token_in, "in");
+        let TokenReceiverMessage::Swap {
+            token_out,
+            min_amount_out,
+            referral,
+            metadata,
+            callback_gas_tgas,
+            priority_fee_bps,
+        } = TokenReceiverMessage::parse(msg).unwrap_or_else(|e| env::panic_str(&e))
+        else {
+            env::panic_str("Expected a Swap message");
+        };
+        let token_out_config = self.listed_token(&token_out, "out");
+
+        self.internal_withdraw_balance(&account_id, &token_in, amount_in);
+        self.internal_initiate_swap(
+            account_id,
+            token_in,
+            token_out,
+            token_in_config,
+            token_out_config,
+            amount_in,
+            min_amount_out.map_or(0, |amount| amount.0),
+            referral,
+            metadata,
+            callback_gas_tgas,
+            priority_fee_bps,
+            true,
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Let the operator swap up to `allowance` of the caller's deposited
+    /// `token_id`; each operator swap uses it up, refunds don't restore it.
+    /// 0 revokes.
+    #[payable]
+    pub fn approve_operator_swaps(&mut self, token_id: TokenId, allowance: U128) {
+        near_sdk::assert_one_yocto();
+        let key = (env::predecessor_account_id(), token_id);
+        if allowance.0 > 0 {
+            self.swap_allowances.insert(&key, &allowance.0);
+        } else {
+            self.swap_allowances.remove(&key);
+        }
+        log!("{} allows operator swaps of {} {}", key.0, allowance.0, key.1);
+    }
+
+    /// Swap `amount_in` of the caller's deposited `token_in`. `msg` is a
+    /// Swap message, as for ft_transfer_call.
+    #[payable]
+    pub fn swap_from_balance(&mut self, token_in: TokenId, amount_in: U128, msg: String) -> Promise {
+        near_sdk::assert_one_yocto();
+        self.internal_swap_from_balance(env::predecessor_account_id(), token_in, amount_in.0, &msg)
+    }
+
+    /// Swap `amount_in` of `account_id`'s deposited `token_in` within the
+    /// allowance they approved. `msg` is a Swap message, as for
+    /// ft_transfer_call; the output goes to `account_id`.
+    pub fn operator_swap(&mut self, account_id: AccountId, token_in: TokenId, amount_in: U128, msg: String) -> Promise {
+        assert_eq!(env::predecessor_account_id(), self.operator_id, "Only operator can call this method");
+
+        let key = (account_id.clone(), token_in.clone());
+        let allowance = self.swap_allowances.get(&key).unwrap_or(0);
+        assert!(
+            amount_in.0 <= allowance,
+            "Swap of {} {} exceeds the operator allowance of {}",
+            amount_in.0,
+            token_in,
+            allowance
+        );
+        if allowance > amount_in.0 {
+            self.swap_allowances.insert(&key, &(allowance - amount_in.0));
+        } else {
+            self.swap_allowances.remove(&key);
+        }
+
+        self.internal_swap_from_balance(account_id, token_in, amount_in.0, &msg)
+    }
+
+    /// Transfer deposited `token_id` (all of it by default) back to the caller
+    #[payable]
+    pub fn withdraw(&mut self, token_id: TokenId, amount: Option<U128>) -> Promise {
+        near_sdk::assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = amount.map_or_else(|| self.get_balance(account_id.clone(), token_id.clone()).0, |amount| amount.0);
+        assert!(amount > 0, "Nothing to withdraw");
+        self.internal_withdraw_balance(&account_id, &token_id, amount);
+
+        ext_ft::ext(token_id.clone())
+            .with_static_gas(GAS_FOR_FT_TRANSFER)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(account_id.clone(), U128(amount), None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_WITHDRAW_CALLBACK)
+                    .on_withdraw(account_id, token_id, U128(amount)),
+            )
+    }
+
+    /// Credits a failed withdrawal back to the balance
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, token_id: TokenId, amount: U128) {
+        if env::promise_result_checked(0, MAX_TRANSFER_RESULT_LEN).is_err() {
+            log!("Withdrawal of {} {} to {} failed, balance restored", amount.0, token_id, account_id);
+            self.internal_deposit(&account_id, &token_id, amount.0);
+        }
+    }
+
+    pub fn get_balance(&self, account_id: AccountId, token_id: TokenId) -> U128 {
+        U128(self.balances.get(&(account_id, token_id)).unwrap_or(0))
+    }
+
+    /// Non-zero balances of `account_id`, in tokens since delisted too
+    pub fn get_balances(&self, account_id: AccountId) -> Vec<(TokenId, U128)> {
+        self.account_tokens
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|token_id| {
+                let balance = self.balances.get(&(account_id.clone(), token_id.clone()))?;
+                Some((token_id, U128(balance)))
+            })
+            .collect()
+    }
+
+    pub fn get_swap_allowance(&self, account_id: AccountId, token_id: TokenId) -> U128 {
+        U128(self.swap_allowances.get(&(account_id, token_id)).unwrap_or(0))
+    }
+}
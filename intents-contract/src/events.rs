@@ -0,0 +1,53 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, log};
+
+/// NEP-297 standard name of this contract's events
+pub const EVENT_STANDARD: &str = "intents_ark";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// Log `EVENT_JSON:{"standard":"intents_ark","version":...,"event":...,"data":...}`
+pub fn emit<T: Serialize>(event: &str, data: &T) {
+    log!(
+        "EVENT_JSON:{}",
+        near_sdk::serde_json::json!({
+            "standard": EVENT_STANDARD,
+            "version": EVENT_VERSION,
+            "event": event,
+            "data": data,
+        })
+    );
+}
+
+/// Gas of a callback (and the OutLayer deposit attached for its swap), for
+/// tuning CALLBACK_GAS and MIN_DEPOSIT from real executions
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[near_sdk::near(serializers=[json])]
+pub struct CallbackTelemetry<'a> {
+    callback: &'a str,
+    request_id: u64,
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    prepaid_gas: U128,
+    /// Gas used up to this event, just before the callback returns
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    used_gas: U128,
+    /// Attached to request_execution. Not what the execution consumed:
+    /// OutLayer refunds the unused part in a separate transfer no callback
+    /// of this contract sees, so consumption is this minus that refund
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    attached_deposit: Option<U128>,
+}
+
+/// Emit `callback_telemetry` for the current callback; call it last
+pub fn callback_telemetry(callback: &str, request_id: u64, attached_deposit: Option<u128>) {
+    emit(
+        "callback_telemetry",
+        &CallbackTelemetry {
+            callback,
+            request_id,
+            prepaid_gas: U128(env::prepaid_gas().as_gas() as u128),
+            used_gas: U128(env::used_gas().as_gas() as u128),
+            attached_deposit: attached_deposit.map(U128),
+        },
+    );
+}
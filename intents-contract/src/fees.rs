@@ -0,0 +1,104 @@
+use crate::Balance;
+
+/// Highest fee the owner can set (10%)
+pub const MAX_FEE_BASIS_POINTS: u16 = 1000;
+
+/// Highest priority fee a swap can add on top of the protocol fee (1%)
+pub const MAX_PRIORITY_FEE_BASIS_POINTS: u16 = 100;
+
+/// Highest transfer tax a whitelisted input token can be configured with (10%)
+pub const MAX_TRANSFER_FEE_BASIS_POINTS: u16 = 1000;
+
+const BASIS_POINTS: u128 = 10_000;
+
+/// Split `amount_in` into (fee, amount swapped) at `fee_basis_points`.
+/// The fee is floor(amount_in * bps / 10000), computed without the
+/// intermediate product so it stays exact for any u128 amount; the two parts
+/// always add up to `amount_in`. `fee_basis_points` must not exceed
+/// [`MAX_FEE_BASIS_POINTS`].
+pub fn split_fee(amount_in: Balance, fee_basis_points: u16) -> (Balance, Balance) {
+    let bps = fee_basis_points as u128;
+    let fee = (amount_in / BASIS_POINTS) * bps + (amount_in % BASIS_POINTS) * bps / BASIS_POINTS;
+    (fee, amount_in - fee)
+}
+
+/// What this contract holds of a transfer of `amount` by a token taxing
+/// `transfer_fee_bps` of it. The tax is rounded up, so the swap never
+/// counts on more than arrived whichever way the token rounds.
+pub fn received_after_transfer_fee(amount: Balance, transfer_fee_bps: u16) -> Balance {
+    let (_, received) = split_fee(amount, transfer_fee_bps);
+    let inexact = !((amount % BASIS_POINTS) * transfer_fee_bps as u128).is_multiple_of(BASIS_POINTS);
+    if inexact {
+        received - 1
+    } else {
+        received
+    }
+}
+
+/// Parse the worker's amount_out and enforce the swap's min_amount_out
+pub fn check_amount_out(amount_out: &str, min_amount_out: Balance) -> Result<Balance, String> {
+    let amount_out: Balance = amount_out
+        .parse()
+        .map_err(|_| format!("Invalid output amount {:?}", amount_out))?;
+    if amount_out < min_amount_out {
+        return Err(format!("Output amount {} is less than minimum {}", amount_out, min_amount_out));
+    }
+    Ok(amount_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_fee_split_is_exact(amount_in in any::<u128>(), bps in 0..=MAX_FEE_BASIS_POINTS) {
+            let (fee, after_fee) = split_fee(amount_in, bps);
+            prop_assert_eq!(fee + after_fee, amount_in);
+            prop_assert!(fee <= amount_in / 10);
+
+            // Same floor as the full-width product, where it fits
+            if let Some(product) = amount_in.checked_mul(bps as u128) {
+                prop_assert_eq!(fee, product / BASIS_POINTS);
+            }
+        }
+
+        #[test]
+        fn test_fee_grows_with_amount_and_rate(amount_in in 0..u128::MAX, bps in 0..MAX_FEE_BASIS_POINTS) {
+            let (fee, _) = split_fee(amount_in, bps);
+            prop_assert!(split_fee(amount_in + 1, bps).0 >= fee);
+            prop_assert!(split_fee(amount_in, bps + 1).0 >= fee);
+        }
+
+        #[test]
+        fn test_transfer_fee_is_rounded_up(amount in any::<u128>(), bps in 0..=MAX_TRANSFER_FEE_BASIS_POINTS) {
+            let received = received_after_transfer_fee(amount, bps);
+            let (floor_fee, _) = split_fee(amount, bps);
+            prop_assert!(amount - received - floor_fee <= 1);
+            if let Some(product) = amount.checked_mul(bps as u128) {
+                prop_assert_eq!(amount - received, product.div_ceil(BASIS_POINTS));
+            }
+        }
+
+        #[test]
+        fn test_min_amount_out_is_enforced(amount_out in any::<u128>(), min_amount_out in any::<u128>()) {
+            let result = check_amount_out(&amount_out.to_string(), min_amount_out);
+            prop_assert_eq!(result.is_ok(), amount_out >= min_amount_out);
+        }
+
+        #[test]
+        fn test_malformed_amount_out_is_rejected(amount_out in "[^0-9]*|-[0-9]+|[1-9][0-9]{39,}") {
+            prop_assert!(check_amount_out(&amount_out, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_fee_bounds() {
+        assert_eq!(split_fee(u128::MAX, 0), (0, u128::MAX));
+        assert_eq!(split_fee(u128::MAX, MAX_FEE_BASIS_POINTS).0, u128::MAX / 10);
+        assert_eq!(split_fee(9_999, 1), (0, 9_999));
+        assert_eq!(split_fee(10_000, 1), (1, 9_999));
+        assert_eq!(split_fee(1, MAX_FEE_BASIS_POINTS), (0, 1));
+    }
+}
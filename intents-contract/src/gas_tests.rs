@@ -0,0 +1,148 @@
+//! Gas budgets for the contract's hot paths
+//!
+//! Runs each entry point on the mocked blockchain and compares the gas it
+//! burns itself (host calls: storage, logs, promise creation; i.e. used gas
+//! minus gas handed to created receipts) against a fixed budget. Unit tests
+//! run natively, so wasm execution (JSON parsing) is not counted; the budgets
+//! leave room for it, and a change that adds storage operations or logging
+//! on these paths fails here first.
+
+use crate::*;
+use near_sdk::mock::MockAction;
+use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig};
+use std::collections::HashMap;
+
+/// Host gas budgets, about 1.5x what each path burns today (receipt
+/// creation fees dominate: ~19.5, ~20 and ~5.1 TGas)
+const FT_ON_TRANSFER_BUDGET: Gas = Gas::from_tgas(30);
+const CALLBACK_BUDGET: Gas = Gas::from_tgas(30);
+const WITHDRAW_FEES_BUDGET: Gas = Gas::from_tgas(8);
+
+/// Minimum left of CALLBACK_GAS for wasm execution after host gas, the
+/// output ft_transfer and its callback
+const CALLBACK_WASM_HEADROOM: Gas = Gas::from_tgas(20);
+
+fn owner() -> AccountId {
+    "owner.near".parse().unwrap()
+}
+
+fn context(predecessor: &str) -> VMContextBuilder {
+    let mut context = VMContextBuilder::new();
+    context
+        .current_account_id("swap.near".parse().unwrap())
+        .predecessor_account_id(predecessor.parse().unwrap())
+        .prepaid_gas(Gas::from_tgas(300));
+    context
+}
+
+fn contract() -> Contract {
+    testing_env!(context("owner.near").build());
+    let mut contract = Contract::new(owner(), None, None, None, None, None);
+    contract.whitelist_token("wrap.near".parse().unwrap(), None, U128(1), None);
+    contract.whitelist_token("usdc.near".parse().unwrap(), None, U128(1), None);
+    contract
+}
+
+/// Gas burnt by the current call, excluding static gas attached to promises
+fn burnt_gas() -> Gas {
+    let attached: u64 = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .map(|action| match action {
+            MockAction::FunctionCallWeight { prepaid_gas, .. } => prepaid_gas.as_gas(),
+            _ => 0,
+        })
+        .sum();
+    Gas::from_gas(env::used_gas().as_gas() - attached)
+}
+
+fn assert_within(name: &str, burnt: Gas, budget: Gas) {
+    assert!(burnt <= budget, "{} burns {}, budget is {}", name, burnt, budget);
+}
+
+#[test]
+fn test_ft_on_transfer_gas() {
+    let mut contract = contract();
+    testing_env!(context("wrap.near").build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900","metadata":{"app":"gas-test"}}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+    assert_within("ft_on_transfer", burnt_gas(), FT_ON_TRANSFER_BUDGET);
+}
+
+#[test]
+fn test_callback_gas() {
+    let mut contract = contract();
+    contract.pending_swaps.insert(
+        &0,
+        &types::SwapRequest {
+            request_id: 0,
+            sender_id: "alice.near".parse().unwrap(),
+            token_in: "wrap.near".parse().unwrap(),
+            token_out: "usdc.near".parse().unwrap(),
+            token_in_asset_id: "nep141:wrap.near".to_string(),
+            token_out_asset_id: "nep141:usdc.near".to_string(),
+            amount_in: 1_000_000,
+            min_amount_out: 900,
+            fee_amount: 1_000,
+            priority_fee_amount: 0,
+            amount_after_fee: 999_000,
+            referral: None,
+            metadata: None,
+            worker_version: None,
+            pair_echo_required: false,
+            callback_gas_tgas: None,
+            from_balance: false,
+            timestamp: 0,
+        },
+    );
+    let response = near_sdk::serde_json::json!({
+        "schema_version": 2,
+        "success": true,
+        "amount_out": "1000",
+        "error_message": null,
+        "intent_hash": "2bNxPqR7dVhXaYkCw3mT9sLfJ5uGe8oZiB4nHcWrK6Dp",
+        "quote_hash": "6XKf5GzjV2Y1wAhRfHnmsHfTVbWWcHW4iBqgwvj3o8iz",
+        "deposit_tx_hash": "9Fw8Jb5YvDwbLqmA4kQ9p1XGh9VbTkM6uBvKcQnE3sRt",
+        "withdraw_intent_hash": "Ea4wZ9tLkQ2nVbR8cXyHs5mJf3uPd7oTgK1iCvN6rBzM",
+        "steps": [],
+        "metrics": null
+    });
+    testing_env!(
+        context("swap.near").prepaid_gas(CALLBACK_GAS).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        HashMap::default(),
+        vec![PromiseResult::Successful(response.to_string().into_bytes())],
+    );
+
+    contract.on_execution_response(
+        0,
+        "alice.near".parse().unwrap(),
+        "wrap.near".parse().unwrap(),
+        "usdc.near".parse().unwrap(),
+        U128(1_000_000),
+        U128(900),
+        U128(1_000),
+        Ok(Some(response)),
+    );
+
+    let burnt = burnt_gas();
+    assert_within("on_execution_response", burnt, CALLBACK_BUDGET);
+    let remaining =
+        CALLBACK_GAS.as_gas() - burnt.as_gas() - GAS_FOR_FT_TRANSFER.as_gas() - GAS_FOR_OUTPUT_CALLBACK.as_gas();
+    assert!(
+        remaining >= CALLBACK_WASM_HEADROOM.as_gas(),
+        "CALLBACK_GAS leaves only {} gas for wasm execution",
+        remaining
+    );
+}
+
+#[test]
+fn test_withdraw_fees_gas() {
+    let mut contract = contract();
+    contract.collected_fees.insert(&"wrap.near".parse().unwrap(), &1_000);
+    testing_env!(context("owner.near").build());
+    contract.withdraw_fees("wrap.near".parse().unwrap(), None);
+    assert_within("withdraw_fees", burnt_gas(), WITHDRAW_FEES_BUDGET);
+}
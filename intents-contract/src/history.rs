@@ -0,0 +1,73 @@
+use crate::*;
+
+// ============================================================================
+// Swap History Retention
+// ============================================================================
+//
+// Statuses, receipts and the processed bitmap would otherwise grow with
+// every swap. `pruned_below` is a cursor over the (sequential) request ids:
+// every id below it has finished and counts as processed. The cursor only
+// moves past swaps that ended at least SWAP_HISTORY_RETENTION_SECONDS ago;
+// Completed and Refunded ones lose their status and receipt, Failed ones
+// keep their status for the owner. Each new swap advances it by up to
+// AUTO_PRUNE_PER_SWAP ids, and `prune_swap_history` catches up in bulk.
+
+/// How long a finished swap's status and receipt are kept
+const SWAP_HISTORY_RETENTION_SECONDS: u64 = 30 * 24 * 3600;
+
+/// Ids the cursor may advance by on each new swap
+pub(crate) const AUTO_PRUNE_PER_SWAP: u64 = 2;
+
+/// Most ids one `prune_swap_history` call advances by
+const MAX_PRUNE_PER_CALL: u64 = 500;
+
+impl Contract {
+    /// Advance `pruned_below` by at most `max_swaps` ids, stopping at the
+    /// first swap still in flight or inside the retention window
+    pub(crate) fn prune_history(&mut self, max_swaps: u64) {
+        let cutoff = env::block_timestamp().saturating_sub(SWAP_HISTORY_RETENTION_SECONDS * 1_000_000_000);
+        let end = self.pruned_below.saturating_add(max_swaps).min(self.next_request_id);
+        while self.pruned_below < end {
+            let request_id = self.pruned_below;
+            if self.pending_swaps.get(&request_id).is_some() {
+                break;
+            }
+            // No state: settled before statuses were stored
+            if let Some(state) = self.swap_states.get(&request_id) {
+                match state.status {
+                    _ if state.updated_at > cutoff => break,
+                    SwapStatus::Completed | SwapStatus::Refunded => {
+                        self.swap_states.remove(&request_id);
+                        self.remove_receipt(request_id);
+                    }
+                    SwapStatus::Failed => {}
+                    _ => break,
+                }
+            }
+
+            self.pruned_below += 1;
+            if self.pruned_below.is_multiple_of(replay::BITS_PER_WORD) {
+                self.processed_requests.remove(&(request_id / replay::BITS_PER_WORD));
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Prune up to `max_swaps` (at most MAX_PRUNE_PER_CALL) finished swaps
+    /// past the retention window; returns the new `pruned_below`
+    pub fn prune_swap_history(&mut self, max_swaps: u64) -> u64 {
+        self.assert_owner();
+        let before = self.pruned_below;
+        self.prune_history(max_swaps.min(MAX_PRUNE_PER_CALL));
+        log!("Pruned swap history of ids {}..{}", before, self.pruned_below);
+        self.pruned_below
+    }
+
+    /// Every request id below this has finished; only Failed ones still
+    /// have a status
+    pub fn get_pruned_below(&self) -> u64 {
+        self.pruned_below
+    }
+}
@@ -1,12 +1,48 @@
+#[cfg(all(test, feature = "abi"))]
+mod abi_tests;
 mod admin;
+mod balances;
+mod events;
+mod fees;
+mod history;
+mod memos;
+mod migration;
+#[cfg(test)]
+mod gas_tests;
+#[cfg(test)]
+mod swap_tests;
+#[cfg(all(test, feature = "schema"))]
+mod schema_tests {
+    /// schemas.json is generated; regenerate it with
+    /// `cargo run -p intents-contract --example export_schemas --features schema > intents-contract/schemas.json`
+    #[test]
+    fn test_committed_schemas_are_up_to_date() {
+        let committed: serde_json::Value = serde_json::from_str(include_str!("../schemas.json")).unwrap();
+        assert_eq!(committed, crate::json_schemas(), "intents-contract/schemas.json is stale");
+    }
+}
+mod placeholders;
+mod receipts;
+mod reconcile;
+mod replay;
+mod secrets;
+mod status;
 mod types;
+mod workers;
 
 use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
+use near_sdk::collections::{LookupMap, UnorderedSet};
 use near_sdk::json_types::U128;
-use near_sdk::{env, ext_contract, log, near_bindgen, AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault, PromiseError};
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue,
+};
 
-use types::{SwapRequest, SwapResponse, TokenConfig, TokenId, TokenReceiverMessage};
+use types::{
+    ExecutionPayload, SwapInput, SwapRequest, SwapResponse, TokenConfig, TokenId, TokenReceiverMessage, WorkerVersion,
+};
+use status::SwapStatus;
+use workers::WorkerRouting;
 
 pub type Balance = u128;
 
@@ -19,19 +55,119 @@ const TGAS: u64 = 1_000_000_000_000;
 /// Gas for FT transfer
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas::from_gas(10 * TGAS);
 
+/// Longest ft_transfer result read by its callbacks; it returns nothing
+const MAX_TRANSFER_RESULT_LEN: usize = 16;
+
 /// Gas for callback
-pub const CALLBACK_GAS: Gas = Gas::from_gas(50 * TGAS);
+pub const CALLBACK_GAS: Gas = Gas::from_gas(60 * TGAS);
+
+/// Bounds of the `callback_gas_tgas` swap msg hint; the callback gets the
+/// amount above GAS_FOR_FT_TRANSFER on top of CALLBACK_GAS
+const MIN_CALLBACK_GAS_TGAS: u64 = 10;
+const MAX_CALLBACK_GAS_TGAS: u64 = 50;
+
+/// Gas for on_output_transferred, after the output ft_transfer
+pub const GAS_FOR_OUTPUT_CALLBACK: Gas = Gas::from_gas(5 * TGAS);
 
 /// Minimum deposit to cover OutLayer execution
 const MIN_DEPOSIT: u128 = 50_000_000_000_000_000_000_000; // 0.05 NEAR
 
-/// OutLayer contract ID
+/// Deposit for priority lane executions
+const PRIORITY_DEPOSIT: u128 = 100_000_000_000_000_000_000_000; // 0.1 NEAR
+
+/// OutLayer execution time limit, also passed to the worker as its wall-clock budget
+const MAX_EXECUTION_SECONDS: u64 = 120;
+
+/// Age after which a pending swap can no longer get its callback (OutLayer
+/// resolves every request well within this), so `cleanup_stale_swaps` may drop it
+const STALE_SWAP_SECONDS: u64 = 3600;
+
+/// Default limit on how long after the request a swap result is accepted:
+/// MAX_EXECUTION_SECONDS plus a minute for the callback receipts
+const DEFAULT_MAX_CALLBACK_AGE_SECONDS: u64 = 180;
+
+/// Maximum serialized size of swap metadata forwarded to the worker
+const MAX_SWAP_METADATA_LEN: usize = 512;
+
+/// OutLayer contract ID (build with `--features testnet` for testnet)
+#[cfg(not(feature = "testnet"))]
 const OUTLAYER_CONTRACT_ID: &str = "outlayer.near";
+#[cfg(feature = "testnet")]
+const OUTLAYER_CONTRACT_ID: &str = "outlayer.testnet";
 
 /// GitHub repo for WASI binary
 const WASI_REPO: &str = "https://github.com/zavodil/intents-ark";
 const WASI_COMMIT: &str = "main";
 
+/// OutLayer deposit of a swap: more for the priority lane
+fn execution_deposit(priority_fee_amount: Balance) -> Balance {
+    if priority_fee_amount > 0 {
+        PRIORITY_DEPOSIT
+    } else {
+        MIN_DEPOSIT
+    }
+}
+
+/// Err unless the worker's echoed token pair is the request's. The echo may
+/// only be missing if the swap went to a worker from before
+/// PAIR_ECHO_SCHEMA_VERSION and the output doesn't claim a later version.
+fn check_response_pair(swap_request: &SwapRequest, swap_response: &SwapResponse) -> Result<(), String> {
+    match (&swap_response.token_in, &swap_response.token_out) {
+        (Some(token_in), Some(token_out))
+            if *token_in == swap_request.token_in_asset_id && *token_out == swap_request.token_out_asset_id =>
+        {
+            Ok(())
+        }
+        (Some(token_in), Some(token_out)) => Err(format!(
+            "Response is for {} -> {}, not swap #{}'s {} -> {}",
+            token_in, token_out, swap_request.request_id, swap_request.token_in_asset_id, swap_request.token_out_asset_id
+        )),
+        _ if !swap_request.pair_echo_required
+            && swap_response.schema_version.unwrap_or(1) < intents_ark_types::PAIR_ECHO_SCHEMA_VERSION =>
+        {
+            Ok(())
+        }
+        _ => Err(format!("Response for swap #{} does not echo its token pair", swap_request.request_id)),
+    }
+}
+
+/// Why a swap's result doesn't end in an output transfer
+enum SwapFailure {
+    /// Nothing was swapped, or the result is refused: the input is refunded
+    Refund(String),
+    /// The swap settled but its output is still in intents.near: the input
+    /// was spent, so it isn't refunded
+    OutputStuck(String),
+}
+
+impl From<String> for SwapFailure {
+    fn from(reason: String) -> Self {
+        SwapFailure::Refund(reason)
+    }
+}
+
+/// Gas for a swap's output ft_transfer: its `callback_gas_tgas` hint, if any
+fn output_transfer_gas(callback_gas_tgas: Option<u64>) -> Gas {
+    callback_gas_tgas.map_or(GAS_FOR_FT_TRANSFER, Gas::from_tgas)
+}
+
+/// JSON Schemas of the JSON exchanged outside the contract ABI: the
+/// `ft_transfer_call` msg, the worker's input and output, and the `data` of
+/// each NEP-297 event
+#[cfg(feature = "schema")]
+pub fn json_schemas() -> serde_json::Value {
+    serde_json::json!({
+        "TokenReceiverMessage": schemars::schema_for!(TokenReceiverMessage),
+        "SwapInput": schemars::schema_for!(SwapInput),
+        "SwapResponse": schemars::schema_for!(SwapResponse),
+        "events": {
+            "swap_status": schemars::schema_for!(status::SwapStatusEvent),
+            "fee_reconciliation": schemars::schema_for!(Vec<reconcile::FeeReconciliation>),
+            "callback_telemetry": schemars::schema_for!(events::CallbackTelemetry<'static>),
+        },
+    })
+}
+
 // ============================================================================
 // Storage Keys
 // ============================================================================
@@ -42,6 +178,20 @@ enum StorageKey {
     Whitelist,
     PendingSwaps,
     CollectedFees,
+    WhitelistedTokens,
+    WorkerVersions,
+    WorkerPairRoutes,
+    SwapStates,
+    Balances,
+    SwapAllowances,
+    CollectedPriorityFees,
+    Receipts,
+    UserReceipts,
+    ProcessedRequests,
+    DepositTotals,
+    PendingTotals,
+    WorkerPendingSwaps,
+    AccountTokens,
 }
 
 // ============================================================================
@@ -67,6 +217,7 @@ trait OutLayer {
 #[ext_contract(ext_ft)]
 pub trait FungibleToken {
     fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
     fn ft_transfer_call(
         &mut self,
         receiver_id: AccountId,
@@ -80,6 +231,7 @@ pub trait FungibleToken {
 #[ext_contract(ext_self)]
 #[allow(dead_code)]
 trait ExtSelf {
+    #[allow(clippy::too_many_arguments)]
     fn on_execution_response(
         &mut self,
         request_id: u64,
@@ -91,6 +243,9 @@ trait ExtSelf {
         fee_amount: U128,
         #[callback_result] result: Result<Option<serde_json::Value>, PromiseError>,
     ) -> Option<U128>;
+    fn on_fee_balances(&mut self, token_ids: Vec<TokenId>) -> Vec<reconcile::FeeReconciliation>;
+    fn on_output_transferred(&mut self, request_id: u64);
+    fn on_withdraw(&mut self, account_id: AccountId, token_id: TokenId, amount: U128);
 }
 
 // ============================================================================
@@ -116,6 +271,9 @@ pub struct Contract {
     /// Whitelist: token_id => TokenConfig
     pub(crate) whitelist: LookupMap<TokenId, TokenConfig>,
 
+    /// Whitelisted token IDs, so the whitelist can be enumerated
+    pub(crate) whitelisted_tokens: UnorderedSet<TokenId>,
+
     /// Active swap requests
     pub(crate) pending_swaps: LookupMap<u64, SwapRequest>,
 
@@ -125,11 +283,61 @@ pub struct Contract {
     /// Secrets profile name (e.g., "production")
     pub(crate) secrets_profile: String,
 
+    /// JSON template of the secrets_ref sent to OutLayer
+    pub(crate) secrets_ref_template: String,
+
     /// Fee percentage in basis points (e.g., 10 = 0.1%, 100 = 1%)
     pub(crate) fee_basis_points: u16,
 
     /// Collected fees per token: token_id => balance
     pub(crate) collected_fees: LookupMap<TokenId, Balance>,
+
+    /// Let the worker register unregistered recipients with the output token
+    /// (storage_deposit paid by this contract) instead of refusing the swap
+    pub(crate) auto_register_storage: bool,
+
+    /// Swap results arriving later than this after the request were priced
+    /// against a stale quote; the swap is refunded instead
+    pub(crate) max_callback_age_seconds: u64,
+
+    /// Registered worker versions and which swaps run on them
+    pub(crate) workers: WorkerRouting,
+
+    /// Memos of the contract's transfers (and logged refunds)
+    pub(crate) memos: memos::MemoTemplates,
+
+    /// Status of every swap: request_id => state
+    pub(crate) swap_states: LookupMap<u64, status::SwapState>,
+
+    /// Deposited balances: (account, token) => amount
+    pub(crate) balances: LookupMap<(AccountId, TokenId), Balance>,
+
+    /// Amount of a deposited token the operator may swap for its owner
+    pub(crate) swap_allowances: LookupMap<(AccountId, TokenId), Balance>,
+
+    /// Priority lane fees collected for the operator: token_id => amount
+    pub(crate) collected_priority_fees: LookupMap<TokenId, Balance>,
+
+    /// Swap receipts: request_id => receipt
+    pub(crate) receipts: LookupMap<u64, receipts::StoredReceipt>,
+
+    /// Each user's latest request ids with a receipt, oldest first
+    pub(crate) user_receipts: LookupMap<AccountId, Vec<u64>>,
+
+    /// Bitmap of request ids whose callback ran: id / 64 => bits
+    pub(crate) processed_requests: LookupMap<u64, u64>,
+
+    /// Sum of the deposited balances of each token: token_id => amount
+    pub(crate) deposit_totals: LookupMap<TokenId, Balance>,
+
+    /// Sum of amount_in of each token's pending swaps: token_id => amount
+    pub(crate) pending_totals: LookupMap<TokenId, Balance>,
+
+    /// Request ids below this have finished and may be pruned
+    pub(crate) pruned_below: u64,
+
+    /// Tokens each account has a deposited balance of, delisted ones included
+    pub(crate) account_tokens: LookupMap<AccountId, Vec<TokenId>>,
 }
 
 // ============================================================================
@@ -144,67 +352,104 @@ impl Contract {
         operator_id: Option<AccountId>,
         secrets_profile: Option<String>,
         fee_basis_points: Option<u16>,
+        auto_register_storage: Option<bool>,
+        max_callback_age_seconds: Option<u64>,
     ) -> Self {
+        let fee_basis_points = fee_basis_points.unwrap_or(10); // Default: 0.1%
+        assert!(fee_basis_points <= fees::MAX_FEE_BASIS_POINTS, "Fee cannot exceed 10%");
+        let max_callback_age_seconds = max_callback_age_seconds.unwrap_or(DEFAULT_MAX_CALLBACK_AGE_SECONDS);
+        admin::assert_callback_age_covers_execution(max_callback_age_seconds);
+
         Self {
             owner_id: owner_id.clone(),
             operator_id: operator_id.unwrap_or(owner_id),
             paused: false,
             swap_paused: false,
             whitelist: LookupMap::new(StorageKey::Whitelist),
+            whitelisted_tokens: UnorderedSet::new(StorageKey::WhitelistedTokens),
             pending_swaps: LookupMap::new(StorageKey::PendingSwaps),
             next_request_id: 0,
             secrets_profile: secrets_profile.unwrap_or_else(|| "production".to_string()),
-            fee_basis_points: fee_basis_points.unwrap_or(10), // Default: 0.1%
+            secrets_ref_template: secrets::default_secrets_ref_template(),
+            fee_basis_points,
             collected_fees: LookupMap::new(StorageKey::CollectedFees),
+            auto_register_storage: auto_register_storage.unwrap_or(false),
+            max_callback_age_seconds,
+            workers: WorkerRouting::new(),
+            memos: memos::MemoTemplates::default(),
+            swap_states: LookupMap::new(StorageKey::SwapStates),
+            balances: LookupMap::new(StorageKey::Balances),
+            swap_allowances: LookupMap::new(StorageKey::SwapAllowances),
+            collected_priority_fees: LookupMap::new(StorageKey::CollectedPriorityFees),
+            receipts: LookupMap::new(StorageKey::Receipts),
+            user_receipts: LookupMap::new(StorageKey::UserReceipts),
+            processed_requests: LookupMap::new(StorageKey::ProcessedRequests),
+            deposit_totals: LookupMap::new(StorageKey::DepositTotals),
+            pending_totals: LookupMap::new(StorageKey::PendingTotals),
+            pruned_below: 0,
+            account_tokens: LookupMap::new(StorageKey::AccountTokens),
         }
     }
 
-    /// Handle incoming token transfers and initiate swap
-    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) {
+    /// Handle incoming token transfers: initiate a swap, or credit a deposit
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
         self.assert_not_paused();
         self.assert_swaps_not_paused();
 
         let token_in = env::predecessor_account_id();
 
         // Get token configs ONCE (gas optimization)
-        let token_in_config = self
-            .whitelist
-            .get(&token_in)
-            .expect("Token in not whitelisted");
+        let token_in_config = self.listed_token(&token_in, "in");
+
+        // A taxed token delivers less than `amount`; swap and refund only
+        // what arrived (the sender bears the tax)
+        let received = fees::received_after_transfer_fee(amount.0, token_in_config.transfer_fee_bps);
+        if received < amount.0 {
+            log!("Transfer fee of {}: {} of {} received", token_in, received, amount.0);
+        }
 
         // Parse message
-        let message: TokenReceiverMessage =
-            serde_json::from_str(&msg).expect("Invalid token receiver message format");
+        // Panicking refunds the transfer (ft_resolve_transfer)
+        let message = TokenReceiverMessage::parse(&msg).unwrap_or_else(|e| env::panic_str(&e));
 
         match message {
             TokenReceiverMessage::Swap {
                 token_out,
                 min_amount_out,
+                referral,
+                metadata,
+                callback_gas_tgas,
+                priority_fee_bps,
             } => {
                 // Get token_out config ONCE (gas optimization)
-                let token_out_config = self
-                    .whitelist
-                    .get(&token_out)
-                    .expect("Token out not whitelisted");
+                let token_out_config = self.listed_token(&token_out, "out");
 
-                let min_amount_out_value = min_amount_out
-                    .and_then(|s| s.parse().ok())
-                    .unwrap_or(0);
+                let min_amount_out_value = min_amount_out.map_or(0, |amount| amount.0);
 
                 // Initiate swap via OutLayer - returns promise
-                self.internal_initiate_swap(
+                PromiseOrValue::Promise(self.internal_initiate_swap(
                     sender_id,
                     token_in,
                     token_out,
                     token_in_config,
                     token_out_config,
-                    amount.0,
+                    received,
                     min_amount_out_value,
-                )
+                    referral,
+                    metadata,
+                    callback_gas_tgas,
+                    priority_fee_bps,
+                    false,
+                ))
+            }
+            TokenReceiverMessage::Deposit {} => {
+                self.internal_deposit(&sender_id, &token_in, received);
+                PromiseOrValue::Value(U128(0))
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn internal_initiate_swap(
         &mut self,
         sender_id: AccountId,
@@ -214,10 +459,31 @@ impl Contract {
         token_out_config: TokenConfig,
         amount_in: Balance,
         min_amount_out: Balance,
-    ) {
+        referral: Option<AccountId>,
+        metadata: Option<near_sdk::serde_json::Value>,
+        callback_gas_tgas: Option<u64>,
+        priority_fee_bps: Option<u16>,
+        from_balance: bool,
+    ) -> Promise {
         // Validate
         assert_ne!(token_in, token_out, "Cannot swap token to itself");
         assert!(amount_in > 0, "Amount in must be greater than 0");
+        if let Some(metadata) = &metadata {
+            assert!(
+                metadata.to_string().len() <= MAX_SWAP_METADATA_LEN,
+                "Swap metadata exceeds {} bytes",
+                MAX_SWAP_METADATA_LEN
+            );
+        }
+
+        if let Some(callback_gas_tgas) = callback_gas_tgas {
+            assert!(
+                (MIN_CALLBACK_GAS_TGAS..=MAX_CALLBACK_GAS_TGAS).contains(&callback_gas_tgas),
+                "callback_gas_tgas must be between {} and {}",
+                MIN_CALLBACK_GAS_TGAS,
+                MAX_CALLBACK_GAS_TGAS
+            );
+        }
 
         // Validate minimum swap amount
         assert!(
@@ -228,16 +494,22 @@ impl Contract {
         );
 
         // Calculate fee (in basis points: 10 = 0.1%, 100 = 1%)
-        let fee_amount = (amount_in as u128)
-            .saturating_mul(self.fee_basis_points as u128)
-            / 10000;
-        let amount_after_fee = amount_in.saturating_sub(fee_amount);
+        let priority_fee_bps = priority_fee_bps.unwrap_or(0);
+        assert!(
+            priority_fee_bps <= fees::MAX_PRIORITY_FEE_BASIS_POINTS,
+            "priority_fee_bps cannot exceed {}",
+            fees::MAX_PRIORITY_FEE_BASIS_POINTS
+        );
+        let (fee_amount, _) = fees::split_fee(amount_in, self.fee_basis_points);
+        let (priority_fee_amount, _) = fees::split_fee(amount_in, priority_fee_bps);
+        let amount_after_fee = amount_in - fee_amount - priority_fee_amount;
 
         log!(
-            "💰 Fee calculation: amount={}, fee_bp={}, fee={}, after_fee={}",
+            "💰 Fee calculation: amount={}, fee_bp={}, fee={}, priority_fee={}, after_fee={}",
             amount_in,
             self.fee_basis_points,
             fee_amount,
+            priority_fee_amount,
             amount_after_fee
         );
 
@@ -245,28 +517,34 @@ impl Contract {
         self.next_request_id += 1;
 
         // Store swap request with ORIGINAL amount (for refunds if failed)
+        let worker_version = self.workers.route(request_id, &token_in, &token_out);
         let swap_request = SwapRequest {
             request_id,
             sender_id: sender_id.clone(),
             token_in: token_in.clone(),
             token_out: token_out.clone(),
+            token_in_asset_id: token_in_config.defuse_asset_id,
+            token_out_asset_id: token_out_config.defuse_asset_id,
             amount_in, // Original amount (with fee)
             min_amount_out,
+            fee_amount,
+            priority_fee_amount,
+            amount_after_fee,
+            referral,
+            metadata: metadata.map(|metadata| metadata.to_string()),
+            pair_echo_required: self.workers.echoes_pair(worker_version.as_deref()),
+            worker_version,
+            callback_gas_tgas,
+            from_balance,
             timestamp: env::block_timestamp(),
         };
 
-        self.pending_swaps.insert(&request_id, &swap_request);
+        self.insert_pending_swap(&swap_request);
+        self.add_receipt(&swap_request);
+        self.set_swap_status(request_id, SwapStatus::Pending, None);
+        self.prune_history(history::AUTO_PRUNE_PER_SWAP);
 
-        // Build input for WASI with REDUCED amount (after fee)
-        let input_data = near_sdk::serde_json::json!({
-            "sender_id": sender_id.to_string(),
-            "token_in": token_in_config.defuse_asset_id,
-            "token_out": token_out_config.defuse_asset_id,
-            "amount_in": amount_after_fee.to_string(),  // Amount after fee
-            "min_amount_out": min_amount_out.to_string(),
-            "swap_contract_id": env::current_account_id().to_string(),
-        })
-        .to_string();
+        let payload = self.execution_payload(&swap_request);
 
         log!(
             "🔄 Requesting swap #{} via OutLayer: {} {} → {} {} (min: {})",
@@ -278,39 +556,23 @@ impl Contract {
             token_out
         );
 
-        // Call OutLayer
-        let code_source = near_sdk::serde_json::json!({
-            "repo": WASI_REPO,
-            "commit": WASI_COMMIT,
-            "build_target": "wasm32-wasip2"
-        });
-
-        let resource_limits = near_sdk::serde_json::json!({
-            "max_instructions": 100_000_000_000u64,
-            "max_memory_mb": 256u32,
-            "max_execution_seconds": 120u64
-        });
-
-        let secrets_ref = near_sdk::serde_json::json!({
-            "profile": self.secrets_profile,
-            "account_id": self.operator_id
-        });
-
         // Create promise chain and return it to maintain execution unity
-        ext_outlayer::ext(OUTLAYER_CONTRACT_ID.parse().unwrap())
-            .with_attached_deposit(NearToken::from_yoctonear(MIN_DEPOSIT))
+        let promise = ext_outlayer::ext(OUTLAYER_CONTRACT_ID.parse().unwrap())
+            .with_attached_deposit(NearToken::from_yoctonear(execution_deposit(priority_fee_amount)))
             .with_unused_gas_weight(1)
             .request_execution(
-                code_source,
-                resource_limits,
-                input_data,
-                Some(secrets_ref),
+                payload.code_source,
+                payload.resource_limits,
+                payload.input_data,
+                Some(payload.secrets_ref),
                 "Json".to_string(),
                 Some(env::current_account_id()), // Refund NEAR to contract, not user
             )
             .then(
                 ext_self::ext(env::current_account_id())
-                    .with_static_gas(CALLBACK_GAS)
+                    .with_static_gas(CALLBACK_GAS.saturating_add(
+                        output_transfer_gas(callback_gas_tgas).saturating_sub(GAS_FOR_FT_TRANSFER),
+                    ))
                     .on_execution_response(
                         request_id,
                         sender_id,
@@ -320,11 +582,55 @@ impl Contract {
                         U128(min_amount_out),
                         U128(fee_amount),
                     ),
-            )
-            .as_return(); // Return the promise to maintain execution unity
+            );
+        self.set_swap_status(request_id, SwapStatus::Executing, None);
+        promise
+    }
+
+    /// OutLayer `request_execution` arguments for a swap: the worker input
+    /// (amount after fee) with its worker version's code source and the
+    /// current limits and secrets
+    fn execution_payload(&self, swap_request: &SwapRequest) -> ExecutionPayload {
+        // Build input for WASI with REDUCED amount (after fee)
+        let input_data = SwapInput {
+            sender_id: swap_request.sender_id.to_string(),
+            token_in: swap_request.token_in_asset_id.clone(),
+            token_out: swap_request.token_out_asset_id.clone(),
+            amount_in: swap_request.amount_after_fee.to_string(), // Amount after fee
+            min_amount_out: swap_request.min_amount_out.to_string(),
+            swap_contract_id: env::current_account_id().to_string(),
+            slippage_buffer_bps: None,
+            referral: swap_request.referral.as_ref().map(|referral| referral.to_string()),
+            metadata: swap_request
+                .metadata
+                .as_ref()
+                .and_then(|metadata| near_sdk::serde_json::from_str(metadata).ok()),
+            max_execution_seconds: Some(MAX_EXECUTION_SECONDS),
+            register_storage: self.auto_register_storage,
+        };
+
+        ExecutionPayload {
+            code_source: self.workers.code_source(swap_request.worker_version.as_deref()),
+            resource_limits: if swap_request.priority_fee_amount > 0 {
+                near_sdk::serde_json::json!({
+                    "max_instructions": 200_000_000_000u64,
+                    "max_memory_mb": 512u32,
+                    "max_execution_seconds": MAX_EXECUTION_SECONDS
+                })
+            } else {
+                near_sdk::serde_json::json!({
+                    "max_instructions": 100_000_000_000u64,
+                    "max_memory_mb": 256u32,
+                    "max_execution_seconds": MAX_EXECUTION_SECONDS
+                })
+            },
+            input_data: near_sdk::serde_json::to_string(&input_data).unwrap(),
+            secrets_ref: self.secrets_ref(),
+        }
     }
 
     #[private]
+    #[allow(clippy::too_many_arguments)]
     pub fn on_execution_response(
         &mut self,
         request_id: u64,
@@ -336,8 +642,13 @@ impl Contract {
         fee_amount: U128,
         #[callback_result] result: Result<Option<serde_json::Value>, PromiseError>,
     ) -> Option<U128> {
+        // Settle each request once, even if its record is gone
+        self.mark_processed(request_id);
+
         // Remove pending swap
-        self.pending_swaps.remove(&request_id);
+        let swap_request = self
+            .remove_pending_swap(request_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Swap #{} is not pending", request_id)));
 
         // Debug: log what we received
         log!("🔍 on_execution_response callback: request_id={}", request_id);
@@ -347,88 +658,167 @@ impl Contract {
             Err(e) => log!("   Result: Err({:?})", e),
         }
 
-        match result {
-            Ok(Some(json_value)) => {
-                log!("✅ Execution #{} completed successfully", request_id);
-
-                // Parse SwapResponse directly from the JSON value returned by outlayer
-                match serde_json::from_value::<SwapResponse>(json_value) {
-                            Ok(swap_response) => {
-                                log!(
-                                    "📊 Swap data: amount_out={:?}, intent_hash={:?}",
-                                    swap_response.amount_out,
-                                    swap_response.intent_hash
-                                );
-
-                                if swap_response.success {
-                                    if let Some(amount_out_str) = swap_response.amount_out {
-                                        let amount_out: Balance = amount_out_str.parse().unwrap_or(0);
-
-                                        // Validate minimum output amount
-                                        assert!(
-                                            amount_out >= min_amount_out.0,
-                                            "Output amount {} is less than minimum {}",
-                                            amount_out,
-                                            min_amount_out.0
-                                        );
-
-                                        // Collect fee (already calculated in internal_initiate_swap)
-                                        let current_fees = self.collected_fees.get(&token_in).unwrap_or(0);
-                                        self.collected_fees.insert(&token_in, &(current_fees + fee_amount.0));
-
-                                        log!(
-                                            "💰 Fee collected: {} {} (total collected: {})",
-                                            fee_amount.0,
-                                            token_in,
-                                            current_fees + fee_amount.0
-                                        );
-
-                                        // Transfer output tokens to user
-                                        ext_ft::ext(token_out.clone())
-                                            .with_static_gas(GAS_FOR_FT_TRANSFER)
-                                            .with_attached_deposit(NearToken::from_yoctonear(1))
-                                            .ft_transfer(
-                                                sender_id.clone(),
-                                                U128(amount_out),
-                                                Some(format!(
-                                                    "NEAR Intents swap completed. Intent: {}",
-                                                    swap_response.intent_hash.unwrap_or_default()
-                                                )),
-                                            );
-
-                                        log!(
-                                            "🎉 Swap completed: {} {} -> {} {} (fee: {})",
-                                            amount_in.0,
-                                            token_in,
-                                            amount_out,
-                                            token_out,
-                                            fee_amount.0
-                                        );
-
-                                        // Return Some(0) - all tokens used successfully
-                                        return Some(U128(0));
-                                    }
-                                }
-
-                                // Swap failed
-                                env::panic_str(&format!(
-                                    "Swap failed: {}",
-                                    swap_response.error_message.unwrap_or_else(|| "Unknown error".to_string())
-                                ));
-                            }
-                            Err(parse_err) => {
-                                env::panic_str(&format!("Failed to parse swap response: {}", parse_err));
-                            }
-                        }
+        // The promise args must describe the stored request
+        let settled = if self.callback_matches(&swap_request, &sender_id, &token_in, &token_out, amount_in.0, min_amount_out.0, fee_amount.0) {
+            self.settled_amount_out(&swap_request, result)
+        } else {
+            Err(SwapFailure::Refund(format!("Callback args do not match swap #{}", request_id)))
+        };
+        let (amount_out, intent_hash) = match settled {
+            Ok(settled) => settled,
+            // The owner recovers the output from intents.near
+            Err(SwapFailure::OutputStuck(reason)) => {
+                log!("⚠️ Swap #{} settled without its output: {}", request_id, reason);
+                self.collect_swap_fees(&swap_request);
+                self.set_swap_status(request_id, SwapStatus::Failed, Some(&reason));
+                events::callback_telemetry(
+                    "on_execution_response",
+                    request_id,
+                    Some(execution_deposit(swap_request.priority_fee_amount)),
+                );
+                return Some(U128(0));
             }
-
-            Ok(None) => {
-                env::panic_str("No output data returned from execution");
+            // Failures return the whole input as unused, so the token refunds it
+            Err(SwapFailure::Refund(reason)) => {
+                let unused = self.refund_swap(&swap_request, &reason);
+                events::callback_telemetry(
+                    "on_execution_response",
+                    request_id,
+                    Some(execution_deposit(swap_request.priority_fee_amount)),
+                );
+                return Some(unused);
             }
+        };
+        self.set_swap_status(request_id, SwapStatus::Settled, None);
+        self.settle_receipt(request_id, amount_out, intent_hash.clone());
+        self.collect_swap_fees(&swap_request);
+
+        // Outputs below the token's dust threshold aren't worth an
+        // ft_transfer; they add up in the sender's balance until withdrawn
+        let dust_threshold = self.whitelist.get(&token_out).map_or(0, |config| config.dust_threshold);
+        if amount_out < dust_threshold {
+            self.internal_deposit(&sender_id, &token_out, amount_out);
+            self.set_swap_status(request_id, SwapStatus::Completed, Some("Output credited to balance as dust"));
+            log!("🧹 Dust output {} {} credited to {}'s balance", amount_out, token_out, sender_id);
+            events::callback_telemetry(
+                "on_execution_response",
+                request_id,
+                Some(execution_deposit(swap_request.priority_fee_amount)),
+            );
+            return Some(U128(0));
+        }
 
-            Err(promise_error) => {
-                env::panic_str(&format!("Promise error: {:?}", promise_error));
+        // Transfer output tokens to user
+        ext_ft::ext(token_out.clone())
+            .with_static_gas(output_transfer_gas(swap_request.callback_gas_tgas))
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .ft_transfer(
+                sender_id.clone(),
+                U128(amount_out),
+                Some(self.completion_memo(request_id, &intent_hash.unwrap_or_default(), &token_out, amount_out)),
+            )
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_OUTPUT_CALLBACK)
+                    .on_output_transferred(request_id),
+            )
+            .detach();
+        self.set_swap_status(request_id, SwapStatus::TransferringOut, None);
+
+        log!(
+            "🎉 Swap completed: {} {} -> {} {} (fee: {})",
+            amount_in.0,
+            token_in,
+            amount_out,
+            token_out,
+            swap_request.fee_amount
+        );
+
+        events::callback_telemetry(
+            "on_execution_response",
+            request_id,
+            Some(execution_deposit(swap_request.priority_fee_amount)),
+        );
+
+        // Return Some(0) - all tokens used successfully
+        Some(U128(0))
+    }
+
+    /// Collect the fees of a swap that settled (calculated in
+    /// internal_initiate_swap)
+    fn collect_swap_fees(&mut self, swap_request: &SwapRequest) {
+        let token_in = &swap_request.token_in;
+        let current_fees = self.collected_fees.get(token_in).unwrap_or(0);
+        self.collected_fees.insert(token_in, &(current_fees + swap_request.fee_amount));
+
+        log!(
+            "💰 Fee collected: {} {} (total collected: {})",
+            swap_request.fee_amount,
+            token_in,
+            current_fees + swap_request.fee_amount
+        );
+        if swap_request.priority_fee_amount > 0 {
+            let priority_fees = self.collected_priority_fees.get(token_in).unwrap_or(0);
+            self.collected_priority_fees
+                .insert(token_in, &(priority_fees + swap_request.priority_fee_amount));
+        }
+    }
+
+    /// Output amount (and intent hash) of a swap the worker settled within
+    /// the request's limits, or why it didn't
+    fn settled_amount_out(
+        &self,
+        swap_request: &SwapRequest,
+        result: Result<Option<serde_json::Value>, PromiseError>,
+    ) -> Result<(Balance, Option<String>), SwapFailure> {
+        let json_value = match result {
+            Ok(Some(json_value)) => json_value,
+            Ok(None) => return Err("No output data returned from execution".to_string().into()),
+            Err(promise_error) => return Err(format!("Promise error: {:?}", promise_error).into()),
+        };
+        log!("✅ Execution #{} completed successfully", swap_request.request_id);
+
+        // Parse SwapResponse directly from the JSON value returned by outlayer
+        let swap_response = serde_json::from_value::<SwapResponse>(json_value)
+            .map_err(|parse_err| format!("Failed to parse swap response: {}", parse_err))?;
+        log!(
+            "📊 Swap data: amount_out={:?}, intent_hash={:?}, quote_hash={:?}, deposit_tx_hash={:?}, withdraw_intent_hash={:?}",
+            swap_response.amount_out,
+            swap_response.intent_hash,
+            swap_response.quote_hash,
+            swap_response.deposit_tx_hash,
+            swap_response.withdraw_intent_hash
+        );
+
+        match &swap_response.amount_out {
+            Some(amount_out) if swap_response.success => {
+                check_response_pair(swap_request, &swap_response)?;
+                self.check_result_fresh(swap_request)?;
+                // Validate minimum output amount
+                let amount_out = fees::check_amount_out(amount_out, swap_request.min_amount_out)?;
+                Ok((amount_out, swap_response.intent_hash))
+            }
+            // Settled, but the withdrawal failed or was still pending
+            Some(amount_out)
+                if matches!(
+                    swap_response.error_code,
+                    Some(intents_ark_types::ErrorCode::WithdrawFailed | intents_ark_types::ErrorCode::WithdrawPending)
+                ) =>
+            {
+                check_response_pair(swap_request, &swap_response)?;
+                Err(SwapFailure::OutputStuck(format!(
+                    "Swap settled for {} but its output is still in intents.near [{}]: {}",
+                    amount_out,
+                    swap_response.error_code.map_or("UNKNOWN", |code| code.as_str()),
+                    swap_response.error_message.unwrap_or_default()
+                )))
             }
+            // Swap failed
+            _ => Err(SwapFailure::Refund(format!(
+                "Swap failed [{}]: {}",
+                swap_response.error_code.map_or("UNKNOWN", |code| code.as_str()),
+                swap_response.error_message.unwrap_or_else(|| "Unknown error".to_string())
+            ))),
         }
     }
     
@@ -0,0 +1,131 @@
+use crate::*;
+use near_sdk::near;
+
+// ============================================================================
+// Transfer Memos
+// ============================================================================
+//
+// Templates for the memos on this contract's FT transfers, so systems
+// ingesting memos get a consistent format. `{name}` placeholders are
+// replaced with the values listed per template; anything else is kept as is.
+// NEP-141 refunds go through the token's ft_resolve_transfer, which carries
+// no memo, so the refund memo is logged (`Refund: ...`) by the callback
+// that returns the input as unused.
+
+const MAX_MEMO_TEMPLATE_LEN: usize = 256;
+
+const COMPLETION_PLACEHOLDERS: &[&str] = &["request_id", "intent_hash", "token_out", "amount_out"];
+const REFUND_PLACEHOLDERS: &[&str] = &["request_id", "token_in", "amount_in", "reason"];
+const FEE_WITHDRAWAL_PLACEHOLDERS: &[&str] = &["token_id", "amount"];
+
+#[derive(Clone, Debug, PartialEq)]
+#[near(serializers=[borsh, json])]
+pub struct MemoTemplates {
+    /// Output transfer of a settled swap
+    pub completion: String,
+    /// Logged when a swap is refunded
+    pub refund: String,
+    /// withdraw_fees transfer to the owner
+    pub fee_withdrawal: String,
+}
+
+impl Default for MemoTemplates {
+    fn default() -> Self {
+        Self {
+            completion: "NEAR Intents swap completed. Intent: {intent_hash}".to_string(),
+            refund: "NEAR Intents swap {request_id} refunded: {reason}".to_string(),
+            fee_withdrawal: "Fee withdrawal".to_string(),
+        }
+    }
+}
+
+/// Reject templates that are too long or use placeholders not in `allowed`
+fn validate(kind: &str, template: &str, allowed: &[&str]) -> Result<(), String> {
+    if template.len() > MAX_MEMO_TEMPLATE_LEN {
+        return Err(format!("{} memo template exceeds {} bytes", kind, MAX_MEMO_TEMPLATE_LEN));
+    }
+    placeholders::validate(template, allowed).map_err(|name| {
+        format!(
+            "Unknown placeholder {{{}}} in {} memo template (allowed: {})",
+            name,
+            kind,
+            allowed.join(", ")
+        )
+    })
+}
+
+impl Contract {
+    pub(crate) fn completion_memo(&self, request_id: u64, intent_hash: &str, token_out: &TokenId, amount_out: Balance) -> String {
+        placeholders::render(
+            &self.memos.completion,
+            &[
+                ("request_id", &request_id.to_string()),
+                ("intent_hash", intent_hash),
+                ("token_out", token_out.as_str()),
+                ("amount_out", &amount_out.to_string()),
+            ],
+        )
+    }
+
+    pub(crate) fn fee_withdrawal_memo(&self, token_id: &TokenId, amount: Balance) -> String {
+        placeholders::render(&self.memos.fee_withdrawal, &[("token_id", token_id.as_str()), ("amount", &amount.to_string())])
+    }
+
+    pub(crate) fn refund_memo(&self, swap_request: &SwapRequest, reason: &str) -> String {
+        placeholders::render(
+            &self.memos.refund,
+            &[
+                ("request_id", &swap_request.request_id.to_string()),
+                ("token_in", swap_request.token_in.as_str()),
+                ("amount_in", &swap_request.amount_in.to_string()),
+                ("reason", reason),
+            ],
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the given memo templates; omitted ones are kept
+    pub fn set_memo_templates(
+        &mut self,
+        completion: Option<String>,
+        refund: Option<String>,
+        fee_withdrawal: Option<String>,
+    ) {
+        self.assert_owner();
+        let mut memos = self.memos.clone();
+        for (kind, template, slot, allowed) in [
+            ("completion", completion, &mut memos.completion, COMPLETION_PLACEHOLDERS),
+            ("refund", refund, &mut memos.refund, REFUND_PLACEHOLDERS),
+            ("fee_withdrawal", fee_withdrawal, &mut memos.fee_withdrawal, FEE_WITHDRAWAL_PLACEHOLDERS),
+        ] {
+            if let Some(template) = template {
+                validate(kind, &template, allowed).unwrap_or_else(|e| env::panic_str(&e));
+                *slot = template;
+            }
+        }
+        self.memos = memos;
+        log!("Memo templates updated");
+    }
+
+    pub fn get_memo_templates(&self) -> MemoTemplates {
+        self.memos.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_unknown_placeholders_and_long_templates() {
+        assert_eq!(validate("refund", "ark:refund:{request_id}:{reason}", REFUND_PLACEHOLDERS), Ok(()));
+        assert_eq!(validate("refund", "no placeholders, {unclosed", REFUND_PLACEHOLDERS), Ok(()));
+        assert_eq!(
+            validate("fee_withdrawal", "{token_id} {amount_out}", FEE_WITHDRAWAL_PLACEHOLDERS),
+            Err("Unknown placeholder {amount_out} in fee_withdrawal memo template (allowed: token_id, amount)".to_string())
+        );
+        assert!(validate("completion", &"x".repeat(MAX_MEMO_TEMPLATE_LEN + 1), COMPLETION_PLACEHOLDERS).is_err());
+    }
+}
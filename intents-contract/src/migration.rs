@@ -0,0 +1,244 @@
+use crate::*;
+
+// ============================================================================
+// State Migration
+// ============================================================================
+//
+// `migrate` upgrades state written by the first deployed version (v1) to the
+// current layout. Whitelist and pending swap entries sit in LookupMaps, which
+// can't be enumerated, so the owner passes the whitelisted token ids and the
+// ids of the swaps still pending; each is rewritten in place under the same
+// storage prefix. Anything not listed keeps the v1 layout and fails to read,
+// so list every whitelisted token and every pending swap.
+//
+// A later change to the borsh layout of `Contract`, `TokenConfig` or
+// `SwapRequest` needs its own old-layout structs and migration step here.
+
+/// `TokenConfig` of v1
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct TokenConfigV1 {
+    pub defuse_asset_id: String,
+    pub min_swap_amount: Balance,
+}
+
+/// `SwapRequest` of v1
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct SwapRequestV1 {
+    pub request_id: u64,
+    pub sender_id: AccountId,
+    pub token_in: TokenId,
+    pub token_out: TokenId,
+    pub amount_in: Balance,
+    pub min_amount_out: Balance,
+    pub timestamp: u64,
+}
+
+/// `Contract` of v1
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct ContractV1 {
+    pub owner_id: AccountId,
+    pub operator_id: AccountId,
+    pub paused: bool,
+    pub swap_paused: bool,
+    pub whitelist: LookupMap<TokenId, TokenConfigV1>,
+    pub pending_swaps: LookupMap<u64, SwapRequestV1>,
+    pub next_request_id: u64,
+    pub secrets_profile: String,
+    pub fee_basis_points: u16,
+    pub collected_fees: LookupMap<TokenId, Balance>,
+}
+
+impl TokenConfigV1 {
+    fn migrate(self) -> TokenConfig {
+        TokenConfig {
+            defuse_asset_id: self.defuse_asset_id,
+            min_swap_amount: self.min_swap_amount,
+            // v1 kept no listing time; count the listing from the upgrade
+            whitelisted_at: env::block_timestamp(),
+            expires_at: None,
+            transfer_fee_bps: 0,
+            dust_threshold: 0,
+        }
+    }
+}
+
+impl SwapRequestV1 {
+    /// The fee is recomputed the way v1 did, so the swap's in-flight
+    /// callback (whose args v1 built) still matches the request
+    fn migrate(self, whitelist: &LookupMap<TokenId, TokenConfig>, fee_basis_points: u16) -> SwapRequest {
+        let asset_id = |token_id: &TokenId| {
+            whitelist
+                .get(token_id)
+                .map_or_else(|| format!("nep141:{}", token_id), |config| config.defuse_asset_id)
+        };
+        let fee_amount = self.amount_in.saturating_mul(fee_basis_points as u128) / 10000;
+        SwapRequest {
+            request_id: self.request_id,
+            sender_id: self.sender_id,
+            token_in_asset_id: asset_id(&self.token_in),
+            token_out_asset_id: asset_id(&self.token_out),
+            token_in: self.token_in,
+            token_out: self.token_out,
+            amount_in: self.amount_in,
+            min_amount_out: self.min_amount_out,
+            fee_amount,
+            priority_fee_amount: 0,
+            amount_after_fee: self.amount_in - fee_amount,
+            referral: None,
+            metadata: None,
+            worker_version: None,
+            // v1 workers don't echo the token pair
+            pair_echo_required: false,
+            callback_gas_tgas: None,
+            from_balance: false,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Upgrade v1 state: every whitelisted token and pending swap must be
+    /// listed (see above). Settings added since v1 take their `new` defaults
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(token_ids: Vec<TokenId>, pending_request_ids: Vec<u64>) -> Self {
+        let mut old: ContractV1 = env::state_read().unwrap_or_else(|| env::panic_str("No v1 state to migrate"));
+
+        let mut contract = Self {
+            owner_id: old.owner_id,
+            operator_id: old.operator_id,
+            paused: old.paused,
+            swap_paused: old.swap_paused,
+            whitelist: LookupMap::new(StorageKey::Whitelist),
+            whitelisted_tokens: UnorderedSet::new(StorageKey::WhitelistedTokens),
+            pending_swaps: LookupMap::new(StorageKey::PendingSwaps),
+            next_request_id: old.next_request_id,
+            secrets_profile: old.secrets_profile,
+            secrets_ref_template: secrets::default_secrets_ref_template(),
+            fee_basis_points: old.fee_basis_points,
+            collected_fees: old.collected_fees,
+            auto_register_storage: false,
+            max_callback_age_seconds: DEFAULT_MAX_CALLBACK_AGE_SECONDS,
+            workers: WorkerRouting::new(),
+            memos: memos::MemoTemplates::default(),
+            swap_states: LookupMap::new(StorageKey::SwapStates),
+            balances: LookupMap::new(StorageKey::Balances),
+            swap_allowances: LookupMap::new(StorageKey::SwapAllowances),
+            collected_priority_fees: LookupMap::new(StorageKey::CollectedPriorityFees),
+            receipts: LookupMap::new(StorageKey::Receipts),
+            user_receipts: LookupMap::new(StorageKey::UserReceipts),
+            processed_requests: LookupMap::new(StorageKey::ProcessedRequests),
+            deposit_totals: LookupMap::new(StorageKey::DepositTotals),
+            pending_totals: LookupMap::new(StorageKey::PendingTotals),
+            pruned_below: 0,
+            account_tokens: LookupMap::new(StorageKey::AccountTokens),
+        };
+
+        for token_id in &token_ids {
+            // Removed first: insert would read the v1 value it replaces
+            let config = old
+                .whitelist
+                .remove(token_id)
+                .unwrap_or_else(|| env::panic_str(&format!("Token {} is not in the v1 whitelist", token_id)));
+            contract.whitelist.insert(token_id, &config.migrate());
+            contract.whitelisted_tokens.insert(token_id);
+        }
+
+        for request_id in &pending_request_ids {
+            let swap_request = old
+                .pending_swaps
+                .remove(request_id)
+                .unwrap_or_else(|| env::panic_str(&format!("Swap #{} is not pending in v1 state", request_id)));
+            let swap_request = swap_request.migrate(&contract.whitelist, contract.fee_basis_points);
+            contract.insert_pending_swap(&swap_request);
+            contract.set_swap_status(*request_id, SwapStatus::Executing, None);
+        }
+
+        log!(
+            "Migrated v1 state: {} tokens, {} pending swaps",
+            token_ids.len(),
+            pending_request_ids.len()
+        );
+        contract
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    pub(crate) fn v1_state() -> ContractV1 {
+        let mut old = ContractV1 {
+            owner_id: "owner.near".parse().unwrap(),
+            operator_id: "operator.near".parse().unwrap(),
+            paused: false,
+            swap_paused: true,
+            whitelist: LookupMap::new(StorageKey::Whitelist),
+            pending_swaps: LookupMap::new(StorageKey::PendingSwaps),
+            next_request_id: 8,
+            secrets_profile: "production".to_string(),
+            fee_basis_points: 30,
+            collected_fees: LookupMap::new(StorageKey::CollectedFees),
+        };
+        for (token_id, asset_id) in [("wrap.near", "nep141:wrap.near"), ("usdc.near", "nep141:usdc.near")] {
+            let config = TokenConfigV1 { defuse_asset_id: asset_id.to_string(), min_swap_amount: 5 };
+            old.whitelist.insert(&token_id.parse().unwrap(), &config);
+        }
+        let swap_request = SwapRequestV1 {
+            request_id: 7,
+            sender_id: "alice.near".parse().unwrap(),
+            token_in: "wrap.near".parse().unwrap(),
+            token_out: "usdc.near".parse().unwrap(),
+            amount_in: 1_000_000,
+            min_amount_out: 900,
+            timestamp: 11,
+        };
+        old.pending_swaps.insert(&7, &swap_request);
+        old.collected_fees.insert(&"wrap.near".parse().unwrap(), &42);
+        old
+    }
+
+    #[test]
+    fn test_migrate_reads_the_v1_layout() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("swap.near".parse().unwrap()).predecessor_account_id("swap.near".parse().unwrap());
+        testing_env!(context.block_timestamp(99).build());
+        env::state_write(&v1_state());
+
+        let contract = Contract::migrate(
+            vec!["wrap.near".parse().unwrap(), "usdc.near".parse().unwrap()],
+            vec![7],
+        );
+        assert_eq!((contract.next_request_id, contract.fee_basis_points), (8, 30));
+        assert!(contract.swap_paused && !contract.paused);
+        assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 42);
+        assert_eq!(contract.get_whitelisted_tokens().len(), 2);
+
+        let config = contract.get_token_config("usdc.near".parse().unwrap()).unwrap();
+        assert_eq!((config.defuse_asset_id.as_str(), config.min_swap_amount), ("nep141:usdc.near", 5));
+        assert_eq!((config.whitelisted_at, config.expires_at), (99, None));
+
+        let swap_request = contract.get_pending_swap(7).unwrap();
+        assert_eq!(swap_request.token_out_asset_id, "nep141:usdc.near");
+        assert_eq!((swap_request.fee_amount, swap_request.amount_after_fee), (3_000, 997_000));
+        assert_eq!(swap_request.timestamp, 11);
+        assert_eq!(contract.get_swap_status(7).unwrap().status, SwapStatus::Executing);
+        assert_eq!(contract.pending_totals.get(&"wrap.near".parse().unwrap()), Some(1_000_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "Swap #6 is not pending in v1 state")]
+    fn test_migrate_refuses_unknown_pending_ids() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("swap.near".parse().unwrap()).predecessor_account_id("swap.near".parse().unwrap());
+        testing_env!(context.build());
+        env::state_write(&v1_state());
+        Contract::migrate(vec![], vec![6]);
+    }
+}
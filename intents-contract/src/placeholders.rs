@@ -0,0 +1,70 @@
+// ============================================================================
+// Template Placeholders
+// ============================================================================
+//
+// Owner-set templates (transfer memos, the secrets_ref) use `{name}`
+// placeholders. An unclosed `{` is plain text.
+
+/// `template` with each `{name}` of `values` replaced, in one left-to-right
+/// pass: values are copied verbatim, so a `{name}` inside one stays as is
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { break };
+        let name = &rest[start + 1..start + len];
+        result.push_str(&rest[..start]);
+        match values.iter().find(|(known, _)| *known == name) {
+            Some((_, value)) => result.push_str(value),
+            None => result.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Err with the first placeholder of `template` not in `allowed`
+pub fn validate(template: &str, allowed: &[&str]) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else { break };
+        let name = &rest[start + 1..start + len];
+        if !allowed.contains(&name) {
+            return Err(name.to_string());
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_replaces_known_placeholders() {
+        assert_eq!(
+            render("swap {request_id}: {intent_hash} {request_id} {other}", &[("request_id", "7"), ("intent_hash", "abc")]),
+            "swap 7: abc 7 {other}"
+        );
+        assert_eq!(render("{unclosed {request_id", &[("request_id", "7")]), "{unclosed {request_id");
+    }
+
+    #[test]
+    fn test_render_copies_values_verbatim() {
+        let values = [("reason", "bad {token_out}"), ("token_out", "usdc.near"), ("amount_in", "{reason}")];
+        assert_eq!(
+            render("{reason} / {token_out} / {amount_in}", &values),
+            "bad {token_out} / usdc.near / {reason}"
+        );
+    }
+
+    #[test]
+    fn test_validate_returns_the_first_unknown_placeholder() {
+        assert_eq!(validate("{a} and {b}", &["a", "b"]), Ok(()));
+        assert_eq!(validate("no placeholders, {unclosed", &["a"]), Ok(()));
+        assert_eq!(validate("{a} {c} {d}", &["a"]), Err("c".to_string()));
+        assert_eq!(validate("{}", &["a"]), Err(String::new()));
+    }
+}
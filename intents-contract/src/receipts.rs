@@ -0,0 +1,123 @@
+use crate::*;
+use near_sdk::near;
+
+// ============================================================================
+// Swap Receipts
+// ============================================================================
+//
+// A compact receipt per swap and each user's last MAX_USER_RECEIPTS request
+// ids, so wallets can render swap history without an indexer. Older
+// receipts are dropped as new swaps come in or once pruned (see
+// history.rs); statuses come from swap_states.
+
+/// Receipts kept per user
+const MAX_USER_RECEIPTS: usize = 50;
+
+/// What a swap was and how it ended, as stored
+#[derive(Clone, Debug, PartialEq)]
+#[near(serializers=[borsh])]
+pub struct StoredReceipt {
+    /// Whose `user_receipts` list holds the id
+    pub sender_id: AccountId,
+    pub token_in: TokenId,
+    pub token_out: TokenId,
+    pub amount_in: Balance,
+    /// Set once the worker's result is accepted
+    pub amount_out: Option<Balance>,
+    pub intent_hash: Option<String>,
+    pub timestamp: u64,
+}
+
+/// A user's swap as returned by `get_user_receipts`
+#[derive(Clone, Debug, PartialEq)]
+#[near(serializers=[json])]
+pub struct SwapReceipt {
+    pub request_id: u64,
+    pub token_in: TokenId,
+    pub token_out: TokenId,
+    pub amount_in: U128,
+    pub amount_out: Option<U128>,
+    pub status: Option<status::SwapStatus>,
+    pub intent_hash: Option<String>,
+    /// Block timestamp (ns) of the request
+    pub timestamp: u64,
+}
+
+impl Contract {
+    /// Start the receipt of a new swap, dropping the sender's oldest one
+    /// past MAX_USER_RECEIPTS
+    pub(crate) fn add_receipt(&mut self, swap_request: &SwapRequest) {
+        self.receipts.insert(
+            &swap_request.request_id,
+            &StoredReceipt {
+                sender_id: swap_request.sender_id.clone(),
+                token_in: swap_request.token_in.clone(),
+                token_out: swap_request.token_out.clone(),
+                amount_in: swap_request.amount_in,
+                amount_out: None,
+                intent_hash: None,
+                timestamp: swap_request.timestamp,
+            },
+        );
+
+        let mut request_ids = self.user_receipts.get(&swap_request.sender_id).unwrap_or_default();
+        request_ids.push(swap_request.request_id);
+        if request_ids.len() > MAX_USER_RECEIPTS {
+            for request_id in request_ids.drain(..request_ids.len() - MAX_USER_RECEIPTS) {
+                self.receipts.remove(&request_id);
+            }
+        }
+        self.user_receipts.insert(&swap_request.sender_id, &request_ids);
+    }
+
+    pub(crate) fn settle_receipt(&mut self, request_id: u64, amount_out: Balance, intent_hash: Option<String>) {
+        if let Some(mut receipt) = self.receipts.get(&request_id) {
+            receipt.amount_out = Some(amount_out);
+            receipt.intent_hash = intent_hash;
+            self.receipts.insert(&request_id, &receipt);
+        }
+    }
+
+    /// Drop a receipt and its id from the sender's list
+    pub(crate) fn remove_receipt(&mut self, request_id: u64) {
+        if let Some(receipt) = self.receipts.remove(&request_id) {
+            let mut request_ids = self.user_receipts.get(&receipt.sender_id).unwrap_or_default();
+            request_ids.retain(|id| *id != request_id);
+            if request_ids.is_empty() {
+                self.user_receipts.remove(&receipt.sender_id);
+            } else {
+                self.user_receipts.insert(&receipt.sender_id, &request_ids);
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// `account_id`'s swaps, newest first, skipping `from` (default 0) and
+    /// returning at most `limit` (default and max MAX_USER_RECEIPTS)
+    pub fn get_user_receipts(&self, account_id: AccountId, from: Option<u64>, limit: Option<u64>) -> Vec<SwapReceipt> {
+        let limit = limit.map_or(MAX_USER_RECEIPTS, |limit| (limit as usize).min(MAX_USER_RECEIPTS));
+        self.user_receipts
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .rev()
+            .skip(from.unwrap_or(0) as usize)
+            .take(limit)
+            .filter_map(|request_id| {
+                let receipt = self.receipts.get(&request_id)?;
+                Some(SwapReceipt {
+                    request_id,
+                    token_in: receipt.token_in,
+                    token_out: receipt.token_out,
+                    amount_in: U128(receipt.amount_in),
+                    amount_out: receipt.amount_out.map(U128),
+                    status: self.swap_states.get(&request_id).map(|state| state.status),
+                    intent_hash: receipt.intent_hash,
+                    timestamp: receipt.timestamp,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,233 @@
+use crate::*;
+use near_sdk::Promise;
+
+// ============================================================================
+// Fee Reconciliation
+// ============================================================================
+//
+// This contract's balance of a token should cover everything it holds for
+// others: collected fees, priority fees, users' deposited balances and the
+// input of pending swaps. Balances and pending swaps sit in LookupMaps, so
+// per-token totals of both are kept as they change. A shortfall means the
+// accounting drifted (e.g. a transfer failed after fees were booked), with
+// one exception: while a swap executes, the worker has moved its input
+// (after fees) into intents.near, so a shortfall up to `pending_swaps` is
+// expected until it settles.
+
+/// Tokens checked per call: one ft_balance_of each, all in one transaction
+const MAX_RECONCILE_TOKENS: usize = 20;
+
+/// Longest ft_balance_of result read: a quoted u128 is at most 41 bytes
+const MAX_BALANCE_RESULT_LEN: usize = 64;
+
+/// Gas for one ft_balance_of view
+const GAS_FOR_FT_BALANCE_OF: Gas = Gas::from_gas(5 * TGAS);
+
+/// Base gas for on_fee_balances, plus this per token
+const GAS_FOR_RECONCILE_CALLBACK: Gas = Gas::from_gas(5 * TGAS);
+const GAS_PER_RECONCILED_TOKEN: Gas = Gas::from_gas(TGAS);
+
+/// Collected fees of one token against the contract's balance of it
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[near_sdk::near(serializers=[json])]
+pub struct FeeReconciliation {
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub token_id: TokenId,
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub collected_fees: U128,
+    /// Priority lane fees held for the operator
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub priority_fees: U128,
+    /// Users' deposited balances
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub escrowed_balances: U128,
+    /// amount_in of swaps still pending
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub pending_swaps: U128,
+    /// None if ft_balance_of failed
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub balance: Option<U128>,
+    /// What the balance doesn't cover of the four amounts above
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub shortfall: U128,
+    /// Balance above them (stray transfers)
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub excess: U128,
+}
+
+/// Add `amount` to `token_id`'s entry of `totals`
+pub(crate) fn add_to_total(totals: &mut LookupMap<TokenId, Balance>, token_id: &TokenId, amount: Balance) {
+    let total = totals.get(token_id).unwrap_or(0);
+    totals.insert(token_id, &total.saturating_add(amount));
+}
+
+/// Take `amount` off `token_id`'s entry of `totals`, dropping it at zero
+pub(crate) fn sub_from_total(totals: &mut LookupMap<TokenId, Balance>, token_id: &TokenId, amount: Balance) {
+    match totals.get(token_id).unwrap_or(0).saturating_sub(amount) {
+        0 => totals.remove(token_id),
+        total => totals.insert(token_id, &total),
+    };
+}
+
+impl Contract {
+    /// Store a new pending swap, counting its input as held and its worker
+    /// version as in use
+    pub(crate) fn insert_pending_swap(&mut self, swap_request: &SwapRequest) {
+        self.pending_swaps.insert(&swap_request.request_id, swap_request);
+        add_to_total(&mut self.pending_totals, &swap_request.token_in, swap_request.amount_in);
+        self.workers.add_pending_swap(swap_request.worker_version.as_deref());
+    }
+
+    /// Remove a pending swap, no longer counting its input or its version
+    pub(crate) fn remove_pending_swap(&mut self, request_id: u64) -> Option<SwapRequest> {
+        let swap_request = self.pending_swaps.remove(&request_id)?;
+        sub_from_total(&mut self.pending_totals, &swap_request.token_in, swap_request.amount_in);
+        self.workers.remove_pending_swap(swap_request.worker_version.as_deref());
+        Some(swap_request)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Compare what this contract holds for others with ft_balance_of(this contract) for
+    /// `token_ids` (default: every whitelisted token) and emit a
+    /// `fee_reconciliation` event
+    pub fn reconcile_fees(&mut self, token_ids: Option<Vec<TokenId>>) -> Promise {
+        self.assert_owner_or_operator();
+        let token_ids = token_ids.unwrap_or_else(|| self.whitelisted_tokens.to_vec());
+        assert!(!token_ids.is_empty(), "No tokens to reconcile");
+        assert!(
+            token_ids.len() <= MAX_RECONCILE_TOKENS,
+            "Can reconcile at most {} tokens per call",
+            MAX_RECONCILE_TOKENS
+        );
+
+        let balances = token_ids
+            .iter()
+            .map(|token_id| {
+                ext_ft::ext(token_id.clone())
+                    .with_static_gas(GAS_FOR_FT_BALANCE_OF)
+                    .ft_balance_of(env::current_account_id())
+            })
+            .reduce(|all, balance| all.and(balance))
+            .unwrap();
+
+        let callback_gas = GAS_FOR_RECONCILE_CALLBACK.saturating_add(Gas::from_gas(
+            GAS_PER_RECONCILED_TOKEN.as_gas() * token_ids.len() as u64,
+        ));
+        balances.then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(callback_gas)
+                .on_fee_balances(token_ids),
+        )
+    }
+
+    /// Results of reconcile_fees' ft_balance_of calls, in `token_ids` order
+    #[private]
+    pub fn on_fee_balances(&mut self, token_ids: Vec<TokenId>) -> Vec<FeeReconciliation> {
+        assert_eq!(env::promise_results_count(), token_ids.len() as u64, "Expected one balance per token");
+
+        let report: Vec<FeeReconciliation> = token_ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, token_id)| {
+                let balance = env::promise_result_checked(i as u64, MAX_BALANCE_RESULT_LEN)
+                    .ok()
+                    .and_then(|value| near_sdk::serde_json::from_slice::<U128>(&value).ok());
+                let collected_fees = self.collected_fees.get(&token_id).unwrap_or(0);
+                let priority_fees = self.collected_priority_fees.get(&token_id).unwrap_or(0);
+                let escrowed_balances = self.deposit_totals.get(&token_id).unwrap_or(0);
+                let pending_swaps = self.pending_totals.get(&token_id).unwrap_or(0);
+                let owed = collected_fees
+                    .saturating_add(priority_fees)
+                    .saturating_add(escrowed_balances)
+                    .saturating_add(pending_swaps);
+                let (shortfall, excess) = match balance {
+                    Some(U128(balance)) => (owed.saturating_sub(balance), balance.saturating_sub(owed)),
+                    None => (0, 0),
+                };
+                FeeReconciliation {
+                    token_id,
+                    collected_fees: U128(collected_fees),
+                    priority_fees: U128(priority_fees),
+                    escrowed_balances: U128(escrowed_balances),
+                    pending_swaps: U128(pending_swaps),
+                    balance,
+                    shortfall: U128(shortfall),
+                    excess: U128(excess),
+                }
+            })
+            .collect();
+
+        for row in report.iter().filter(|row| row.shortfall.0 > 0 || row.balance.is_none()) {
+            log!(
+                "⚠️ Fee reconciliation: {} collected_fees={} priority_fees={} escrowed_balances={} pending_swaps={} balance={} shortfall={}",
+                row.token_id,
+                row.collected_fees.0,
+                row.priority_fees.0,
+                row.escrowed_balances.0,
+                row.pending_swaps.0,
+                row.balance.map_or("unavailable".to_string(), |balance| balance.0.to_string()),
+                row.shortfall.0
+            );
+        }
+        events::emit("fee_reconciliation", &report);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_reports_shortfall_excess_and_failed_balances() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id("owner.near".parse().unwrap()).build());
+        let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+        let tokens: Vec<TokenId> = ["wrap.near", "usdc.near", "gone.near"].iter().map(|t| t.parse().unwrap()).collect();
+        for token_id in &tokens {
+            contract.collected_fees.insert(token_id, &1_000);
+        }
+        contract.collected_priority_fees.insert(&tokens[0], &300);
+
+        testing_env!(
+            VMContextBuilder::new()
+                .current_account_id("swap.near".parse().unwrap())
+                .predecessor_account_id("swap.near".parse().unwrap())
+                .build(),
+            near_sdk::test_vm_config(),
+            RuntimeFeesConfig::test(),
+            HashMap::default(),
+            vec![
+                PromiseResult::Successful(b"\"1500\"".to_vec()),
+                PromiseResult::Successful(b"\"400\"".to_vec()),
+                PromiseResult::Failed,
+            ],
+        );
+        let report = contract.on_fee_balances(tokens);
+
+        let summary: Vec<_> = report.iter().map(|row| (row.balance.map(|b| b.0), row.shortfall.0, row.excess.0)).collect();
+        assert_eq!(summary, vec![(Some(1_500), 0, 200), (Some(400), 600, 0), (None, 0, 0)]);
+
+        let event = get_logs().into_iter().find_map(|log| log.strip_prefix("EVENT_JSON:").map(String::from)).unwrap();
+        let event: near_sdk::serde_json::Value = near_sdk::serde_json::from_str(&event).unwrap();
+        assert_eq!(event["event"], "fee_reconciliation");
+        assert_eq!(event["data"][1]["shortfall"], "600");
+        let warning = "⚠️ Fee reconciliation: usdc.near collected_fees=1000 priority_fees=0 escrowed_balances=0 \
+                       pending_swaps=0 balance=400 shortfall=600";
+        assert!(get_logs().contains(&warning.to_string()), "{:?}", get_logs());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner or operator can call this method")]
+    fn test_reconcile_is_owner_or_operator_only() {
+        testing_env!(VMContextBuilder::new().predecessor_account_id("owner.near".parse().unwrap()).build());
+        let mut contract = Contract::new("owner.near".parse().unwrap(), Some("operator.near".parse().unwrap()), None, None, None, None);
+        testing_env!(VMContextBuilder::new().predecessor_account_id("alice.near".parse().unwrap()).build());
+        contract.reconcile_fees(None).detach();
+    }
+}
@@ -0,0 +1,40 @@
+use crate::*;
+
+// ============================================================================
+// Replay Protection
+// ============================================================================
+//
+// A swap's callback settles at most once. Removing the pending record
+// already makes a second callback panic, but that relies on the record
+// never being re-inserted; a bitmap of processed request ids (one word
+// per 64 ids, ids being sequential) keeps the guarantee on its own after
+// the record is gone, whether the callback or cleanup_stale_swaps removed it.
+// Ids below `pruned_below` (see history.rs) are processed and their words
+// dropped.
+
+pub(crate) const BITS_PER_WORD: u64 = 64;
+
+fn word_and_bit(request_id: u64) -> (u64, u64) {
+    (request_id / BITS_PER_WORD, 1 << (request_id % BITS_PER_WORD))
+}
+
+impl Contract {
+    /// Mark `request_id` processed; panics if it already was
+    pub(crate) fn mark_processed(&mut self, request_id: u64) {
+        let (word, bit) = word_and_bit(request_id);
+        let bits = self.processed_requests.get(&word).unwrap_or(0);
+        if request_id < self.pruned_below || bits & bit != 0 {
+            env::panic_str(&format!("Swap #{} was already processed", request_id));
+        }
+        self.processed_requests.insert(&word, &(bits | bit));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Whether the swap's callback ran (or cleanup removed it)
+    pub fn is_request_processed(&self, request_id: u64) -> bool {
+        let (word, bit) = word_and_bit(request_id);
+        request_id < self.pruned_below || self.processed_requests.get(&word).unwrap_or(0) & bit != 0
+    }
+}
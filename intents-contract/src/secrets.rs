@@ -0,0 +1,127 @@
+use crate::*;
+use near_sdk::serde_json::Value;
+
+// ============================================================================
+// Secrets Reference
+// ============================================================================
+//
+// The `secrets_ref` sent with each execution tells OutLayer which stored
+// secrets to decrypt for the worker. Its shape follows OutLayer's secrets
+// API, so it is kept as an owner-set JSON template rather than hardcoded:
+// `{profile}` and `{operator_id}` in any string value are replaced with the
+// secrets profile and the operator account.
+
+const MAX_SECRETS_REF_TEMPLATE_LEN: usize = 1024;
+
+const SECRETS_REF_PLACEHOLDERS: &[&str] = &["profile", "operator_id"];
+
+pub(crate) fn default_secrets_ref_template() -> String {
+    r#"{"profile":"{profile}","account_id":"{operator_id}"}"#.to_string()
+}
+
+/// `template` with the placeholders of every string value replaced
+fn render_value(template: &Value, values: &[(&str, &str)]) -> Value {
+    match template {
+        Value::String(s) => Value::String(placeholders::render(s, values)),
+        Value::Array(items) => Value::Array(items.iter().map(|item| render_value(item, values)).collect()),
+        Value::Object(fields) => {
+            Value::Object(fields.iter().map(|(key, value)| (key.clone(), render_value(value, values))).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// Reject templates that aren't objects, are too long, or use unknown
+/// placeholders
+fn validate(template: &Value) -> Result<String, String> {
+    if !template.is_object() {
+        return Err("secrets_ref template must be a JSON object".to_string());
+    }
+    let text = near_sdk::serde_json::to_string(template).unwrap();
+    if text.len() > MAX_SECRETS_REF_TEMPLATE_LEN {
+        return Err(format!("secrets_ref template exceeds {} bytes", MAX_SECRETS_REF_TEMPLATE_LEN));
+    }
+    check_placeholders(template)?;
+    Ok(text)
+}
+
+fn check_placeholders(template: &Value) -> Result<(), String> {
+    match template {
+        Value::String(s) => placeholders::validate(s, SECRETS_REF_PLACEHOLDERS).map_err(|name| {
+            format!(
+                "Unknown placeholder {{{}}} in secrets_ref template (allowed: {})",
+                name,
+                SECRETS_REF_PLACEHOLDERS.join(", ")
+            )
+        }),
+        Value::Array(items) => items.iter().try_for_each(check_placeholders),
+        Value::Object(fields) => fields.values().try_for_each(check_placeholders),
+        _ => Ok(()),
+    }
+}
+
+impl Contract {
+    /// The `secrets_ref` of an execution, rendered from the current template
+    pub(crate) fn secrets_ref(&self) -> Value {
+        let template: Value = near_sdk::serde_json::from_str(&self.secrets_ref_template).unwrap();
+        render_value(
+            &template,
+            &[("profile", &self.secrets_profile), ("operator_id", self.operator_id.as_str())],
+        )
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Replace the secrets_ref template; `None` restores the default
+    /// `{"profile": "{profile}", "account_id": "{operator_id}"}`
+    pub fn set_secrets_ref_template(&mut self, template: Option<Value>) {
+        self.assert_owner();
+        self.secrets_ref_template = match template {
+            Some(template) => validate(&template).unwrap_or_else(|e| env::panic_str(&e)),
+            None => default_secrets_ref_template(),
+        };
+        log!("Secrets ref template set to {}", self.secrets_ref_template);
+    }
+
+    pub fn get_secrets_ref_template(&self) -> Value {
+        near_sdk::serde_json::from_str(&self.secrets_ref_template).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::json;
+
+    #[test]
+    fn test_render_value_replaces_placeholders_in_nested_strings() {
+        let template = json!({
+            "owner": "{operator_id}",
+            "paths": ["secrets/{profile}", 3],
+            "scope": {"profile": "{profile}@{operator_id}", "strict": true}
+        });
+        assert_eq!(
+            render_value(&template, &[("profile", "staging"), ("operator_id", "op.near")]),
+            json!({
+                "owner": "op.near",
+                "paths": ["secrets/staging", 3],
+                "scope": {"profile": "staging@op.near", "strict": true}
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_non_objects_unknown_placeholders_and_long_templates() {
+        assert!(validate(&json!({"profile": "{profile}", "account_id": "{operator_id}"})).is_ok());
+        assert_eq!(validate(&json!("{profile}")), Err("secrets_ref template must be a JSON object".to_string()));
+        assert_eq!(
+            validate(&json!({"nested": ["{owner_id}"]})),
+            Err("Unknown placeholder {owner_id} in secrets_ref template (allowed: profile, operator_id)".to_string())
+        );
+        assert_eq!(
+            validate(&json!({"padding": "x".repeat(MAX_SECRETS_REF_TEMPLATE_LEN)})),
+            Err(format!("secrets_ref template exceeds {} bytes", MAX_SECRETS_REF_TEMPLATE_LEN))
+        );
+    }
+}
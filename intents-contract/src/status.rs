@@ -0,0 +1,124 @@
+use crate::*;
+use near_sdk::near;
+
+// ============================================================================
+// Swap Status
+// ============================================================================
+//
+// Every swap moves through
+//
+//   Pending -> Executing -> Settled -> TransferringOut -> Completed
+//
+// or ends early as Failed -> Refunded when the worker's result is refused
+// (the input goes back through ft_resolve_transfer). A failed output
+// transfer ends as Failed: the output stays in this contract for the owner
+// to resolve. So does a swap that settled but whose withdrawal from
+// intents.near failed or was still pending: its input was spent, so it
+// isn't refunded, and the owner recovers the output. A dust output,
+// credited to the sender's balance rather than transferred, goes straight
+// from Settled to Completed. Each change is stored and emitted as a
+// `swap_status` event; Completed and Refunded states are pruned once past
+// the retention window (see history.rs).
+
+/// Longest failure reason stored; longer ones are cut at a char boundary
+const MAX_STATUS_REASON_LEN: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[near(serializers=[borsh, json])]
+pub enum SwapStatus {
+    /// Input received, request stored
+    Pending,
+    /// OutLayer execution requested
+    Executing,
+    /// Worker result accepted, fee collected
+    Settled,
+    /// Output ft_transfer to the sender in flight
+    TransferringOut,
+    /// Output delivered
+    Completed,
+    /// Result refused or output transfer failed; see the reason
+    Failed,
+    /// Input returned to the sender
+    Refunded,
+}
+
+/// Latest status of a swap
+#[derive(Clone, Debug, PartialEq)]
+#[near(serializers=[borsh, json])]
+pub struct SwapState {
+    pub status: SwapStatus,
+    pub reason: Option<String>,
+    /// Block timestamp (ns) of the change
+    pub updated_at: u64,
+}
+
+/// Data of the `swap_status` event
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[near(serializers=[json])]
+pub struct SwapStatusEvent {
+    pub request_id: u64,
+    pub status: SwapStatus,
+    pub reason: Option<String>,
+}
+
+fn truncate_reason(reason: &str) -> String {
+    let mut end = reason.len().min(MAX_STATUS_REASON_LEN);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    reason[..end].to_string()
+}
+
+impl Contract {
+    pub(crate) fn set_swap_status(&mut self, request_id: u64, status: SwapStatus, reason: Option<&str>) {
+        let state = SwapState {
+            status,
+            reason: reason.map(truncate_reason),
+            updated_at: env::block_timestamp(),
+        };
+        events::emit(
+            "swap_status",
+            &SwapStatusEvent {
+                request_id,
+                status: state.status,
+                reason: state.reason.clone(),
+            },
+        );
+        self.swap_states.insert(&request_id, &state);
+    }
+
+    /// Mark the swap failed and log the refund memo; returns the unused
+    /// amount so the token contract returns the input to the sender, or
+    /// credits it back to the sender's balance for balance-funded swaps
+    pub(crate) fn refund_swap(&mut self, swap_request: &SwapRequest, reason: &str) -> U128 {
+        self.set_swap_status(swap_request.request_id, SwapStatus::Failed, Some(reason));
+        log!("Refund: {}", self.refund_memo(swap_request, reason));
+        self.set_swap_status(swap_request.request_id, SwapStatus::Refunded, Some(reason));
+        if swap_request.from_balance {
+            self.internal_deposit(&swap_request.sender_id, &swap_request.token_in, swap_request.amount_in);
+            return U128(0);
+        }
+        U128(swap_request.amount_in)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Marks the swap Completed, or Failed if the output transfer failed
+    #[private]
+    pub fn on_output_transferred(&mut self, request_id: u64) {
+        match env::promise_result_checked(0, MAX_TRANSFER_RESULT_LEN) {
+            Ok(_) => self.set_swap_status(request_id, SwapStatus::Completed, None),
+            Err(_) => {
+                log!("Output transfer of swap #{} failed", request_id);
+                self.set_swap_status(request_id, SwapStatus::Failed, Some("Output transfer failed"));
+            }
+        }
+        events::callback_telemetry("on_output_transferred", request_id, None);
+    }
+
+    pub fn get_swap_status(&self, request_id: u64) -> Option<SwapState> {
+        self.swap_states.get(&request_id)
+    }
+}
@@ -0,0 +1,960 @@
+//! Swap lifecycle on the mocked blockchain: ft_on_transfer, then the
+//! OutLayer callback with a scripted worker result
+
+use crate::*;
+use near_sdk::mock::MockAction;
+use status::SwapStatus;
+use near_sdk::serde_json::json;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig};
+use std::collections::HashMap;
+
+const SECOND_NS: u64 = 1_000_000_000;
+
+fn context(predecessor: &str, timestamp: u64) -> VMContextBuilder {
+    let mut context = VMContextBuilder::new();
+    context
+        .current_account_id("swap.near".parse().unwrap())
+        .predecessor_account_id(predecessor.parse().unwrap())
+        .block_timestamp(timestamp)
+        .prepaid_gas(Gas::from_tgas(300));
+    context
+}
+
+fn contract() -> Contract {
+    testing_env!(context("owner.near", 0).build());
+    let mut contract = Contract::new("owner.near".parse().unwrap(), None, None, None, None, None);
+    contract.whitelist_token("wrap.near".parse().unwrap(), None, U128(1), None);
+    contract.whitelist_token("usdc.near".parse().unwrap(), None, U128(1), None);
+    contract
+}
+
+/// alice swaps 1_000_000 wrap.near for usdc.near at `timestamp`; returns the request id
+fn start_swap(contract: &mut Contract, timestamp: u64) -> u64 {
+    testing_env!(context("wrap.near", timestamp).build());
+    let request_id = contract.next_request_id;
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900"}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+    request_id
+}
+
+/// The callback of `request_id` at `timestamp`, with the worker output `response`
+fn callback(contract: &mut Contract, request_id: u64, timestamp: u64, response: near_sdk::serde_json::Value) -> Option<U128> {
+    let (fee_amount, _) = fees::split_fee(1_000_000, contract.fee_basis_points);
+    callback_with_fee(contract, request_id, timestamp, response, fee_amount)
+}
+
+fn callback_with_fee(
+    contract: &mut Contract,
+    request_id: u64,
+    timestamp: u64,
+    response: near_sdk::serde_json::Value,
+    fee_amount: Balance,
+) -> Option<U128> {
+    testing_env!(
+        context("swap.near", timestamp).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        HashMap::default(),
+        vec![PromiseResult::Successful(response.to_string().into_bytes())],
+    );
+    contract.on_execution_response(
+        request_id,
+        "alice.near".parse().unwrap(),
+        "wrap.near".parse().unwrap(),
+        "usdc.near".parse().unwrap(),
+        U128(1_000_000),
+        U128(900),
+        U128(fee_amount),
+        Ok(Some(response)),
+    )
+}
+
+/// A settled wrap.near -> usdc.near response
+fn settled(amount_out: &str) -> near_sdk::serde_json::Value {
+    json!({
+        "schema_version": 3,
+        "success": true,
+        "amount_out": amount_out,
+        "token_in": "nep141:wrap.near",
+        "token_out": "nep141:usdc.near"
+    })
+}
+
+#[test]
+fn test_settled_swap_collects_fee() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert!(contract.get_pending_swap(request_id).is_some());
+
+    assert_eq!(callback(&mut contract, request_id, 90 * SECOND_NS, settled("950")), Some(U128(0)));
+    assert!(contract.get_pending_swap(request_id).is_none());
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 1_000);
+}
+
+#[test]
+fn test_pending_swap_records_fee_split() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    let swap = contract.get_pending_swap(request_id).unwrap();
+    assert_eq!((swap.amount_in, swap.fee_amount, swap.amount_after_fee), (1_000_000, 1_000, 999_000));
+}
+
+/// Status and reason of `request_id`
+fn status(contract: &Contract, request_id: u64) -> (SwapStatus, Option<String>) {
+    let state = contract.get_swap_status(request_id).unwrap();
+    (state.status, state.reason)
+}
+
+fn refunded(reason: &str) -> (SwapStatus, Option<String>) {
+    (SwapStatus::Refunded, Some(reason.to_string()))
+}
+
+#[test]
+fn test_callback_args_must_match_stored_request() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(
+        callback_with_fee(&mut contract, request_id, SECOND_NS, settled("950"), 10),
+        Some(U128(1_000_000))
+    );
+    assert_eq!(status(&contract, request_id), refunded("Callback args do not match swap #0"));
+    assert!(contract.get_pending_swap(request_id).is_none());
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 0);
+}
+
+#[test]
+#[should_panic(expected = "Swap #7 is not pending")]
+fn test_callback_needs_pending_swap() {
+    let mut contract = contract();
+    callback(&mut contract, 7, SECOND_NS, settled("950"));
+}
+
+#[test]
+fn test_execution_payload_view_matches_request_execution() {
+    let mut contract = contract();
+    testing_env!(context("wrap.near", 0).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","referral":"ref.near","metadata":{"app":"bot"}}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+
+    let sent = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| receipt.actions)
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight { method_name, args, .. } if method_name == b"request_execution" => {
+                Some(near_sdk::serde_json::from_slice::<near_sdk::serde_json::Value>(&args).unwrap())
+            }
+            _ => None,
+        })
+        .unwrap();
+
+    let payload = contract.get_request_execution_payload(0).unwrap();
+    assert_eq!(sent["code_source"], payload.code_source);
+    assert_eq!(sent["resource_limits"], payload.resource_limits);
+    assert_eq!(sent["input_data"], payload.input_data.as_str());
+    assert_eq!(sent["secrets_ref"], payload.secrets_ref);
+
+    let input: SwapInput = near_sdk::serde_json::from_str(&payload.input_data).unwrap();
+    assert_eq!(input.amount_in, "999000");
+    assert_eq!(input.referral.as_deref(), Some("ref.near"));
+    assert_eq!(input.metadata, Some(json!({ "app": "bot" })));
+    assert!(contract.get_request_execution_payload(1).is_none());
+}
+
+/// The secrets_ref of a new swap's execution, leaving the owner as predecessor
+fn sent_secrets_ref(contract: &mut Contract) -> near_sdk::serde_json::Value {
+    let request_id = start_swap(contract, 0);
+    testing_env!(context("owner.near", 0).build());
+    contract.get_request_execution_payload(request_id).unwrap().secrets_ref
+}
+
+#[test]
+fn test_secrets_ref_follows_the_owner_template() {
+    let mut contract = contract();
+    assert_eq!(
+        sent_secrets_ref(&mut contract),
+        json!({ "profile": "production", "account_id": "owner.near" })
+    );
+
+    contract.set_secrets_ref_template(Some(json!({
+        "owner": "{operator_id}",
+        "profiles": ["{profile}", "fallback"],
+        "version": 2
+    })));
+    contract.set_secrets_profile("staging".to_string());
+    assert_eq!(
+        sent_secrets_ref(&mut contract),
+        json!({ "owner": "owner.near", "profiles": ["staging", "fallback"], "version": 2 })
+    );
+
+    contract.set_secrets_ref_template(None);
+    assert_eq!(contract.get_secrets_ref_template(), json!({ "profile": "{profile}", "account_id": "{operator_id}" }));
+}
+
+#[test]
+#[should_panic(expected = "Unknown placeholder {owner_id} in secrets_ref template")]
+fn test_secrets_ref_template_rejects_unknown_placeholders() {
+    let mut contract = contract();
+    contract.set_secrets_ref_template(Some(json!({ "account_id": "{owner_id}" })));
+}
+
+#[test]
+#[should_panic(expected = "Token out listing of usdc.near expired at 10000000000")]
+fn test_expired_listing_refuses_swaps() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", SECOND_NS).build());
+    contract.update_token_config("usdc.near".parse().unwrap(), None, None, Some(10 * SECOND_NS), None, None);
+    let config = contract.get_token_config("usdc.near".parse().unwrap()).unwrap();
+    assert_eq!((config.whitelisted_at, config.expires_at), (0, Some(10 * SECOND_NS)));
+
+    start_swap(&mut contract, 10 * SECOND_NS - 1);
+    start_swap(&mut contract, 10 * SECOND_NS);
+}
+
+#[test]
+#[should_panic(expected = "expires_at must be in the future")]
+fn test_listing_cannot_start_expired() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 5 * SECOND_NS).build());
+    contract.whitelist_token("neko.near".parse().unwrap(), None, U128(1), Some(5 * SECOND_NS));
+}
+
+#[test]
+fn test_v1_swap_in_flight_settles_after_migration() {
+    testing_env!(context("swap.near", 0).build());
+    env::state_write(&migration::tests::v1_state());
+    let mut contract = Contract::migrate(vec!["wrap.near".parse().unwrap(), "usdc.near".parse().unwrap()], vec![7]);
+
+    // The v1 worker running it reports neither a schema_version nor the pair
+    let v1_response = json!({ "success": true, "amount_out": "950" });
+    assert_eq!(callback(&mut contract, 7, SECOND_NS, v1_response), Some(U128(0)));
+    assert_eq!(contract.get_swap_status(7).unwrap().status, SwapStatus::TransferringOut);
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 42 + 3_000);
+}
+
+#[test]
+fn test_late_result_is_refunded() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(callback(&mut contract, request_id, 181 * SECOND_NS, settled("950")), Some(U128(1_000_000)));
+    assert_eq!(
+        status(&contract, request_id),
+        refunded("Swap #0 result arrived 181s after the request (max 180s), refunding")
+    );
+}
+
+#[test]
+fn test_max_callback_age_is_configurable() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.set_max_callback_age(600);
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(callback(&mut contract, request_id, 400 * SECOND_NS, settled("950")), Some(U128(0)));
+}
+
+#[test]
+#[should_panic(expected = "Max callback age must be at least 120s")]
+fn test_max_callback_age_covers_execution_time() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.set_max_callback_age(60);
+}
+
+fn code_source_commit(contract: &Contract, request_id: u64) -> String {
+    let payload = contract.get_request_execution_payload(request_id).unwrap();
+    payload.code_source["commit"].as_str().unwrap().to_string()
+}
+
+#[test]
+fn test_swaps_route_to_worker_versions() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.register_worker_version("v2".to_string(), WASI_REPO.to_string(), "abc123".to_string(), None);
+    contract.register_worker_version("v3".to_string(), WASI_REPO.to_string(), "def456".to_string(), None);
+
+    // Built-in worker until a rule matches
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(code_source_commit(&contract, request_id), WASI_COMMIT);
+
+    testing_env!(context("owner.near", 0).build());
+    contract.set_worker_rollout(Some("v3".to_string()), 50);
+    contract.set_default_worker_version(Some("v2".to_string()));
+    let commits: Vec<String> = (0..4)
+        .map(|_| {
+            let request_id = start_swap(&mut contract, 0);
+            code_source_commit(&contract, request_id)
+        })
+        .collect();
+    // Request ids 1..=4: ids below 50 (mod 100) go to the rollout
+    assert_eq!(commits, vec!["def456"; 4]);
+
+    testing_env!(context("owner.near", 0).build());
+    contract.set_worker_rollout(Some("v3".to_string()), 5);
+    contract.set_pair_worker_version("wrap.near".parse().unwrap(), "usdc.near".parse().unwrap(), Some("v2".to_string()));
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!((request_id, code_source_commit(&contract, request_id)), (5, "abc123".to_string()));
+
+    testing_env!(context("owner.near", 0).build());
+    contract.set_pair_worker_version("wrap.near".parse().unwrap(), "usdc.near".parse().unwrap(), None);
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!((request_id, code_source_commit(&contract, request_id)), (6, "abc123".to_string()));
+    assert_eq!(contract.get_pending_swap(6).unwrap().worker_version.as_deref(), Some("v2"));
+}
+
+#[test]
+#[should_panic(expected = "Worker version v2 is still routed to")]
+fn test_routed_worker_version_cannot_be_removed() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.register_worker_version("v2".to_string(), WASI_REPO.to_string(), "abc123".to_string(), None);
+    contract.set_default_worker_version(Some("v2".to_string()));
+    contract.remove_worker_version("v2".to_string());
+}
+
+/// A swap started on v2, which no rule routes to any more
+fn swap_on_unrouted_version(contract: &mut Contract) -> u64 {
+    testing_env!(context("owner.near", 0).build());
+    contract.register_worker_version("v2".to_string(), WASI_REPO.to_string(), "abc123".to_string(), None);
+    contract.set_default_worker_version(Some("v2".to_string()));
+    let request_id = start_swap(contract, 0);
+    testing_env!(context("owner.near", 0).build());
+    contract.set_default_worker_version(None);
+    request_id
+}
+
+#[test]
+#[should_panic(expected = "Worker version v2 still runs 1 pending swaps")]
+fn test_worker_version_of_a_pending_swap_cannot_be_removed() {
+    let mut contract = contract();
+    let request_id = swap_on_unrouted_version(&mut contract);
+    assert!(contract.get_request_execution_payload(request_id).is_some());
+    contract.remove_worker_version("v2".to_string());
+}
+
+#[test]
+fn test_worker_version_is_removable_once_its_swaps_finish() {
+    let mut contract = contract();
+    let request_id = swap_on_unrouted_version(&mut contract);
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    testing_env!(context("owner.near", 0).build());
+    contract.remove_worker_version("v2".to_string());
+    assert!(contract.get_worker_versions().is_empty());
+}
+
+#[test]
+fn test_completion_transfer_uses_memo_template() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.set_memo_templates(Some("ark:done:{request_id}:{intent_hash}:{amount_out}".to_string()), None, None);
+    let request_id = start_swap(&mut contract, 0);
+    let mut response = settled("950");
+    response["intent_hash"] = json!("Hx");
+    callback(&mut contract, request_id, SECOND_NS, response);
+
+    let memo = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| receipt.actions)
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight { method_name, args, .. } if method_name == b"ft_transfer" => {
+                let args: near_sdk::serde_json::Value = near_sdk::serde_json::from_slice(&args).unwrap();
+                Some(args["memo"].as_str().unwrap().to_string())
+            }
+            _ => None,
+        });
+    assert_eq!(memo.as_deref(), Some("ark:done:0:Hx:950"));
+}
+
+#[test]
+fn test_refund_memo_is_logged() {
+    let mut contract = contract();
+    testing_env!(context("owner.near", 0).build());
+    contract.set_memo_templates(None, Some("ark:refund:{request_id}:{amount_in}:{reason}".to_string()), None);
+    let request_id = start_swap(&mut contract, 0);
+    let failed = json!({ "success": false, "error_code": "INSUFFICIENT_LIQUIDITY", "error_message": "no route" });
+
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, failed), Some(U128(1_000_000)));
+    assert!(near_sdk::test_utils::get_logs()
+        .contains(&"Refund: ark:refund:0:1000000:Swap failed [INSUFFICIENT_LIQUIDITY]: no route".to_string()));
+}
+
+/// on_output_transferred for `request_id` with the output ft_transfer `result`
+fn output_transferred(contract: &mut Contract, request_id: u64, result: PromiseResult) {
+    testing_env!(
+        context("swap.near", 2 * SECOND_NS).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        HashMap::default(),
+        vec![result],
+    );
+    contract.on_output_transferred(request_id);
+}
+
+/// `swap_status` events logged by the last call, as (status, reason)
+fn status_events() -> Vec<(String, near_sdk::serde_json::Value)> {
+    near_sdk::test_utils::get_logs()
+        .iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .map(|event| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(event).unwrap())
+        .filter(|event| event["event"] == "swap_status")
+        .map(|event| (event["data"]["status"].as_str().unwrap().to_string(), event["data"]["reason"].clone()))
+        .collect()
+}
+
+#[test]
+fn test_swap_status_follows_the_swap() {
+    let mut contract = contract();
+    assert!(contract.get_swap_status(0).is_none());
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(status_events(), vec![("Pending".to_string(), json!(null)), ("Executing".to_string(), json!(null))]);
+    assert_eq!(status(&contract, request_id), (SwapStatus::Executing, None));
+
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    assert_eq!(
+        status_events(),
+        vec![("Settled".to_string(), json!(null)), ("TransferringOut".to_string(), json!(null))]
+    );
+    let receipts = near_sdk::test_utils::get_created_receipts();
+    assert!(receipts.iter().flat_map(|receipt| &receipt.actions).any(|action| matches!(
+        action,
+        MockAction::FunctionCallWeight { method_name, .. } if method_name == b"on_output_transferred"
+    )));
+
+    output_transferred(&mut contract, request_id, PromiseResult::Successful(vec![]));
+    let state = contract.get_swap_status(request_id).unwrap();
+    assert_eq!((state.status, state.reason, state.updated_at), (SwapStatus::Completed, None, 2 * SECOND_NS));
+}
+
+#[test]
+fn test_failed_output_transfer_is_marked_failed() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    output_transferred(&mut contract, request_id, PromiseResult::Failed);
+    assert_eq!(status(&contract, request_id), (SwapStatus::Failed, Some("Output transfer failed".to_string())));
+}
+
+#[test]
+fn test_refused_result_fails_then_refunds() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, settled("899")), Some(U128(1_000_000)));
+    let reason = json!("Output amount 899 is less than minimum 900");
+    assert_eq!(
+        status_events(),
+        vec![("Failed".to_string(), reason.clone()), ("Refunded".to_string(), reason)]
+    );
+    assert!(contract.get_pending_swap(request_id).is_none());
+}
+
+/// Static gas of the created calls to `method`
+fn attached_gas(method: &[u8]) -> Vec<Gas> {
+    near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| receipt.actions)
+        .filter_map(|action| match action {
+            MockAction::FunctionCallWeight { method_name, prepaid_gas, .. } if method_name == method => Some(prepaid_gas),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_callback_gas_hint_raises_output_transfer_gas() {
+    let mut contract = contract();
+    testing_env!(context("wrap.near", 0).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900","callback_gas_tgas":30}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+    assert_eq!(attached_gas(b"on_execution_response"), vec![Gas::from_tgas(80)]);
+    assert_eq!(contract.get_pending_swap(0).unwrap().callback_gas_tgas, Some(30));
+
+    callback(&mut contract, 0, SECOND_NS, settled("950"));
+    assert_eq!(attached_gas(b"ft_transfer"), vec![Gas::from_tgas(30)]);
+}
+
+#[test]
+fn test_output_transfer_gas_defaults_without_hint() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(attached_gas(b"on_execution_response"), vec![CALLBACK_GAS]);
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    assert_eq!(attached_gas(b"ft_transfer"), vec![GAS_FOR_FT_TRANSFER]);
+}
+
+#[test]
+#[should_panic(expected = "callback_gas_tgas must be between 10 and 50")]
+fn test_callback_gas_hint_is_bounded() {
+    let mut contract = contract();
+    testing_env!(context("wrap.near", 0).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","callback_gas_tgas":51}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+}
+
+/// alice deposits `amount` wrap.near and lets the operator swap `allowance` of it
+fn deposit(contract: &mut Contract, amount: Balance, allowance: Balance) {
+    testing_env!(context("wrap.near", 0).build());
+    let result = contract.ft_on_transfer("alice.near".parse().unwrap(), U128(amount), r#"{"Deposit":{}}"#.to_string());
+    assert!(matches!(result, PromiseOrValue::Value(U128(0))));
+
+    testing_env!(context("alice.near", 0).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.approve_operator_swaps("wrap.near".parse().unwrap(), U128(allowance));
+}
+
+fn operator_swap(contract: &mut Contract, amount_in: Balance) -> u64 {
+    testing_env!(context("operator.near", 0).build());
+    let request_id = contract.next_request_id;
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900"}}"#;
+    contract
+        .operator_swap("alice.near".parse().unwrap(), "wrap.near".parse().unwrap(), U128(amount_in), msg.to_string())
+        .detach();
+    request_id
+}
+
+fn balance(contract: &Contract) -> (Balance, Balance) {
+    let (account_id, token_id): (AccountId, TokenId) = ("alice.near".parse().unwrap(), "wrap.near".parse().unwrap());
+    (
+        contract.get_balance(account_id.clone(), token_id.clone()).0,
+        contract.get_swap_allowance(account_id, token_id).0,
+    )
+}
+
+#[test]
+fn test_taxed_input_token_swaps_and_refunds_what_arrived() {
+    let mut contract = contract();
+    contract.update_token_config("wrap.near".parse().unwrap(), None, None, None, Some(150), None);
+    let request_id = start_swap(&mut contract, 0);
+    let swap_request = contract.get_pending_swap(request_id).unwrap();
+    assert_eq!(swap_request.amount_in, 985_000);
+    assert_eq!(swap_request.amount_after_fee, 985_000 - swap_request.fee_amount);
+
+    let failed = json!({ "success": false, "error_code": "INSUFFICIENT_LIQUIDITY", "error_message": "no route" });
+    let refund = callback_with_fee(&mut contract, request_id, SECOND_NS, failed, swap_request.fee_amount);
+    assert_eq!(refund, Some(U128(985_000)));
+
+    deposit(&mut contract, 1_000_001, 0);
+    assert_eq!(balance(&contract).0, 985_000);
+}
+
+#[test]
+fn test_dust_outputs_add_up_in_the_balance() {
+    let mut contract = contract();
+    contract.update_token_config("usdc.near".parse().unwrap(), None, None, None, None, Some(U128(1_000)));
+    for (request_id, timestamp) in [(0, 0), (1, SECOND_NS)] {
+        start_swap(&mut contract, timestamp);
+        assert_eq!(callback(&mut contract, request_id, timestamp + SECOND_NS, settled("950")), Some(U128(0)));
+        assert_eq!(contract.get_swap_status(request_id).unwrap().status, SwapStatus::Completed);
+    }
+    assert!(!near_sdk::test_utils::get_created_receipts().into_iter().flat_map(|receipt| receipt.actions).any(
+        |action| matches!(action, MockAction::FunctionCallWeight { method_name, .. } if method_name == b"ft_transfer")
+    ));
+    assert_eq!(contract.get_balance("alice.near".parse().unwrap(), "usdc.near".parse().unwrap()), U128(1_900));
+
+    start_swap(&mut contract, 2 * SECOND_NS);
+    callback(&mut contract, 2, 3 * SECOND_NS, settled("1000"));
+    assert_eq!(contract.get_swap_status(2).unwrap().status, SwapStatus::TransferringOut);
+}
+
+#[test]
+fn test_operator_swaps_from_deposited_balance() {
+    let mut contract = contract();
+    contract.set_operator("operator.near".parse().unwrap());
+    deposit(&mut contract, 3_000_000, 2_000_000);
+    assert_eq!(balance(&contract), (3_000_000, 2_000_000));
+
+    let request_id = operator_swap(&mut contract, 1_000_000);
+    assert_eq!(balance(&contract), (2_000_000, 1_000_000));
+    assert!(contract.get_pending_swap(request_id).unwrap().from_balance);
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, settled("950")), Some(U128(0)));
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 1_000);
+
+    // A refused swap goes back to the balance, not through the token
+    let request_id = operator_swap(&mut contract, 1_000_000);
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, settled("1")), Some(U128(0)));
+    assert_eq!(balance(&contract), (2_000_000, 0));
+    assert_eq!(status(&contract, request_id).0, SwapStatus::Refunded);
+}
+
+#[test]
+#[should_panic(expected = "Swap of 1000000 wrap.near exceeds the operator allowance of 999999")]
+fn test_operator_swap_needs_allowance() {
+    let mut contract = contract();
+    contract.set_operator("operator.near".parse().unwrap());
+    deposit(&mut contract, 3_000_000, 999_999);
+    operator_swap(&mut contract, 1_000_000);
+}
+
+#[test]
+#[should_panic(expected = "Only operator can call this method")]
+fn test_only_operator_swaps_balances() {
+    let mut contract = contract();
+    contract.set_operator("operator.near".parse().unwrap());
+    deposit(&mut contract, 3_000_000, 3_000_000);
+    testing_env!(context("alice.near", 0).build());
+    contract
+        .operator_swap("alice.near".parse().unwrap(), "wrap.near".parse().unwrap(), U128(1_000_000), "{}".to_string())
+        .detach();
+}
+
+#[test]
+fn test_failed_withdrawal_restores_balance() {
+    let mut contract = contract();
+    deposit(&mut contract, 3_000_000, 0);
+    testing_env!(context("alice.near", 0).attached_deposit(NearToken::from_yoctonear(1)).build());
+    contract.withdraw("wrap.near".parse().unwrap(), Some(U128(1_000_000))).detach();
+    assert_eq!(balance(&contract).0, 2_000_000);
+
+    testing_env!(
+        context("swap.near", 0).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        HashMap::default(),
+        vec![PromiseResult::Failed],
+    );
+    contract.on_withdraw("alice.near".parse().unwrap(), "wrap.near".parse().unwrap(), U128(1_000_000));
+    assert_eq!(balance(&contract).0, 3_000_000);
+}
+
+#[test]
+fn test_cleanup_returns_balance_funded_input() {
+    let mut contract = contract();
+    contract.set_operator("operator.near".parse().unwrap());
+    deposit(&mut contract, 1_000_000, 1_000_000);
+    let request_id = operator_swap(&mut contract, 1_000_000);
+    assert_eq!(balance(&contract), (0, 0));
+
+    testing_env!(context("owner.near", STALE_SWAP_SECONDS * SECOND_NS).build());
+    assert_eq!(contract.cleanup_stale_swaps(vec![request_id]), vec![request_id]);
+    assert_eq!(balance(&contract).0, 1_000_000);
+}
+
+#[test]
+#[should_panic(expected = "Swap #0 was already processed")]
+fn test_callback_settles_once_even_if_the_record_reappears() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    let swap_request = contract.get_pending_swap(request_id).unwrap();
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, settled("950")), Some(U128(0)));
+    assert!(contract.is_request_processed(request_id));
+
+    contract.pending_swaps.insert(&request_id, &swap_request);
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+}
+
+#[test]
+#[should_panic(expected = "Swap #0 was already processed")]
+fn test_callback_after_cleanup_is_refused() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    let swap_request = contract.get_pending_swap(request_id).unwrap();
+    testing_env!(context("owner.near", STALE_SWAP_SECONDS * SECOND_NS).build());
+    assert_eq!(contract.cleanup_stale_swaps(vec![request_id]), vec![request_id]);
+    assert!(contract.is_request_processed(request_id));
+    assert!(!contract.is_request_processed(request_id + 1));
+
+    contract.pending_swaps.insert(&request_id, &swap_request);
+    callback(&mut contract, request_id, STALE_SWAP_SECONDS * SECOND_NS, settled("950"));
+}
+
+#[test]
+fn test_user_swaps_from_own_balance() {
+    let mut contract = contract();
+    deposit(&mut contract, 2_500_000, 0);
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900"}}"#;
+    for _ in 0..2 {
+        testing_env!(context("alice.near", 0).attached_deposit(NearToken::from_yoctonear(1)).build());
+        contract.swap_from_balance("wrap.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+    }
+    assert_eq!(contract.get_pending_swap(1).unwrap().sender_id.as_str(), "alice.near");
+    assert_eq!(
+        contract.get_balances("alice.near".parse().unwrap()),
+        vec![("wrap.near".parse().unwrap(), U128(500_000))]
+    );
+    assert!(contract.get_balances("bob.near".parse().unwrap()).is_empty());
+}
+
+#[test]
+fn test_balances_of_delisted_tokens_stay_visible() {
+    let mut contract = contract();
+    deposit(&mut contract, 1_000_000, 0);
+    testing_env!(context("owner.near", 0).build());
+    contract.remove_token_from_whitelist("wrap.near".parse().unwrap());
+    assert_eq!(
+        contract.get_balances("alice.near".parse().unwrap()),
+        vec![("wrap.near".parse().unwrap(), U128(1_000_000))]
+    );
+
+    contract.internal_withdraw_balance(&"alice.near".parse().unwrap(), &"wrap.near".parse().unwrap(), 1_000_000);
+    assert!(contract.get_balances("alice.near".parse().unwrap()).is_empty());
+    assert!(contract.account_tokens.get(&"alice.near".parse().unwrap()).is_none());
+}
+
+#[test]
+#[should_panic(expected = "Cannot use 1000000 wrap.near - only 999999 deposited")]
+fn test_swap_from_balance_needs_funds() {
+    let mut contract = contract();
+    deposit(&mut contract, 999_999, 0);
+    testing_env!(context("alice.near", 0).attached_deposit(NearToken::from_yoctonear(1)).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near"}}"#;
+    contract.swap_from_balance("wrap.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+}
+
+#[test]
+fn test_priority_fee_buys_the_priority_lane() {
+    let mut contract = contract();
+    contract.set_operator("operator.near".parse().unwrap());
+    testing_env!(context("wrap.near", 0).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","min_amount_out":"900","priority_fee_bps":50}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+
+    let swap = contract.get_pending_swap(0).unwrap();
+    assert_eq!(
+        (swap.fee_amount, swap.priority_fee_amount, swap.amount_after_fee),
+        (1_000, 5_000, 994_000)
+    );
+    let deposits: Vec<u128> = near_sdk::test_utils::get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| receipt.actions)
+        .filter_map(|action| match action {
+            MockAction::FunctionCallWeight { method_name, attached_deposit, .. } if method_name == b"request_execution" => {
+                Some(attached_deposit.as_yoctonear())
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(deposits, vec![PRIORITY_DEPOSIT]);
+    assert_eq!(contract.get_request_execution_payload(0).unwrap().resource_limits["max_memory_mb"], 512);
+
+    assert_eq!(callback(&mut contract, 0, SECOND_NS, settled("950")), Some(U128(0)));
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 1_000);
+    assert_eq!(contract.get_collected_priority_fees("wrap.near".parse().unwrap()), U128(5_000));
+
+    testing_env!(context("operator.near", SECOND_NS).build());
+    contract.withdraw_priority_fees("wrap.near".parse().unwrap(), None);
+    assert_eq!(contract.get_collected_priority_fees("wrap.near".parse().unwrap()), U128(0));
+}
+
+#[test]
+fn test_standard_lane_without_priority_fee() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    assert_eq!(contract.get_pending_swap(request_id).unwrap().priority_fee_amount, 0);
+    assert_eq!(contract.get_request_execution_payload(request_id).unwrap().resource_limits["max_memory_mb"], 256);
+}
+
+#[test]
+#[should_panic(expected = "priority_fee_bps cannot exceed 100")]
+fn test_priority_fee_is_bounded() {
+    let mut contract = contract();
+    testing_env!(context("wrap.near", 0).build());
+    let msg = r#"{"Swap":{"token_out":"usdc.near","priority_fee_bps":101}}"#;
+    contract.ft_on_transfer("alice.near".parse().unwrap(), U128(1_000_000), msg.to_string()).detach();
+}
+
+#[test]
+fn test_user_receipts_show_history_newest_first() {
+    let mut contract = contract();
+    let settled_id = start_swap(&mut contract, 0);
+    let mut response = settled("950");
+    response["intent_hash"] = json!("Hx");
+    callback(&mut contract, settled_id, SECOND_NS, response);
+    let refunded_id = start_swap(&mut contract, 0);
+    callback(&mut contract, refunded_id, SECOND_NS, settled("1"));
+    let pending_id = start_swap(&mut contract, 0);
+
+    let receipts = contract.get_user_receipts("alice.near".parse().unwrap(), None, None);
+    let summary: Vec<_> = receipts
+        .iter()
+        .map(|receipt| (receipt.request_id, receipt.amount_out.map(|amount| amount.0), receipt.status))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (pending_id, None, Some(SwapStatus::Executing)),
+            (refunded_id, None, Some(SwapStatus::Refunded)),
+            (settled_id, Some(950), Some(SwapStatus::TransferringOut)),
+        ]
+    );
+    assert_eq!(receipts[2].intent_hash.as_deref(), Some("Hx"));
+    assert_eq!((receipts[2].token_in.as_str(), receipts[2].amount_in.0), ("wrap.near", 1_000_000));
+
+    let page = contract.get_user_receipts("alice.near".parse().unwrap(), Some(1), Some(1));
+    assert_eq!(page.iter().map(|receipt| receipt.request_id).collect::<Vec<_>>(), vec![refunded_id]);
+    assert!(contract.get_user_receipts("bob.near".parse().unwrap(), None, None).is_empty());
+}
+
+#[test]
+fn test_user_receipts_keep_the_latest_fifty() {
+    let mut contract = contract();
+    for _ in 0..52 {
+        start_swap(&mut contract, 0);
+    }
+    let receipts = contract.get_user_receipts("alice.near".parse().unwrap(), None, Some(100));
+    assert_eq!(receipts.len(), 50);
+    assert_eq!((receipts[0].request_id, receipts[49].request_id), (51, 2));
+    assert!(contract.receipts.get(&1).is_none());
+}
+
+/// Data of the `callback_telemetry` events logged by the last call
+fn telemetry_events() -> Vec<near_sdk::serde_json::Value> {
+    near_sdk::test_utils::get_logs()
+        .iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .map(|event| near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(event).unwrap())
+        .filter(|event| event["event"] == "callback_telemetry")
+        .map(|event| event["data"].clone())
+        .collect()
+}
+
+#[test]
+fn test_callbacks_emit_gas_and_attached_deposit_telemetry() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    let events = telemetry_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["callback"], "on_execution_response");
+    assert_eq!(events[0]["prepaid_gas"], Gas::from_tgas(300).as_gas().to_string());
+    assert_eq!(events[0]["attached_deposit"], MIN_DEPOSIT.to_string());
+    let used_gas: u64 = events[0]["used_gas"].as_str().unwrap().parse().unwrap();
+    assert!(used_gas > 0);
+
+    output_transferred(&mut contract, request_id, PromiseResult::Successful(vec![]));
+    let events = telemetry_events();
+    assert_eq!((events[0]["callback"].as_str(), &events[0]["attached_deposit"]), (Some("on_output_transferred"), &json!(null)));
+
+    // Refunds report too
+    let request_id = start_swap(&mut contract, 0);
+    callback(&mut contract, request_id, SECOND_NS, settled("1"));
+    assert_eq!(telemetry_events()[0]["request_id"], request_id);
+}
+
+/// A v3 settled response echoing `token_in` -> `token_out`
+fn settled_echo(token_in: &str, token_out: &str) -> near_sdk::serde_json::Value {
+    json!({ "schema_version": 3, "success": true, "amount_out": "950", "token_in": token_in, "token_out": token_out })
+}
+
+#[test]
+fn test_echoed_pair_must_match_request() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    let response = settled_echo("nep141:wrap.near", "nep141:usdc.near");
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, response), Some(U128(0)));
+
+    let request_id = start_swap(&mut contract, 0);
+    let crossed = settled_echo("nep141:usdc.near", "nep141:wrap.near");
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, crossed), Some(U128(1_000_000)));
+    assert_eq!(
+        status(&contract, request_id),
+        refunded("Response is for nep141:usdc.near -> nep141:wrap.near, not swap #1's nep141:wrap.near -> nep141:usdc.near")
+    );
+}
+
+#[test]
+fn test_v3_response_without_pair_is_refunded() {
+    let mut contract = contract();
+    let request_id = start_swap(&mut contract, 0);
+    let response = json!({ "schema_version": 3, "success": true, "amount_out": "950" });
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, response), Some(U128(1_000_000)));
+    assert_eq!(status(&contract, request_id), refunded("Response for swap #0 does not echo its token pair"));
+}
+
+#[test]
+fn test_echo_is_required_from_workers_that_send_it() {
+    let mut contract = contract();
+    let legacy = json!({ "schema_version": 2, "success": true, "amount_out": "950" });
+
+    // The built-in worker echoes the pair, whatever the response claims
+    let request_id = start_swap(&mut contract, 0);
+    assert!(contract.get_pending_swap(request_id).unwrap().pair_echo_required);
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, legacy.clone()), Some(U128(1_000_000)));
+    assert_eq!(status(&contract, request_id), refunded("Response for swap #0 does not echo its token pair"));
+    let request_id = start_swap(&mut contract, 0);
+    let unversioned = json!({ "success": true, "amount_out": "950" });
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, unversioned), Some(U128(1_000_000)));
+
+    // A version registered as predating the echo isn't held to it
+    testing_env!(context("owner.near", 0).build());
+    contract.register_worker_version("old".to_string(), WASI_REPO.to_string(), "abc123".to_string(), Some(2));
+    contract.set_default_worker_version(Some("old".to_string()));
+    let request_id = start_swap(&mut contract, 0);
+    assert!(!contract.get_pending_swap(request_id).unwrap().pair_echo_required);
+    assert_eq!(callback(&mut contract, request_id, SECOND_NS, legacy), Some(U128(0)));
+}
+
+#[test]
+fn test_settled_swap_with_stuck_output_is_not_refunded() {
+    let mut contract = contract();
+    for code in ["WITHDRAW_FAILED", "WITHDRAW_PENDING"] {
+        let request_id = start_swap(&mut contract, 0);
+        let response = json!({
+            "schema_version": 3, "success": false, "amount_out": "950", "error_code": code,
+            "error_message": "budget ran out", "token_in": "nep141:wrap.near", "token_out": "nep141:usdc.near"
+        });
+        assert_eq!(callback(&mut contract, request_id, SECOND_NS, response), Some(U128(0)));
+        assert_eq!(
+            status(&contract, request_id),
+            (
+                SwapStatus::Failed,
+                Some(format!(
+                    "Swap settled for 950 but its output is still in intents.near [{}]: budget ran out",
+                    code
+                ))
+            )
+        );
+    }
+    assert_eq!(contract.get_collected_fees("wrap.near".parse().unwrap()), 2_000);
+}
+
+/// on_fee_balances for wrap.near with `balance` as its ft_balance_of result
+fn reconcile_wrap(contract: &mut Contract, balance: &str) -> reconcile::FeeReconciliation {
+    testing_env!(
+        context("swap.near", SECOND_NS).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        HashMap::default(),
+        vec![PromiseResult::Successful(format!("\"{}\"", balance).into_bytes())],
+    );
+    contract.on_fee_balances(vec!["wrap.near".parse().unwrap()]).remove(0)
+}
+
+#[test]
+fn test_reconciliation_counts_deposits_and_pending_swaps() {
+    let mut contract = contract();
+    deposit(&mut contract, 2_500_000, 0);
+    let request_id = start_swap(&mut contract, 0);
+
+    let row = reconcile_wrap(&mut contract, "3000000");
+    assert_eq!((row.escrowed_balances.0, row.pending_swaps.0, row.collected_fees.0), (2_500_000, 1_000_000, 0));
+    assert_eq!((row.shortfall.0, row.excess.0), (500_000, 0));
+
+    callback(&mut contract, request_id, SECOND_NS, settled("950"));
+    let row = reconcile_wrap(&mut contract, "3000000");
+    assert_eq!((row.escrowed_balances.0, row.pending_swaps.0, row.collected_fees.0), (2_500_000, 0, 1_000));
+    assert_eq!((row.shortfall.0, row.excess.0), (0, 499_000));
+}
+
+#[test]
+fn test_finished_swaps_are_pruned_after_retention() {
+    let mut contract = contract();
+    for request_id in 0..3 {
+        start_swap(&mut contract, 0);
+        let amount_out = if request_id == 2 { "899" } else { "950" };
+        callback(&mut contract, request_id, SECOND_NS, settled(amount_out));
+    }
+    output_transferred(&mut contract, 0, PromiseResult::Successful(vec![]));
+    output_transferred(&mut contract, 1, PromiseResult::Failed);
+    let in_flight = start_swap(&mut contract, 0);
+
+    let retention_ns = 30 * 24 * 3600 * SECOND_NS;
+    testing_env!(context("owner.near", retention_ns + SECOND_NS).build());
+    assert_eq!(contract.prune_swap_history(10), 0);
+    testing_env!(context("owner.near", retention_ns + 2 * SECOND_NS).build());
+    assert_eq!(contract.prune_swap_history(10), in_flight);
+
+    assert!(contract.get_swap_status(0).is_none());
+    assert_eq!(status(&contract, 1).0, SwapStatus::Failed);
+    assert!(contract.get_swap_status(2).is_none());
+    let receipts = contract.get_user_receipts("alice.near".parse().unwrap(), None, None);
+    assert_eq!(receipts.iter().map(|receipt| receipt.request_id).collect::<Vec<_>>(), vec![in_flight, 1]);
+    assert!((0..in_flight).all(|request_id| contract.is_request_processed(request_id)));
+    assert!(!contract.is_request_processed(in_flight));
+}
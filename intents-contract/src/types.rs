@@ -1,5 +1,8 @@
+use near_sdk::json_types::U128;
 use near_sdk::{near, AccountId};
 
+pub use intents_ark_types::{SwapInput, SwapResponse};
+
 pub type Balance = u128;
 
 pub type TokenId = AccountId;
@@ -12,16 +15,37 @@ pub struct TokenConfig {
     pub defuse_asset_id: String,
     /// Minimum swap amount (in token's smallest unit)
     pub min_swap_amount: Balance,
+    /// Block timestamp (ns) of the listing
+    pub whitelisted_at: u64,
+    /// Block timestamp (ns) from which swaps of the token are refused
+    pub expires_at: Option<u64>,
+    /// Tax the token takes on each transfer, in basis points (0 for
+    /// ordinary tokens); deducted from what this contract counts as received
+    pub transfer_fee_bps: u16,
+    /// Outputs below this are credited to the recipient's deposited
+    /// balance instead of transferred (0 transfers every output)
+    pub dust_threshold: Balance,
 }
 
-/// Swap response from WASI execution
-#[derive(Clone, Debug)]
-#[near(serializers=[borsh, json])]
-pub struct SwapResponse {
-    pub success: bool,
-    pub amount_out: Option<String>,
-    pub error_message: Option<String>,
-    pub intent_hash: Option<String>,
+/// Check that `defuse_asset_id` names a token intents.near can hold:
+/// `nep141:<account>` or `nep245:<account>:<token id>`
+pub fn validate_defuse_asset_id(defuse_asset_id: &str) -> Result<(), String> {
+    let invalid = |reason: &str| Err(format!("Invalid defuse_asset_id {}: {}", defuse_asset_id, reason));
+    let (contract_id, token_id) = match defuse_asset_id.split_once(':') {
+        Some(("nep141", contract_id)) => (contract_id, None),
+        Some(("nep245", rest)) => match rest.split_once(':') {
+            Some((contract_id, token_id)) => (contract_id, Some(token_id)),
+            None => return invalid("expected nep245:<account>:<token id>"),
+        },
+        _ => return invalid("expected nep141:<account> or nep245:<account>:<token id>"),
+    };
+    if contract_id.parse::<AccountId>().is_err() {
+        return invalid("not a valid account id");
+    }
+    if token_id == Some("") {
+        return invalid("empty token id");
+    }
+    Ok(())
 }
 
 /// Swap request stored in contract
@@ -32,18 +56,224 @@ pub struct SwapRequest {
     pub sender_id: AccountId,
     pub token_in: TokenId,
     pub token_out: TokenId,
+    /// Defuse asset ids of token_in / token_out sent to the worker
+    pub token_in_asset_id: String,
+    pub token_out_asset_id: String,
+    /// Amount transferred, fee included
     pub amount_in: Balance,
     pub min_amount_out: Balance,
+    /// Kept by the contract if the swap settles
+    pub fee_amount: Balance,
+    /// Priority lane fee, on top of fee_amount; collected for the operator
+    pub priority_fee_amount: Balance,
+    /// Sent to the worker as its amount_in: amount_in minus both fees
+    pub amount_after_fee: Balance,
+    /// Referral account forwarded to the solver relay
+    pub referral: Option<AccountId>,
+    /// Swap metadata forwarded to the solver relay, as JSON text
+    pub metadata: Option<String>,
+    /// Registered worker version the swap was routed to (None: built-in)
+    pub worker_version: Option<String>,
+    /// That worker echoes the token pair, so a result without the echo is
+    /// refused whatever schema_version it claims
+    pub pair_echo_required: bool,
+    /// Requested gas (TGas) for the output ft_transfer (None: GAS_FOR_FT_TRANSFER)
+    pub callback_gas_tgas: Option<u64>,
+    /// Paid from the sender's deposited balance, which refunds go back to
+    pub from_balance: bool,
     pub timestamp: u64,
 }
 
-/// Message format for ft_transfer_call
+/// WASI worker build OutLayer compiles and runs
+#[derive(Clone, Debug, PartialEq)]
+#[near(serializers=[borsh, json])]
+pub struct WorkerVersion {
+    pub repo: String,
+    /// Commit hash, tag or branch
+    pub commit: String,
+    /// schema_version of the build's output
+    pub output_schema_version: u32,
+}
+
+/// Partial rollout of a worker version (canary)
+#[derive(Clone, Debug)]
 #[near(serializers=[borsh, json])]
+pub struct WorkerRollout {
+    pub version: String,
+    /// Share of swaps routed to `version`, 0-100
+    pub percent: u8,
+}
+
+/// Arguments of the OutLayer `request_execution` call for a swap
+#[derive(Clone, Debug)]
+#[near(serializers=[json])]
+pub struct ExecutionPayload {
+    pub code_source: near_sdk::serde_json::Value,
+    pub resource_limits: near_sdk::serde_json::Value,
+    /// Worker input (SwapInput) as a JSON string
+    pub input_data: String,
+    pub secrets_ref: near_sdk::serde_json::Value,
+}
+
+/// Message format for ft_transfer_call
+#[derive(Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[near(serializers=[json])]
 pub enum TokenReceiverMessage {
     Swap {
+        #[cfg_attr(feature = "schema", schemars(with = "String"))]
         token_out: TokenId,
+        /// Decimal string; a malformed value rejects the message rather than
+        /// disabling the minimum
+        #[serde(default)]
+        #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+        min_amount_out: Option<U128>,
+        /// Referral account forwarded to the solver relay
         #[serde(default)]
-        min_amount_out: Option<String>,
+        #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+        referral: Option<AccountId>,
+        /// Free-form attribution data forwarded to the solver relay
+        #[serde(default)]
+        metadata: Option<near_sdk::serde_json::Value>,
+        /// Gas (TGas) for the output ft_transfer, for gas-hungry tokens;
+        /// 10 to 50, default 10
+        #[serde(default)]
+        callback_gas_tgas: Option<u64>,
+        /// Extra fee (bps of the amount, up to 100) for the priority lane:
+        /// higher OutLayer limits and deposit; paid to the operator
+        #[serde(default)]
+        priority_fee_bps: Option<u16>,
     },
+    /// Credit the transfer to the sender's balance, for swaps the operator
+    /// starts on their behalf (see `approve_operator_swaps`)
+    Deposit {},
 }
 
+impl TokenReceiverMessage {
+    /// Parse an `ft_on_transfer` msg. `msg` is chosen by the sender, so the
+    /// error names what was wrong without echoing the message back.
+    pub fn parse(msg: &str) -> Result<Self, String> {
+        near_sdk::serde_json::from_str(msg).map_err(|e| {
+            format!(
+                "Invalid token receiver message format ({:?} error at column {})",
+                e.classify(),
+                e.column()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::serde_json::{json, Value};
+    use proptest::prelude::*;
+
+    fn json_value() -> impl Strategy<Value = Value> {
+        let leaf = prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            ".{0,24}".prop_map(Value::from),
+            "[a-z0-9._-]{2,64}".prop_map(Value::from),
+        ];
+        leaf.prop_recursive(4, 32, 6, |inner| {
+            let key = prop_oneof![
+                Just("Swap".to_string()),
+                Just("token_out".to_string()),
+                Just("min_amount_out".to_string()),
+                Just("referral".to_string()),
+                Just("metadata".to_string()),
+                "[a-z_]{1,10}",
+            ];
+            prop_oneof![
+                proptest::collection::vec(inner.clone(), 0..4).prop_map(Value::from),
+                proptest::collection::btree_map(key, inner, 0..6).prop_map(|map| Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_never_panics_on_bytes(msg in ".{0,256}") {
+            let _ = TokenReceiverMessage::parse(&msg);
+        }
+
+        #[test]
+        fn test_parse_never_panics_on_json(value in json_value()) {
+            let _ = TokenReceiverMessage::parse(&value.to_string());
+        }
+
+        #[test]
+        fn test_min_amount_out_is_exact_or_rejected(min_amount_out in ".{0,48}") {
+            let msg = json!({ "Swap": { "token_out": "usdc.near", "min_amount_out": min_amount_out } });
+            match (TokenReceiverMessage::parse(&msg.to_string()), min_amount_out.parse::<u128>()) {
+                (Ok(TokenReceiverMessage::Swap { min_amount_out: parsed, .. }), Ok(expected)) => {
+                    prop_assert_eq!(parsed.map(|amount| amount.0), Some(expected));
+                }
+                (Ok(other), Ok(_)) => prop_assert!(false, "parsed as {:?}", other),
+                (Ok(_), Err(_)) => prop_assert!(false, "accepted {:?}", min_amount_out),
+                (Err(_), Ok(_)) => prop_assert!(false, "rejected {:?}", min_amount_out),
+                (Err(_), Err(_)) => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_defuse_asset_id_validation() {
+        for valid in [
+            "nep141:wrap.near",
+            "nep141:17208628f84f5d6ad33f0da3bbbeb27ffcb398eac501a31bd6ad2011e36133a1",
+            "nep245:v2_1.omni.hot.tg:56_11111111111111111111",
+            "nep245:mt.near:token:with:colons",
+        ] {
+            assert_eq!(validate_defuse_asset_id(valid), Ok(()), "{}", valid);
+        }
+        for invalid in [
+            "wrap.near",
+            "nep141:",
+            "nep141:Wrap.near",
+            "nep141:wrap..near",
+            "nep171:nft.near",
+            "nep245:mt.near",
+            "nep245:mt.near:",
+            "NEP141:wrap.near",
+        ] {
+            assert!(validate_defuse_asset_id(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parses_shared_swap_message() {
+        let msg = intents_ark_types::SwapMessage::new("usdc.near")
+            .min_amount_out(900)
+            .referral("ref.near")
+            .metadata(json!({ "app": "bot" }))
+            .callback_gas_tgas(30)
+            .priority_fee_bps(20)
+            .to_msg();
+        let TokenReceiverMessage::Swap { token_out, min_amount_out, referral, metadata, callback_gas_tgas, priority_fee_bps } =
+            TokenReceiverMessage::parse(&msg).unwrap()
+        else {
+            panic!("not a Swap message");
+        };
+        assert_eq!(token_out.as_str(), "usdc.near");
+        assert_eq!(min_amount_out.map(|amount| amount.0), Some(900));
+        assert_eq!(referral.unwrap().as_str(), "ref.near");
+        assert_eq!(metadata, Some(json!({ "app": "bot" })));
+        assert_eq!(callback_gas_tgas, Some(30));
+        assert_eq!(priority_fee_bps, Some(20));
+
+        let bare = TokenReceiverMessage::parse(&intents_ark_types::SwapMessage::new("usdc.near").to_msg()).unwrap();
+        assert!(matches!(bare, TokenReceiverMessage::Swap { min_amount_out: None, referral: None, metadata: None, callback_gas_tgas: None, .. }));
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_accounts() {
+        let msg = json!({ "Swap": { "token_out": "Not An Account" } }).to_string();
+        let error = TokenReceiverMessage::parse(&msg).unwrap_err();
+        assert!(!error.contains("Not An Account"), "{}", error);
+        assert!(TokenReceiverMessage::parse(r#"{"Swap":{"token_out":"usdc.near"}}"#).is_ok());
+        assert!(matches!(TokenReceiverMessage::parse(r#"{"Deposit":{}}"#), Ok(TokenReceiverMessage::Deposit {})));
+    }
+}
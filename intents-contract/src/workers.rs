@@ -0,0 +1,221 @@
+use crate::types::WorkerRollout;
+use crate::*;
+use near_sdk::collections::UnorderedMap;
+
+// ============================================================================
+// Worker Versions
+// ============================================================================
+//
+// Swaps run the built-in worker (WASI_REPO at WASI_COMMIT) unless the owner
+// registers named versions and routes to them. For each swap the first
+// matching rule wins:
+// 1. a version set for the token pair
+// 2. the rollout version, for `percent` of request ids
+// 3. the default version, if set
+// 4. the built-in worker
+
+/// Routing state. It is part of the contract's borsh layout: `migrate`
+/// starts it empty for v1 state, and a change to its fields needs a
+/// migration step of its own
+#[derive(BorshDeserialize, BorshSerialize)]
+#[borsh(crate = "near_sdk::borsh")]
+pub struct WorkerRouting {
+    /// name => code source
+    versions: UnorderedMap<String, WorkerVersion>,
+    /// "token_in:token_out" => version name
+    pairs: UnorderedMap<String, String>,
+    rollout: Option<WorkerRollout>,
+    default_version: Option<String>,
+    /// version name => pending swaps running it, so it can't be removed
+    /// while their payload still needs its code source
+    pending_swaps: LookupMap<String, u64>,
+}
+
+impl WorkerRouting {
+    pub fn new() -> Self {
+        Self {
+            versions: UnorderedMap::new(StorageKey::WorkerVersions),
+            pairs: UnorderedMap::new(StorageKey::WorkerPairRoutes),
+            rollout: None,
+            default_version: None,
+            pending_swaps: LookupMap::new(StorageKey::WorkerPendingSwaps),
+        }
+    }
+
+    /// Version for a new swap; None for the built-in worker
+    pub fn route(&self, request_id: u64, token_in: &TokenId, token_out: &TokenId) -> Option<String> {
+        if let Some(version) = self.pairs.get(&pair_key(token_in, token_out)) {
+            return Some(version);
+        }
+        if let Some(rollout) = &self.rollout {
+            if request_id % 100 < rollout.percent as u64 {
+                return Some(rollout.version.clone());
+            }
+        }
+        self.default_version.clone()
+    }
+
+    /// `code_source` of `version` (None: the built-in worker)
+    pub fn code_source(&self, version: Option<&str>) -> near_sdk::serde_json::Value {
+        let (repo, commit) = match version {
+            Some(name) => {
+                let version = self
+                    .versions
+                    .get(&name.to_string())
+                    .unwrap_or_else(|| env::panic_str(&format!("Worker version {} is not registered", name)));
+                (version.repo, version.commit)
+            }
+            None => (WASI_REPO.to_string(), WASI_COMMIT.to_string()),
+        };
+        near_sdk::serde_json::json!({
+            "repo": repo,
+            "commit": commit,
+            "build_target": "wasm32-wasip2"
+        })
+    }
+
+    /// Whether `version` (None: the built-in worker, built from this repo)
+    /// echoes the token pair in its output
+    pub fn echoes_pair(&self, version: Option<&str>) -> bool {
+        version.and_then(|name| self.versions.get(&name.to_string())).map_or(
+            intents_ark_types::OUTPUT_SCHEMA_VERSION,
+            |version| version.output_schema_version,
+        ) >= intents_ark_types::PAIR_ECHO_SCHEMA_VERSION
+    }
+
+    /// Count a new pending swap of `version` (None: the built-in worker)
+    pub(crate) fn add_pending_swap(&mut self, version: Option<&str>) {
+        if let Some(name) = version {
+            let name = name.to_string();
+            let count = self.pending_swaps.get(&name).unwrap_or(0);
+            self.pending_swaps.insert(&name, &(count + 1));
+        }
+    }
+
+    /// Stop counting a finished (or removed) pending swap of `version`
+    pub(crate) fn remove_pending_swap(&mut self, version: Option<&str>) {
+        if let Some(name) = version {
+            let name = name.to_string();
+            match self.pending_swaps.get(&name).unwrap_or(0) {
+                0 | 1 => {
+                    self.pending_swaps.remove(&name);
+                }
+                count => {
+                    self.pending_swaps.insert(&name, &(count - 1));
+                }
+            }
+        }
+    }
+
+    fn assert_registered(&self, name: &str) {
+        assert!(
+            self.versions.get(&name.to_string()).is_some(),
+            "Worker version {} is not registered",
+            name
+        );
+    }
+}
+
+fn pair_key(token_in: &TokenId, token_out: &TokenId) -> String {
+    format!("{}:{}", token_in, token_out)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Add or replace a named worker version. `output_schema_version` is
+    /// the schema_version its output reports (default: the current one);
+    /// builds before PAIR_ECHO_SCHEMA_VERSION aren't held to the pair echo
+    pub fn register_worker_version(
+        &mut self,
+        name: String,
+        repo: String,
+        commit: String,
+        output_schema_version: Option<u32>,
+    ) {
+        self.assert_owner();
+        assert!(!name.is_empty() && !repo.is_empty() && !commit.is_empty(), "Name, repo and commit are required");
+        let output_schema_version = output_schema_version.unwrap_or(intents_ark_types::OUTPUT_SCHEMA_VERSION);
+        self.workers.versions.insert(
+            &name,
+            &WorkerVersion { repo: repo.clone(), commit: commit.clone(), output_schema_version },
+        );
+        log!(
+            "Worker version {} registered: {} @ {} (output schema v{})",
+            name,
+            repo,
+            commit,
+            output_schema_version
+        );
+    }
+
+    /// Remove a version no routing rule and no pending swap uses
+    pub fn remove_worker_version(&mut self, name: String) {
+        self.assert_owner();
+        let routing = &self.workers;
+        let in_use = routing.default_version.as_ref() == Some(&name)
+            || routing.rollout.as_ref().map(|rollout| &rollout.version) == Some(&name)
+            || routing.pairs.values().any(|version| version == name);
+        assert!(!in_use, "Worker version {} is still routed to", name);
+        let pending = routing.pending_swaps.get(&name).unwrap_or(0);
+        assert!(pending == 0, "Worker version {} still runs {} pending swaps", name, pending);
+        assert!(self.workers.versions.remove(&name).is_some(), "Worker version {} is not registered", name);
+        log!("Worker version {} removed", name);
+    }
+
+    /// Version for swaps no other rule matches (None: the built-in worker)
+    pub fn set_default_worker_version(&mut self, name: Option<String>) {
+        self.assert_owner();
+        if let Some(name) = &name {
+            self.workers.assert_registered(name);
+        }
+        log!("Default worker version: {}", name.as_deref().unwrap_or("built-in"));
+        self.workers.default_version = name;
+    }
+
+    /// Route swaps from `token_in` to `token_out` to a version (None removes the rule)
+    pub fn set_pair_worker_version(&mut self, token_in: TokenId, token_out: TokenId, name: Option<String>) {
+        self.assert_owner();
+        let key = pair_key(&token_in, &token_out);
+        match &name {
+            Some(name) => {
+                self.workers.assert_registered(name);
+                self.workers.pairs.insert(&key, name);
+            }
+            None => {
+                self.workers.pairs.remove(&key);
+            }
+        }
+        log!("Worker version for {} -> {}: {}", token_in, token_out, name.as_deref().unwrap_or("not set"));
+    }
+
+    /// Route `percent` of swaps without a pair rule to `name` (None, or 0%,
+    /// ends the rollout)
+    pub fn set_worker_rollout(&mut self, name: Option<String>, percent: u8) {
+        self.assert_owner();
+        assert!(percent <= 100, "Rollout percent cannot exceed 100");
+        self.workers.rollout = match name {
+            Some(version) if percent > 0 => {
+                self.workers.assert_registered(&version);
+                log!("Worker rollout: {} for {}% of swaps", version, percent);
+                Some(WorkerRollout { version, percent })
+            }
+            _ => {
+                log!("Worker rollout ended");
+                None
+            }
+        };
+    }
+
+    pub fn get_worker_versions(&self) -> Vec<(String, WorkerVersion)> {
+        self.workers.versions.to_vec()
+    }
+
+    pub fn get_worker_routing(&self) -> near_sdk::serde_json::Value {
+        near_sdk::serde_json::json!({
+            "default_version": self.workers.default_version,
+            "rollout": self.workers.rollout,
+            "pairs": self.workers.pairs.iter().collect::<std::collections::BTreeMap<_, _>>(),
+            "built_in": { "repo": WASI_REPO, "commit": WASI_COMMIT },
+        })
+    }
+}
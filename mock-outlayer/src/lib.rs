@@ -0,0 +1,145 @@
+//! Mock OutLayer contract for end-to-end tests of intents-contract
+//!
+//! `request_execution` returns the configured response right away, so the
+//! promise the swap contract chains `on_execution_response` onto resolves in
+//! the same transaction. Every request is recorded for assertions.
+//!
+//! Typical setup in a sandbox test: deploy this at the OutLayer account the
+//! swap contract was built for, then before each swap call one of
+//! - `respond_success(amount_out)`: settled swap (pass less than
+//!   min_amount_out to exercise the slippage check)
+//! - `respond_failure(error_code, error_message)`: worker-reported failure
+//! - `respond_with(response)`: any raw output, e.g. malformed JSON or `null`
+//!
+//! Responses set with `respond_success` / `respond_failure` echo each
+//! request's token pair, as the worker does.
+
+use intents_ark_types::{ErrorCode, SwapInput, SwapResponse, OUTPUT_SCHEMA_VERSION};
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::collections::Vector;
+use near_sdk::json_types::U128;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{env, log, near, near_bindgen, AccountId, BorshStorageKey, PanicOnDefault};
+
+#[derive(BorshSerialize, BorshStorageKey)]
+#[borsh(crate = "near_sdk::borsh")]
+enum StorageKey {
+    Requests,
+}
+
+/// One `request_execution` call, as received
+#[derive(Clone, Debug)]
+#[near(serializers=[borsh, json])]
+pub struct ExecutionRequest {
+    pub caller_id: AccountId,
+    pub input_data: String,
+    /// JSON of the secrets_ref argument
+    pub secrets_ref: Option<String>,
+    pub response_format: String,
+    pub payer_account_id: Option<AccountId>,
+    pub attached_deposit: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+#[borsh(crate = "near_sdk::borsh")]
+#[near_bindgen]
+pub struct MockOutLayer {
+    /// JSON returned by the next executions (None returns no output)
+    response: Option<String>,
+    /// Fill in token_in / token_out from each request's input
+    echo_pair: bool,
+    requests: Vector<ExecutionRequest>,
+}
+
+#[near_bindgen]
+impl MockOutLayer {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            response: None,
+            echo_pair: false,
+            requests: Vector::new(StorageKey::Requests),
+        }
+    }
+
+    /// Return `response` verbatim from every following execution
+    pub fn respond_with(&mut self, response: Option<Value>) {
+        self.response = response.map(|response| response.to_string());
+        self.echo_pair = false;
+    }
+
+    /// Return a settled swap of `amount_out`
+    pub fn respond_success(&mut self, amount_out: U128) {
+        self.set_swap_response(SwapResponse {
+            success: true,
+            amount_out: Some(amount_out.0.to_string()),
+            intent_hash: Some("mock-intent".to_string()),
+            ..failed_response(None, None)
+        });
+    }
+
+    /// Return a failed swap with `error_code` (e.g. "INSUFFICIENT_LIQUIDITY")
+    pub fn respond_failure(&mut self, error_code: String, error_message: String) {
+        let error_code: ErrorCode = serde_json::from_value(Value::String(error_code)).unwrap();
+        self.set_swap_response(failed_response(Some(error_code), Some(error_message)));
+    }
+
+    #[payable]
+    pub fn request_execution(
+        &mut self,
+        code_source: Value,
+        resource_limits: Value,
+        input_data: String,
+        secrets_ref: Option<Value>,
+        response_format: String,
+        payer_account_id: Option<AccountId>,
+    ) -> Option<Value> {
+        log!("Mock execution of {} with limits {}", code_source, resource_limits);
+        let input: Option<SwapInput> = serde_json::from_str(&input_data).ok();
+        self.requests.push(&ExecutionRequest {
+            caller_id: env::predecessor_account_id(),
+            input_data,
+            secrets_ref: secrets_ref.map(|secrets_ref| secrets_ref.to_string()),
+            response_format,
+            payer_account_id,
+            attached_deposit: U128(env::attached_deposit().as_yoctonear()),
+        });
+        let mut response: Option<Value> = self.response.as_ref().map(|response| serde_json::from_str(response).unwrap());
+        if let (true, Some(response), Some(input)) = (self.echo_pair, response.as_mut(), input) {
+            response["token_in"] = Value::String(input.token_in);
+            response["token_out"] = Value::String(input.token_out);
+        }
+        response
+    }
+
+    pub fn get_requests(&self) -> Vec<ExecutionRequest> {
+        self.requests.to_vec()
+    }
+
+    pub fn get_last_request(&self) -> Option<ExecutionRequest> {
+        self.requests.len().checked_sub(1).and_then(|i| self.requests.get(i))
+    }
+}
+
+impl MockOutLayer {
+    fn set_swap_response(&mut self, response: SwapResponse) {
+        self.response = Some(serde_json::to_string(&response).unwrap());
+        self.echo_pair = true;
+    }
+}
+
+fn failed_response(error_code: Option<ErrorCode>, error_message: Option<String>) -> SwapResponse {
+    SwapResponse {
+        schema_version: Some(OUTPUT_SCHEMA_VERSION),
+        success: false,
+        amount_out: None,
+        error_message,
+        intent_hash: None,
+        error_code,
+        quote_hash: None,
+        deposit_tx_hash: None,
+        withdraw_intent_hash: None,
+        token_in: None,
+        token_out: None,
+    }
+}
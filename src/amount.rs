@@ -0,0 +1,66 @@
+//! Token amounts as u128 decimal strings
+//!
+//! Relay quotes, RPC results and worker input all carry amounts as decimal
+//! strings. [`parse`] rejects anything that isn't a plain u128 with an error
+//! naming the value, rather than letting bad data turn into zero. [`format`]
+//! renders an amount with its token's decimals for logs.
+
+use std::num::IntErrorKind;
+
+/// Parse a non-negative decimal integer amount (no sign, whitespace or fraction)
+pub fn parse(amount: &str) -> Result<u128, String> {
+    if amount.is_empty() {
+        return Err("Invalid amount: empty string".to_string());
+    }
+    // u128::from_str also accepts a leading '+'
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("Invalid amount {:?}: not a decimal integer", amount));
+    }
+
+    amount.parse::<u128>().map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => format!("Invalid amount {:?}: exceeds u128 range", amount),
+        _ => format!("Invalid amount {:?}: {}", amount, e),
+    })
+}
+
+/// `amount` in whole tokens, e.g. `format(1_500_000, 6)` is `"1.5"`.
+/// Trailing fractional zeros are dropped.
+pub fn format(amount: u128, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejects_bad_amounts_and_format_uses_decimals() {
+        assert_eq!(parse("0"), Ok(0));
+        assert_eq!(parse(&u128::MAX.to_string()), Ok(u128::MAX));
+        assert!(parse("340282366920938463463374607431768211456").unwrap_err().contains("exceeds u128"));
+        for bad in ["", "-1", "+1", " 1", "1.5", "1e18", "abc"] {
+            assert!(parse(bad).is_err(), "{:?} should be rejected", bad);
+        }
+
+        assert_eq!(format(1_500_000, 6), "1.5");
+        assert_eq!(format(10_000, 6), "0.01");
+        assert_eq!(format(2_000_000, 6), "2");
+        assert_eq!(format(1, 24), "0.000000000000000000000001");
+        assert_eq!(format(42, 0), "42");
+        assert_eq!(format(u128::MAX, 255).len(), 257);
+    }
+}
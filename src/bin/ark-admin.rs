@@ -0,0 +1,220 @@
+//! ark-admin: operator CLI for the swap contract (intents-contract)
+//!
+//! Reads the network from `NEAR_NETWORK`/`NEAR_RPC_URL` (see `network`), the
+//! contract from `ARK_CONTRACT_ID` and, for commands that change state, the
+//! owner's key from `ARK_OWNER_ACCOUNT_ID` and `ARK_OWNER_PRIVATE_KEY`.
+//!
+//! Build with `cargo build --features native --bin ark-admin`.
+
+use intents_ark::client::SwapClient;
+use intents_ark::near_tx::{self, RpcClient, TxSigner};
+use intents_ark::network::NetworkConfig;
+use std::env;
+
+const USAGE: &str = "Usage: ark-admin <command> [args]
+
+Views:
+  config                                  Contract configuration
+  tokens                                  Whitelisted tokens and their config
+  fees <token_id>                         Collected fees not yet withdrawn
+  pending [last]                          Pending swaps among the last ids (default 100)
+  payload <request_id>                    OutLayer request_execution args of a pending swap
+
+Owner commands:
+  whitelist <token_id> <min_swap_amount> [defuse_asset_id]
+  unwhitelist <token_id>
+  set-fee <basis_points>
+  pause | unpause                         Stop/resume everything, callbacks included
+  pause-swaps | unpause-swaps             Stop/resume new swaps only
+  withdraw-fees <token_id> [amount]       All collected fees unless amount is given
+  cleanup [last]                          Drop stale pending swaps among the last ids (default 100)
+  reconcile [token_id...]                 Check collected fees against token balances (default: all tokens)";
+
+/// Gas for owner calls; withdraw_fees forwards 10 TGas to ft_transfer
+const ADMIN_CALL_GAS: u64 = 50_000_000_000_000;
+
+/// reconcile_fees reads one balance per token (up to 20) and then reports
+const RECONCILE_GAS: u64 = 200_000_000_000_000;
+
+/// How many recent request ids `pending` and `cleanup` scan by default
+const DEFAULT_SCAN: u64 = 100;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, args) = match args.split_first() {
+        Some((&"help", _)) | Some((&"--help", _)) | None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Some((command, args)) => (*command, args),
+    };
+
+    let network = NetworkConfig::from_env()?;
+    near_tx::set_archival_rpc_url(network.archival_rpc_url.clone());
+    let rpc = RpcClient::with_fallbacks(&network.rpc_url, &network.rpc_fallback_urls);
+    let contract_id = env::var("ARK_CONTRACT_ID").map_err(|_| "ARK_CONTRACT_ID is not set")?;
+    let client = SwapClient::new(rpc.clone(), &contract_id);
+
+    match (command, args) {
+        ("config", []) => {
+            let config: serde_json::Value =
+                near_tx::view_typed(&rpc, &contract_id, "get_config", &serde_json::json!({}))?;
+            println!("{}", serde_json::to_string_pretty(&config)?);
+        }
+        ("tokens", []) => {
+            for token_id in client.whitelisted_tokens()? {
+                match client.token_config(&token_id)? {
+                    Some(config) => println!(
+                        "{}  {}  min_swap_amount={}",
+                        token_id, config.defuse_asset_id, config.min_swap_amount
+                    ),
+                    None => println!("{}  (no config)", token_id),
+                }
+            }
+        }
+        ("fees", [token_id]) => println!("{}", client.collected_fees(token_id)?),
+        ("pending", rest) => {
+            let swaps = client.pending_swaps(scan_window(rest)?)?;
+            for swap in &swaps {
+                println!(
+                    "#{}  {}  {} {} -> {} (min {})  at {}",
+                    swap.request_id,
+                    swap.sender_id,
+                    swap.amount_in,
+                    swap.token_in,
+                    swap.token_out,
+                    swap.min_amount_out,
+                    swap.timestamp
+                );
+            }
+            eprintln!("{} pending", swaps.len());
+        }
+        ("payload", [request_id]) => {
+            let request_id: u64 = request_id.parse().map_err(|_| format!("Invalid request id: {}", request_id))?;
+            let payload = client
+                .execution_payload(request_id)?
+                .ok_or_else(|| format!("Swap #{} is not pending", request_id))?;
+            println!("{}", serde_json::to_string_pretty(&payload)?);
+        }
+
+        ("whitelist", [token_id, min_swap_amount, rest @ ..]) if rest.len() <= 1 => {
+            let args = serde_json::json!({
+                "token_id": token_id,
+                "defuse_asset_id": rest.first(),
+                "min_swap_amount": parse_amount(min_swap_amount)?.to_string(),
+            });
+            owner_call(&rpc, &network, &contract_id, "whitelist_token", args.to_string())?;
+        }
+        ("unwhitelist", [token_id]) => {
+            let args = serde_json::json!({ "token_id": token_id });
+            owner_call(&rpc, &network, &contract_id, "remove_token_from_whitelist", args.to_string())?;
+        }
+        ("set-fee", [basis_points]) => {
+            let basis_points: u16 = basis_points.parse().map_err(|_| format!("Invalid basis points: {}", basis_points))?;
+            let args = serde_json::json!({ "fee_basis_points": basis_points });
+            owner_call(&rpc, &network, &contract_id, "set_fee_percentage", args.to_string())?;
+        }
+        ("pause", []) | ("unpause", []) => {
+            let args = serde_json::json!({ "paused": command == "pause" });
+            owner_call(&rpc, &network, &contract_id, "set_paused", args.to_string())?;
+        }
+        ("pause-swaps", []) | ("unpause-swaps", []) => {
+            let args = serde_json::json!({ "swap_paused": command == "pause-swaps" });
+            owner_call(&rpc, &network, &contract_id, "set_swap_paused", args.to_string())?;
+        }
+        ("withdraw-fees", [token_id, rest @ ..]) if rest.len() <= 1 => {
+            // withdraw_fees takes a plain u128, which serde_json::Value can't hold
+            let amount = match rest.first() {
+                Some(amount) => parse_amount(amount)?.to_string(),
+                None => "null".to_string(),
+            };
+            let args = format!(r#"{{"token_id":{},"amount":{}}}"#, serde_json::json!(token_id), amount);
+            owner_call(&rpc, &network, &contract_id, "withdraw_fees", args)?;
+        }
+        ("cleanup", rest) => {
+            let request_ids: Vec<u64> =
+                client.pending_swaps(scan_window(rest)?)?.iter().map(|swap| swap.request_id).collect();
+            if request_ids.is_empty() {
+                eprintln!("No pending swaps");
+                return Ok(());
+            }
+            // The contract skips swaps that may still get their callback
+            let args = serde_json::json!({ "request_ids": request_ids });
+            let outcome = owner_call(&rpc, &network, &contract_id, "cleanup_stale_swaps", args.to_string())?;
+            let removed: Vec<u64> = outcome.success_json()?.unwrap_or_default();
+            println!("Removed {} of {} pending swaps: {:?}", removed.len(), request_ids.len(), removed);
+        }
+        ("reconcile", token_ids) => {
+            let args = serde_json::json!({ "token_ids": Some(token_ids).filter(|ids| !ids.is_empty()) });
+            let outcome =
+                owner_call_with_gas(&rpc, &network, &contract_id, "reconcile_fees", args.to_string(), RECONCILE_GAS)?;
+            let report: Vec<serde_json::Value> = outcome.success_json()?.unwrap_or_default();
+            for row in &report {
+                println!(
+                    "{}  fees={}  priority_fees={}  deposits={}  pending={}  balance={}  shortfall={}  excess={}",
+                    row["token_id"].as_str().unwrap_or_default(),
+                    row["collected_fees"].as_str().unwrap_or_default(),
+                    row["priority_fees"].as_str().unwrap_or_default(),
+                    row["escrowed_balances"].as_str().unwrap_or_default(),
+                    row["pending_swaps"].as_str().unwrap_or_default(),
+                    row["balance"].as_str().unwrap_or("unavailable"),
+                    row["shortfall"].as_str().unwrap_or_default(),
+                    row["excess"].as_str().unwrap_or_default()
+                );
+            }
+        }
+
+        _ => return Err(format!("Unknown command or wrong arguments: {}\n\n{}", command, USAGE).into()),
+    }
+    Ok(())
+}
+
+fn owner_call(
+    rpc: &RpcClient,
+    network: &NetworkConfig,
+    contract_id: &str,
+    method_name: &str,
+    args: String,
+) -> Result<near_tx::TxOutcome, Box<dyn std::error::Error>> {
+    owner_call_with_gas(rpc, network, contract_id, method_name, args, ADMIN_CALL_GAS)
+}
+
+fn owner_call_with_gas(
+    rpc: &RpcClient,
+    network: &NetworkConfig,
+    contract_id: &str,
+    method_name: &str,
+    args: String,
+    gas: u64,
+) -> Result<near_tx::TxOutcome, Box<dyn std::error::Error>> {
+    let account_id = env::var("ARK_OWNER_ACCOUNT_ID").map_err(|_| "ARK_OWNER_ACCOUNT_ID is not set")?;
+    let private_key = env::var("ARK_OWNER_PRIVATE_KEY").map_err(|_| "ARK_OWNER_PRIVATE_KEY is not set")?;
+    let signer = TxSigner::new(rpc, &account_id, &private_key)?;
+
+    let outcome = signer.call(contract_id, method_name, &args, gas, 0)?;
+    for log in &outcome.logs {
+        eprintln!("   {}", log);
+    }
+    eprintln!("✅ {}", network.tx_url(&outcome.tx_hash));
+    Ok(outcome)
+}
+
+fn scan_window(args: &[&str]) -> Result<u64, String> {
+    match args {
+        [] => Ok(DEFAULT_SCAN),
+        [last] => last.parse().map_err(|_| format!("Invalid count: {}", last)),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn parse_amount(amount: &str) -> Result<u128, String> {
+    amount.parse().map_err(|_| format!("Invalid amount: {}", amount))
+}
@@ -0,0 +1,41 @@
+//! ark-worker: run the worker natively, without OutLayer
+//!
+//! Takes the same Input JSON as the WASI binary (from a file, or stdin when
+//! no path or `-` is given), runs the whole quote/deposit/publish/withdraw
+//! flow over reqwest and prints the Output. Defaults to testnet unless
+//! `NEAR_NETWORK` is set; secrets come inline or from env vars as usual.
+//!
+//! Build with `cargo build --features native --bin ark-worker`.
+
+// The worker itself; its `main` (stdin only) is unused here
+#[allow(dead_code)]
+#[path = "../main.rs"]
+mod worker;
+
+use std::env;
+use std::io::{self, Read};
+use zeroize::Zeroizing;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if env::var_os("NEAR_NETWORK").is_none() {
+        env::set_var("NEAR_NETWORK", "testnet");
+    }
+
+    let mut input = Zeroizing::new(String::new());
+    match env::args().nth(1).as_deref() {
+        None | Some("-") => {
+            io::stdin().read_to_string(&mut input)?;
+        }
+        Some("help") | Some("--help") => {
+            println!("Usage: ark-worker [input.json | -]");
+            return Ok(());
+        }
+        Some(path) => {
+            *input = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        }
+    }
+
+    worker::run(input)?;
+    println!();
+    Ok(())
+}
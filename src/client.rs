@@ -0,0 +1,340 @@
+//! Typed client for the swap contract (intents-contract)
+//!
+//! For Rust dApps and bots (native builds use the `native` feature for
+//! HTTP): build the `ft_transfer_call` that starts a swap, read the
+//! contract's views, and pick the contract's NEP-297 events out of a
+//! transaction outcome.
+//!
+//! ```ignore
+//! let client = SwapClient::new(rpc, "v1.publishintent.near");
+//! let message = SwapMessage::new("usdc.near").min_amount_out(990_000);
+//! let outcome = client.swap(&signer, "wrap.near", 1_000_000_000_000_000_000_000_000, &message)?;
+//! ```
+
+use crate::near_tx::{self, Nep297Event, RpcClient, TxOutcome, TxSigner, ViewSpec};
+use serde::Deserialize;
+
+pub use intents_ark_types::SwapMessage;
+
+/// `ft_transfer_call` msg crediting the transfer to the sender's balance
+pub const DEPOSIT_MSG: &str = r#"{"Deposit":{}}"#;
+
+/// Gas for `swap_from_balance`; like `ft_transfer_call`, the rest goes to
+/// the OutLayer execution
+const SWAP_FROM_BALANCE_GAS: u64 = 300_000_000_000_000;
+const WITHDRAW_GAS: u64 = 50_000_000_000_000;
+
+/// `get_config` of the swap contract
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ContractConfig {
+    pub owner_id: String,
+    pub operator_id: String,
+    pub paused: bool,
+    pub swap_paused: bool,
+    pub secrets_profile: String,
+    /// Id the next swap will get; every earlier id was used
+    pub next_request_id: u64,
+    pub fee_basis_points: u16,
+    #[serde(default)]
+    pub auto_register_storage: bool,
+    /// Results arriving later than this after the request are refunded
+    #[serde(default)]
+    pub max_callback_age_seconds: u64,
+}
+
+/// `get_token_config` of a whitelisted token (the contract returns balances
+/// as plain JSON numbers, which serde_json reads into u128 exactly)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenConfig {
+    pub defuse_asset_id: String,
+    pub min_swap_amount: u128,
+    /// Block timestamp (ns) of the listing
+    #[serde(default)]
+    pub whitelisted_at: u64,
+    /// Swaps are refused from this block timestamp (ns) on
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// Tax the token takes on transfers, deducted from received amounts
+    #[serde(default)]
+    pub transfer_fee_bps: u16,
+    /// Outputs below this are credited to the balance, not transferred
+    #[serde(default)]
+    pub dust_threshold: u128,
+}
+
+/// A swap waiting for its OutLayer callback (`get_pending_swap`)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingSwap {
+    pub request_id: u64,
+    pub sender_id: String,
+    pub token_in: String,
+    pub token_out: String,
+    /// Amount transferred, before the contract fee
+    pub amount_in: u128,
+    pub min_amount_out: u128,
+    /// Kept by the contract if the swap settles
+    #[serde(default)]
+    pub fee_amount: u128,
+    /// Priority lane fee on top of fee_amount, paid to the operator
+    #[serde(default)]
+    pub priority_fee_amount: u128,
+    /// What the worker swaps: amount_in minus both fees
+    #[serde(default)]
+    pub amount_after_fee: u128,
+    #[serde(default)]
+    pub referral: Option<String>,
+    /// Swap metadata as JSON text
+    #[serde(default)]
+    pub metadata: Option<String>,
+    /// Registered worker version running the swap (None: the built-in one)
+    #[serde(default)]
+    pub worker_version: Option<String>,
+    /// A result without the token pair echo is refused
+    #[serde(default)]
+    pub pair_echo_required: bool,
+    /// Requested gas (TGas) for the output ft_transfer (None: the default 10)
+    #[serde(default)]
+    pub callback_gas_tgas: Option<u64>,
+    /// Block timestamp (ns) the swap was requested at
+    pub timestamp: u64,
+}
+
+/// Where a swap is (`get_swap_status`)
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapStatus {
+    Pending,
+    Executing,
+    Settled,
+    TransferringOut,
+    Completed,
+    Failed,
+    Refunded,
+}
+
+/// Latest status of a swap, with the reason for Failed / Refunded
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SwapState {
+    pub status: SwapStatus,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Block timestamp (ns) of the change
+    pub updated_at: u64,
+}
+
+/// A swap in a user's history (`get_user_receipts`)
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SwapReceipt {
+    pub request_id: u64,
+    pub token_in: String,
+    pub token_out: String,
+    #[serde(deserialize_with = "near_tx::u128_string")]
+    pub amount_in: u128,
+    /// Set once the worker's result was accepted
+    #[serde(default, deserialize_with = "near_tx::option_u128_string")]
+    pub amount_out: Option<u128>,
+    pub status: Option<SwapStatus>,
+    pub intent_hash: Option<String>,
+    /// Block timestamp (ns) of the request
+    pub timestamp: u64,
+}
+
+/// Client for one deployment of the swap contract
+pub struct SwapClient {
+    rpc: RpcClient,
+    contract_id: String,
+}
+
+impl SwapClient {
+    pub fn new(rpc: RpcClient, contract_id: &str) -> Self {
+        Self { rpc, contract_id: contract_id.to_string() }
+    }
+
+    pub fn contract_id(&self) -> &str {
+        &self.contract_id
+    }
+
+    pub fn config(&self) -> Result<ContractConfig, Box<dyn std::error::Error>> {
+        self.view("get_config", serde_json::json!({}))
+    }
+
+    pub fn whitelisted_tokens(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.view("get_whitelisted_tokens", serde_json::json!({}))
+    }
+
+    /// None if `token_id` isn't whitelisted
+    pub fn token_config(&self, token_id: &str) -> Result<Option<TokenConfig>, Box<dyn std::error::Error>> {
+        self.view("get_token_config", serde_json::json!({ "token_id": token_id }))
+    }
+
+    /// Fees collected in `token_id` and not yet withdrawn
+    pub fn collected_fees(&self, token_id: &str) -> Result<u128, Box<dyn std::error::Error>> {
+        self.view("get_collected_fees", serde_json::json!({ "token_id": token_id }))
+    }
+
+    /// Deposited balances of `account_id`, as (token, amount)
+    pub fn balances(&self, account_id: &str) -> Result<Vec<(String, u128)>, Box<dyn std::error::Error>> {
+        let balances: Vec<(String, String)> =
+            self.view("get_balances", serde_json::json!({ "account_id": account_id }))?;
+        balances
+            .into_iter()
+            .map(|(token_id, amount)| Ok((token_id, amount.parse()?)))
+            .collect()
+    }
+
+    pub fn pending_swap(&self, request_id: u64) -> Result<Option<PendingSwap>, Box<dyn std::error::Error>> {
+        self.view("get_pending_swap", serde_json::json!({ "request_id": request_id }))
+    }
+
+    /// Status of any swap, including finished ones
+    pub fn swap_status(&self, request_id: u64) -> Result<Option<SwapState>, Box<dyn std::error::Error>> {
+        self.view("get_swap_status", serde_json::json!({ "request_id": request_id }))
+    }
+
+    /// `account_id`'s latest swaps (the contract keeps 50), newest first,
+    /// skipping `from` and returning at most `limit`
+    pub fn user_receipts(
+        &self,
+        account_id: &str,
+        from: u64,
+        limit: u64,
+    ) -> Result<Vec<SwapReceipt>, Box<dyn std::error::Error>> {
+        self.view(
+            "get_user_receipts",
+            serde_json::json!({ "account_id": account_id, "from": from, "limit": limit }),
+        )
+    }
+
+    /// The OutLayer `request_execution` arguments of a pending swap
+    /// (`code_source`, `resource_limits`, `input_data`, `secrets_ref`), to
+    /// resubmit a stuck execution by hand
+    pub fn execution_payload(&self, request_id: u64) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+        self.view("get_request_execution_payload", serde_json::json!({ "request_id": request_id }))
+    }
+
+    /// Swaps still waiting for their callback among the `last` most recent
+    /// request ids, read at one block (see [`near_tx::view_many`])
+    pub fn pending_swaps(&self, last: u64) -> Result<Vec<PendingSwap>, Box<dyn std::error::Error>> {
+        let next_request_id = self.config()?.next_request_id;
+        let specs = (next_request_id.saturating_sub(last)..next_request_id)
+            .map(|request_id| {
+                ViewSpec::new(&self.contract_id, "get_pending_swap", serde_json::json!({ "request_id": request_id }))
+            })
+            .collect();
+
+        let mut pending = Vec::new();
+        for result in near_tx::view_many(&self.rpc, specs) {
+            if let Some(swap) = result.json::<Option<PendingSwap>>()? {
+                pending.push(swap);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Start a swap: `ft_transfer_call` of `amount_in` of `token_in` to the
+    /// contract with `message`, signed by `signer` (the swap's sender)
+    pub fn swap(
+        &self,
+        signer: &TxSigner,
+        token_in: &str,
+        amount_in: u128,
+        message: &SwapMessage,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        signer.ft_transfer_call(token_in, &self.contract_id, &amount_in.to_string(), &message.to_msg())
+    }
+
+    /// Credit `amount` of `token_id` to the signer's balance on the contract
+    pub fn deposit(&self, signer: &TxSigner, token_id: &str, amount: u128) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        signer.ft_transfer_call(token_id, &self.contract_id, &amount.to_string(), DEPOSIT_MSG)
+    }
+
+    /// Start a swap of `amount_in` from the signer's deposited `token_in`:
+    /// one function call, no token transfer
+    pub fn swap_from_balance(
+        &self,
+        signer: &TxSigner,
+        token_in: &str,
+        amount_in: u128,
+        message: &SwapMessage,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let args = serde_json::json!({
+            "token_in": token_in,
+            "amount_in": amount_in.to_string(),
+            "msg": message.to_msg(),
+        });
+        signer.call(&self.contract_id, "swap_from_balance", &args.to_string(), SWAP_FROM_BALANCE_GAS, 1)
+    }
+
+    /// Transfer the signer's deposited `token_id` back (all of it if `amount` is None)
+    pub fn withdraw(
+        &self,
+        signer: &TxSigner,
+        token_id: &str,
+        amount: Option<u128>,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let args = serde_json::json!({
+            "token_id": token_id,
+            "amount": amount.map(|amount| amount.to_string()),
+        });
+        signer.call(&self.contract_id, "withdraw", &args.to_string(), WITHDRAW_GAS, 1)
+    }
+
+    /// NEP-297 events emitted by this contract in `outcome`, in order
+    pub fn events(&self, outcome: &TxOutcome) -> Vec<Nep297Event> {
+        outcome
+            .receipts
+            .iter()
+            .filter(|receipt| receipt.executor_id == self.contract_id)
+            .flat_map(|receipt| &receipt.logs)
+            .filter_map(|log| serde_json::from_str(log.strip_prefix("EVENT_JSON:")?).ok())
+            .collect()
+    }
+
+    fn view<T: serde::de::DeserializeOwned>(
+        &self,
+        method_name: &str,
+        args: serde_json::Value,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        near_tx::view_typed(&self.rpc, &self.contract_id, method_name, &args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decodes_contract_views() {
+        let swap: PendingSwap = serde_json::from_str(
+            r#"{"request_id":7,"sender_id":"alice.near","token_in":"wrap.near","token_out":"usdc.near",
+                "amount_in":1000000000000000000000000,"min_amount_out":990000,"timestamp":1700000000000000000}"#,
+        )
+        .unwrap();
+        assert_eq!(swap.amount_in, 1_000_000_000_000_000_000_000_000);
+
+        let state: SwapState = serde_json::from_str(
+            r#"{"status":"Refunded","reason":"Swap failed [NO_QUOTES]: no route","updated_at":1700000000000000000}"#,
+        )
+        .unwrap();
+        assert_eq!(state.status, SwapStatus::Refunded);
+
+        let receipt: SwapReceipt = serde_json::from_str(
+            r#"{"request_id":7,"token_in":"wrap.near","token_out":"usdc.near","amount_in":"1000000000000000000000000",
+                "amount_out":"990000","status":"TransferringOut","intent_hash":"Hx","timestamp":1700000000000000000}"#,
+        )
+        .unwrap();
+        assert_eq!((receipt.amount_in, receipt.amount_out), (1_000_000_000_000_000_000_000_000, Some(990_000)));
+
+        let config: ContractConfig = serde_json::from_str(
+            r#"{"owner_id":"owner.near","operator_id":"operator.near","paused":false,"swap_paused":false,
+                "secrets_profile":"production","next_request_id":8,"fee_basis_points":10,"fee_percentage":"0.1%"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.next_request_id, 8);
+        assert!(!config.auto_register_storage);
+
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&SwapMessage::new("usdc.near").min_amount_out(5).to_msg()).unwrap(),
+            serde_json::json!({ "Swap": { "token_out": "usdc.near", "min_amount_out": "5" } })
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Base64 and base58 encoding
+//!
+//! NEAR uses standard padded base64 for transactions, call arguments and
+//! return values, and base58 (Bitcoin alphabet) for keys, signatures and
+//! hashes. Every encoding in the crate goes through here so the variants
+//! stay the same everywhere.
+
+use base64::Engine;
+
+const BASE64: base64::engine::GeneralPurpose = base64::engine::general_purpose::STANDARD;
+
+/// Standard, padded base64
+pub fn base64_encode(bytes: impl AsRef<[u8]>) -> String {
+    BASE64.encode(bytes)
+}
+
+pub fn base64_decode(encoded: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    BASE64.decode(encoded)
+}
+
+pub fn base58_encode(bytes: impl AsRef<[u8]>) -> String {
+    bs58::encode(bytes).into_string()
+}
+
+pub fn base58_decode(encoded: &str) -> Result<Vec<u8>, bs58::decode::Error> {
+    bs58::decode(encoded).into_vec()
+}
+
+/// Base58 value that must decode to exactly `N` bytes (hashes, keys)
+pub fn base58_decode_array<const N: usize>(encoded: &str) -> Result<[u8; N], String> {
+    let bytes = base58_decode(encoded).map_err(|e| format!("Invalid base58 {:?}: {}", encoded, e))?;
+    let len = bytes.len();
+    bytes
+        .try_into()
+        .map_err(|_| format!("Invalid base58 {:?}: expected {} bytes, got {}", encoded, N, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_encodings_round_trip(bytes in proptest::collection::vec(any::<u8>(), 0..128)) {
+            prop_assert_eq!(base64_decode(&base64_encode(&bytes)).unwrap(), bytes.clone());
+            prop_assert_eq!(base58_decode(&base58_encode(&bytes)).unwrap(), bytes.clone());
+
+            let hash = base58_decode_array::<32>(&base58_encode(&bytes));
+            prop_assert_eq!(hash.is_ok(), bytes.len() == 32);
+        }
+    }
+
+    #[test]
+    fn test_known_encodings() {
+        assert_eq!(base64_encode(br#""10000""#), "IjEwMDAwIg==");
+        assert_eq!(base58_encode([0u8; 32]), "11111111111111111111111111111111");
+        assert!(base64_decode("IjEwMDAwIg").is_err());
+        assert!(base58_decode("0OIl").is_err());
+    }
+}
@@ -1,6 +1,10 @@
+use crate::codec;
+use crate::near_tx::{LocalSigner, TransactionSigner};
 use borsh::{BorshDeserialize, BorshSerialize};
-use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::Serialize;
+use ed25519_dalek::{Signer, SigningKey};
 use sha2::{Digest, Sha256};
+use zeroize::{Zeroize, Zeroizing};
 
 /// NEP-413 Payload structure
 #[derive(BorshSerialize, BorshDeserialize, Debug)]
@@ -17,7 +21,8 @@ pub struct Payload {
 /// * `message` - JSON string of the intent message
 /// * `nonce` - Base64-encoded 32-byte nonce
 /// * `recipient` - Contract account ID (e.g. "intents.near")
-/// * `private_key_base58` - Base58-encoded ed25519 private key (64 bytes)
+/// * `callback_url` - Optional URL a wallet redirects to after signing; part of the signed payload
+/// * `private_key_base58` - ed25519 private key in any format [`parse_ed25519_key`] accepts
 ///
 /// # Returns
 /// Tuple of (signature_base58, public_key_base58)
@@ -25,32 +30,328 @@ pub fn sign_nep413_intent(
     message: &str,
     nonce: &str,
     recipient: &str,
+    callback_url: Option<&str>,
     private_key_base58: &str,
 ) -> Result<(String, String), Box<dyn std::error::Error>> {
-    // Decode private key from base58 (should be 64 bytes)
-    let private_key_bytes = bs58::decode(private_key_base58)
-        .into_vec()
-        .map_err(|e| format!("Failed to decode private key: {}", e))?;
-
-    if private_key_bytes.len() != 64 {
-        return Err(format!(
-            "Invalid private key length: {} (expected 64)",
-            private_key_bytes.len()
-        )
-        .into());
+    let signing_key = parse_ed25519_key(private_key_base58)?;
+    let verifying_key = signing_key.verifying_key();
+
+    let hash_to_sign = nep413_hash(message, nonce, recipient, callback_url)?;
+
+    // Sign the hash
+    let signature = signing_key.sign(&hash_to_sign);
+
+    // Encode to base58
+    let signature_base58 = codec::base58_encode(signature.to_bytes());
+    let public_key_base58 = codec::base58_encode(verifying_key.to_bytes());
+
+    Ok((signature_base58, public_key_base58))
+}
+
+/// An intent signed under one of the standards intents.near verifies, in the
+/// `signed_data` shape the solver relay's `publish_intent` takes
+#[derive(Serialize, Debug, Clone)]
+pub struct SignedIntent {
+    pub standard: &'static str,
+    pub payload: serde_json::Value,
+    pub signature: String,
+    /// Absent for standards whose verifier recovers the key (`erc191`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
+
+/// Signs intent messages under one signature standard, so callers pick the
+/// standard once (see [`intent_signer`]) instead of branching per intent
+pub trait IntentSigner {
+    /// Value of `standard` in the signed data, e.g. `nep413`
+    fn standard(&self) -> &'static str;
+
+    /// Curve-prefixed public key, e.g. `ed25519:...`
+    fn public_key(&self) -> String;
+
+    /// Sign `message` (a JSON object with `signer_id`, `deadline` and
+    /// `intents`) with base64 `nonce`, for the verifying contract `recipient`
+    fn sign_intent(&self, message: &str, nonce: &str, recipient: &str) -> Result<SignedIntent, Box<dyn std::error::Error>>;
+}
+
+/// Signer for `standard` ("nep413", "raw_ed25519" or "erc191") with `private_key`
+pub fn intent_signer(standard: &str, private_key: &str) -> Result<Box<dyn IntentSigner>, Box<dyn std::error::Error>> {
+    match standard {
+        "nep413" => Ok(Box::new(Nep413Signer::new(private_key)?)),
+        "raw_ed25519" => Ok(Box::new(RawEd25519Signer::new(private_key)?)),
+        "erc191" => Ok(Box::new(Erc191Signer::new(private_key)?)),
+        other => Err(format!("Unsupported intent signature standard: {} (expected nep413, raw_ed25519 or erc191)", other).into()),
     }
+}
 
-    // Extract seed (first 32 bytes)
-    let seed: [u8; 32] = private_key_bytes[..32]
-        .try_into()
-        .map_err(|_| "Failed to extract seed")?;
+/// NEP-413 with an ed25519 or secp256k1 key (see [`sign_nep413`])
+pub struct Nep413Signer(LocalSigner);
 
-    // Create signing key
-    let signing_key = SigningKey::from_bytes(&seed);
-    let verifying_key = signing_key.verifying_key();
+impl Nep413Signer {
+    pub fn new(private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self(LocalSigner::new(private_key)?))
+    }
+}
+
+impl IntentSigner for Nep413Signer {
+    fn standard(&self) -> &'static str {
+        "nep413"
+    }
+
+    fn public_key(&self) -> String {
+        self.0.public_key()
+    }
+
+    fn sign_intent(&self, message: &str, nonce: &str, recipient: &str) -> Result<SignedIntent, Box<dyn std::error::Error>> {
+        let hash = nep413_hash(message, nonce, recipient, None)?;
+        let public_key = self.0.public_key();
+        let curve = public_key.split(':').next().unwrap_or_default();
+        Ok(SignedIntent {
+            standard: self.standard(),
+            payload: serde_json::json!({"message": message, "nonce": nonce, "recipient": recipient}),
+            signature: format!("{}:{}", curve, codec::base58_encode(self.0.sign(&hash)?)),
+            public_key: Some(public_key),
+        })
+    }
+}
+
+/// `raw_ed25519`: an ed25519 signature over the message bytes themselves,
+/// with the nonce and verifying contract inside the message
+pub struct RawEd25519Signer(SigningKey);
+
+impl RawEd25519Signer {
+    pub fn new(private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self(parse_ed25519_key(private_key)?))
+    }
+}
+
+impl IntentSigner for RawEd25519Signer {
+    fn standard(&self) -> &'static str {
+        "raw_ed25519"
+    }
+
+    fn public_key(&self) -> String {
+        format!("ed25519:{}", codec::base58_encode(self.0.verifying_key().to_bytes()))
+    }
+
+    fn sign_intent(&self, message: &str, nonce: &str, recipient: &str) -> Result<SignedIntent, Box<dyn std::error::Error>> {
+        let payload = self_contained_payload(message, nonce, recipient)?;
+        let signature = self.0.sign(payload.as_bytes());
+        Ok(SignedIntent {
+            standard: self.standard(),
+            signature: format!("ed25519:{}", codec::base58_encode(signature.to_bytes())),
+            payload: payload.into(),
+            public_key: Some(self.public_key()),
+        })
+    }
+}
+
+/// `message` with the nonce and verifying contract added, for standards that
+/// sign the message itself rather than a NEP-413 envelope
+fn self_contained_payload(message: &str, nonce: &str, recipient: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut payload: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(message).map_err(|e| format!("Intent message is not a JSON object: {}", e))?;
+    payload.insert("verifying_contract".to_string(), recipient.into());
+    payload.insert("nonce".to_string(), nonce.into());
+    Ok(serde_json::to_string(&payload)?)
+}
+
+/// `erc191`: an Ethereum `personal_sign` signature by a secp256k1 key, for
+/// accounts identified by an EVM address. intents.near recovers the key from
+/// the signature, so the intent's `signer_id` must be [`address`](Self::address).
+pub struct Erc191Signer(k256::ecdsa::SigningKey);
 
+impl Erc191Signer {
+    /// `0x<hex>` (as exported by EVM wallets) or `secp256k1:<base58>` secret key
+    pub fn new(private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let secret = Zeroizing::new(match private_key.strip_prefix("0x") {
+            Some(hex_key) => hex::decode(hex_key).map_err(|e| format!("Failed to decode private key: {}", e))?,
+            None => codec::base58_decode(private_key.strip_prefix("secp256k1:").unwrap_or(private_key))
+                .map_err(|e| format!("Failed to decode private key: {}", e))?,
+        });
+        let key = k256::ecdsa::SigningKey::from_slice(&secret)
+            .map_err(|_| format!("Invalid secp256k1 private key ({} bytes)", secret.len()))?;
+        Ok(Self(key))
+    }
+
+    /// Lowercase `0x` EVM address: the last 20 bytes of keccak256(public key)
+    pub fn address(&self) -> String {
+        let point = self.0.verifying_key().to_encoded_point(false);
+        format!("0x{}", hex::encode(&keccak256(&point.as_bytes()[1..])[12..]))
+    }
+}
+
+impl IntentSigner for Erc191Signer {
+    fn standard(&self) -> &'static str {
+        "erc191"
+    }
+
+    fn public_key(&self) -> String {
+        let point = self.0.verifying_key().to_encoded_point(false);
+        format!("secp256k1:{}", codec::base58_encode(&point.as_bytes()[1..]))
+    }
+
+    fn sign_intent(&self, message: &str, nonce: &str, recipient: &str) -> Result<SignedIntent, Box<dyn std::error::Error>> {
+        let payload = self_contained_payload(message, nonce, recipient)?;
+        let hash = erc191_hash(payload.as_bytes());
+        let (signature, recovery_id) = self
+            .0
+            .sign_prehash_recoverable(&hash)
+            .map_err(|e| format!("secp256k1 signing failed: {}", e))?;
+
+        // r || s || v, with v = 27 + recovery id as personal_sign returns it
+        let mut bytes = [0u8; 65];
+        bytes[..64].copy_from_slice(&signature.to_bytes());
+        bytes[64] = 27 + recovery_id.to_byte();
+        Ok(SignedIntent {
+            standard: self.standard(),
+            payload: payload.into(),
+            signature: format!("secp256k1:{}", codec::base58_encode(bytes)),
+            public_key: None,
+        })
+    }
+}
+
+/// ERC-191 version 0x45 hash: keccak256("\x19Ethereum Signed Message:\n" || len || message)
+pub fn erc191_hash(message: &[u8]) -> [u8; 32] {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    prefixed.extend_from_slice(message);
+    keccak256(&prefixed)
+}
+
+fn keccak256(data: &[u8]) -> [u8; 32] {
+    use sha3::{Digest as _, Keccak256};
+    Keccak256::digest(data).into()
+}
+
+/// A NEAR public key, as `ed25519:<base58>` / `secp256k1:<base58>` in RPC
+/// and intents payloads (`FromStr` / `Display`) and as the borsh enum in
+/// transactions
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PublicKey {
+    ED25519([u8; 32]),
+    /// Uncompressed point without the 0x04 prefix
+    SECP256K1([u8; 64]),
+}
+
+impl PublicKey {
+    /// Key of `curve` ("ed25519" or "secp256k1") from its raw bytes, checking the length
+    pub fn from_bytes(curve: &str, bytes: &[u8]) -> Result<Self, String> {
+        let invalid_length = |_| format!("Invalid {} public key length: {}", curve, bytes.len());
+        match curve {
+            "ed25519" => Ok(PublicKey::ED25519(bytes.try_into().map_err(invalid_length)?)),
+            "secp256k1" => Ok(PublicKey::SECP256K1(bytes.try_into().map_err(invalid_length)?)),
+            other => Err(format!("Unsupported key curve (expected ed25519 or secp256k1): {}", other)),
+        }
+    }
+
+    pub fn curve(&self) -> &'static str {
+        match self {
+            PublicKey::ED25519(_) => "ed25519",
+            PublicKey::SECP256K1(_) => "secp256k1",
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            PublicKey::ED25519(bytes) => bytes,
+            PublicKey::SECP256K1(bytes) => bytes,
+        }
+    }
+}
+
+impl std::str::FromStr for PublicKey {
+    type Err = String;
+
+    fn from_str(public_key: &str) -> Result<Self, String> {
+        let (curve, key_base58) = public_key
+            .split_once(':')
+            .ok_or_else(|| format!("Unsupported public key (expected ed25519:... or secp256k1:...): {}", public_key))?;
+        let bytes = codec::base58_decode(key_base58).map_err(|e| format!("Failed to decode public key: {}", e))?;
+        Self::from_bytes(curve, &bytes)
+    }
+}
+
+impl std::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.curve(), codec::base58_encode(self.as_bytes()))
+    }
+}
+
+/// Parse an ed25519 private key: base58 of the 32-byte seed or of the 64-byte
+/// seed || public key pair NEAR key files use (only the seed is read), with or
+/// without `ed25519:`
+pub fn parse_ed25519_key(private_key: &str) -> Result<SigningKey, Box<dyn std::error::Error>> {
+    let key_base58 = private_key.strip_prefix("ed25519:").unwrap_or(private_key);
+    let key_bytes = Zeroizing::new(
+        codec::base58_decode(key_base58).map_err(|e| format!("Failed to decode private key: {}", e))?,
+    );
+
+    if key_bytes.len() != 32 && key_bytes.len() != 64 {
+        return Err(format!("Invalid private key length: {} (expected 32 or 64)", key_bytes.len()).into());
+    }
+    Ok(SigningKey::from_bytes(key_bytes[..32].try_into()?))
+}
+
+/// [`sign_nep413_intent`] with an ed25519 or secp256k1 key, as accepted by
+/// intents.near
+///
+/// `private_key` is `ed25519:<base58 keypair>` (the prefix may be omitted) or
+/// `secp256k1:<base58 32-byte secret>`. Returns (signature, public_key), both
+/// prefixed with their curve. secp256k1 signatures are 65 bytes: r || s || recovery id.
+pub fn sign_nep413(
+    message: &str,
+    nonce: &str,
+    recipient: &str,
+    callback_url: Option<&str>,
+    private_key: &str,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    let payload = Nep413Message { message, nonce, callback_url };
+    let (mut signatures, public_key) = sign_nep413_intents(&[payload], recipient, private_key)?;
+    Ok((signatures.remove(0), public_key))
+}
+
+/// One message of a [`sign_nep413_intents`] batch
+#[derive(Debug, Clone, Copy)]
+pub struct Nep413Message<'a> {
+    pub message: &'a str,
+    /// Base64-encoded 32-byte nonce
+    pub nonce: &'a str,
+    pub callback_url: Option<&'a str>,
+}
+
+/// [`sign_nep413`] for several messages to `recipient`, parsing the key once.
+/// Returns the curve-prefixed signatures in input order, and the public key.
+pub fn sign_nep413_intents(
+    messages: &[Nep413Message],
+    recipient: &str,
+    private_key: &str,
+) -> Result<(Vec<String>, String), Box<dyn std::error::Error>> {
+    let signer = LocalSigner::new(private_key)?;
+    let public_key = signer.public_key();
+    let curve = public_key.split(':').next().unwrap_or_default();
+
+    let signatures = messages
+        .iter()
+        .map(|m| {
+            let hash = nep413_hash(m.message, m.nonce, recipient, m.callback_url)?;
+            Ok(format!("{}:{}", curve, codec::base58_encode(signer.sign(&hash)?)))
+        })
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+    Ok((signatures, public_key))
+}
+
+/// NEP-413 hash to sign: sha256(borsh(2^31 + 413) || borsh(payload)).
+/// What [`sign_nep413`] signs, for signing elsewhere (e.g. a KMS) or for
+/// checking a signature made by another implementation.
+pub fn nep413_hash(
+    message: &str,
+    nonce: &str,
+    recipient: &str,
+    callback_url: Option<&str>,
+) -> Result<[u8; 32], Box<dyn std::error::Error>> {
     // Decode and prepare nonce
-    let nonce_bytes = base64::decode(nonce)
+    let nonce_bytes = codec::base64_decode(nonce)
         .map_err(|e| format!("Failed to decode nonce: {}", e))?;
 
     let mut nonce_array = [0u8; 32];
@@ -65,7 +366,7 @@ pub fn sign_nep413_intent(
         message: message.to_string(),
         nonce: nonce_array,
         recipient: recipient.to_string(),
-        callback_url: None,
+        callback_url: callback_url.map(String::from),
     };
 
     // Serialize payload using Borsh
@@ -78,38 +379,321 @@ pub fn sign_nep413_intent(
 
     // Hash: sha256(discriminant || borsh_payload)
     let mut hasher = Sha256::new();
-    hasher.update(&discriminant_bytes);
+    hasher.update(discriminant_bytes);
     hasher.update(&borsh_payload);
     let hash_to_sign = hasher.finalize();
 
-    // Sign the hash
-    let signature = signing_key.sign(&hash_to_sign);
+    Ok(hash_to_sign.into())
+}
 
-    // Encode to base58
-    let signature_base58 = bs58::encode(signature.to_bytes()).into_string();
-    let public_key_base58 = bs58::encode(verifying_key.to_bytes()).into_string();
+/// ed25519 keypair derived from `seed`, as (`ed25519:<base58 keypair>`
+/// private key, `ed25519:<base58>` public key). The same seed always gives
+/// the same keys, which makes test accounts reproducible.
+pub fn keypair_from_seed(seed: &[u8; 32]) -> (Zeroizing<String>, String) {
+    let signing_key = SigningKey::from_bytes(seed);
+    let keypair = Zeroizing::new(signing_key.to_keypair_bytes());
+    let keypair_base58 = Zeroizing::new(codec::base58_encode(keypair.as_slice()));
+    (
+        Zeroizing::new(format!("ed25519:{}", keypair_base58.as_str())),
+        format!("ed25519:{}", codec::base58_encode(signing_key.verifying_key().to_bytes())),
+    )
+}
 
-    Ok((signature_base58, public_key_base58))
+/// Fresh ed25519 keypair from the host's secure randomness (`wasi:random`,
+/// or the OS with the `native` feature), in the format of [`keypair_from_seed`]
+pub fn generate_keypair() -> Result<(Zeroizing<String>, String), Box<dyn std::error::Error>> {
+    #[cfg(not(feature = "native"))]
+    let random = Zeroizing::new(wasi::random::random::get_random_bytes(32));
+    #[cfg(feature = "native")]
+    let random = {
+        let mut random = Zeroizing::new(vec![0u8; 32]);
+        getrandom::getrandom(&mut random).map_err(|e| format!("OS randomness unavailable: {}", e))?;
+        random
+    };
+    let seed: &[u8; 32] = random.as_slice().try_into().map_err(|_| "wasi:random returned too few bytes")?;
+    Ok(keypair_from_seed(seed))
+}
+
+/// Implicit account id of an `ed25519:...` public key: the key's 32 bytes in
+/// lowercase hex. Sending NEAR to it creates the account with that key.
+pub fn implicit_account_id(public_key: &str) -> Result<String, Box<dyn std::error::Error>> {
+    match public_key.parse::<PublicKey>()? {
+        PublicKey::ED25519(key) => Ok(hex::encode(key)),
+        PublicKey::SECP256K1(_) => Err(format!("Implicit accounts need an ed25519 key, got {}", public_key).into()),
+    }
+}
+
+/// HD path NEAR wallets derive seed-phrase keys with (near-seed-phrase)
+pub const NEAR_HD_PATH: &str = "m/44'/397'/0'";
+
+/// ed25519 keypair of a 12/24-word BIP-39 `mnemonic` (English, no
+/// passphrase) at `hd_path` (SLIP-10, hardened indexes only; usually
+/// [`NEAR_HD_PATH`]), in the format of [`keypair_from_seed`]
+pub fn keypair_from_mnemonic(mnemonic: &str, hd_path: &str) -> Result<(Zeroizing<String>, String), Box<dyn std::error::Error>> {
+    let words = Zeroizing::new(mnemonic.split_whitespace().collect::<Vec<_>>().join(" "));
+    let words = Zeroizing::new(words.to_lowercase());
+    // Zeroized on drop (bip39 `zeroize` feature)
+    let mnemonic = bip39::Mnemonic::parse_normalized(&words).map_err(|e| format!("Invalid seed phrase: {}", e))?;
+    let seed = Zeroizing::new(mnemonic.to_seed_normalized(""));
+    let key = slip10_ed25519(seed.as_slice(), &parse_hd_path(hd_path)?);
+    Ok(keypair_from_seed(&key))
+}
+
+/// Indexes of a hardened path like `m/44'/397'/0'`
+fn parse_hd_path(hd_path: &str) -> Result<Vec<u32>, String> {
+    let invalid = || format!("Invalid HD path {:?} (expected e.g. {})", hd_path, NEAR_HD_PATH);
+    let mut parts = hd_path.split('/');
+    if parts.next() != Some("m") {
+        return Err(invalid());
+    }
+    parts
+        .map(|part| {
+            // ed25519 (SLIP-10) only defines hardened derivation
+            let index = part.strip_suffix('\'').or_else(|| part.strip_suffix('H')).ok_or_else(invalid)?;
+            index.parse::<u32>().ok().filter(|i| *i < HARDENED).ok_or_else(invalid)
+        })
+        .collect()
+}
+
+const HARDENED: u32 = 1 << 31;
+
+/// SLIP-10 ed25519 private key of `seed` at the hardened `path`
+fn slip10_ed25519(seed: &[u8], path: &[u32]) -> Zeroizing<[u8; 32]> {
+    use hmac::{Hmac, Mac};
+
+    let hmac = |key: &[u8], data: &[&[u8]]| {
+        let mut mac = Hmac::<sha2::Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+        for part in data {
+            mac.update(part);
+        }
+        let mut bytes = mac.finalize().into_bytes();
+        let mut output = Zeroizing::new([0u8; 64]);
+        output.copy_from_slice(&bytes);
+        bytes.as_mut_slice().zeroize();
+        output
+    };
+
+    let mut node = hmac(b"ed25519 seed", &[seed]);
+    for index in path {
+        let (key, chain_code) = node.split_at(32);
+        node = hmac(chain_code, &[&[0], key, &(index | HARDENED).to_be_bytes()]);
+    }
+    let mut key = Zeroizing::new([0u8; 32]);
+    key.copy_from_slice(&node[..32]);
+    key
+}
+
+/// Prefix of NEP-366 delegate action hashes (2^30 + 366), so a signed
+/// delegate action can never be replayed as a transaction or NEP-413 message
+const NEP366_DISCRIMINANT: u32 = (1 << 30) + 366;
+
+/// Hash a NEP-366 delegate action is signed over:
+/// sha256(borsh(2^30 + 366) || borsh(delegate_action))
+pub fn nep366_hash(delegate_action: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(NEP366_DISCRIMINANT.to_le_bytes());
+    hasher.update(delegate_action);
+    hasher.finalize().into()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::near_tx;
 
     #[test]
     fn test_nep413_signing() {
         // This is a placeholder test
         // In production, you'd test with known test vectors
         let message = r#"{"signer_id":"test.near","deadline":"2025-01-01T00:00:00.000Z","intents":[]}"#;
-        let nonce = base64::encode(&[0u8; 32]);
+        let nonce = codec::base64_encode([0u8; 32]);
         let recipient = "intents.near";
 
         // Generate a test key
         let signing_key = SigningKey::from_bytes(&[1u8; 32]);
-        let private_key_bytes = signing_key.to_bytes();
-        let private_key_base58 = bs58::encode(&private_key_bytes).into_string();
+        let private_key_bytes = signing_key.to_keypair_bytes();
+        let private_key_base58 = codec::base58_encode(private_key_bytes);
 
-        let result = sign_nep413_intent(message, &nonce, recipient, &private_key_base58);
+        let result = sign_nep413_intent(message, &nonce, recipient, None, &private_key_base58);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_nep413_signs_with_both_curves() {
+        let message = r#"{"signer_id":"test.near","intents":[]}"#;
+        let nonce = codec::base64_encode([3u8; 32]);
+        let hash = nep413_hash(message, &nonce, "intents.near", None).unwrap();
+
+        let ed25519 = SigningKey::from_bytes(&[1u8; 32]);
+        let keypair = codec::base58_encode(ed25519.to_keypair_bytes());
+        let (signature, public_key) = sign_nep413(message, &nonce, "intents.near", None, &keypair).unwrap();
+        let (bare_signature, bare_public_key) = sign_nep413_intent(message, &nonce, "intents.near", None, &keypair).unwrap();
+        assert_eq!(signature, format!("ed25519:{}", bare_signature));
+        assert_eq!(public_key, format!("ed25519:{}", bare_public_key));
+
+        let secret = format!("secp256k1:{}", codec::base58_encode([7u8; 32]));
+        let (signature, public_key) = sign_nep413(message, &nonce, "intents.near", None, &secret).unwrap();
+        let signature = codec::base58_decode(signature.strip_prefix("secp256k1:").unwrap()).unwrap();
+        assert_eq!(signature.len(), 65);
+        let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+            &hash,
+            &k256::ecdsa::Signature::from_slice(&signature[..64]).unwrap(),
+            k256::ecdsa::RecoveryId::from_byte(signature[64]).unwrap(),
+        )
+        .unwrap();
+        let uncompressed = recovered.to_encoded_point(false);
+        assert_eq!(public_key, format!("secp256k1:{}", codec::base58_encode(&uncompressed.as_bytes()[1..])));
+    }
+
+    #[test]
+    fn test_callback_url_is_part_of_the_signed_payload() {
+        let nonce = codec::base64_encode([0u8; 32]);
+        let keypair = codec::base58_encode(SigningKey::from_bytes(&[1u8; 32]).to_keypair_bytes());
+        let sign = |callback_url| sign_nep413_intent("{}", &nonce, "intents.near", callback_url, &keypair).unwrap().0;
+
+        assert_ne!(sign(None), sign(Some("https://wallet.example/done")));
+        assert_ne!(sign(Some("https://wallet.example/done")), sign(Some("https://wallet.example/other")));
+    }
+
+    #[test]
+    fn test_seeded_keypair_and_implicit_account() {
+        let (private_key, public_key) = keypair_from_seed(&[9u8; 32]);
+        assert_eq!(keypair_from_seed(&[9u8; 32]).1, public_key);
+        assert_eq!(near_tx::public_key_of(&private_key).unwrap(), public_key);
+
+        let account_id = implicit_account_id(&public_key).unwrap();
+        assert_eq!(account_id.len(), 64);
+        assert_eq!(codec::base58_encode(hex::decode(&account_id).unwrap()), public_key["ed25519:".len()..]);
+        assert!(implicit_account_id("secp256k1:abc").is_err());
+    }
+
+    #[test]
+    fn test_mnemonic_keys_follow_bip39_and_slip10() {
+        // SLIP-10 ed25519 test vector 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert_eq!(
+            hex::encode(*slip10_ed25519(&seed, &[])),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(*slip10_ed25519(&seed, &[0])),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let (private_key, public_key) = keypair_from_mnemonic(phrase, NEAR_HD_PATH).unwrap();
+        assert_eq!(near_tx::public_key_of(&private_key).unwrap(), public_key);
+        let messy = format!("  {}\n", phrase.to_uppercase().replace(' ', "   "));
+        assert_eq!(keypair_from_mnemonic(&messy, "m/44H/397H/0H").unwrap().1, public_key);
+        assert_ne!(keypair_from_mnemonic(phrase, "m/44'/397'/1'").unwrap().1, public_key);
+
+        assert!(keypair_from_mnemonic(&phrase.replace("about", "abandon"), NEAR_HD_PATH).is_err());
+        for path in ["44'/397'/0'", "m/44/397'/0'", "m/x'"] {
+            assert!(parse_hd_path(path).is_err(), "{} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn test_ed25519_keys_parse_as_seed_or_keypair() {
+        let signing_key = SigningKey::from_bytes(&[4u8; 32]);
+        let seed = codec::base58_encode(signing_key.to_bytes());
+        let keypair = codec::base58_encode(signing_key.to_keypair_bytes());
+
+        for key in [seed.clone(), keypair.clone(), format!("ed25519:{}", seed), format!("ed25519:{}", keypair)] {
+            assert_eq!(parse_ed25519_key(&key).unwrap().to_bytes(), signing_key.to_bytes(), "{}", key);
+        }
+        assert!(parse_ed25519_key(&codec::base58_encode([1u8; 48])).unwrap_err().to_string().contains("length: 48"));
+
+        let nonce = codec::base64_encode([0u8; 32]);
+        let sign = |key: &str| sign_nep413_intent("{}", &nonce, "intents.near", None, key).unwrap();
+        assert_eq!(sign(&seed), sign(&keypair));
+    }
+
+    #[test]
+    fn test_batch_signing_matches_single_signatures() {
+        let keypair = codec::base58_encode(SigningKey::from_bytes(&[2u8; 32]).to_keypair_bytes());
+        let nonces = [codec::base64_encode([1u8; 32]), codec::base64_encode([2u8; 32])];
+        let messages = [
+            Nep413Message { message: r#"{"intents":["token_diff"]}"#, nonce: &nonces[0], callback_url: None },
+            Nep413Message { message: r#"{"intents":["ft_withdraw"]}"#, nonce: &nonces[1], callback_url: Some("https://x") },
+        ];
+
+        let (signatures, public_key) = sign_nep413_intents(&messages, "intents.near", &keypair).unwrap();
+        assert_eq!(signatures.len(), 2);
+        for (m, signature) in messages.iter().zip(&signatures) {
+            let single = sign_nep413(m.message, m.nonce, "intents.near", m.callback_url, &keypair).unwrap();
+            assert_eq!(single, (signature.clone(), public_key.clone()));
+        }
+        assert!(sign_nep413_intents(&[], "intents.near", &keypair).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_intent_signers_produce_their_standard() {
+        use ed25519_dalek::Verifier;
+
+        let signing_key = SigningKey::from_bytes(&[6u8; 32]);
+        let private_key = format!("ed25519:{}", codec::base58_encode(signing_key.to_bytes()));
+        let message = r#"{"signer_id":"alice.near","deadline":"2030-01-01T00:00:00.000Z","intents":[]}"#;
+        let nonce = codec::base64_encode([8u8; 32]);
+
+        let nep413 = intent_signer("nep413", &private_key).unwrap().sign_intent(message, &nonce, "intents.near").unwrap();
+        assert_eq!(nep413.standard, "nep413");
+        assert_eq!(nep413.payload["recipient"], "intents.near");
+        let (signature, public_key) = sign_nep413(message, &nonce, "intents.near", None, &private_key).unwrap();
+        assert_eq!((nep413.signature, nep413.public_key), (signature, Some(public_key)));
+
+        let raw = intent_signer("raw_ed25519", &private_key).unwrap().sign_intent(message, &nonce, "intents.near").unwrap();
+        let payload = raw.payload.as_str().unwrap();
+        let fields: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!((fields["verifying_contract"].as_str(), fields["nonce"].as_str()), (Some("intents.near"), Some(nonce.as_str())));
+        let signature: [u8; 64] = codec::base58_decode_array(raw.signature.strip_prefix("ed25519:").unwrap()).unwrap();
+        signing_key
+            .verifying_key()
+            .verify(payload.as_bytes(), &ed25519_dalek::Signature::from_bytes(&signature))
+            .unwrap();
+
+        assert!(intent_signer("eip712", &private_key).is_err());
+        assert!(intent_signer("raw_ed25519", &format!("secp256k1:{}", codec::base58_encode([7u8; 32]))).is_err());
+    }
+
+    #[test]
+    fn test_erc191_signature_recovers_evm_address() {
+        // Hardhat/Anvil account #0
+        let signer = Erc191Signer::new("0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80").unwrap();
+        assert_eq!(signer.address(), "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266");
+        assert_eq!(hex::encode(erc191_hash(b"hello")), "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750");
+
+        let message = r#"{"signer_id":"0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266","intents":[]}"#;
+        let nonce = codec::base64_encode([1u8; 32]);
+        let signed = signer.sign_intent(message, &nonce, "intents.near").unwrap();
+        assert!(signed.public_key.is_none());
+        assert!(serde_json::to_value(&signed).unwrap().get("public_key").is_none());
+
+        let signature = codec::base58_decode(signed.signature.strip_prefix("secp256k1:").unwrap()).unwrap();
+        let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+            &erc191_hash(signed.payload.as_str().unwrap().as_bytes()),
+            &k256::ecdsa::Signature::from_slice(&signature[..64]).unwrap(),
+            k256::ecdsa::RecoveryId::from_byte(signature[64] - 27).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(&recovered, signer.0.verifying_key());
+    }
+
+    #[test]
+    fn test_public_keys_parse_and_validate() {
+        let ed25519 = "ed25519:DcA2MzgpJbrUATQLLceocVckhhAqrkingax4oJ9kZ847";
+        let key: PublicKey = ed25519.parse().unwrap();
+        assert_eq!(key.curve(), "ed25519");
+        assert_eq!(key.as_bytes().len(), 32);
+        assert_eq!(key.to_string(), ed25519);
+        assert_eq!(PublicKey::from_bytes("ed25519", key.as_bytes()).unwrap(), key);
+
+        let secp256k1 = format!("secp256k1:{}", codec::base58_encode([4u8; 64]));
+        assert_eq!(secp256k1.parse::<PublicKey>().unwrap(), PublicKey::SECP256K1([4u8; 64]));
+
+        assert!("DcA2MzgpJbrUATQLLceocVckhhAqrkingax4oJ9kZ847".parse::<PublicKey>().is_err());
+        assert!(format!("secp256k1:{}", codec::base58_encode([4u8; 33])).parse::<PublicKey>().is_err());
+        assert!("ed25519:0OIl".parse::<PublicKey>().is_err());
+        assert!(PublicKey::from_bytes("sr25519", &[0u8; 32]).is_err());
+    }
 }
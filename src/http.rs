@@ -0,0 +1,501 @@
+//! Shared HTTP transport for relay and NEAR RPC calls
+//!
+//! Every JSON-RPC request (and webhook event) goes through [`post_json`], so headers, timeouts,
+//! response size limits and request metrics live in one place. Requests are
+//! sent through the wasi:http bindings directly (rather than
+//! `wasi_http_client`, which only exposes a connect timeout) so the host can
+//! enforce read timeouts too. Connections belong to the WASI host, which
+//! decides whether to reuse them (guests may not set `Connection` headers).
+//!
+//! Limits are per [`Endpoint`] and configurable via env:
+//! `{RELAY,RPC,WEBHOOK}_CONNECT_TIMEOUT_MS`, `{RELAY,RPC,WEBHOOK}_READ_TIMEOUT_MS`
+//! and `{RELAY,RPC,WEBHOOK}_MAX_RESPONSE_BYTES`.
+//!
+//! Paid RPC providers need credentials: `{RELAY,RPC,WEBHOOK}_HEADERS` adds
+//! headers (`Name: value`, separated by `;`), and an API key set with
+//! [`set_api_key`] is sent as `Authorization: Bearer <key>`.
+//!
+//! Bytes go out through a [`Transport`]: [`WasiHttp`] in the WASI build,
+//! [`Reqwest`] with the `native` feature (host tools, native tests), or one
+//! installed with [`set_transport`].
+//!
+//! With the `record-replay` feature, traffic can be recorded to or replayed
+//! from a fixture file (see [`crate::replay`]).
+
+use crate::metrics;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+#[cfg(not(feature = "native"))]
+use wasi::http::outgoing_handler;
+#[cfg(not(feature = "native"))]
+use wasi::http::types::{Fields, Method, OutgoingBody, OutgoingRequest, RequestOptions, Scheme};
+#[cfg(not(feature = "native"))]
+use wasi::io::streams::StreamError;
+use zeroize::Zeroizing;
+
+/// wasi:io writes at most this many bytes per blocking call
+#[cfg(not(feature = "native"))]
+const MAX_WRITE_CHUNK: usize = 4096;
+#[cfg(not(feature = "native"))]
+const READ_CHUNK: u64 = 64 * 1024;
+
+/// Service a request goes to; each has its own limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// NEAR Intents solver relay
+    Relay,
+    /// NEAR JSON-RPC
+    Rpc,
+    /// Operator progress webhook (see [`crate::webhook`])
+    Webhook,
+}
+
+/// Timeouts and response size limit for one endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limits {
+    pub connect_timeout: Duration,
+    /// Max wait for the first response byte, and between body chunks
+    pub read_timeout: Duration,
+    pub max_response_bytes: u64,
+}
+
+impl Endpoint {
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Endpoint::Relay => "RELAY",
+            Endpoint::Rpc => "RPC",
+            Endpoint::Webhook => "WEBHOOK",
+        }
+    }
+
+    /// Built-in limits, without env overrides
+    pub fn default_limits(self) -> Limits {
+        match self {
+            Endpoint::Relay => Limits {
+                connect_timeout: Duration::from_secs(10),
+                read_timeout: Duration::from_secs(30),
+                max_response_bytes: 1024 * 1024,
+            },
+            // broadcast_tx_commit only answers once the transaction is final
+            Endpoint::Rpc => Limits {
+                connect_timeout: Duration::from_secs(10),
+                read_timeout: Duration::from_secs(60),
+                max_response_bytes: 4 * 1024 * 1024,
+            },
+            // Progress events must not hold up the swap
+            Endpoint::Webhook => Limits {
+                connect_timeout: Duration::from_secs(5),
+                read_timeout: Duration::from_secs(5),
+                max_response_bytes: 64 * 1024,
+            },
+        }
+    }
+
+    /// Limits with env overrides applied
+    pub fn limits(self) -> Limits {
+        let defaults = self.default_limits();
+        let prefix = self.env_prefix();
+        let env_u64 = |name: &str| -> Option<u64> {
+            env::var(format!("{}_{}", prefix, name)).ok().and_then(|v| v.parse().ok())
+        };
+
+        Limits {
+            connect_timeout: env_u64("CONNECT_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.connect_timeout),
+            read_timeout: env_u64("READ_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.read_timeout),
+            max_response_bytes: env_u64("MAX_RESPONSE_BYTES").unwrap_or(defaults.max_response_bytes),
+        }
+    }
+}
+
+thread_local! {
+    static API_KEYS: RefCell<HashMap<Endpoint, Zeroizing<String>>> = RefCell::new(HashMap::new());
+}
+
+/// Send `api_key` as a bearer token with every request to `endpoint`
+/// (None removes it). Kept in memory only, never logged.
+pub fn set_api_key(endpoint: Endpoint, api_key: Option<Zeroizing<String>>) {
+    API_KEYS.with(|keys| match api_key.filter(|key| !key.is_empty()) {
+        Some(key) => keys.borrow_mut().insert(endpoint, key),
+        None => keys.borrow_mut().remove(&endpoint),
+    });
+}
+
+impl Endpoint {
+    /// Headers sent besides `Content-Type`: `{PREFIX}_HEADERS` from env, then
+    /// the API key, if any
+    fn extra_headers(self) -> Result<Vec<(String, Vec<u8>)>, String> {
+        let mut headers = Vec::new();
+        if let Ok(list) = env::var(format!("{}_HEADERS", self.env_prefix())) {
+            headers = parse_headers(&list)?;
+        }
+        API_KEYS.with(|keys| {
+            if let Some(key) = keys.borrow().get(&self) {
+                headers.push(("Authorization".to_string(), format!("Bearer {}", key.as_str()).into_bytes()));
+            }
+        });
+        Ok(headers)
+    }
+}
+
+/// Parse `Name: value; Name2: value2`
+fn parse_headers(list: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    list.split(';')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .enumerate()
+        .map(|(i, header)| {
+            // The header itself isn't echoed, as it may hold a key
+            let (name, value) = header
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid header #{}: expected Name: value", i + 1))?;
+            Ok((name.trim().to_string(), value.trim().as_bytes().to_vec()))
+        })
+        .collect()
+}
+
+/// A fully read response
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// POST `body` as JSON to `url`, within `endpoint`'s limits
+pub fn post_json<T: Serialize + ?Sized>(
+    endpoint: Endpoint,
+    url: &str,
+    body: &T,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let body = serde_json::to_vec(body)?;
+
+    metrics::record_request(url);
+
+    #[cfg(feature = "record-replay")]
+    if let Some(response) = crate::replay::replay(url, &body)? {
+        return Ok(response);
+    }
+
+    let (limits, extra_headers) = (endpoint.limits(), endpoint.extra_headers()?);
+    let response = TRANSPORT.with(|transport| match &*transport.borrow() {
+        Some(transport) => transport.send(url, &body, &limits, extra_headers),
+        None => DefaultTransport.send(url, &body, &limits, extra_headers),
+    })?;
+
+    #[cfg(feature = "record-replay")]
+    crate::replay::record(url, &body, &response)?;
+
+    Ok(response)
+}
+
+/// Sends one POST with a JSON body and reads the whole response, within `limits`
+pub trait Transport {
+    fn send(
+        &self,
+        url: &str,
+        body: &[u8],
+        limits: &Limits,
+        extra_headers: Vec<(String, Vec<u8>)>,
+    ) -> Result<Response, Box<dyn std::error::Error>>;
+}
+
+/// The transport this build uses unless [`set_transport`] replaced it
+#[cfg(not(feature = "native"))]
+pub use WasiHttp as DefaultTransport;
+#[cfg(feature = "native")]
+pub use Reqwest as DefaultTransport;
+
+thread_local! {
+    static TRANSPORT: RefCell<Option<Box<dyn Transport>>> = RefCell::new(None);
+}
+
+/// Send this thread's requests through `transport` (None restores
+/// [`DefaultTransport`])
+pub fn set_transport(transport: Option<Box<dyn Transport>>) {
+    TRANSPORT.with(|current| *current.borrow_mut() = transport);
+}
+
+/// wasi:http outgoing-handler
+#[cfg(not(feature = "native"))]
+pub struct WasiHttp;
+
+#[cfg(not(feature = "native"))]
+impl Transport for WasiHttp {
+    fn send(
+        &self,
+        url: &str,
+        body: &[u8],
+        limits: &Limits,
+        extra_headers: Vec<(String, Vec<u8>)>,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        send_wasi(url, body, limits, extra_headers)
+    }
+}
+
+#[cfg(not(feature = "native"))]
+fn send_wasi(
+    url: &str,
+    body: &[u8],
+    limits: &Limits,
+    extra_headers: Vec<(String, Vec<u8>)>,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let url = url::Url::parse(url)?;
+
+    let mut header_list = vec![("Content-Type".to_string(), b"application/json".to_vec())];
+    header_list.extend(extra_headers);
+    let headers = Fields::from_list(&header_list)?;
+    let request = OutgoingRequest::new(headers);
+    request.set_method(&Method::Post).map_err(|()| "Failed to set method")?;
+    let scheme = match url.scheme() {
+        "http" => Scheme::Http,
+        "https" => Scheme::Https,
+        other => Scheme::Other(other.to_string()),
+    };
+    request.set_scheme(Some(&scheme)).map_err(|()| "Failed to set scheme")?;
+    request
+        .set_authority(Some(url.authority()))
+        .map_err(|()| "Failed to set authority")?;
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    request
+        .set_path_with_query(Some(&path))
+        .map_err(|()| "Failed to set path")?;
+
+    let outgoing_body = request.body().map_err(|()| "Failed to open request body")?;
+    {
+        // The stream must be dropped before the body is finished
+        let stream = outgoing_body.write().map_err(|()| "Failed to open request stream")?;
+        for chunk in body.chunks(MAX_WRITE_CHUNK) {
+            stream.blocking_write_and_flush(chunk)?;
+        }
+    }
+    OutgoingBody::finish(outgoing_body, None)?;
+
+    let options = RequestOptions::new();
+    let nanos = |d: Duration| Some(d.as_nanos() as u64);
+    options
+        .set_connect_timeout(nanos(limits.connect_timeout))
+        .map_err(|()| "Failed to set connect timeout")?;
+    options
+        .set_first_byte_timeout(nanos(limits.read_timeout))
+        .map_err(|()| "Failed to set first byte timeout")?;
+    options
+        .set_between_bytes_timeout(nanos(limits.read_timeout))
+        .map_err(|()| "Failed to set between bytes timeout")?;
+
+    let future_response = outgoing_handler::handle(request, Some(options))?;
+    let incoming = match future_response.get() {
+        Some(result) => result,
+        None => {
+            future_response.subscribe().block();
+            future_response.get().ok_or("Response not ready after wait")?
+        }
+    }
+    .map_err(|()| "Response already taken")??;
+
+    let status = incoming.status();
+
+    // Reject oversized bodies up front when the server declares their length
+    let declared_len = incoming
+        .headers()
+        .get(&"content-length".to_string())
+        .first()
+        .and_then(|v| std::str::from_utf8(v).ok()?.trim().parse::<u64>().ok());
+    if let Some(len) = declared_len.filter(|len| *len > limits.max_response_bytes) {
+        return Err(format!(
+            "Response from {} too large: {} bytes (limit {})",
+            url, len, limits.max_response_bytes
+        )
+        .into());
+    }
+
+    let incoming_body = incoming.consume().map_err(|()| "Response body already consumed")?;
+    let mut body = Vec::new();
+    {
+        // The stream must be dropped before the body
+        let stream = incoming_body.stream().map_err(|()| "Failed to open response stream")?;
+        loop {
+            match stream.blocking_read(READ_CHUNK) {
+                Ok(chunk) => {
+                    body.extend_from_slice(&chunk);
+                    if body.len() as u64 > limits.max_response_bytes {
+                        return Err(format!(
+                            "Response from {} exceeds {} bytes",
+                            url, limits.max_response_bytes
+                        )
+                        .into());
+                    }
+                }
+                Err(StreamError::Closed) => break,
+                Err(e) => return Err(format!("Failed to read response from {}: {}", url, e).into()),
+            }
+        }
+    }
+
+    Ok(Response { status, body })
+}
+
+/// Blocking reqwest client for native builds (`native` feature), e.g. against
+/// a local [`crate::mock_relay`] server. reqwest has no separate first-byte
+/// timeout, so the read timeout bounds the whole request. Each thread keeps
+/// one client per [`Limits`], so connections are reused across requests.
+#[cfg(feature = "native")]
+pub struct Reqwest;
+
+#[cfg(feature = "native")]
+impl Transport for Reqwest {
+    fn send(
+        &self,
+        url: &str,
+        body: &[u8],
+        limits: &Limits,
+        extra_headers: Vec<(String, Vec<u8>)>,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        send_reqwest(url, body, limits, extra_headers)
+    }
+}
+
+#[cfg(feature = "native")]
+thread_local! {
+    /// One client, and so one connection pool, per set of limits
+    static REQWEST_CLIENTS: RefCell<HashMap<Limits, reqwest::blocking::Client>> = RefCell::new(HashMap::new());
+}
+
+#[cfg(feature = "native")]
+fn reqwest_client(limits: &Limits) -> Result<reqwest::blocking::Client, reqwest::Error> {
+    REQWEST_CLIENTS.with(|clients| {
+        if let Some(client) = clients.borrow().get(limits) {
+            return Ok(client.clone());
+        }
+        let client = reqwest::blocking::Client::builder()
+            .connect_timeout(limits.connect_timeout)
+            .timeout(limits.read_timeout)
+            .build()?;
+        clients.borrow_mut().insert(*limits, client.clone());
+        Ok(client)
+    })
+}
+
+#[cfg(feature = "native")]
+fn send_reqwest(
+    url: &str,
+    body: &[u8],
+    limits: &Limits,
+    extra_headers: Vec<(String, Vec<u8>)>,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    use std::io::Read;
+
+    let mut request = reqwest_client(limits)?
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_vec());
+    for (name, value) in extra_headers {
+        request = request.header(name, value);
+    }
+
+    let response = request.send()?;
+    let status = response.status().as_u16();
+    if let Some(len) = response.content_length().filter(|len| *len > limits.max_response_bytes) {
+        return Err(format!(
+            "Response from {} too large: {} bytes (limit {})",
+            url, len, limits.max_response_bytes
+        )
+        .into());
+    }
+    // Without a declared length, read one byte past the limit at most
+    let mut body = Vec::new();
+    response.take(limits.max_response_bytes + 1).read_to_end(&mut body)?;
+    if body.len() as u64 > limits.max_response_bytes {
+        return Err(format!("Response from {} exceeds {} bytes", url, limits.max_response_bytes).into());
+    }
+
+    Ok(Response { status, body })
+}
+
+/// What a JSON-RPC request is routed or matched on: the method, and for NEAR
+/// `query` calls also the request type and contract method, e.g.
+/// `query/call_function/storage_balance_of`
+pub fn request_key(request: &serde_json::Value) -> String {
+    let mut key = request["method"].as_str().unwrap_or_default().to_string();
+    for field in ["request_type", "method_name"] {
+        if let Some(value) = request["params"][field].as_str() {
+            key.push('/');
+            key.push_str(value);
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    type Sent = Rc<RefCell<Vec<(String, Vec<(String, Vec<u8>)>)>>>;
+
+    struct Fake(Sent);
+
+    impl Transport for Fake {
+        fn send(
+            &self,
+            url: &str,
+            _body: &[u8],
+            _limits: &Limits,
+            extra_headers: Vec<(String, Vec<u8>)>,
+        ) -> Result<Response, Box<dyn std::error::Error>> {
+            self.0.borrow_mut().push((url.to_string(), extra_headers));
+            Ok(Response { status: 200, body: b"{}".to_vec() })
+        }
+    }
+
+    #[test]
+    fn test_requests_go_through_the_installed_transport() {
+        let sent = Sent::default();
+        set_transport(Some(Box::new(Fake(sent.clone()))));
+        set_api_key(Endpoint::Webhook, Some(Zeroizing::new("k3y".to_string())));
+
+        let response = post_json(Endpoint::Webhook, "http://hooks.test/event", &serde_json::json!({})).unwrap();
+        set_transport(None);
+        set_api_key(Endpoint::Webhook, None);
+
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            *sent.borrow(),
+            vec![(
+                "http://hooks.test/event".to_string(),
+                vec![("Authorization".to_string(), b"Bearer k3y".to_vec())]
+            )]
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn test_reqwest_caps_bodies_without_content_length() {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 4096]);
+            // Close-delimited: the body's length is only known at EOF
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n");
+            let _ = stream.write_all(&[b'x'; 4096]);
+        });
+
+        let limits = Limits {
+            connect_timeout: Duration::from_secs(5),
+            read_timeout: Duration::from_secs(5),
+            max_response_bytes: 1024,
+        };
+        let error = send_reqwest(&url, b"{}", &limits, Vec::new()).unwrap_err();
+        assert!(error.to_string().contains("exceeds 1024 bytes"), "{}", error);
+    }
+}
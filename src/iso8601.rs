@@ -0,0 +1,131 @@
+//! Minimal UTC ISO 8601 timestamps (`YYYY-MM-DDTHH:MM:SS.sssZ`)
+//!
+//! NEAR Intents deadlines and quote expiration times use this format. Only the
+//! UTC `Z` form is supported, which is all the relay produces.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MS_PER_DAY: u64 = 86_400_000;
+
+/// Current time in milliseconds since the Unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Format Unix milliseconds as `YYYY-MM-DDTHH:MM:SS.sssZ`
+pub fn format_millis(unix_ms: u64) -> String {
+    let days = unix_ms / MS_PER_DAY;
+    let ms_of_day = unix_ms % MS_PER_DAY;
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        ms_of_day / 3_600_000,
+        ms_of_day / 60_000 % 60,
+        ms_of_day / 1000 % 60,
+        ms_of_day % 1000
+    )
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS[.fraction]Z` into Unix milliseconds
+pub fn parse_millis(timestamp: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid ISO 8601 timestamp: {}", timestamp);
+
+    let body = timestamp.strip_suffix('Z').ok_or_else(invalid)?;
+    let (date, time) = body.split_once('T').ok_or_else(invalid)?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let mut next_date = || -> Result<u64, String> {
+        date_parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)
+    };
+    let (year, month, day) = (next_date()?, next_date()?, next_date()?);
+
+    let (hms, fraction) = match time.split_once('.') {
+        Some((hms, fraction)) => (hms, fraction),
+        None => (time, ""),
+    };
+    let mut time_parts = hms.splitn(3, ':');
+    let mut next_time = || -> Result<u64, String> {
+        time_parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)
+    };
+    let (hours, minutes, seconds) = (next_time()?, next_time()?, next_time()?);
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return Err(invalid());
+    }
+
+    // Milliseconds from the first 3 fraction digits (right-padded: ".5" = 500ms)
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let millis = fraction
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(3)
+        .collect::<String>()
+        .parse::<u64>()
+        .map_err(|_| invalid())?;
+
+    let days = days_from_civil(year as i64, month as u32, day as u32);
+    if days < 0 {
+        return Err(invalid());
+    }
+
+    Ok(days as u64 * MS_PER_DAY + hours * 3_600_000 + minutes * 60_000 + seconds * 1000 + millis)
+}
+
+// Howard Hinnant's days <-> civil date algorithms (proleptic Gregorian calendar)
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let mp = (month as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_timestamps() {
+        assert_eq!(format_millis(0), "1970-01-01T00:00:00.000Z");
+        // Leap day
+        assert_eq!(format_millis(1_709_210_096_789), "2024-02-29T12:34:56.789Z");
+        assert_eq!(parse_millis("2024-02-29T12:34:56.789Z"), Ok(1_709_210_096_789));
+        assert_eq!(parse_millis("2024-02-29T12:34:56Z"), Ok(1_709_210_096_000));
+        assert_eq!(parse_millis("2024-02-29T12:34:56.5Z"), Ok(1_709_210_096_500));
+    }
+
+    #[test]
+    fn test_roundtrip_and_rejects_garbage() {
+        for ms in [1u64, 951_782_400_000, 1_767_225_599_999, 4_102_444_800_000] {
+            assert_eq!(parse_millis(&format_millis(ms)), Ok(ms));
+        }
+        assert!(parse_millis("2024-02-29 12:34:56Z").is_err());
+        assert!(parse_millis("2024-13-01T00:00:00Z").is_err());
+        assert!(parse_millis("2024-01-01T00:00:00+01:00").is_err());
+    }
+}
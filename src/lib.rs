@@ -1,8 +1,37 @@
-/// NEAR Intents Ark - WASI worker for off-chain swap execution
-///
-/// This library provides modules for:
-/// - NEAR transaction signing and sending (near_tx)
-/// - NEP-413 cryptographic signing (crypto)
+//! NEAR Intents Ark - WASI worker for off-chain swap execution
+//!
+//! This library provides modules for:
+//! - NEAR transaction signing and sending (near_tx)
+//! - Typed client for the swap contract: swap msg, views, events (client)
+//! - NEP-413/NEP-366 signing, key generation and implicit accounts (crypto)
+//! - Base64/base58 encoding shared by every module (codec)
+//! - Overflow-checked amount parsing and decimal formatting (amount)
+//! - Log verbosity and secret redaction (logging)
+//! - ISO 8601 timestamps for intent deadlines (iso8601)
+//! - Mainnet/testnet endpoints (network)
+//! - Inline or env-provided worker secrets (secrets)
+//! - Per-execution retry and request counters (metrics)
+//! - Shared HTTP client for relay and RPC requests, over wasi:http or reqwest (http)
+//! - Progress events to an operator webhook (webhook)
+//! - HTTP record/replay for offline tests, behind the `record-replay` feature (replay)
+//! - Local relay/RPC server for offline tests, behind the `mock-relay` feature (mock_relay)
 
+#[cfg(not(any(feature = "wasi", feature = "native")))]
+compile_error!("enable `wasi` (the default, for wasm32-wasip2) or `native` (reqwest) for HTTP");
+
+pub mod amount;
+pub mod client;
+pub mod codec;
 pub mod crypto;
+pub mod http;
+pub mod iso8601;
+pub mod logging;
+pub mod metrics;
+#[cfg(feature = "mock-relay")]
+pub mod mock_relay;
 pub mod near_tx;
+pub mod network;
+#[cfg(feature = "record-replay")]
+pub mod replay;
+pub mod secrets;
+pub mod webhook;
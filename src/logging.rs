@@ -0,0 +1,150 @@
+//! Log verbosity and secret redaction for stderr output
+//!
+//! stderr is captured into OutLayer execution logs, so anything printed there
+//! must be safe to share. Two env switches control it:
+//! - `LOG_LEVEL`: `info` (default) or `debug`. Raw request/response bodies are
+//!   only printed at `debug`.
+//! - `REDACT`: `true` (default) or `false`. When enabled, the values of
+//!   secret-named JSON keys (private keys, seeds, signatures) are masked in
+//!   every logged body and error message.
+
+use std::env;
+
+/// JSON keys containing any of these name a secret. Matched by key rather
+/// than by value, since a 32-byte seed looks like a public key and
+/// secp256k1 or ERC-191 signatures aren't `ed25519:` at all
+const SECRET_KEY_MARKERS: &[&str] = &["private_key", "secret", "seed", "signature"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Debug,
+}
+
+/// Current log level from `LOG_LEVEL` (unknown values fall back to info)
+pub fn level() -> LogLevel {
+    match env::var("LOG_LEVEL").map(|v| v.to_lowercase()).as_deref() {
+        Ok("debug") | Ok("trace") => LogLevel::Debug,
+        _ => LogLevel::Info,
+    }
+}
+
+pub fn debug_enabled() -> bool {
+    level() >= LogLevel::Debug
+}
+
+/// Whether redaction is on (`REDACT=false`/`0` turns it off)
+pub fn redact_enabled() -> bool {
+    !matches!(
+        env::var("REDACT").map(|v| v.to_lowercase()).as_deref(),
+        Ok("false") | Ok("0") | Ok("no")
+    )
+}
+
+/// Mask the string values of secret-named JSON keys, keeping the rest
+/// (public keys included) readable. JSON embedded in a string, with its
+/// quotes escaped, is masked too.
+pub fn redact(text: &str) -> String {
+    if !redact_enabled() {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('"') {
+        let key_start = pos + 1;
+        match secret_value(&rest[..pos], &rest[key_start..]) {
+            Some((value_start, value_len)) => {
+                result.push_str(&rest[..key_start + value_start]);
+                result.push_str("<redacted>");
+                rest = &rest[key_start + value_start + value_len..];
+            }
+            None => {
+                result.push_str(&rest[..key_start]);
+                rest = &rest[key_start..];
+            }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// If the quote ending `before` opens a secret-named key, the offset and
+/// length of that key's string value in `after`
+fn secret_value(before: &str, after: &str) -> Option<(usize, usize)> {
+    let quote = if before.ends_with('\\') { "\\\"" } else { "\"" };
+    let key_len = after.find(quote)?;
+    let key = &after[..key_len];
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let key = key.to_ascii_lowercase();
+    if !is_key || !SECRET_KEY_MARKERS.iter().any(|marker| key.contains(marker)) {
+        return None;
+    }
+
+    let value = after[key_len + quote.len()..]
+        .trim_start()
+        .strip_prefix(':')?
+        .trim_start()
+        .strip_prefix(quote)?;
+    Some((after.len() - value.len(), value.find(quote)?))
+}
+
+/// Print a raw body at debug level only, truncated and redacted
+pub fn debug_body(label: &str, body: &str, max_chars: usize) {
+    if debug_enabled() {
+        let truncated: String = body.chars().take(max_chars).collect();
+        eprintln!("{} (first {} chars): {}", label, max_chars, redact(&truncated));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_masks_signatures_but_not_public_keys() {
+        let public_key = "ed25519:DcA2MzgpJbrUATQLLceocVckhhAqrkingax4oJ9kZ847";
+        let signature = format!("ed25519:{}", "5".repeat(88));
+        let body = format!(
+            r#"{{"public_key":"{}","signature":"{}"}}"#,
+            public_key, signature
+        );
+
+        let redacted = redact(&body);
+
+        assert!(redacted.contains(public_key));
+        assert!(!redacted.contains(&signature));
+        assert!(redacted.contains(r#""signature":"<redacted>""#));
+    }
+
+    #[test]
+    fn test_redact_masks_seeds_and_non_ed25519_secrets() {
+        // A 32-byte seed is as long as a public key
+        let seed = "ed25519:DcA2MzgpJbrUATQLLceocVckhhAqrkingax4oJ9kZ847";
+        let secp256k1 = format!("secp256k1:{}", "3".repeat(87));
+        let erc191 = format!("0x{}", "ab".repeat(65));
+        let body = format!(
+            r#"{{"private_key": "{}", "signed": {{"signature":"{}"}}, "SECRET_KEY":"{}", "amount":"10"}}"#,
+            seed, secp256k1, erc191
+        );
+
+        let redacted = redact(&body);
+
+        assert_eq!(
+            redacted,
+            r#"{"private_key": "<redacted>", "signed": {"signature":"<redacted>"}, "SECRET_KEY":"<redacted>", "amount":"10"}"#
+        );
+    }
+
+    #[test]
+    fn test_redact_masks_escaped_json_in_strings() {
+        let body = r#"{"payload":"{\"signature\":\"secp256k1:abc\",\"nonce\":\"n\"}","signer":"seed.near"}"#;
+
+        assert_eq!(
+            redact(body),
+            r#"{"payload":"{\"signature\":\"<redacted>\",\"nonce\":\"n\"}","signer":"seed.near"}"#
+        );
+    }
+}
@@ -1,46 +1,301 @@
-mod crypto;
-mod near_tx;
-
+use intents_ark::http::Endpoint;
+use intents_ark::near_tx::AccessKeyPermissionView;
+use intents_ark::network::NetworkConfig;
+use intents_ark::secrets::Secrets;
+use intents_ark::{amount, codec, crypto, http, iso8601, logging, metrics, near_tx, webhook};
+use intents_ark_types::{ErrorCode, SwapInput, OUTPUT_SCHEMA_VERSION};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::io::{self, Read, Write};
-use std::time::Duration;
-use wasi_http_client::Client;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
 
 // ============================================================================
 // Input/Output Types
 // ============================================================================
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
+/// Stdin payload carrying secrets inline next to the request
+#[derive(Deserialize)]
+struct Envelope {
+    secrets: Secrets,
+    request: Input,
+}
+
+#[derive(Debug)]
 enum Input {
+    /// An object with an `action`: it must name one of these exactly
+    Action(Action),
+    Swap(SwapInput),
+}
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Not untagged: a misspelled action that also carries swap fields
+        // would fall through to Swap and run one
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let input = if value.get("action").is_some() {
+            Action::deserialize(value).map(Input::Action)
+        } else if value.is_array() {
+            // A derived struct would take its fields from an array by position
+            Err(serde::de::Error::custom("expected an object, not an array"))
+        } else {
+            SwapInput::deserialize(value).map(Input::Swap)
+        };
+        input.map_err(serde::de::Error::custom)
+    }
+}
+
+/// Operator actions, picked by their `action` field
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Action {
     TestStorage {
-        action: String, // "test_storage"
         token_contract: String,
     },
     Withdraw {
-        action: String, // "withdraw"
         token: String,
         receiver_id: String,
         amount: String,
         swap_contract_id: String,
     },
-    Swap {
-        sender_id: String,
-        token_in: String,
-        token_out: String,
-        amount_in: String,
-        min_amount_out: String,
+    /// Register `public_key` for the swap contract on the intents contract
+    /// (`add_public_key`), so intents signed with it are accepted
+    RegisterKey {
+        public_key: String,
+        swap_contract_id: String,
+    },
+    /// Balances of the swap contract on every whitelisted token, for treasury
+    /// reconciliation against the contract's fee accounting
+    Report {
         swap_contract_id: String,
     },
 }
 
 #[derive(Serialize, Debug)]
 struct Output {
+    /// Output format version, bumped on incompatible changes
+    schema_version: u32,
     success: bool,
     amount_out: Option<String>,
     error_message: Option<String>,
     intent_hash: Option<String>,
+    /// Relay quote(s) the swap intent was built on, comma-separated for split swaps
+    quote_hash: Option<String>,
+    /// ft_transfer_call moving amount_in into intents.near
+    deposit_tx_hash: Option<String>,
+    /// ft_withdraw intent returning amount_out to the swap contract
+    withdraw_intent_hash: Option<String>,
+    /// The input's token_in / token_out, echoed for the contract to check
+    token_in: Option<String>,
+    token_out: Option<String>,
+    /// Machine-readable failure reason (None on success)
+    error_code: Option<ErrorCode>,
+    /// Step at which the swap stopped (None on success)
+    failed_stage: Option<SwapStage>,
+    /// Steps that were executed, in order
+    steps: Vec<StepReport>,
+    /// Timings, retries and endpoints for latency tracking across executions
+    metrics: Option<ExecutionMetrics>,
+}
+
+#[derive(Serialize, Debug)]
+struct ExecutionMetrics {
+    total_ms: u64,
+    /// Duration of each executed step, keyed by stage
+    stage_ms: BTreeMap<SwapStage, u64>,
+    #[serde(flatten)]
+    counters: metrics::Counters,
+}
+
+impl ExecutionMetrics {
+    /// Collect the counters recorded since the last call (see `metrics::take`)
+    fn collect(started: Instant, steps: &[StepReport]) -> Self {
+        let mut stage_ms = BTreeMap::new();
+        for step in steps {
+            *stage_ms.entry(step.stage).or_default() += step.duration_ms;
+        }
+        Self {
+            total_ms: started.elapsed().as_millis() as u64,
+            stage_ms,
+            counters: metrics::take(),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            schema_version: OUTPUT_SCHEMA_VERSION,
+            success: false,
+            amount_out: None,
+            error_message: None,
+            intent_hash: None,
+            quote_hash: None,
+            deposit_tx_hash: None,
+            withdraw_intent_hash: None,
+            token_in: None,
+            token_out: None,
+            error_code: None,
+            failed_stage: None,
+            steps: Vec::new(),
+            metrics: None,
+        }
+    }
+}
+
+impl Output {
+    /// Copy the quote, deposit and withdraw artifacts recorded in `steps` to
+    /// the top level, so every failure path reports whatever was produced
+    fn collect_artifacts(&mut self) {
+        let step = |stage: SwapStage| {
+            self.steps
+                .iter()
+                .find(|s| s.stage == stage && s.status == StepStatus::Success)
+        };
+        self.quote_hash = step(SwapStage::Quote).and_then(|s| s.quote_hash.clone());
+        self.deposit_tx_hash = step(SwapStage::Deposit).and_then(|s| s.tx_hash.clone());
+        // A pending withdraw intent may still settle, so it's reported too
+        self.withdraw_intent_hash = self
+            .steps
+            .iter()
+            .find_map(|s| if s.stage == SwapStage::Withdraw { s.intent_hash.clone() } else { None });
+    }
+}
+
+/// Progress events sent to PROGRESS_WEBHOOK_URL, in the order they occur
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ProgressEvent {
+    Quoted,
+    Deposited,
+    Published,
+    Settled,
+    Withdrawn,
+    Failed,
+}
+
+/// Body of a progress webhook request
+#[derive(Serialize)]
+struct ProgressUpdate<'a> {
+    event: ProgressEvent,
+    timestamp: String,
+    sender_id: &'a str,
+    swap_contract_id: &'a str,
+    token_in: &'a str,
+    token_out: &'a str,
+    amount_in: &'a str,
+    amount_out: Option<&'a str>,
+    /// The step that just completed (or failed), with its tx/intent hash
+    step: Option<&'a StepReport>,
+    error_code: Option<ErrorCode>,
+    error_message: Option<&'a str>,
+}
+
+impl<'a> ProgressUpdate<'a> {
+    fn new(event: ProgressEvent, swap: &'a SwapInput, steps: &'a [StepReport]) -> Self {
+        Self {
+            event,
+            timestamp: iso8601::format_millis(iso8601::now_millis()),
+            sender_id: &swap.sender_id,
+            swap_contract_id: &swap.swap_contract_id,
+            token_in: &swap.token_in,
+            token_out: &swap.token_out,
+            amount_in: &swap.amount_in,
+            amount_out: None,
+            step: steps.last(),
+            error_code: None,
+            error_message: None,
+        }
+    }
+}
+
+/// Steps of the swap flow, in execution order
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+enum SwapStage {
+    Quote,
+    StorageCheck,
+    Deposit,
+    Publish,
+    Settle,
+    Withdraw,
+}
+
+impl SwapStage {
+    const ALL: [SwapStage; 6] = [
+        SwapStage::Quote,
+        SwapStage::StorageCheck,
+        SwapStage::Deposit,
+        SwapStage::Publish,
+        SwapStage::Settle,
+        SwapStage::Withdraw,
+    ];
+
+    /// Rough worst-case duration of this stage
+    fn estimate(self) -> Duration {
+        Duration::from_secs(match self {
+            SwapStage::Quote => 10,
+            SwapStage::StorageCheck => 5,
+            SwapStage::Deposit => 15,
+            SwapStage::Publish => 5,
+            SwapStage::Settle => 30,
+            SwapStage::Withdraw => 15,
+        })
+    }
+
+    /// Time needed to run this stage and every later one
+    fn time_to_finish(self) -> Duration {
+        Self::ALL.iter().filter(|s| **s >= self).map(|s| s.estimate()).sum()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum StepStatus {
+    Success,
+    Failed,
+    /// Step couldn't be performed but the flow continued (e.g. storage check RPC error)
+    Skipped,
+}
+
+/// Result and timing of one executed step
+#[derive(Serialize, Debug)]
+struct StepReport {
+    stage: SwapStage,
+    status: StepStatus,
+    duration_ms: u64,
+    tx_hash: Option<String>,
+    intent_hash: Option<String>,
+    quote_hash: Option<String>,
+    error: Option<String>,
+}
+
+impl StepReport {
+    fn new(stage: SwapStage, status: StepStatus, started: Instant) -> Self {
+        Self {
+            stage,
+            status,
+            duration_ms: started.elapsed().as_millis() as u64,
+            tx_hash: None,
+            intent_hash: None,
+            quote_hash: None,
+            error: None,
+        }
+    }
+
+    fn success(stage: SwapStage, started: Instant) -> Self {
+        Self::new(stage, StepStatus::Success, started)
+    }
+
+    fn failed(stage: SwapStage, started: Instant, error: &str) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            ..Self::new(stage, StepStatus::Failed, started)
+        }
+    }
 }
 
 // ============================================================================
@@ -60,6 +315,17 @@ struct QuoteParams {
     defuse_asset_identifier_in: String,
     defuse_asset_identifier_out: String,
     exact_amount_in: String,
+    #[serde(flatten)]
+    attribution: RelayAttribution,
+}
+
+/// Optional attribution fields the solver relay accepts on quote/publish
+#[derive(Serialize, Clone, Default)]
+struct RelayAttribution {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referral: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
@@ -83,23 +349,10 @@ struct Quote {
 
 #[derive(Serialize)]
 struct PublishIntentParams {
-    signed_data: SignedData,
+    signed_data: crypto::SignedIntent,
     quote_hashes: Option<Vec<String>>,
-}
-
-#[derive(Serialize)]
-struct SignedData {
-    payload: Payload,
-    standard: String,
-    signature: String,
-    public_key: String,
-}
-
-#[derive(Serialize)]
-struct Payload {
-    message: String,
-    nonce: String,
-    recipient: String,
+    #[serde(flatten)]
+    attribution: RelayAttribution,
 }
 
 #[derive(Deserialize, Debug)]
@@ -119,7 +372,11 @@ struct IntentMessage {
 #[serde(tag = "intent")]
 enum IntentAction {
     #[serde(rename = "token_diff")]
-    TokenDiff { diff: serde_json::Value },
+    TokenDiff {
+        diff: serde_json::Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        referral: Option<String>,
+    },
     #[serde(rename = "ft_withdraw")]
     FtWithdraw {
         token: String,
@@ -142,8 +399,64 @@ struct GetStatusResult {
 // Constants
 // ============================================================================
 
-const INTENTS_API_URL: &str = "https://solver-relay-v2.chaindefuser.com/rpc";
-const INTENTS_CONTRACT: &str = "intents.near";
+/// Network endpoints, resolved once from NEAR_NETWORK and its overrides
+static NETWORK: OnceLock<NetworkConfig> = OnceLock::new();
+
+fn network() -> &'static NetworkConfig {
+    NETWORK.get_or_init(|| NetworkConfig::from_env().expect("NEAR_NETWORK is validated in main"))
+}
+
+thread_local! {
+    /// NEAR RPC endpoints for the whole run, so failover state is shared
+    static RPC: near_tx::RpcClient =
+        near_tx::RpcClient::with_fallbacks(&network().rpc_url, &network().rpc_fallback_urls);
+}
+
+fn rpc() -> near_tx::RpcClient {
+    RPC.with(Clone::clone)
+}
+
+/// Withdrawal attempts after the swap settled (override with WITHDRAW_MAX_ATTEMPTS)
+const DEFAULT_WITHDRAW_MAX_ATTEMPTS: u32 = 3;
+const WITHDRAW_RETRY_DELAY_MS: u64 = 1000;
+
+/// Max number of quotes a swap may be split across when no single quote fills
+/// it (override with MAX_QUOTE_SPLITS; 1 disables splitting)
+const DEFAULT_MAX_QUOTE_SPLITS: u32 = 1;
+const MAX_QUOTE_SPLITS_LIMIT: u32 = 8;
+
+/// Price-impact guard probes with amount_in / this (override with
+/// PRICE_IMPACT_PROBE_DIVISOR; the guard itself is off unless MAX_PRICE_IMPACT_BPS is set)
+const DEFAULT_PRICE_IMPACT_PROBE_DIVISOR: u128 = 1000;
+
+/// Settlement polling: 120 * 0.25s = 30 seconds at most
+const SETTLE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SETTLE_POLL_ATTEMPTS: u32 = 120;
+
+/// OutLayer's execution limit when the input doesn't carry one
+/// (override with MAX_EXECUTION_SECONDS)
+const DEFAULT_MAX_EXECUTION_SECONDS: u64 = 120;
+/// Part of the execution limit kept back for printing the Output
+const BUDGET_OUTPUT_MARGIN_SECONDS: u64 = 5;
+
+/// With several operator keys, skip function-call keys with less allowance
+/// than this, in yoctoNEAR (override with MIN_KEY_ALLOWANCE)
+const DEFAULT_MIN_KEY_ALLOWANCE: u128 = 250_000_000_000_000_000_000_000; // 0.25 NEAR
+
+/// Upper bound for slippage_buffer_bps (100%)
+const MAX_SLIPPAGE_BUFFER_BPS: u32 = 10_000;
+
+/// Swap intents expire this long before the quote backing them
+/// (override with DEADLINE_SAFETY_MARGIN_SECONDS)
+const DEFAULT_DEADLINE_SAFETY_MARGIN_SECONDS: u64 = 5;
+
+/// Typical time from quote to settled intent (deposit, publish, settle);
+/// quotes valid for less are refused (override with EXPECTED_SETTLEMENT_SECONDS)
+const DEFAULT_EXPECTED_SETTLEMENT_SECONDS: u64 = 15;
+
+/// Validity of withdraw intents, which aren't backed by a quote
+/// (override with WITHDRAW_DEADLINE_SECONDS)
+const DEFAULT_WITHDRAW_DEADLINE_SECONDS: u64 = 180;
 
 // ============================================================================
 // Test Functions
@@ -158,14 +471,12 @@ struct TestStorageOutput {
     error: Option<String>,
 }
 
-fn handle_test_storage(token_contract: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_test_storage(token_contract: &str, secrets: &Secrets) -> Result<(), Box<dyn std::error::Error>> {
     // Get credentials from environment
     let swap_contract_id = env::var("SWAP_CONTRACT_ID")
         .map_err(|_| "Missing SWAP_CONTRACT_ID env var")?;
-    let swap_contract_private_key = env::var("SWAP_CONTRACT_PRIVATE_KEY")
-        .map_err(|_| "Missing SWAP_CONTRACT_PRIVATE_KEY env var")?;
-    let rpc_url = env::var("NEAR_RPC_URL")
-        .unwrap_or_else(|_| "https://rpc.mainnet.near.org".to_string());
+    let swap_contract_private_key = select_signing_key(secrets, &swap_contract_id)?;
+    let rpc = rpc();
 
     eprintln!("📊 Step 1: Checking storage_balance_of...");
 
@@ -174,31 +485,24 @@ fn handle_test_storage(token_contract: &str) -> Result<(), Box<dyn std::error::E
         "account_id": swap_contract_id
     });
 
-    let balance_result = near_tx::view(
-        &rpc_url,
-        token_contract,
-        "storage_balance_of",
-        &args.to_string(),
-    );
+    let balance_result =
+        near_tx::view_typed::<serde_json::Value>(&rpc, token_contract, "storage_balance_of", &args);
 
     let output = match balance_result {
-        Ok(result_str) => {
-            // Parse storage balance
-            let balance_json: serde_json::Value = serde_json::from_str(&result_str)?;
-
+        Ok(balance_json) => {
             if balance_json.is_null() {
                 // Not registered - call storage_deposit
                 eprintln!("⚠️  Not registered. Calling storage_deposit...");
 
                 match near_tx::storage_deposit(
-                    &rpc_url,
+                    &rpc,
                     &swap_contract_id,
                     &swap_contract_private_key,
                     token_contract,
                     None,
                     false,
                 ) {
-                    Ok(tx_hash) => {
+                    Ok(near_tx::TxOutcome { tx_hash, .. }) => {
                         eprintln!("✅ Transaction successful! TX: {}", tx_hash);
                         TestStorageOutput {
                             success: true,
@@ -240,14 +544,14 @@ fn handle_test_storage(token_contract: &str) -> Result<(), Box<dyn std::error::E
             eprintln!("⚠️  Error checking balance: {}. Trying storage_deposit...", e);
 
             match near_tx::storage_deposit(
-                &rpc_url,
+                &rpc,
                 &swap_contract_id,
                 &swap_contract_private_key,
                 token_contract,
                 None,
                 false,
             ) {
-                Ok(tx_hash) => {
+                Ok(near_tx::TxOutcome { tx_hash, .. }) => {
                     eprintln!("✅ Transaction successful! TX: {}", tx_hash);
                     TestStorageOutput {
                         success: true,
@@ -278,43 +582,314 @@ fn handle_test_storage(token_contract: &str) -> Result<(), Box<dyn std::error::E
     Ok(())
 }
 
+// ============================================================================
+// Key Registration
+// ============================================================================
+
+/// Gas for add_public_key on the intents contract
+const ADD_PUBLIC_KEY_GAS: u64 = 30_000_000_000_000;
+
+#[derive(Serialize)]
+struct RegisterKeyOutput {
+    success: bool,
+    already_registered: bool,
+    public_key: String,
+    intents_contract: String,
+    tx_hash: Option<String>,
+    error: Option<String>,
+}
+
+fn handle_register_key(
+    swap_contract_id: &str,
+    public_key: &str,
+    secrets: &Secrets,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let intents_contract = network().intents_contract.clone();
+    let result = register_key(swap_contract_id, public_key, &intents_contract, secrets);
+    if let Err(e) = &result {
+        eprintln!("❌ Key registration failed: {}", e);
+    }
+    let output = RegisterKeyOutput {
+        success: result.is_ok(),
+        already_registered: matches!(result, Ok(None)),
+        public_key: public_key.to_string(),
+        intents_contract,
+        tx_hash: result.as_ref().ok().cloned().flatten(),
+        error: result.err().map(|e| e.to_string()),
+    };
+
+    print!("{}", serde_json::to_string(&output)?);
+    io::stdout().flush()?;
+
+    Ok(())
+}
+
+/// Add `public_key` to the swap contract's keys on the intents contract;
+/// returns the transaction hash, or None if it was registered already
+fn register_key(
+    swap_contract_id: &str,
+    public_key: &str,
+    intents_contract: &str,
+    secrets: &Secrets,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    public_key
+        .parse::<crypto::PublicKey>()
+        .map_err(|e| format!("Invalid public key {}: {}", public_key, e))?;
+    let rpc = rpc();
+
+    eprintln!("🔑 Step 1: Checking has_public_key on {}...", intents_contract);
+    let args = serde_json::json!({ "account_id": swap_contract_id, "public_key": public_key });
+    if near_tx::view_typed::<bool>(&rpc, intents_contract, "has_public_key", &args)? {
+        eprintln!("✅ Already registered");
+        return Ok(None);
+    }
+
+    eprintln!("🔑 Step 2: Calling add_public_key...");
+    let signing_key = select_signing_key(secrets, swap_contract_id)?;
+    let near_tx::TxOutcome { tx_hash, .. } = near_tx::call(
+        &rpc,
+        swap_contract_id,
+        &signing_key,
+        intents_contract,
+        "add_public_key",
+        &serde_json::json!({ "public_key": public_key }).to_string(),
+        ADD_PUBLIC_KEY_GAS,
+        1,
+    )?;
+    eprintln!("✅ Transaction successful! TX: {}", tx_hash);
+    Ok(Some(tx_hash))
+}
+
+// ============================================================================
+// Report
+// ============================================================================
+
+#[derive(Serialize)]
+struct ReportOutput {
+    success: bool,
+    swap_contract_id: String,
+    intents_contract: String,
+    generated_at: String,
+    tokens: Vec<TokenReport>,
+    error: Option<String>,
+}
+
+/// Balances of one whitelisted token. Amounts are decimal strings; a field is
+/// null if its lookup failed, with the reason in `error`.
+#[derive(Serialize, Default)]
+struct TokenReport {
+    token_id: String,
+    defuse_asset_id: Option<String>,
+    /// ft_balance_of(swap_contract) on the token contract
+    wallet_balance: Option<String>,
+    /// Balance held by the swap contract inside the intents contract
+    intents_balance: Option<String>,
+    /// Fees the swap contract has accounted for this token
+    collected_fees: Option<String>,
+    error: Option<String>,
+}
+
+fn handle_report(swap_contract_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let generated_at = iso8601::format_millis(iso8601::now_millis());
+    let output = match build_report(swap_contract_id) {
+        Ok(tokens) => ReportOutput {
+            success: tokens.iter().all(|t| t.error.is_none()),
+            swap_contract_id: swap_contract_id.to_string(),
+            intents_contract: network().intents_contract.clone(),
+            generated_at,
+            tokens,
+            error: None,
+        },
+        Err(e) => {
+            eprintln!("❌ Report failed: {}", e);
+            ReportOutput {
+                success: false,
+                swap_contract_id: swap_contract_id.to_string(),
+                intents_contract: network().intents_contract.clone(),
+                generated_at,
+                tokens: Vec::new(),
+                error: Some(e.to_string()),
+            }
+        }
+    };
+
+    print!("{}", serde_json::to_string(&output)?);
+    io::stdout().flush()?;
+
+    Ok(())
+}
+
+fn build_report(swap_contract_id: &str) -> Result<Vec<TokenReport>, Box<dyn std::error::Error>> {
+    let rpc = &rpc();
+
+    let token_ids: Vec<String> =
+        near_tx::view_typed(rpc, swap_contract_id, "get_whitelisted_tokens", &serde_json::json!({}))?;
+    eprintln!("📋 Reporting on {} whitelisted tokens", token_ids.len());
+
+    let mut tokens: Vec<TokenReport> = token_ids
+        .into_iter()
+        .map(|token_id| token_report(swap_contract_id, token_id))
+        .collect();
+
+    // One batched lookup for every token held in the intents contract
+    let asset_ids: Vec<&str> = tokens.iter().filter_map(|t| t.defuse_asset_id.as_deref()).collect();
+    if !asset_ids.is_empty() {
+        let args = serde_json::json!({
+            "account_id": swap_contract_id,
+            "token_ids": asset_ids,
+        });
+        match near_tx::view_typed::<Vec<String>>(rpc, &network().intents_contract, "mt_batch_balance_of", &args) {
+            Ok(balances) => {
+                let with_asset = tokens.iter_mut().filter(|t| t.defuse_asset_id.is_some());
+                for (token, balance) in with_asset.zip(balances) {
+                    token.intents_balance = Some(balance);
+                }
+            }
+            Err(e) => {
+                for token in tokens.iter_mut().filter(|t| t.defuse_asset_id.is_some()) {
+                    token.error.get_or_insert_with(|| format!("mt_batch_balance_of: {}", e));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn token_report(swap_contract_id: &str, token_id: String) -> TokenReport {
+    let rpc = &rpc();
+    let mut report = TokenReport { token_id, ..Default::default() };
+    let mut errors = Vec::new();
+
+    // One snapshot of the token's config and balances
+    let token_args = serde_json::json!({ "token_id": report.token_id });
+    let balance_args = serde_json::json!({ "account_id": swap_contract_id });
+    let results = near_tx::view_many(rpc, vec![
+        near_tx::ViewSpec::new(swap_contract_id, "get_token_config", token_args.clone()),
+        near_tx::ViewSpec::new(&report.token_id, "ft_balance_of", balance_args),
+        near_tx::ViewSpec::new(swap_contract_id, "get_collected_fees", token_args),
+    ]);
+    let [config, balance, fees] = &results[..] else {
+        unreachable!("view_many returns one result per spec");
+    };
+
+    match config.json::<serde_json::Value>() {
+        Ok(config) => {
+            report.defuse_asset_id = config
+                .get("defuse_asset_id")
+                .and_then(|id| id.as_str())
+                .map(String::from);
+        }
+        Err(e) => errors.push(format!("get_token_config: {}", e)),
+    }
+
+    match balance.json::<String>() {
+        Ok(balance) => report.wallet_balance = Some(balance),
+        Err(e) => errors.push(format!("ft_balance_of: {}", e)),
+    }
+
+    // get_collected_fees returns a bare JSON number that can exceed u64, so it
+    // is parsed as u128 rather than through serde_json::Value
+    match fees
+        .raw()
+        .and_then(|result| Ok(amount::parse(String::from_utf8_lossy(result).trim().trim_matches('"'))?))
+    {
+        Ok(fees) => report.collected_fees = Some(fees.to_string()),
+        Err(e) => errors.push(format!("get_collected_fees: {}", e)),
+    }
+
+    if !errors.is_empty() {
+        eprintln!("⚠️  {}: {}", report.token_id, errors.join("; "));
+        report.error = Some(errors.join("; "));
+    }
+    report
+}
+
 // ============================================================================
 // Main Logic
 // ============================================================================
 
+/// Parse stdin: a secrets envelope, or a bare request with secrets in env vars.
+/// The input is attacker-controlled (it comes from ft_on_transfer) and may
+/// carry secrets, so errors give only the position, never the offending value.
+fn parse_input(raw: &str) -> Result<(Input, Secrets), String> {
+    // Only an object is an envelope: a two-element array would otherwise
+    // fill its fields by position
+    if !raw.trim_start().starts_with('[') {
+        if let Ok(Envelope { secrets, request }) = serde_json::from_str::<Envelope>(raw) {
+            return Ok((request, secrets));
+        }
+    }
+    serde_json::from_str::<Input>(raw)
+        .map(|input| (input, Secrets::default()))
+        .map_err(|e| format!("Invalid input ({:?} error at line {} column {})", e.classify(), e.line(), e.column()))
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Read input from stdin
-    let mut input_string = String::new();
+    // Read input from stdin. It may carry secrets inline, so the raw buffer is
+    // wiped as soon as it has been parsed.
+    let mut input_string = Zeroizing::new(String::new());
     io::stdin().read_to_string(&mut input_string)?;
+    run(input_string)
+}
 
-    // Parse input JSON
-    let input: Input = serde_json::from_str(&input_string)?;
+/// Execute one request and print its Output to stdout (shared with the
+/// native `ark-worker` runner)
+pub fn run(input_string: Zeroizing<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let started = Instant::now();
+
+    let parsed = parse_input(&input_string);
+    drop(input_string);
+    let (input, secrets) = match parsed {
+        Ok(parsed) => parsed,
+        Err(error_message) => {
+            let output = Output {
+                error_message: Some(error_message),
+                error_code: Some(ErrorCode::InvalidInput),
+                ..Default::default()
+            };
+            print!("{}", serde_json::to_string(&output)?);
+            io::stdout().flush()?;
+            return Ok(());
+        }
+    };
+    if secrets.is_inline() {
+        eprintln!("🔐 Secrets provided inline: {:?}", secrets);
+    }
+    http::set_api_key(Endpoint::Rpc, secrets.get("RPC_API_KEY"));
+
+    let config = NetworkConfig::from_env()?;
+    eprintln!("🌐 Network: {:?} (relay {}, rpc {})", config.network, config.relay_url, config.rpc_url);
+    near_tx::set_archival_rpc_url(config.archival_rpc_url.clone());
+    NETWORK.get_or_init(|| config);
 
     // Route based on input type
     match input {
-        Input::TestStorage { ref token_contract, .. } => {
+        Input::Action(Action::TestStorage { ref token_contract }) => {
             eprintln!("🧪 Test mode: checking storage for {}", token_contract);
-            handle_test_storage(token_contract)?;
+            handle_test_storage(token_contract, &secrets)?;
+        }
+        Input::Action(Action::RegisterKey { ref swap_contract_id, ref public_key }) => {
+            eprintln!("🔑 Register key mode: {} for {}", public_key, swap_contract_id);
+            handle_register_key(swap_contract_id, public_key, &secrets)?;
+        }
+        Input::Action(Action::Report { ref swap_contract_id }) => {
+            eprintln!("📋 Report mode: balances of {}", swap_contract_id);
+            handle_report(swap_contract_id)?;
         }
-        Input::Withdraw {
+        Input::Action(Action::Withdraw {
             ref token,
             ref receiver_id,
             ref amount,
             ref swap_contract_id,
-            ..
-        } => {
+        }) => {
             eprintln!("🏦 Withdraw mode: {} {} to {}", amount, token, receiver_id);
 
-            // Get swap contract private key from environment
-            let swap_contract_private_key = match env::var("SWAP_CONTRACT_PRIVATE_KEY") {
+            // Get swap contract private key from secrets
+            let swap_contract_private_key = match select_signing_key(&secrets, swap_contract_id) {
                 Ok(key) => key,
-                Err(_) => {
-                    let output = Output {
-                        success: false,
-                        amount_out: None,
-                        error_message: Some("SWAP_CONTRACT_PRIVATE_KEY not found in environment".to_string()),
-                        intent_hash: None,
-                    };
+                Err(e) => {
+                    let output = missing_key_output(e);
                     print!("{}", serde_json::to_string(&output)?);
                     io::stdout().flush()?;
                     return Ok(());
@@ -322,285 +897,753 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             };
 
             // Execute withdraw
-            match withdraw_tokens(
+            let budget = Budget::new(started, None);
+            let started = Instant::now();
+            let mut output = match withdraw_with_retry(
                 swap_contract_id,
                 &swap_contract_private_key,
                 token,
                 receiver_id,
                 amount,
+                &budget,
             ) {
-                Ok(success) => {
-                    let output = Output {
-                        success,
-                        amount_out: Some(amount.clone()),
-                        error_message: if success { None } else { Some("Withdraw failed to settle".to_string()) },
-                        intent_hash: None,
-                    };
-                    print!("{}", serde_json::to_string(&output)?);
-                    io::stdout().flush()?;
+                Ok(WithdrawOutcome::Settled(intent_hash)) => Output {
+                    success: true,
+                    amount_out: Some(amount.clone()),
+                    steps: vec![StepReport {
+                        intent_hash: Some(intent_hash),
+                        ..StepReport::success(SwapStage::Withdraw, started)
+                    }],
+                    ..Default::default()
+                },
+                Ok(WithdrawOutcome::Pending(intent_hash)) => {
+                    withdraw_pending_output(started, amount, None, intent_hash, Vec::new())
                 }
                 Err(e) => {
                     eprintln!("Withdraw execution failed: {:?}", e);
-                    let output = Output {
-                        success: false,
-                        amount_out: None,
-                        error_message: Some(format!("Internal error: {}", e)),
-                        intent_hash: None,
-                    };
-                    print!("{}", serde_json::to_string(&output)?);
-                    io::stdout().flush()?;
+                    let error_message = e.to_string();
+                    Output {
+                        steps: vec![StepReport::failed(SwapStage::Withdraw, started, &error_message)],
+                        error_message: Some(error_message),
+                        error_code: Some(ErrorCode::WithdrawFailed),
+                        failed_stage: Some(SwapStage::Withdraw),
+                        ..Default::default()
+                    }
                 }
-            }
+            };
+            output.collect_artifacts();
+            output.metrics = Some(ExecutionMetrics::collect(started, &output.steps));
+            print!("{}", serde_json::to_string(&output)?);
+            io::stdout().flush()?;
         }
-        Input::Swap {
-            ref sender_id,
-            ref token_in,
-            ref token_out,
-            ref amount_in,
-            ref min_amount_out,
-            ref swap_contract_id,
-        } => {
-            eprintln!("Processing swap for {}: {} {} → {} {}",
-                sender_id, amount_in, token_in, min_amount_out, token_out);
+        Input::Swap(ref swap) => {
+            let budget = Budget::new(started, swap.max_execution_seconds);
 
-            // Get swap contract private key from environment (passed via secrets)
-            let swap_contract_private_key = match env::var("SWAP_CONTRACT_PRIVATE_KEY") {
-                Ok(key) => key,
-                Err(_) => {
-                    let output = Output {
-                        success: false,
-                        amount_out: None,
-                        error_message: Some("SWAP_CONTRACT_PRIVATE_KEY not found in environment".to_string()),
-                        intent_hash: None,
-                    };
-                    print!("{}", serde_json::to_string(&output)?);
-                    io::stdout().flush()?;
-                    return Ok(());
-                }
+            // Get swap contract private key from secrets
+            let output = match select_signing_key(&secrets, &swap.swap_contract_id) {
+                Ok(key) => run_swap(swap, &key, &budget),
+                Err(e) => missing_key_output(e),
             };
+            print!("{}", serde_json::to_string(&output)?);
+            io::stdout().flush()?;
+        }
+    }
 
-            // Execute swap flow
-            match execute_swap(
-                sender_id,
-                token_in,
-                token_out,
-                amount_in,
-                min_amount_out,
-                swap_contract_id,
-                &swap_contract_private_key,
-            ) {
-                Ok(result) => {
-                    print!("{}", serde_json::to_string(&result)?);
-                    io::stdout().flush()?;
-                }
-                Err(e) => {
-                    eprintln!("Swap execution failed: {:?}", e);
-                    let output = Output {
-                        success: false,
-                        amount_out: None,
-                        error_message: Some(format!("Internal error: {}", e)),
-                        intent_hash: None,
-                    };
-                    print!("{}", serde_json::to_string(&output)?);
-                    io::stdout().flush()?;
-                }
+    Ok(())
+}
+
+fn missing_key_output(error_message: String) -> Output {
+    Output {
+        success: false,
+        error_message: Some(error_message),
+        error_code: Some(ErrorCode::KeyMissing),
+        ..Default::default()
+    }
+}
+
+/// The withdrawal was left unfinished when the budget ran out. Not a
+/// failure: the output may still arrive, so the contract must not refund.
+/// The withdraw intent hash (if one was published) is kept for recovery.
+fn withdraw_pending_output(
+    started: Instant,
+    amount_out: &str,
+    intent_hash: Option<String>,
+    withdraw_intent_hash: Option<String>,
+    mut steps: Vec<StepReport>,
+) -> Output {
+    let error_message = match &withdraw_intent_hash {
+        Some(hash) => format!("Withdrawal pending: intent {} had not settled when the execution budget ran out", hash),
+        None => "Withdrawal pending: the execution budget ran out before it was published".to_string(),
+    };
+    steps.push(StepReport {
+        intent_hash: withdraw_intent_hash.clone(),
+        ..StepReport::failed(SwapStage::Withdraw, started, &error_message)
+    });
+    Output {
+        success: false,
+        amount_out: Some(amount_out.to_string()),
+        error_message: Some(error_message),
+        intent_hash,
+        error_code: Some(ErrorCode::WithdrawPending),
+        failed_stage: Some(SwapStage::Withdraw),
+        withdraw_intent_hash,
+        steps,
+        ..Default::default()
+    }
+}
+
+/// Wall-clock budget for the whole execution. OutLayer kills the worker after
+/// max_execution_seconds, so each stage checks there is still time to finish
+/// the rest of the flow before starting it.
+struct Budget {
+    deadline: Instant,
+}
+
+impl Budget {
+    fn new(started: Instant, max_execution_seconds: Option<u64>) -> Self {
+        let seconds = max_execution_seconds
+            .unwrap_or_else(|| env_or("MAX_EXECUTION_SECONDS", DEFAULT_MAX_EXECUTION_SECONDS));
+        let usable = Duration::from_secs(seconds.saturating_sub(BUDGET_OUTPUT_MARGIN_SECONDS));
+        Budget { deadline: started + usable }
+    }
+
+    fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    /// Fails if there isn't enough time left to run `stage` and everything after it
+    fn check(&self, stage: SwapStage) -> Result<(), String> {
+        let remaining = self.remaining();
+        let needed = stage.time_to_finish();
+        if remaining < needed {
+            return Err(format!(
+                "Execution budget exhausted before {:?}: {}s left, {}s needed to finish",
+                stage,
+                remaining.as_secs(),
+                needed.as_secs()
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn budget_exhausted_output(stage: SwapStage, error_message: String, mut steps: Vec<StepReport>) -> Output {
+    eprintln!("⏱️  {}", error_message);
+    steps.push(StepReport::failed(stage, Instant::now(), &error_message));
+    Output {
+        success: false,
+        error_message: Some(error_message),
+        error_code: Some(ErrorCode::BudgetExhausted),
+        failed_stage: Some(stage),
+        steps,
+        ..Default::default()
+    }
+}
+
+/// Run one swap, turning internal errors into a failed Output
+fn run_swap(swap: &SwapInput, swap_contract_private_key: &str, budget: &Budget) -> Output {
+    eprintln!("Processing swap for {}: {} {} → {} {}",
+        swap.sender_id, swap.amount_in, swap.token_in, swap.min_amount_out, swap.token_out);
+
+    let started = Instant::now();
+    let mut output = match execute_swap(swap, swap_contract_private_key, budget) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("Swap execution failed: {:?}", e);
+            Output {
+                success: false,
+                error_message: Some(format!("Internal error: {}", e)),
+                error_code: Some(ErrorCode::InternalError),
+                ..Default::default()
             }
         }
+    };
+    output.collect_artifacts();
+    output.token_in = Some(swap.token_in.clone());
+    output.token_out = Some(swap.token_out.clone());
+    output.metrics = Some(ExecutionMetrics::collect(started, &output.steps));
+
+    if !output.success {
+        webhook::notify(&ProgressUpdate {
+            amount_out: output.amount_out.as_deref(),
+            error_code: output.error_code,
+            error_message: output.error_message.as_deref(),
+            ..ProgressUpdate::new(ProgressEvent::Failed, swap, &output.steps)
+        });
     }
+    output
+}
 
-    Ok(())
+/// Report a completed stage to the progress webhook, if configured
+fn notify_progress(swap: &SwapInput, event: ProgressEvent, steps: &[StepReport], amount_out: Option<&str>) {
+    webhook::notify(&ProgressUpdate {
+        amount_out,
+        ..ProgressUpdate::new(event, swap, steps)
+    });
 }
 
 fn execute_swap(
-    sender_id: &str,
-    token_in: &str,
-    token_out: &str,
-    amount_in: &str,
-    min_amount_out: &str,
-    swap_contract_id: &str,
+    swap: &SwapInput,
     swap_contract_private_key: &str,
+    budget: &Budget,
 ) -> Result<Output, Box<dyn std::error::Error>> {
+    let SwapInput {
+        sender_id,
+        token_in,
+        token_out,
+        amount_in,
+        min_amount_out,
+        swap_contract_id,
+        slippage_buffer_bps,
+        referral,
+        metadata,
+        max_execution_seconds: _,
+        register_storage,
+    } = swap;
+
+    // Validate input before spending anything on quotes or transactions
+    let (Some(token_in_contract), Some(token_out_contract)) =
+        (token_in.strip_prefix("nep141:"), token_out.strip_prefix("nep141:"))
+    else {
+        return Ok(Output {
+            success: false,
+            error_message: Some(format!(
+                "Invalid swap input: expected nep141:address tokens, got {} → {}",
+                token_in, token_out
+            )),
+            error_code: Some(ErrorCode::InvalidInput),
+            ..Default::default()
+        });
+    };
+    let (amount_in_num, min_amount_out_num) =
+        match amount::parse(amount_in).and_then(|a| Ok((a, amount::parse(min_amount_out)?))) {
+            Ok(amounts) => amounts,
+            Err(e) => {
+                return Ok(Output {
+                    success: false,
+                    error_message: Some(format!("Invalid swap input: {}", e)),
+                    error_code: Some(ErrorCode::InvalidInput),
+                    ..Default::default()
+                });
+            }
+        };
+
+    let slippage_buffer_bps = slippage_buffer_bps.unwrap_or(0);
+    if slippage_buffer_bps > MAX_SLIPPAGE_BUFFER_BPS {
+        return Ok(Output {
+            success: false,
+            error_message: Some(format!(
+                "Invalid swap input: slippage_buffer_bps {} exceeds {}",
+                slippage_buffer_bps, MAX_SLIPPAGE_BUFFER_BPS
+            )),
+            error_code: Some(ErrorCode::InvalidInput),
+            ..Default::default()
+        });
+    }
+
+    let mut steps = Vec::new();
+
+    if let Err(error_message) = budget.check(SwapStage::Quote) {
+        return Ok(budget_exhausted_output(SwapStage::Quote, error_message, steps));
+    }
+
     // Step 1: Get quote
     eprintln!("Step 1: Getting quote from NEAR Intents API");
-    let quote = get_quote(token_in, token_out, amount_in)?;
+    let started = Instant::now();
+    let attribution = RelayAttribution {
+        referral: referral.clone(),
+        metadata: metadata.clone(),
+    };
+
+    let required_amount_out = add_bps(min_amount_out_num, slippage_buffer_bps);
+    let max_splits =
+        env_or("MAX_QUOTE_SPLITS", DEFAULT_MAX_QUOTE_SPLITS).clamp(1, MAX_QUOTE_SPLITS_LIMIT);
+
+    let best_quote = get_quotes(token_in, token_out, amount_in, &attribution)
+        .map(|mut quotes| quotes.remove(0));
+
+    // When the best single quote is missing or falls short, try filling the
+    // swap from several solvers instead
+    let single_quote_fills = matches!(
+        &best_quote,
+        Ok(quote) if amount::parse(&quote.amount_out).is_ok_and(|out| out >= required_amount_out)
+    );
+    let split = if max_splits > 1 && !single_quote_fills {
+        eprintln!("🔀 No single quote fills the swap, trying up to {} parts", max_splits);
+        get_split_quotes(token_in, token_out, amount_in_num, max_splits, &attribution)
+    } else {
+        None
+    };
 
-    let amount_out_num: u128 = quote.amount_out.parse()
-        .map_err(|_| "Failed to parse amount_out")?;
-    let min_amount_out_num: u128 = min_amount_out.parse()
-        .map_err(|_| "Failed to parse min_amount_out")?;
+    let quotes = match (best_quote, split) {
+        // Only split if it actually beats the single quote
+        (Ok(quote), Some(split)) => {
+            if total_amount_out(&split) > amount::parse(&quote.amount_out).ok() {
+                split
+            } else {
+                vec![quote]
+            }
+        }
+        (Err(_), Some(split)) => split,
+        (Ok(quote), None) => vec![quote],
+        (Err(e), None) => {
+            let error_message = e.to_string();
+            steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
+            return Ok(Output {
+                success: false,
+                error_message: Some(error_message),
+                error_code: Some(ErrorCode::QuoteUnavailable),
+                failed_stage: Some(SwapStage::Quote),
+                steps,
+                ..Default::default()
+            });
+        }
+    };
 
-    if amount_out_num < min_amount_out_num {
+    let Some(amount_out_num) = total_amount_out(&quotes) else {
+        let amounts: Vec<&str> = quotes.iter().map(|q| q.amount_out.as_str()).collect();
+        let error_message = format!("Failed to parse quote amount_out: {}", amounts.join(", "));
+        steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
         return Ok(Output {
             success: false,
-            amount_out: None,
-            error_message: Some(format!(
+            error_message: Some(error_message),
+            error_code: Some(ErrorCode::QuoteUnavailable),
+            failed_stage: Some(SwapStage::Quote),
+            steps,
+            ..Default::default()
+        });
+    };
+    let amount_out = amount_out_num.to_string();
+
+    if amount_out_num < required_amount_out {
+        let error_message = if slippage_buffer_bps > 0 {
+            format!(
+                "Insufficient liquidity: {} < {} (min_amount_out {} + {} bps slippage buffer)",
+                amount_out_num, required_amount_out, min_amount_out_num, slippage_buffer_bps
+            )
+        } else {
+            format!(
                 "Insufficient liquidity: {} < {}",
                 amount_out_num, min_amount_out_num
-            )),
-            intent_hash: None,
+            )
+        };
+        steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
+        return Ok(Output {
+            success: false,
+            error_message: Some(error_message),
+            error_code: Some(ErrorCode::InsufficientLiquidity),
+            failed_stage: Some(SwapStage::Quote),
+            steps,
+            ..Default::default()
+        });
+    }
+
+    // Price-impact guard: compare against the rate quoted for a small probe amount
+    if let Some(max_impact_bps) = env_opt::<u32>("MAX_PRICE_IMPACT_BPS") {
+        match probe_price_impact_bps(token_in, token_out, amount_in_num, amount_out_num, &attribution) {
+            Ok(impact_bps) if impact_bps > max_impact_bps as f64 => {
+                let error_message = format!(
+                    "Price impact too high: {:.0} bps > {} bps (vs probe quote)",
+                    impact_bps, max_impact_bps
+                );
+                steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
+                return Ok(Output {
+                    success: false,
+                    error_message: Some(error_message),
+                    error_code: Some(ErrorCode::PriceImpactTooHigh),
+                    failed_stage: Some(SwapStage::Quote),
+                    steps,
+                    ..Default::default()
+                });
+            }
+            Ok(impact_bps) => {
+                eprintln!("✅ Price impact {:.0} bps within {} bps", impact_bps, max_impact_bps);
+            }
+            Err(e) => {
+                // A missing reference shouldn't block the swap; min_amount_out still applies
+                eprintln!("⚠️  Warning: Could not check price impact ({}). Proceeding anyway...", e);
+            }
+        }
+    }
+
+    let deadline_ms = match swap_deadline(&quotes) {
+        Ok(deadline_ms) => deadline_ms,
+        Err(error_message) => {
+            steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
+            return Ok(Output {
+                success: false,
+                error_message: Some(error_message),
+                error_code: Some(ErrorCode::QuoteUnavailable),
+                failed_stage: Some(SwapStage::Quote),
+                steps,
+                ..Default::default()
+            });
+        }
+    };
+    let deadline = iso8601::format_millis(deadline_ms);
+
+    // Deposit, publish and settlement all have to happen before the intent
+    // deadline. Nothing has moved yet, so the contract can retry with fresh quotes.
+    let expected_settlement_ms =
+        env_or("EXPECTED_SETTLEMENT_SECONDS", DEFAULT_EXPECTED_SETTLEMENT_SECONDS) * 1000;
+    let validity_ms = deadline_ms.saturating_sub(iso8601::now_millis());
+    if validity_ms < expected_settlement_ms {
+        let error_message = format!(
+            "Quote valid for {} ms, less than the expected settlement time of {} ms",
+            validity_ms, expected_settlement_ms
+        );
+        steps.push(StepReport::failed(SwapStage::Quote, started, &error_message));
+        return Ok(Output {
+            success: false,
+            error_message: Some(error_message),
+            error_code: Some(ErrorCode::QuoteTooShort),
+            failed_stage: Some(SwapStage::Quote),
+            steps,
+            ..Default::default()
         });
     }
 
-    eprintln!("✅ Quote received: {} out, expires at {}", quote.amount_out, quote.expiration_time);
+    let quote_hash = quotes.iter().map(|q| q.quote_hash.as_str()).collect::<Vec<_>>().join(",");
+    steps.push(StepReport {
+        quote_hash: Some(quote_hash),
+        ..StepReport::success(SwapStage::Quote, started)
+    });
+    eprintln!("✅ Quote received: {} {} out from {} quote(s) (intent deadline {})",
+        display_amount(amount_out_num, token_out_contract), token_out_contract, quotes.len(), deadline);
+    notify_progress(swap, ProgressEvent::Quoted, &steps, Some(&amount_out));
 
     // Get RPC URL from environment
-    let rpc_url = std::env::var("NEAR_RPC_URL")
-        .unwrap_or_else(|_| "https://rpc.mainnet.near.org".to_string());
+    let rpc = rpc();
+    // One signer for the swap's transactions, so the nonce is fetched once
+    let signer = near_tx::TxSigner::new(&rpc, swap_contract_id, swap_contract_private_key)?;
+
+    if let Err(error_message) = budget.check(SwapStage::StorageCheck) {
+        return Ok(budget_exhausted_output(SwapStage::StorageCheck, error_message, steps));
+    }
 
     // Step 1.5: Pre-flight check - verify sender has storage deposit for output token
     eprintln!("Step 1.5: Checking storage deposit for output token...");
-
-    // Extract token contract from defuse asset ID
-    let token_out_contract = token_out.strip_prefix("nep141:")
-        .ok_or("Invalid token_out format, expected nep141:address")?;
+    let started = Instant::now();
 
     let storage_check_args = serde_json::json!({
         "account_id": sender_id
     });
 
     match near_tx::view(
-        &rpc_url,
+        &rpc,
         token_out_contract,
         "storage_balance_of",
         &storage_check_args.to_string(),
     ) {
         Ok(result_str) => {
-            let balance_json: serde_json::Value = serde_json::from_str(&result_str)
-                .map_err(|_| "Failed to parse storage balance response")?;
+            let Ok(balance_json) = serde_json::from_str::<serde_json::Value>(&result_str) else {
+                let error_message = format!("Failed to parse storage balance response: {}", result_str);
+                steps.push(StepReport::failed(SwapStage::StorageCheck, started, &error_message));
+                return Ok(Output {
+                    success: false,
+                    error_message: Some(error_message),
+                    error_code: Some(ErrorCode::RpcError),
+                    failed_stage: Some(SwapStage::StorageCheck),
+                    steps,
+                    ..Default::default()
+                });
+            };
 
-            if balance_json.is_null() {
+            if balance_json.is_null() && !*register_storage {
                 eprintln!("❌ Pre-flight check failed: sender {} has no storage deposit for {}",
                     sender_id, token_out_contract);
+                let error_message = format!(
+                    "User {} has no storage deposit for output token {}. Please call storage_deposit first.",
+                    sender_id, token_out_contract
+                );
+                steps.push(StepReport::failed(SwapStage::StorageCheck, started, &error_message));
                 return Ok(Output {
                     success: false,
-                    amount_out: None,
-                    error_message: Some(format!(
-                        "User {} has no storage deposit for output token {}. Please call storage_deposit first.",
-                        sender_id, token_out_contract
-                    )),
-                    intent_hash: None,
+                    error_message: Some(error_message),
+                    error_code: Some(ErrorCode::NoStorage),
+                    failed_stage: Some(SwapStage::StorageCheck),
+                    steps,
+                    ..Default::default()
                 });
             }
-            eprintln!("✅ Storage deposit verified for {}", sender_id);
+
+            if balance_json.is_null() {
+                // Auto-registration enabled by the contract: pay for the sender's storage
+                eprintln!("📝 Registering {} with {} (paid by {})",
+                    sender_id, token_out_contract, swap_contract_id);
+                match signer.storage_deposit(token_out_contract, Some(sender_id), true) {
+                    Ok(near_tx::TxOutcome { tx_hash, .. }) => {
+                        eprintln!("✅ Storage registered: {}", tx_hash);
+                        steps.push(StepReport {
+                            tx_hash: Some(tx_hash),
+                            ..StepReport::success(SwapStage::StorageCheck, started)
+                        });
+                    }
+                    Err(e) => {
+                        let error_message = format!(
+                            "Failed to register {} with output token {}: {}",
+                            sender_id, token_out_contract, e
+                        );
+                        steps.push(StepReport::failed(SwapStage::StorageCheck, started, &error_message));
+                        return Ok(Output {
+                            success: false,
+                            error_message: Some(error_message),
+                            error_code: Some(ErrorCode::NoStorage),
+                            failed_stage: Some(SwapStage::StorageCheck),
+                            steps,
+                            ..Default::default()
+                        });
+                    }
+                }
+            } else {
+                steps.push(StepReport::success(SwapStage::StorageCheck, started));
+                eprintln!("✅ Storage deposit verified for {}", sender_id);
+            }
         }
         Err(e) => {
             eprintln!("⚠️  Warning: Could not verify storage deposit ({}). Proceeding anyway...", e);
             // Continue - storage check failure shouldn't block swap in production
+            steps.push(StepReport {
+                error: Some(e.to_string()),
+                ..StepReport::new(SwapStage::StorageCheck, StepStatus::Skipped, started)
+            });
         }
     }
 
-    // Step 2: Deposit tokens to intents.near
-    eprintln!("Step 2: Depositing {} to intents.near", amount_in);
+    // Don't deposit unless there is time to settle and withdraw
+    if let Err(error_message) = budget.check(SwapStage::Deposit) {
+        return Ok(budget_exhausted_output(SwapStage::Deposit, error_message, steps));
+    }
 
-    // Extract token contract address from defuse asset ID (format: "nep141:token.near")
-    let token_contract = token_in.strip_prefix("nep141:")
-        .ok_or("Invalid token_in format, expected nep141:address")?;
+    // Step 2: Deposit tokens to intents.near
+    eprintln!("Step 2: Depositing {} to {}", amount_in, network().intents_contract);
+    let started = Instant::now();
 
     eprintln!("📤 Calling ft_transfer_call: {} {} from {} to {}",
-        amount_in, token_contract, swap_contract_id, INTENTS_CONTRACT);
+        amount_in, token_in_contract, swap_contract_id, network().intents_contract);
+
+    match signer.ft_transfer_call(token_in_contract, &network().intents_contract, amount_in, "") {
+        Ok(outcome) => {
+            // ft_transfer_call returns the amount the receiver kept; the rest was refunded
+            match outcome.success_json::<String>() {
+                Ok(Some(used)) if &used != amount_in => {
+                    let error_message = format!(
+                        "Deposit refunded by {}: {} of {} accepted (tx {})",
+                        network().intents_contract, used, amount_in, outcome.tx_hash
+                    );
+                    eprintln!("❌ {}", error_message);
+                    steps.push(StepReport {
+                        tx_hash: Some(outcome.tx_hash),
+                        ..StepReport::failed(SwapStage::Deposit, started, &error_message)
+                    });
+                    return Ok(Output {
+                        success: false,
+                        error_message: Some(error_message),
+                        error_code: Some(ErrorCode::DepositFailed),
+                        failed_stage: Some(SwapStage::Deposit),
+                        steps,
+                        ..Default::default()
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("⚠️  Could not read deposited amount: {}", e),
+            }
 
-    let deposit_tx_hash = match near_tx::ft_transfer_call(
-        &rpc_url,
-        swap_contract_id,
-        swap_contract_private_key,
-        token_contract,
-        INTENTS_CONTRACT,
-        amount_in,
-        "",
-    ) {
-        Ok(tx_hash) => {
-            eprintln!("✅ Deposit successful: {}", tx_hash);
-            eprintln!("   🔗 View on explorer: https://nearblocks.io/txns/{}", tx_hash);
-            tx_hash
+            // broadcast_tx_commit returns once the deposit executed optimistically;
+            // optionally wait for it to become final before the intent trades on
+            // it. The input is in intents.near either way, so a miss is only
+            // logged: failing here would have the contract refund an input it
+            // no longer holds
+            let finality_timeout = env_opt::<u64>("DEPOSIT_FINALITY_TIMEOUT_SECONDS")
+                .filter(|_| outcome.finality < near_tx::TxFinality::Final);
+            if let Some(seconds) = finality_timeout {
+                let timeout = Duration::from_secs(seconds).min(budget.remaining());
+                if let Err(e) = near_tx::wait_for_finality(
+                    &rpc, &outcome.tx_hash, swap_contract_id, near_tx::TxFinality::Final, timeout,
+                ) {
+                    eprintln!("⚠️  Deposit executed but not final, publishing the intent anyway: {}", e);
+                }
+            }
+
+            let events: Vec<String> =
+                outcome.events().iter().map(|e| format!("{}/{}", e.standard, e.event)).collect();
+            if !events.is_empty() {
+                eprintln!("📜 Deposit events: {}", events.join(", "));
+            }
+            let tx_hash = outcome.tx_hash;
+            eprintln!("✅ Deposit successful: {} (gas burnt {})", tx_hash, outcome.gas_burnt);
+            eprintln!("   🔗 View on explorer: {}", network().tx_url(&tx_hash));
+            steps.push(StepReport {
+                tx_hash: Some(tx_hash),
+                ..StepReport::success(SwapStage::Deposit, started)
+            });
+            notify_progress(swap, ProgressEvent::Deposited, &steps, Some(&amount_out));
         }
         Err(e) => {
             eprintln!("❌ Deposit failed: {}", e);
-            return Err(e);
+            let error_message = format!("Deposit failed: {}", e);
+            // The transaction may still have landed if only the response was lost
+            let tx_hash = signer.last_tx_hash();
+            if let Some(tx_hash) = &tx_hash {
+                eprintln!("   🔗 Check on explorer: {}", network().tx_url(tx_hash));
+            }
+            steps.push(StepReport {
+                tx_hash,
+                ..StepReport::failed(SwapStage::Deposit, started, &error_message)
+            });
+            return Ok(Output {
+                success: false,
+                error_message: Some(error_message),
+                error_code: Some(ErrorCode::DepositFailed),
+                failed_stage: Some(SwapStage::Deposit),
+                steps,
+                ..Default::default()
+            });
         }
-    };
+    }
+
+    // Tokens are in intents.near now; stop before swapping if time is short
+    if let Err(error_message) = budget.check(SwapStage::Publish) {
+        return Ok(budget_exhausted_output(SwapStage::Publish, error_message, steps));
+    }
 
     // Step 3: Publish swap intent
     eprintln!("Step 3: Publishing swap intent to NEAR Intents API");
-    eprintln!("   Swap: {} {} → {} {}", quote.amount_in, token_in, quote.amount_out, token_out);
+    eprintln!("   Swap: {} {} → {} {}", amount_in, token_in, amount_out, token_out);
+    let started = Instant::now();
 
     let intent_hash = match publish_swap_intent(
         swap_contract_id,
         swap_contract_private_key,
         token_in,
         token_out,
-        &quote,
+        &quotes,
+        &deadline,
+        &attribution,
     ) {
         Ok(hash) => {
             eprintln!("✅ Intent published successfully");
             eprintln!("   Intent hash: {}", hash);
+            steps.push(StepReport {
+                intent_hash: Some(hash.clone()),
+                ..StepReport::success(SwapStage::Publish, started)
+            });
+            notify_progress(swap, ProgressEvent::Published, &steps, Some(&amount_out));
             hash
         }
         Err(e) => {
             eprintln!("❌ Failed to publish intent: {}", e);
-            return Err(e);
+            let error_message = format!("Failed to publish intent: {}", e);
+            steps.push(StepReport::failed(SwapStage::Publish, started, &error_message));
+            return Ok(Output {
+                success: false,
+                error_message: Some(error_message),
+                error_code: Some(ErrorCode::IntentNotSettled),
+                failed_stage: Some(SwapStage::Publish),
+                steps,
+                ..Default::default()
+            });
         }
     };
 
-    // Step 4: Wait for settlement
-    eprintln!("Step 4: Waiting for intent settlement (max 30 seconds)...");
-
-    let settled = match wait_for_settlement(&intent_hash) {
-        Ok(s) => s,
+    // Step 4: Wait for settlement, leaving enough of the budget to withdraw
+    let settle_window = budget
+        .remaining()
+        .saturating_sub(SwapStage::Withdraw.estimate())
+        .min(SETTLE_POLL_INTERVAL * SETTLE_POLL_ATTEMPTS);
+    let settle_attempts = (settle_window.as_millis() / SETTLE_POLL_INTERVAL.as_millis()).max(1) as u32;
+    eprintln!("Step 4: Waiting for intent settlement (max {} seconds)...", settle_window.as_secs());
+    let started = Instant::now();
+
+    let settle_error = match wait_for_settlement_with_timeout(&intent_hash, settle_attempts) {
+        Ok(true) => None,
+        Ok(false) => {
+            eprintln!("❌ Intent failed to settle within {} second timeout", settle_window.as_secs());
+            eprintln!("   Intent hash: {}", intent_hash);
+            Some("Intent failed to settle within timeout".to_string())
+        }
         Err(e) => {
             eprintln!("❌ Error checking settlement status: {}", e);
-            return Err(e);
+            Some(format!("Error checking settlement status: {}", e))
         }
     };
 
-    if !settled {
-        eprintln!("❌ Intent failed to settle within 30 second timeout");
-        eprintln!("   Intent hash: {}", intent_hash);
+    if let Some(error_message) = settle_error {
+        steps.push(StepReport::failed(SwapStage::Settle, started, &error_message));
         return Ok(Output {
             success: false,
-            amount_out: None,
-            error_message: Some("Intent failed to settle within timeout".to_string()),
+            error_message: Some(error_message),
             intent_hash: Some(intent_hash),
+            error_code: Some(ErrorCode::IntentNotSettled),
+            failed_stage: Some(SwapStage::Settle),
+            steps,
+            ..Default::default()
         });
     }
 
+    steps.push(StepReport::success(SwapStage::Settle, started));
     eprintln!("✅ Intent settled successfully!");
+    notify_progress(swap, ProgressEvent::Settled, &steps, Some(&amount_out));
 
     // Step 5: Withdraw tokens back to swap contract (NOT to original sender!)
-    eprintln!("Step 5: Withdrawing {} {} to swap contract {}", quote.amount_out, token_out, swap_contract_id);
+    eprintln!("Step 5: Withdrawing {} {} to swap contract {}", amount_out, token_out, swap_contract_id);
+    let started = Instant::now();
 
-    let withdraw_success = match withdraw_tokens(
+    // The swap has settled at this point, so a withdrawal failure must not be
+    // reported as a generic error: only the withdrawal is retried, and the
+    // Output keeps the intent hash so the funds can be recovered.
+    let withdraw_intent_hash = match withdraw_with_retry(
         swap_contract_id,
         swap_contract_private_key,
         token_out,
         swap_contract_id, // Withdraw to swap contract, not sender!
-        &quote.amount_out,
+        &amount_out,
+        budget,
     ) {
-        Ok(success) => success,
+        Ok(WithdrawOutcome::Settled(hash)) => hash,
+        Ok(WithdrawOutcome::Pending(withdraw_intent_hash)) => {
+            eprintln!("⏳ Withdrawal still pending when the execution budget ran out");
+            return Ok(withdraw_pending_output(
+                started,
+                &amount_out,
+                Some(intent_hash),
+                withdraw_intent_hash,
+                steps,
+            ));
+        }
         Err(e) => {
             eprintln!("❌ Withdrawal failed: {}", e);
-            return Err(e);
+            let error_message = format!("Swap settled but withdrawal failed: {}", e);
+            steps.push(StepReport::failed(SwapStage::Withdraw, started, &error_message));
+            return Ok(Output {
+                success: false,
+                amount_out: Some(amount_out),
+                error_message: Some(error_message),
+                intent_hash: Some(intent_hash),
+                error_code: Some(ErrorCode::WithdrawFailed),
+                failed_stage: Some(SwapStage::Withdraw),
+                steps,
+                ..Default::default()
+            });
         }
     };
 
-    if !withdraw_success {
-        eprintln!("❌ Withdrawal returned failure status");
-        return Ok(Output {
-            success: false,
-            amount_out: Some(quote.amount_out.clone()),
-            error_message: Some("Failed to withdraw tokens from intents contract".to_string()),
-            intent_hash: Some(intent_hash),
-        });
-    }
-
+    steps.push(StepReport {
+        intent_hash: Some(withdraw_intent_hash),
+        ..StepReport::success(SwapStage::Withdraw, started)
+    });
     eprintln!("✅ Withdrawal successful!");
+    notify_progress(swap, ProgressEvent::Withdrawn, &steps, Some(&amount_out));
     eprintln!("🎉 Swap completed successfully: {} {} → {} {}",
-        quote.amount_in, token_in, quote.amount_out, token_out);
+        display_amount(amount_in_num, token_in_contract), token_in_contract,
+        display_amount(amount_out_num, token_out_contract), token_out_contract);
 
     Ok(Output {
         success: true,
-        amount_out: Some(quote.amount_out.clone()),
-        error_message: None,
+        amount_out: Some(amount_out),
         intent_hash: Some(intent_hash),
+        steps,
+        ..Default::default()
     })
 }
 
@@ -608,11 +1651,13 @@ fn execute_swap(
 // NEAR Intents API Functions
 // ============================================================================
 
-fn get_quote(
+/// All quotes the relay returned for `amount_in`, best (highest amount_out) first
+fn get_quotes(
     token_in: &str,
     token_out: &str,
     amount_in: &str,
-) -> Result<Quote, Box<dyn std::error::Error>> {
+    attribution: &RelayAttribution,
+) -> Result<Vec<Quote>, Box<dyn std::error::Error>> {
     let request = JsonRpcRequest {
         id: 1,
         jsonrpc: "2.0".to_string(),
@@ -621,6 +1666,7 @@ fn get_quote(
             defuse_asset_identifier_in: token_in.to_string(),
             defuse_asset_identifier_out: token_out.to_string(),
             exact_amount_in: amount_in.to_string(),
+            attribution: attribution.clone(),
         }],
     };
 
@@ -632,16 +1678,13 @@ fn get_quote(
 
     for attempt in 1..=MAX_RETRIES {
         eprintln!("🔄 Quote API attempt {}/{}", attempt, MAX_RETRIES);
+        if attempt > 1 {
+            metrics::record_retry("quote");
+        }
 
-        match Client::new()
-            .post(INTENTS_API_URL)
-            .header("Content-Type", "application/json")
-            .connect_timeout(Duration::from_secs(10))
-            .body(serde_json::to_string(&request)?.as_bytes())
-            .send()
-        {
+        match http::post_json(Endpoint::Relay, &network().relay_url, &request) {
             Ok(response) => {
-                let status = response.status();
+                let status = response.status;
                 if status != 200 {
                     last_error = format!("Quote API returned status {}", status);
                     eprintln!("⚠️  Attempt {} failed: {}", attempt, last_error);
@@ -650,39 +1693,42 @@ fn get_quote(
                         continue;
                     }
                 } else {
-                    match response.body() {
-                        Ok(body) => {
-                            match serde_json::from_slice::<JsonRpcResponse<Vec<Quote>>>(&body) {
-                                Ok(json_response) => {
-                                    if let Some(error) = json_response.error {
-                                        last_error = format!("Quote API error: {}", error.message);
-                                        eprintln!("⚠️  Attempt {} failed: {}", attempt, last_error);
-                                        if attempt < MAX_RETRIES {
-                                            std::thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
-                                            continue;
-                                        }
-                                    } else if let Some(quotes) = json_response.result {
-                                        // Find best quote (highest amount_out)
-                                        if let Some(best_quote) = quotes
-                                            .into_iter()
-                                            .max_by_key(|q| q.amount_out.parse::<u128>().unwrap_or(0))
-                                        {
-                                            eprintln!("✅ Quote received successfully");
-                                            return Ok(best_quote);
-                                        } else {
-                                            last_error = "No valid quotes".to_string();
-                                        }
-                                    } else {
-                                        last_error = "No quotes returned".to_string();
-                                    }
+                    match serde_json::from_slice::<JsonRpcResponse<Vec<Quote>>>(&response.body) {
+                        Ok(json_response) => {
+                            if let Some(error) = json_response.error {
+                                last_error = format!("Quote API error: {}", error.message);
+                                eprintln!("⚠️  Attempt {} failed: {}", attempt, last_error);
+                                if attempt < MAX_RETRIES {
+                                    std::thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
+                                    continue;
                                 }
-                                Err(e) => {
-                                    last_error = format!("Failed to parse response: {}", e);
+                            } else if let Some(quotes) = json_response.result {
+                                // Drop quotes with malformed amounts rather than ranking them as zero
+                                let mut quotes: Vec<(u128, Quote)> = quotes
+                                    .into_iter()
+                                    .filter_map(|q| match amount::parse(&q.amount_out) {
+                                        Ok(out) => Some((out, q)),
+                                        Err(e) => {
+                                            eprintln!("⚠️  Ignoring quote {}: {}", q.quote_hash, e);
+                                            None
+                                        }
+                                    })
+                                    .collect();
+                                if quotes.is_empty() {
+                                    last_error = "No valid quotes".to_string();
+                                } else {
+                                    // Best quote (highest amount_out) first
+                                    quotes.sort_by_key(|(out, _)| std::cmp::Reverse(*out));
+                                    let quotes: Vec<Quote> = quotes.into_iter().map(|(_, q)| q).collect();
+                                    eprintln!("✅ {} quote(s) received successfully", quotes.len());
+                                    return Ok(quotes);
                                 }
+                            } else {
+                                last_error = "No quotes returned".to_string();
                             }
                         }
                         Err(e) => {
-                            last_error = format!("Failed to read response body: {}", e);
+                            last_error = format!("Failed to parse response: {}", e);
                         }
                     }
                 }
@@ -701,28 +1747,119 @@ fn get_quote(
     Err(format!("Quote API failed after {} retries. Last error: {}", MAX_RETRIES, last_error).into())
 }
 
+/// Fill `amount_in` from several solver quotes when no single quote is good
+/// enough. Tries 2..=max_splits equal parts and returns the split with the
+/// highest total amount_out, or None if no split could be fully quoted.
+fn get_split_quotes(
+    token_in: &str,
+    token_out: &str,
+    amount_in: u128,
+    max_splits: u32,
+    attribution: &RelayAttribution,
+) -> Option<Vec<Quote>> {
+    let mut best: Option<(u128, Vec<Quote>)> = None;
+
+    for parts in 2..=max_splits {
+        let Some(amounts) = split_amounts(amount_in, parts) else {
+            break;
+        };
+        let Some(quotes) = quote_parts(token_in, token_out, &amounts, attribution) else {
+            eprintln!("⚠️  Not enough quotes to split into {} parts", parts);
+            continue;
+        };
+        let Some(total) = total_amount_out(&quotes) else {
+            continue;
+        };
+
+        eprintln!("   Split into {} parts: {} out", parts, total);
+        if best.as_ref().is_none_or(|(best_total, _)| total > *best_total) {
+            best = Some((total, quotes));
+        }
+    }
+
+    best.map(|(_, quotes)| quotes)
+}
+
+/// One distinct solver quote per entry in `amounts`, taking the best remaining
+/// quote each time. None if the relay doesn't return enough of them.
+fn quote_parts(
+    token_in: &str,
+    token_out: &str,
+    amounts: &[u128],
+    attribution: &RelayAttribution,
+) -> Option<Vec<Quote>> {
+    let mut offers: Vec<(u128, Vec<Quote>)> = Vec::new();
+    let mut quotes = Vec::with_capacity(amounts.len());
+
+    for &amount in amounts {
+        // Parts of equal size share one quote request
+        if !offers.iter().any(|(offered, _)| *offered == amount) {
+            let fetched = get_quotes(token_in, token_out, &amount.to_string(), attribution).ok()?;
+            offers.push((amount, fetched));
+        }
+        let (_, available) = offers.iter_mut().find(|(offered, _)| *offered == amount)?;
+        if available.is_empty() {
+            return None;
+        }
+        quotes.push(available.remove(0));
+    }
+
+    Some(quotes)
+}
+
+/// Price impact of swapping `amount_in` for `amount_out`, in basis points,
+/// relative to the rate quoted for a probe of amount_in / PRICE_IMPACT_PROBE_DIVISOR
+fn probe_price_impact_bps(
+    token_in: &str,
+    token_out: &str,
+    amount_in: u128,
+    amount_out: u128,
+    attribution: &RelayAttribution,
+) -> Result<f64, Box<dyn std::error::Error>> {
+    let divisor = env_or("PRICE_IMPACT_PROBE_DIVISOR", DEFAULT_PRICE_IMPACT_PROBE_DIVISOR).max(1);
+    let probe_in = amount_in / divisor;
+    if probe_in == 0 {
+        return Err(format!("amount_in {} is too small to probe", amount_in).into());
+    }
+
+    let probe = get_quotes(token_in, token_out, &probe_in.to_string(), attribution)?.remove(0);
+    let probe_out = amount::parse(&probe.amount_out)?;
+    eprintln!("   Probe quote: {} → {}", probe_in, probe_out);
+
+    price_impact_bps(amount_in, amount_out, probe_in, probe_out)
+        .ok_or_else(|| "Probe quote returned zero amount_out".into())
+}
+
 fn publish_swap_intent(
     signer_id: &str,
     private_key: &str,
     token_in: &str,
     token_out: &str,
-    quote: &Quote,
+    quotes: &[Quote],
+    deadline: &str,
+    attribution: &RelayAttribution,
 ) -> Result<String, Box<dyn std::error::Error>> {
     // Use tokens WITH "nep141:" prefix (as per official docs)
     // https://docs.near-intents.org/near-intents/market-makers/bus/solver-relay
 
-    // Build intent message using json! macro to preserve field order
-    let diff = serde_json::json!({
-        token_in: format!("-{}", quote.amount_in),
-        token_out: quote.amount_out.clone()
-    });
+    // One token_diff per quote. A split swap signs them all in a single message,
+    // so the relay settles every part atomically or none of them.
+    let intents = quotes
+        .iter()
+        .map(|quote| IntentAction::TokenDiff {
+            // Build diff using json! macro to preserve field order
+            diff: serde_json::json!({
+                token_in: format!("-{}", quote.amount_in),
+                token_out: quote.amount_out.clone()
+            }),
+            referral: attribution.referral.clone(),
+        })
+        .collect();
 
     let intent_message = IntentMessage {
         signer_id: signer_id.to_string(),
-        deadline: quote.expiration_time.clone(),
-        intents: vec![IntentAction::TokenDiff {
-            diff,
-        }],
+        deadline: deadline.to_string(),
+        intents,
     };
 
     // Serialize with spaces after colons (like Python json.dumps)
@@ -737,22 +1874,14 @@ fn publish_swap_intent(
     // Generate nonce
     let nonce = generate_nonce();
 
-    // Sign the intent (simplified - in production use proper ed25519 signing)
-    let signature = sign_intent(&message_str, &nonce, private_key)?;
+    // Sign the intent
+    let signed_data = sign_intent(&message_str, &nonce, private_key)?;
 
     // Publish intent
     let params = PublishIntentParams {
-        signed_data: SignedData {
-            payload: Payload {
-                message: message_str,
-                nonce: nonce.clone(),
-                recipient: INTENTS_CONTRACT.to_string(),
-            },
-            standard: "nep413".to_string(),
-            signature: format!("ed25519:{}", signature),
-            public_key: derive_public_key(private_key)?,
-        },
-        quote_hashes: Some(vec![quote.quote_hash.clone()]),
+        signed_data,
+        quote_hashes: Some(quotes.iter().map(|q| q.quote_hash.clone()).collect()),
+        attribution: attribution.clone(),
     };
 
     let request = JsonRpcRequest {
@@ -762,35 +1891,31 @@ fn publish_swap_intent(
         params: vec![params],
     };
 
-    eprintln!("📤 Publishing swap intent to: {}", INTENTS_API_URL);
+    eprintln!("📤 Publishing swap intent to: {}", network().relay_url);
     eprintln!("   Method: publish_intent");
     eprintln!("   Signer: {}", signer_id);
-    eprintln!("   Token in: {} (amount: {})", token_in, quote.amount_in);
-    eprintln!("   Token out: {} (amount: {})", token_out, quote.amount_out);
-    eprintln!("   Quote hash: {}", quote.quote_hash);
+    for quote in quotes {
+        eprintln!("   {} {} → {} {} (quote hash: {})",
+            quote.amount_in, token_in, quote.amount_out, token_out, quote.quote_hash);
+    }
 
     let request_json = serde_json::to_string_pretty(&request)?;
-    eprintln!("📦 Request body (first 2000 chars):\n{}", &request_json.chars().take(2000).collect::<String>());
+    logging::debug_body("📦 Request body", &request_json, 2000);
 
-    let response = Client::new()
-        .post(INTENTS_API_URL)
-        .header("Content-Type", "application/json")
-        .connect_timeout(Duration::from_secs(10))
-        .body(serde_json::to_string(&request)?.as_bytes())
-        .send()?;
+    let response = http::post_json(Endpoint::Relay, &network().relay_url, &request)?;
 
-    if response.status() != 200 {
-        return Err(format!("Publish intent API returned status {}", response.status()).into());
+    if response.status != 200 {
+        return Err(format!("Publish intent API returned status {}", response.status).into());
     }
 
-    let body = response.body()?;
+    let body = response.body;
 
     // Debug: print response body
     let body_str = String::from_utf8_lossy(&body);
-    eprintln!("📥 Publish intent response (first 1000 chars): {}", &body_str.chars().take(1000).collect::<String>());
+    logging::debug_body("📥 Publish intent response", &body_str, 1000);
 
     let json_response: JsonRpcResponse<PublishIntentResult> = serde_json::from_slice(&body)
-        .map_err(|e| format!("Failed to parse publish_intent response: {}. Body: {}", e, body_str))?;
+        .map_err(|e| format!("Failed to parse publish_intent response: {}. Body: {}", e, logging::redact(&body_str)))?;
 
     if let Some(error) = json_response.error {
         eprintln!("❌ API returned error object: {:?}", error);
@@ -808,18 +1933,29 @@ fn publish_swap_intent(
     result.intent_hash.ok_or("No intent_hash returned".into())
 }
 
-fn wait_for_settlement(intent_hash: &str) -> Result<bool, Box<dyn std::error::Error>> {
-    wait_for_settlement_with_timeout(intent_hash, 120) // 120 * 0.25s = 30 seconds
-}
-
 fn wait_for_settlement_with_timeout(
     intent_hash: &str,
     max_attempts: u32,
 ) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(poll_intent_status(intent_hash, max_attempts)? == IntentStatus::Settled)
+}
+
+/// Where an intent stands after polling get_status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntentStatus {
+    Settled,
+    /// Rejected or no longer valid: it will never settle
+    Failed,
+    /// Still pending when polling stopped: it may settle later
+    Pending,
+}
+
+/// Poll `intent_hash` until it settles or fails, at most `max_attempts` times
+fn poll_intent_status(intent_hash: &str, max_attempts: u32) -> Result<IntentStatus, Box<dyn std::error::Error>> {
     for attempt in 0..max_attempts {
         if attempt > 0 {
-            // Sleep 250ms between checks
-            std::thread::sleep(Duration::from_millis(250));
+            metrics::record_retry("status_poll");
+            std::thread::sleep(SETTLE_POLL_INTERVAL);
         }
 
         let request = JsonRpcRequest {
@@ -831,35 +1967,121 @@ fn wait_for_settlement_with_timeout(
             }],
         };
 
-        let response = Client::new()
-            .post(INTENTS_API_URL)
-            .header("Content-Type", "application/json")
-            .connect_timeout(Duration::from_secs(5))
-            .body(serde_json::to_string(&request)?.as_bytes())
-            .send()?;
+        let response = http::post_json(Endpoint::Relay, &network().relay_url, &request)?;
 
-        if response.status() != 200 {
-            eprintln!("get_status returned status {}, retrying...", response.status());
+        if response.status != 200 {
+            eprintln!("get_status returned status {}, retrying...", response.status);
             continue;
         }
 
-        let body = response.body()?;
+        let body = response.body;
         let json_response: JsonRpcResponse<GetStatusResult> = serde_json::from_slice(&body)?;
 
         if let Some(result) = json_response.result {
             eprintln!("Intent status (attempt {}): {}", attempt + 1, result.status);
 
             match result.status.as_str() {
-                "SETTLED" => return Ok(true),
+                "SETTLED" => return Ok(IntentStatus::Settled),
                 "NOT_FOUND_OR_NOT_VALID_ANYMORE" | "NOT_FOUND_OR_NOT_VALID" | "FAILED" => {
-                    return Ok(false);
+                    return Ok(IntentStatus::Failed);
                 }
                 _ => {} // Continue polling
             }
         }
     }
 
-    Ok(false) // Timeout
+    Ok(IntentStatus::Pending) // Timeout
+}
+
+/// Where a withdrawal ended up
+#[derive(Debug, PartialEq)]
+enum WithdrawOutcome {
+    /// The withdraw intent settled
+    Settled(String),
+    /// The budget ran out with the withdrawal unfinished: the intent (if one
+    /// was published) may still settle, or the operator withdraws later
+    Pending(Option<String>),
+}
+
+/// Withdraw with retries, used once the swap itself has settled.
+///
+/// The swap contract's balance in intents.near is shared by every swap in
+/// flight and by leftover fees, so a new withdraw intent while an earlier
+/// one may still settle could withdraw twice from it. A new intent is only
+/// signed once the previous one is known to have failed; while it is
+/// pending, later attempts keep polling it. Attempts stop when `budget`
+/// runs low, leaving the withdrawal Pending rather than failed. Err only
+/// when no intent is in flight.
+fn withdraw_with_retry(
+    signer_id: &str,
+    private_key: &str,
+    token: &str,
+    receiver_id: &str,
+    amount: &str,
+    budget: &Budget,
+) -> Result<WithdrawOutcome, Box<dyn std::error::Error>> {
+    let max_attempts = env_or("WITHDRAW_MAX_ATTEMPTS", DEFAULT_WITHDRAW_MAX_ATTEMPTS).max(1);
+
+    let mut last_error = String::new();
+    let mut in_flight: Option<String> = None;
+
+    for attempt in 1..=max_attempts {
+        if budget.remaining() < SwapStage::Withdraw.estimate() {
+            eprintln!("⏱️  Execution budget exhausted before withdraw attempt {}", attempt);
+            return Ok(WithdrawOutcome::Pending(in_flight));
+        }
+        eprintln!("🔄 Withdraw attempt {}/{}", attempt, max_attempts);
+        if attempt > 1 {
+            metrics::record_retry("withdraw");
+        }
+
+        let intent_hash = match in_flight.take() {
+            Some(intent_hash) => {
+                eprintln!("   Still waiting on withdraw intent {}", intent_hash);
+                Some(intent_hash)
+            }
+            None => match withdraw_tokens(signer_id, private_key, token, receiver_id, amount) {
+                Ok(intent_hash) => Some(intent_hash),
+                Err(e) => {
+                    last_error = e.to_string();
+                    None
+                }
+            },
+        };
+
+        if let Some(intent_hash) = intent_hash {
+            let poll_window = budget.remaining().min(SETTLE_POLL_INTERVAL * SETTLE_POLL_ATTEMPTS);
+            let poll_attempts = (poll_window.as_millis() / SETTLE_POLL_INTERVAL.as_millis()).max(1) as u32;
+            match poll_intent_status(&intent_hash, poll_attempts) {
+                Ok(IntentStatus::Settled) => return Ok(WithdrawOutcome::Settled(intent_hash)),
+                Ok(IntentStatus::Failed) => last_error = format!("withdraw intent {} did not settle", intent_hash),
+                Ok(IntentStatus::Pending) => {
+                    last_error = format!("withdraw intent {} has not settled yet", intent_hash);
+                    in_flight = Some(intent_hash);
+                }
+                Err(e) => {
+                    last_error = format!("status of withdraw intent {} unknown: {}", intent_hash, e);
+                    in_flight = Some(intent_hash);
+                }
+            }
+        }
+        eprintln!("⚠️  Withdraw attempt {} failed: {}", attempt, last_error);
+
+        // No point waiting out the delay if the next attempt won't fit anyway
+        let delay = Duration::from_millis(WITHDRAW_RETRY_DELAY_MS);
+        if attempt < max_attempts && budget.remaining() >= delay + SwapStage::Withdraw.estimate() {
+            std::thread::sleep(delay);
+        }
+    }
+
+    if in_flight.is_some() {
+        return Ok(WithdrawOutcome::Pending(in_flight));
+    }
+    Err(format!(
+        "Withdraw failed after {} attempts. Last error: {}",
+        max_attempts, last_error
+    )
+    .into())
 }
 
 fn withdraw_tokens(
@@ -868,22 +2090,18 @@ fn withdraw_tokens(
     token: &str,
     receiver_id: &str,
     amount: &str,
-) -> Result<bool, Box<dyn std::error::Error>> {
+) -> Result<String, Box<dyn std::error::Error>> {
     // IMPORTANT: ft_withdraw uses token WITHOUT "nep141:" prefix
     // (unlike token_diff which uses WITH prefix)
     // https://docs.near-intents.org/near-intents/market-makers/bus/solver-relay
 
     // Strip "nep141:" prefix if present
-    let token_without_prefix = if token.starts_with("nep141:") {
-        &token[7..]
-    } else {
-        token
-    };
+    let token_without_prefix = token.strip_prefix("nep141:").unwrap_or(token);
 
     // Build withdraw intent message
     let intent_message = IntentMessage {
         signer_id: signer_id.to_string(),
-        deadline: get_deadline_180s(),
+        deadline: withdraw_deadline(),
         intents: vec![IntentAction::FtWithdraw {
             token: token_without_prefix.to_string(),
             receiver_id: receiver_id.to_string(),
@@ -903,21 +2121,13 @@ fn withdraw_tokens(
     let nonce = generate_nonce();
 
     // Sign the intent
-    let signature = sign_intent(&message_str, &nonce, private_key)?;
+    let signed_data = sign_intent(&message_str, &nonce, private_key)?;
 
     // Publish withdraw intent
     let params = PublishIntentParams {
-        signed_data: SignedData {
-            payload: Payload {
-                message: message_str,
-                nonce: nonce.clone(),
-                recipient: INTENTS_CONTRACT.to_string(),
-            },
-            standard: "nep413".to_string(),
-            signature: format!("ed25519:{}", signature),
-            public_key: derive_public_key(private_key)?,
-        },
+        signed_data,
         quote_hashes: None,
+        attribution: RelayAttribution::default(),
     };
 
     let request = JsonRpcRequest {
@@ -927,7 +2137,7 @@ fn withdraw_tokens(
         params: vec![params],
     };
 
-    eprintln!("📤 Publishing withdraw intent to: {}", INTENTS_API_URL);
+    eprintln!("📤 Publishing withdraw intent to: {}", network().relay_url);
     eprintln!("   Method: publish_intent (withdraw)");
     eprintln!("   Signer: {}", signer_id);
     eprintln!("   Token: {}", token);
@@ -935,28 +2145,23 @@ fn withdraw_tokens(
     eprintln!("   Amount: {}", amount);
 
     let request_json = serde_json::to_string_pretty(&request)?;
-    eprintln!("📦 Request body (first 2000 chars):\n{}", &request_json.chars().take(2000).collect::<String>());
+    logging::debug_body("📦 Request body", &request_json, 2000);
 
-    let response = Client::new()
-        .post(INTENTS_API_URL)
-        .header("Content-Type", "application/json")
-        .connect_timeout(Duration::from_secs(10))
-        .body(serde_json::to_string(&request)?.as_bytes())
-        .send()?;
+    let response = http::post_json(Endpoint::Relay, &network().relay_url, &request)?;
 
-    if response.status() != 200 {
-        eprintln!("❌ Withdraw API returned status: {}", response.status());
-        return Err(format!("Withdraw API returned status {}", response.status()).into());
+    if response.status != 200 {
+        eprintln!("❌ Withdraw API returned status: {}", response.status);
+        return Err(format!("Withdraw API returned status {}", response.status).into());
     }
 
-    let body = response.body()?;
+    let body = response.body;
 
     // Debug: print response body
     let body_str = String::from_utf8_lossy(&body);
-    eprintln!("📥 Withdraw intent response (first 1000 chars): {}", &body_str.chars().take(1000).collect::<String>());
+    logging::debug_body("📥 Withdraw intent response", &body_str, 1000);
 
     let json_response: JsonRpcResponse<PublishIntentResult> = serde_json::from_slice(&body)
-        .map_err(|e| format!("Failed to parse withdraw response: {}. Body: {}", e, body_str))?;
+        .map_err(|e| format!("Failed to parse withdraw response: {}. Body: {}", e, logging::redact(&body_str)))?;
 
     if let Some(error) = json_response.error {
         eprintln!("❌ Withdraw API returned error object: {:?}", error);
@@ -967,10 +2172,8 @@ fn withdraw_tokens(
 
     eprintln!("📊 Withdraw intent result: status={}, intent_hash={:?}", result.status, result.intent_hash);
 
-    let intent_hash = result.intent_hash.ok_or("No intent_hash for withdraw")?;
-
-    // Wait for withdrawal settlement (120 * 0.25s = 30 seconds timeout - same as swap)
-    wait_for_settlement_with_timeout(&intent_hash, 120)
+    // Settlement is polled by withdraw_with_retry
+    result.intent_hash.ok_or_else(|| "No intent_hash for withdraw".into())
 }
 
 // ============================================================================
@@ -991,70 +2194,485 @@ fn generate_nonce() -> String {
     hasher.update(timestamp.as_bytes());
     let result = hasher.finalize();
 
-    base64::encode(result)
+    codec::base64_encode(result)
 }
 
-fn get_deadline_180s() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
+thread_local! {
+    /// ft_metadata decimals per token contract (None if the lookup failed)
+    static TOKEN_DECIMALS: RefCell<HashMap<String, Option<u8>>> = RefCell::new(HashMap::new());
+}
 
-    let now_plus_180 = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() + 180;
-
-    // Convert to ISO 8601 format: YYYY-MM-DDTHH:MM:SS.000Z
-    // Simplified calculation (assumes Unix epoch)
-    let total_seconds = now_plus_180;
-    let seconds = total_seconds % 60;
-    let total_minutes = total_seconds / 60;
-    let minutes = total_minutes % 60;
-    let total_hours = total_minutes / 60;
-    let hours = total_hours % 24;
-    let total_days = total_hours / 24;
-
-    // Days since 1970-01-01
-    // Simplified: doesn't account for leap years, good enough for deadlines
-    let year = 1970 + (total_days / 365);
-    let day_of_year = total_days % 365;
-    let month = (day_of_year / 30) + 1; // Approximate
-    let day = (day_of_year % 30) + 1;
-
-    format!(
-        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.000Z",
-        year, month, day, hours, minutes, seconds
-    )
+/// `amount` of `token_contract` for logs, in whole tokens with the raw amount
+/// alongside, e.g. `1.5 (1500000)`. Decimals are looked up once per token;
+/// if that fails the raw amount is shown alone.
+fn display_amount(amount: u128, token_contract: &str) -> String {
+    let decimals = TOKEN_DECIMALS.with(|cache| {
+        *cache.borrow_mut().entry(token_contract.to_string()).or_insert_with(|| {
+            let args = serde_json::json!({});
+            near_tx::view_typed::<serde_json::Value>(&rpc(), token_contract, "ft_metadata", &args)
+                .ok()
+                .and_then(|metadata| metadata["decimals"].as_u64())
+                .and_then(|decimals| u8::try_from(decimals).ok())
+        })
+    });
+
+    match decimals {
+        Some(decimals) => format!("{} ({})", amount::format(amount, decimals), amount),
+        None => amount.to_string(),
+    }
+}
+
+/// `amount` increased by `bps` basis points, rounded up so the margin is never
+/// smaller than requested. Split into quotient/remainder to avoid u128 overflow.
+fn add_bps(amount: u128, bps: u32) -> u128 {
+    let bps = bps as u128;
+    let extra = amount / 10_000 * bps + ((amount % 10_000) * bps).div_ceil(10_000);
+    amount.saturating_add(extra)
+}
+
+/// `amount` split into `parts` equal parts, the first absorbing the remainder.
+/// None if a part would be empty.
+fn split_amounts(amount: u128, parts: u32) -> Option<Vec<u128>> {
+    let parts = parts as u128;
+    let part = amount / parts;
+    if part == 0 {
+        return None;
+    }
+
+    let mut amounts = vec![part; parts as usize];
+    amounts[0] += amount % parts;
+    Some(amounts)
+}
+
+/// Sum of amount_out across quotes (None if any amount is unparseable)
+fn total_amount_out(quotes: &[Quote]) -> Option<u128> {
+    quotes
+        .iter()
+        .try_fold(0u128, |total, q| total.checked_add(amount::parse(&q.amount_out).ok()?))
+}
+
+/// How much worse the rate `amount_out / amount_in` is than the reference rate
+/// `ref_out / ref_in`, in basis points (negative if better). Floating point is
+/// fine here: the result is only compared against a threshold, and exact u128
+/// cross-multiplication would overflow for 24-decimal tokens.
+fn price_impact_bps(amount_in: u128, amount_out: u128, ref_in: u128, ref_out: u128) -> Option<f64> {
+    if amount_in == 0 || ref_in == 0 || ref_out == 0 {
+        return None;
+    }
+    let rate = amount_out as f64 / amount_in as f64;
+    let reference_rate = ref_out as f64 / ref_in as f64;
+    Some((1.0 - rate / reference_rate) * 10_000.0)
+}
+
+/// Numeric setting from the environment, falling back to `default` when unset or invalid
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env_opt(name).unwrap_or(default)
+}
+
+/// Optional numeric setting from the environment (None when unset or invalid)
+fn env_opt<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// Deadline for a swap intent: the earliest expiration of the quotes backing it
+/// minus a safety margin, so the intent can never outlive any of them.
+/// Fails if that deadline has already passed.
+fn swap_deadline(quotes: &[Quote]) -> Result<u64, String> {
+    let margin_ms =
+        env_or("DEADLINE_SAFETY_MARGIN_SECONDS", DEFAULT_DEADLINE_SAFETY_MARGIN_SECONDS) * 1000;
+    let mut expires_at = u64::MAX;
+    for quote in quotes {
+        expires_at = expires_at.min(iso8601::parse_millis(&quote.expiration_time)?);
+    }
+    let deadline = expires_at.saturating_sub(margin_ms);
+
+    let now = iso8601::now_millis();
+    if deadline <= now {
+        return Err(format!(
+            "Quote expires too soon: {} (safety margin {} ms)",
+            iso8601::format_millis(expires_at), margin_ms
+        ));
+    }
+
+    Ok(deadline)
+}
+
+fn withdraw_deadline() -> String {
+    let validity_ms = env_or("WITHDRAW_DEADLINE_SECONDS", DEFAULT_WITHDRAW_DEADLINE_SECONDS) * 1000;
+    iso8601::format_millis(iso8601::now_millis() + validity_ms)
 }
 
+/// Sign an intent message for the intents contract under
+/// INTENT_SIGNATURE_STANDARD (`nep413` by default)
 fn sign_intent(
     message: &str,
     nonce: &str,
     private_key: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    // Remove "ed25519:" prefix if present
-    let key_base58 = if private_key.starts_with("ed25519:") {
-        &private_key[8..]
-    } else {
-        private_key
-    };
+) -> Result<crypto::SignedIntent, Box<dyn std::error::Error>> {
+    let standard = env::var("INTENT_SIGNATURE_STANDARD").unwrap_or_else(|_| "nep413".to_string());
+    crypto::intent_signer(&standard, private_key)?.sign_intent(message, nonce, &network().intents_contract)
+}
+
+/// Operator keys in order of preference: SWAP_CONTRACT_PRIVATE_KEYS
+/// (comma-separated), then SWAP_CONTRACT_PRIVATE_KEY, then the key derived
+/// from SWAP_CONTRACT_SEED_PHRASE
+fn operator_keys(secrets: &Secrets) -> Vec<Zeroizing<String>> {
+    let mut keys: Vec<Zeroizing<String>> = secrets
+        .get("SWAP_CONTRACT_PRIVATE_KEYS")
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(|key| Zeroizing::new(key.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    keys.extend(secrets.get("SWAP_CONTRACT_PRIVATE_KEY"));
+    if let Some(phrase) = secrets.get("SWAP_CONTRACT_SEED_PHRASE") {
+        let hd_path = env::var("SWAP_CONTRACT_HD_PATH").unwrap_or_else(|_| crypto::NEAR_HD_PATH.to_string());
+        match crypto::keypair_from_mnemonic(&phrase, &hd_path) {
+            Ok((private_key, _)) => keys.push(private_key),
+            Err(e) => eprintln!("⚠️  Ignoring SWAP_CONTRACT_SEED_PHRASE: {}", e),
+        }
+    }
+    keys
+}
+
+/// First operator key that exists on `account_id` with at least MIN_KEY_ALLOWANCE
+/// left (full-access and unlimited keys always qualify). A single configured key
+/// is used as-is, without an RPC round trip.
+fn select_signing_key(secrets: &Secrets, account_id: &str) -> Result<Zeroizing<String>, String> {
+    let mut keys = operator_keys(secrets);
+    if keys.len() <= 1 {
+        return keys
+            .pop()
+            .ok_or_else(|| "SWAP_CONTRACT_PRIVATE_KEY (or SWAP_CONTRACT_SEED_PHRASE) not found in secrets or environment".to_string());
+    }
+
+    let min_allowance = env_or("MIN_KEY_ALLOWANCE", DEFAULT_MIN_KEY_ALLOWANCE);
+    let access_keys = near_tx::view_access_key_list(&rpc(), account_id)
+        .map_err(|e| format!("Failed to list access keys of {}: {}", account_id, e))?;
+    let mut rejected = Vec::new();
+
+    for (i, key) in keys.into_iter().enumerate() {
+        let public_key = match near_tx::public_key_of(&key).and_then(|k| Ok(k.parse::<crypto::PublicKey>()?)) {
+            Ok(public_key) => public_key,
+            Err(e) => {
+                rejected.push(format!("key #{}: {}", i + 1, e));
+                continue;
+            }
+        };
+
+        let access_key = access_keys.iter().find(|k| k.public_key.parse::<crypto::PublicKey>().as_ref() == Ok(&public_key));
+        let usable = match access_key {
+            Some(info) => match &info.access_key.permission {
+                AccessKeyPermissionView::FullAccess => Ok(()),
+                AccessKeyPermissionView::FunctionCall { allowance: None, .. } => Ok(()),
+                AccessKeyPermissionView::FunctionCall { allowance: Some(allowance), .. } => {
+                    if amount::parse(allowance).is_ok_and(|a| a >= min_allowance) {
+                        Ok(())
+                    } else {
+                        Err(format!("allowance {} below {}", allowance, min_allowance))
+                    }
+                }
+            },
+            None => Err(format!("not an access key of {}", account_id)),
+        };
 
-    let (signature, _public_key) =
-        crypto::sign_nep413_intent(message, nonce, INTENTS_CONTRACT, key_base58)?;
+        match usable {
+            Ok(()) => {
+                eprintln!("🔑 Using operator key #{} ({})", i + 1, public_key);
+                return Ok(key);
+            }
+            Err(e) => {
+                eprintln!("⚠️  Skipping operator key #{} ({}): {}", i + 1, public_key, e);
+                rejected.push(format!("{}: {}", public_key, e));
+            }
+        }
+    }
 
-    Ok(signature)
+    Err(format!("No usable operator key for {}: {}", account_id, rejected.join("; ")))
 }
 
-fn derive_public_key(private_key: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // Remove "ed25519:" prefix if present
-    let key_base58 = if private_key.starts_with("ed25519:") {
-        &private_key[8..]
-    } else {
-        private_key
-    };
+#[cfg(all(test, feature = "record-replay"))]
+mod tests {
+    use super::*;
+    use intents_ark::replay;
+
+    fn test_private_key() -> String {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        format!("ed25519:{}", codec::base58_encode(signing_key.to_keypair_bytes()))
+    }
+
+    #[test]
+    fn test_execute_swap_replays_settled_swap() {
+        let fixture = include_str!("../tests/fixtures/swap_settled.json");
+        replay::start(serde_json::from_str(fixture).unwrap());
+
+        let swap: SwapInput = serde_json::from_value(serde_json::json!({
+            "sender_id": "alice.near",
+            "token_in": "nep141:17208628f84f5d6ad33f0da3bbbeb27ffcb398eac501a31bd6ad2011e36133a1",
+            "token_out": "nep141:wrap.near",
+            "amount_in": "10000",
+            "min_amount_out": "1000000000000000000000",
+            "swap_contract_id": "swap.near"
+        }))
+        .unwrap();
+        let budget = Budget::new(Instant::now(), Some(120));
+
+        let output = serde_json::to_value(run_swap(&swap, &test_private_key(), &budget)).unwrap();
+
+        assert!(replay::finish().is_empty(), "every recorded request was made");
+        assert_eq!(output["success"], true, "{}", output);
+        assert_eq!(output["amount_out"], "2100000000000000000000");
+        assert_eq!(output["intent_hash"], "2bNxPqR7dVhXaYkCw3mT9sLfJ5uGe8oZiB4nHcWrK6Dp");
+        assert_eq!(output["steps"].as_array().unwrap().len(), 6);
+        assert_eq!(output["quote_hash"], "6XKf5GzjV2Y1wAhRfHnmsHfTVbWWcHW4iBqgwvj3o8iz");
+        assert_eq!(output["deposit_tx_hash"], "9Fw8Jb5YvDwbLqmA4kQ9p1XGh9VbTkM6uBvKcQnE3sRt");
+        assert_eq!(output["withdraw_intent_hash"], "Ea4wZ9tLkQ2nVbR8cXyHs5mJf3uPd7oTgK1iCvN6rBzM");
+        assert_eq!(output["metrics"]["retries"]["status_poll"], 1);
+
+        let response: intents_ark_types::SwapResponse = serde_json::from_value(output).unwrap();
+        assert!(response.success);
+        assert_eq!(response.schema_version, Some(OUTPUT_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn test_failure_output_parses_as_contract_swap_response() {
+        let output = serde_json::to_value(missing_key_output("no key".to_string())).unwrap();
+        let response: intents_ark_types::SwapResponse = serde_json::from_value(output).unwrap();
+        assert!(!response.success);
+        assert_eq!(response.error_code, Some(ErrorCode::KeyMissing));
+        assert_eq!(response.error_message.as_deref(), Some("no key"));
+    }
+}
+
+/// End-to-end swaps against a local relay/RPC server. Kept apart from the
+/// replay tests: both point the process-wide network config somewhere else.
+#[cfg(all(test, feature = "mock-relay", not(feature = "record-replay")))]
+mod mock_relay_tests {
+    use super::*;
+    use intents_ark::mock_relay::{MockServer, Reply};
+
+    fn swap_input() -> SwapInput {
+        serde_json::from_value(serde_json::json!({
+            "sender_id": "alice.near",
+            "token_in": "nep141:17208628f84f5d6ad33f0da3bbbeb27ffcb398eac501a31bd6ad2011e36133a1",
+            "token_out": "nep141:wrap.near",
+            "amount_in": "10000",
+            "min_amount_out": "1000000000000000000000",
+            "swap_contract_id": "swap.near"
+        }))
+        .unwrap()
+    }
+
+    fn swap() -> intents_ark_types::SwapResponse {
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]);
+        let private_key = format!("ed25519:{}", codec::base58_encode(signing_key.to_keypair_bytes()));
+        let budget = Budget::new(Instant::now(), Some(120));
+        let output = serde_json::to_value(run_swap(&swap_input(), &private_key, &budget)).unwrap();
+        eprintln!("mock relay output: {}", output);
+        serde_json::from_value(output).unwrap()
+    }
+
+    #[test]
+    fn test_swap_scenarios_against_mock_relay() {
+        let server = MockServer::start().unwrap();
+        env::set_var("INTENTS_API_URL", server.url());
+        env::set_var("NEAR_RPC_URL", server.url());
+        env::set_var("NEAR_ARCHIVAL_RPC_URL", "");
+        env::set_var("WITHDRAW_MAX_ATTEMPTS", "1");
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/fixtures/swap_settled.json")).unwrap();
+
+        // Settled swap
+        server.script_fixture(&fixture);
+        let response = swap();
+        assert!(response.success, "{:?}", response);
+        assert_eq!(response.amount_out.as_deref(), Some("2100000000000000000000"));
+        assert_eq!(response.token_out.as_deref(), Some("nep141:wrap.near"));
+        assert!(server.unused().is_empty(), "unused replies: {:?}", server.unused());
+        // Later swaps skip the token metadata lookup (cached), so their queues may keep one
+        server.reset();
+
+        // The relay fails the first quote request; the retry gets it
+        server.script_fixture(&fixture);
+        server.script_first("quote", Reply::json(503, &serde_json::json!({ "error": "overloaded" })));
+        assert!(swap().success);
+        assert_eq!(server.requests().iter().filter(|r| r["method"] == "quote").count(), 2);
+        server.reset();
+
+        // A quote slower than the relay read timeout is retried like any other failure
+        env::set_var("RELAY_READ_TIMEOUT_MS", "300");
+        server.script_fixture(&fixture);
+        let slow = Reply::json(200, &fixture[0]["response"]).delayed(Duration::from_millis(1000));
+        server.script_first("quote", slow);
+        assert!(swap().success);
+        assert_eq!(server.requests().iter().filter(|r| r["method"] == "quote").count(), 2);
+        env::remove_var("RELAY_READ_TIMEOUT_MS");
+        server.reset();
+
+        // Swap settles but the withdraw intent is rejected: tokens stay in intents.near
+        let mut partial = fixture.clone();
+        partial[8]["response"] = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": { "status": "FAILED", "reason": "nonce used" } });
+        partial.as_array_mut().unwrap().truncate(9);
+        server.script_fixture(&partial);
+        let response = swap();
+        assert!(!response.success);
+        assert_eq!(response.error_code, Some(ErrorCode::WithdrawFailed));
+        assert_eq!(response.intent_hash.as_deref(), Some("2bNxPqR7dVhXaYkCw3mT9sLfJ5uGe8oZiB4nHcWrK6Dp"));
+        server.reset();
+
+        // The withdraw intent's status can't be read: the retry polls it
+        // again rather than signing a second withdrawal
+        env::set_var("WITHDRAW_MAX_ATTEMPTS", "2");
+        let mut unknown = fixture.clone();
+        unknown[9]["response"] = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": { "unexpected": true } });
+        unknown.as_array_mut().unwrap().insert(10, fixture[9].clone());
+        server.script_fixture(&unknown);
+        let response = swap();
+        assert!(response.success, "{:?}", response);
+        assert_eq!(response.withdraw_intent_hash.as_deref(), Some("Ea4wZ9tLkQ2nVbR8cXyHs5mJf3uPd7oTgK1iCvN6rBzM"));
+        assert_eq!(server.requests().iter().filter(|r| r["method"] == "publish_intent").count(), 2);
+        env::set_var("WITHDRAW_MAX_ATTEMPTS", "1");
+        server.reset();
+
+        // The deposit executed but doesn't become final in time: the swap
+        // goes on rather than failing with its input already in intents.near
+        env::set_var("DEPOSIT_FINALITY_TIMEOUT_SECONDS", "1");
+        server.script_fixture(&fixture);
+        let mut optimistic = fixture[4]["response"].clone();
+        optimistic["result"]["final_execution_status"] = "EXECUTED_OPTIMISTIC".into();
+        server.script("tx", [Reply::json(200, &optimistic)]);
+        let response = swap();
+        env::remove_var("DEPOSIT_FINALITY_TIMEOUT_SECONDS");
+        assert!(response.success, "{:?}", response);
+        assert!(server.requests().iter().any(|r| r["method"] == "tx"));
+    }
+}
+
+#[cfg(test)]
+mod input_tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Arbitrary JSON, biased towards the shapes and keys the worker expects
+    fn json_value() -> impl Strategy<Value = serde_json::Value> {
+        let key = prop_oneof![
+            Just("action".to_string()),
+            Just("sender_id".to_string()),
+            Just("amount_in".to_string()),
+            Just("min_amount_out".to_string()),
+            Just("secrets".to_string()),
+            Just("request".to_string()),
+            "[a-z_]{1,12}",
+        ];
+        let leaf = prop_oneof![
+            Just(serde_json::Value::Null),
+            any::<bool>().prop_map(serde_json::Value::from),
+            any::<i64>().prop_map(serde_json::Value::from),
+            any::<f64>().prop_map(serde_json::Value::from),
+            ".{0,24}".prop_map(serde_json::Value::from),
+            "[0-9]{1,45}".prop_map(serde_json::Value::from),
+        ];
+        leaf.prop_recursive(4, 48, 8, move |inner| {
+            prop_oneof![
+                proptest::collection::vec(inner.clone(), 0..6).prop_map(serde_json::Value::from),
+                proptest::collection::btree_map(key.clone(), inner, 0..8)
+                    .prop_map(|map| serde_json::Value::Object(map.into_iter().collect())),
+            ]
+        })
+    }
 
-    // Sign a dummy message to get the public key
-    let dummy_nonce = base64::encode(&[0u8; 32]);
-    let (_signature, public_key) =
-        crypto::sign_nep413_intent("{}", &dummy_nonce, INTENTS_CONTRACT, key_base58)?;
+    proptest! {
+        #[test]
+        fn test_parse_input_never_panics_on_bytes(raw in ".{0,256}") {
+            let _ = parse_input(&raw);
+        }
+
+        #[test]
+        fn test_parse_input_never_panics_on_json(value in json_value()) {
+            let _ = parse_input(&value.to_string());
+        }
+
+        #[test]
+        fn test_parsed_swap_amounts_never_panic(amount_in in ".{0,48}", min_amount_out in "[0-9]{0,48}") {
+            let raw = serde_json::json!({
+                "sender_id": "alice.near",
+                "token_in": "nep141:a.near",
+                "token_out": "nep141:b.near",
+                "amount_in": amount_in,
+                "min_amount_out": min_amount_out,
+                "swap_contract_id": "swap.near"
+            });
+            let (input, _) = parse_input(&raw.to_string()).unwrap();
+            let Input::Swap(swap) = input else { panic!("parsed as {:?}", input) };
+            let _ = amount::parse(&swap.amount_in);
+            let _ = amount::parse(&swap.min_amount_out);
+        }
+    }
+
+    #[test]
+    fn test_register_key_and_report_inputs_parse_apart() {
+        let raw = r#"{"action":"register_key","swap_contract_id":"swap.near","public_key":"ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"}"#;
+        let (input, _) = parse_input(raw).unwrap();
+        assert!(
+            matches!(input, Input::Action(Action::RegisterKey { ref public_key, .. }) if public_key.starts_with("ed25519:")),
+            "{:?}",
+            input
+        );
+
+        let (input, _) = parse_input(r#"{"action":"report","swap_contract_id":"swap.near"}"#).unwrap();
+        assert!(matches!(input, Input::Action(Action::Report { .. })), "{:?}", input);
+    }
 
-    Ok(format!("ed25519:{}", public_key))
+    #[test]
+    fn test_action_must_match_its_fields() {
+        // Report's fields, but not a report
+        assert!(parse_input(r#"{"action":"register_key","swap_contract_id":"swap.near"}"#).is_err());
+        assert!(parse_input(r#"{"action":"reprot","swap_contract_id":"swap.near"}"#).is_err());
+        assert!(parse_input(r#"{"action":"report","token_contract":"wrap.near"}"#).is_err());
+
+        // A bare swap has no action
+        let swap = serde_json::json!({
+            "sender_id": "alice.near",
+            "token_in": "nep141:a.near",
+            "token_out": "nep141:b.near",
+            "amount_in": "1000",
+            "min_amount_out": "900",
+            "swap_contract_id": "swap.near"
+        });
+        let (input, _) = parse_input(&swap.to_string()).unwrap();
+        assert!(matches!(input, Input::Swap(_)), "{:?}", input);
+
+        // ...and one with a misspelled action isn't a swap either
+        let mut misspelled = swap;
+        misspelled["action"] = "withdrw".into();
+        assert!(parse_input(&misspelled.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_arrays_are_not_inputs() {
+        let swap = serde_json::json!({
+            "sender_id": "alice.near",
+            "token_in": "nep141:a.near",
+            "token_out": "nep141:b.near",
+            "amount_in": "1000",
+            "min_amount_out": "900",
+            "swap_contract_id": "swap.near"
+        });
+        // Neither a list of swaps nor an envelope or a swap given by position
+        assert!(parse_input(&serde_json::json!([swap.clone(), swap.clone()]).to_string()).is_err());
+        assert!(parse_input(&serde_json::json!([{}, swap]).to_string()).is_err());
+        let by_position = ["alice.near", "nep141:a.near", "nep141:b.near", "1000", "900", "swap.near"];
+        assert!(parse_input(&serde_json::json!(by_position).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_parse_input_errors_do_not_echo_values() {
+        let raw = r#"{"secrets":{"SWAP_CONTRACT_PRIVATE_KEY":["ed25519:hunter2"]},"request":{}}"#;
+        let error = parse_input(raw).unwrap_err();
+        assert!(!error.contains("hunter2"), "{}", error);
+        assert!(parse_input("").is_err());
+    }
 }
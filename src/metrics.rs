@@ -0,0 +1,54 @@
+//! Per-execution counters for the worker Output
+//!
+//! Retry loops and HTTP call sites record into a thread-local [`Counters`]
+//! (the WASI worker is single-threaded), which the worker takes once per swap
+//! and reports as part of its `metrics` object.
+
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Counters {
+    /// Retries per operation (attempts beyond the first), e.g. `quote`, `withdraw`
+    pub retries: BTreeMap<String, u32>,
+    /// HTTP requests per endpoint URL (relay and NEAR RPC)
+    pub requests: BTreeMap<String, u32>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Counters> = RefCell::new(Counters::default());
+}
+
+/// Count one retry of `operation`
+pub fn record_retry(operation: &str) {
+    CURRENT.with(|c| *c.borrow_mut().retries.entry(operation.to_string()).or_default() += 1);
+}
+
+/// Count one HTTP request to `url`
+pub fn record_request(url: &str) {
+    CURRENT.with(|c| *c.borrow_mut().requests.entry(url.to_string()).or_default() += 1);
+}
+
+/// Counters recorded since the last call, resetting them
+pub fn take() -> Counters {
+    CURRENT.with(|c| std::mem::take(&mut *c.borrow_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_returns_and_resets_counters() {
+        take();
+        record_retry("quote");
+        record_retry("quote");
+        record_request("https://rpc.example");
+
+        let counters = take();
+        assert_eq!(counters.retries.get("quote"), Some(&2));
+        assert_eq!(counters.requests.get("https://rpc.example"), Some(&1));
+        assert_eq!(take(), Counters::default());
+    }
+}
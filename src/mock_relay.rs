@@ -0,0 +1,172 @@
+//! Local solver-relay and NEAR RPC server for offline worker tests
+//!
+//! With the `mock-relay` feature (native builds), [`MockServer::start`] serves
+//! JSON-RPC on a local port. Point `INTENTS_API_URL` and `NEAR_RPC_URL` at
+//! [`MockServer::url`] and script replies per [`request_key`]: each request
+//! takes the next reply queued for its key, so a test can make `quote` fail
+//! once before succeeding, delay `get_status` past the read timeout, or make
+//! only the withdraw `publish_intent` fail. A request with nothing queued gets
+//! a 500, which shows up in the swap output rather than hanging the test.
+//!
+//! Unlike [`crate::replay`], requests really go over HTTP, so timeouts,
+//! status codes and the transport itself are exercised too.
+
+use crate::http::request_key;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// A scripted response
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Vec<u8>,
+    /// Wait this long before answering
+    pub delay: Duration,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: &Value) -> Self {
+        Self {
+            status,
+            body: serde_json::to_vec(body).expect("JSON value serializes"),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 200 with a JSON-RPC `result`
+    pub fn result(result: Value) -> Self {
+        Self::json(200, &serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+#[derive(Default)]
+struct State {
+    replies: HashMap<String, VecDeque<Reply>>,
+    requests: Vec<Value>,
+}
+
+/// Running mock server. The listener thread lives until the process exits.
+pub struct MockServer {
+    url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let shared = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&shared);
+                // One thread per connection, so a delayed reply doesn't hold up others
+                thread::spawn(move || {
+                    if let Err(e) = serve(stream, &state) {
+                        eprintln!("mock relay: {}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(Self { url, state })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Queue `replies` for requests with [`request_key`] `key` (e.g. `quote`,
+    /// `query/view_access_key`), after any already queued
+    pub fn script(&self, key: &str, replies: impl IntoIterator<Item = Reply>) {
+        let mut state = self.state.lock().unwrap();
+        state.replies.entry(key.to_string()).or_default().extend(replies);
+    }
+
+    /// Queue the responses of a [`crate::replay`]-format fixture (URLs are ignored)
+    pub fn script_fixture(&self, exchanges: &Value) {
+        for exchange in exchanges.as_array().expect("fixture is an array of exchanges") {
+            let status = exchange["status"].as_u64().unwrap_or(200) as u16;
+            let reply = match &exchange["response"] {
+                Value::String(raw) => Reply { status, body: raw.clone().into_bytes(), delay: Duration::ZERO },
+                json => Reply::json(status, json),
+            };
+            self.script(&request_key(&exchange["request"]), [reply]);
+        }
+    }
+
+    /// Put `reply` in front of the queue for `key`
+    pub fn script_first(&self, key: &str, reply: Reply) {
+        let mut state = self.state.lock().unwrap();
+        state.replies.entry(key.to_string()).or_default().push_front(reply);
+    }
+
+    /// Drop queued replies and recorded requests
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = State::default();
+    }
+
+    /// Request bodies received so far, in order
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Keys that still have replies queued
+    pub fn unused(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let mut keys: Vec<_> = state.replies.iter().filter(|(_, q)| !q.is_empty()).map(|(k, _)| k.clone()).collect();
+        keys.sort();
+        keys
+    }
+}
+
+fn serve(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    let key = request_key(&request);
+    let reply = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(request);
+        state.replies.get_mut(&key).and_then(VecDeque::pop_front)
+    };
+    let reply = reply.unwrap_or_else(|| {
+        Reply::json(500, &serde_json::json!({ "error": format!("mock relay: no reply scripted for {}", key) }))
+    });
+
+    thread::sleep(reply.delay);
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        reply.status,
+        reply.body.len()
+    )?;
+    stream.write_all(&reply.body)?;
+    stream.flush()
+}
@@ -1,11 +1,27 @@
 /// Simple NEAR transaction signing and RPC without near-primitives
 /// Uses only ed25519-dalek + borsh + HTTP for WASM compatibility
+use crate::codec;
+use crate::crypto::{self, PublicKey};
+use crate::http::{self, Endpoint};
+use crate::logging;
 use borsh::{BorshDeserialize, BorshSerialize};
-use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use ed25519_dalek::{Signer, SigningKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::time::Duration;
-use wasi_http_client::Client;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// Attempts per transaction when it is rejected for a stale nonce or block hash
+const MAX_TX_ATTEMPTS: u32 = 3;
+/// Storage a standard fungible token uses per registered account
+const FT_REGISTRATION_BYTES: u64 = 125;
+/// Storage deposit when neither `storage_balance_bounds` nor the protocol
+/// config can be read (125 bytes at 10^19 yoctoNEAR per byte = 0.00125 NEAR)
+const DEFAULT_STORAGE_DEPOSIT: u128 = 1_250_000_000_000_000_000_000;
+/// Delay between `tx_status` polls in [`wait_for_finality`]
+const TX_POLL_INTERVAL: Duration = Duration::from_secs(1);
 
 // ============================================================================
 // NEAR Transaction Types (minimal borsh-serializable versions)
@@ -21,12 +37,7 @@ struct Transaction {
     actions: Vec<Action>,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-enum PublicKey {
-    ED25519([u8; 32]),
-}
-
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
 enum Action {
     CreateAccount,
     DeployContract(Vec<u8>),
@@ -36,263 +47,1851 @@ enum Action {
     AddKey { public_key: PublicKey, access_key: AccessKey },
     DeleteKey(PublicKey),
     DeleteAccount(String),
+    Delegate(Box<SignedDelegateAction>),
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
+/// NEP-366 meta transaction: actions `sender_id` signed for a relayer to
+/// submit (and pay gas for) as a [`Action::Delegate`]
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+struct DelegateAction {
+    sender_id: String,
+    receiver_id: String,
+    /// Any actions but `Delegate`
+    actions: Vec<Action>,
+    /// Nonce of `public_key`, like a transaction's
+    nonce: u64,
+    /// Last block the relayer may include it in
+    max_block_height: u64,
+    public_key: PublicKey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+struct SignedDelegateAction {
+    delegate_action: DelegateAction,
+    signature: Signature,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
 struct AccessKey {
     nonce: u64,
     permission: AccessKeyPermission,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-enum AccessKeyPermission {
-    FunctionCall {
-        allowance: Option<u128>,
-        receiver_id: String,
-        method_names: Vec<String>,
-    },
-    FullAccess,
-}
+/// Permission of an access key added with [`add_access_key`]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Eq)]
+pub enum AccessKeyPermission {
+    /// Function calls to `receiver_id` only, paying gas from `allowance`
+    /// (None = unlimited). Empty `method_names` allows every method.
+    FunctionCall {
+        allowance: Option<u128>,
+        receiver_id: String,
+        method_names: Vec<String>,
+    },
+    FullAccess,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+struct FunctionCallAction {
+    method_name: String,
+    args: Vec<u8>,
+    gas: u64,
+    deposit: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct SignedTransaction {
+    transaction: Transaction,
+    signature: Signature,
+}
+
+impl SignedTransaction {
+    /// base58 sha256 of the unsigned transaction, the hash explorers and RPCs use
+    fn hash(&self) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(codec::base58_encode(Sha256::digest(borsh::to_vec(&self.transaction)?)))
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+enum Signature {
+    ED25519([u8; 64]),
+    /// r || s || recovery id
+    SECP256K1([u8; 65]),
+}
+
+impl Signature {
+    /// Raw signature bytes made by the key behind `public_key`
+    fn from_bytes(public_key: &PublicKey, bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let invalid = || format!("Invalid signature length for {}: {} bytes", public_key, bytes.len());
+        Ok(match public_key {
+            PublicKey::ED25519(_) => Signature::ED25519(bytes.try_into().map_err(|_| invalid())?),
+            PublicKey::SECP256K1(_) => Signature::SECP256K1(bytes.try_into().map_err(|_| invalid())?),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Signature::ED25519(bytes) => bytes.to_vec(),
+            Signature::SECP256K1(bytes) => bytes.to_vec(),
+        }
+    }
+}
+
+/// Private key of a transaction signer
+enum TxSigningKey {
+    Ed25519(SigningKey),
+    Secp256k1(k256::ecdsa::SigningKey),
+}
+
+impl TxSigningKey {
+    /// Parse `secp256k1:<base58 32-byte secret>` or `[ed25519:]<base58 seed or keypair>`
+    fn parse(private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(key_str) = private_key.strip_prefix("secp256k1:") {
+            let key_bytes = Zeroizing::new(
+                codec::base58_decode(key_str).map_err(|e| format!("Failed to decode private key: {}", e))?,
+            );
+            let key = k256::ecdsa::SigningKey::from_slice(&key_bytes)
+                .map_err(|_| format!("Invalid secp256k1 private key ({} bytes)", key_bytes.len()))?;
+            return Ok(TxSigningKey::Secp256k1(key));
+        }
+
+        Ok(TxSigningKey::Ed25519(crypto::parse_ed25519_key(private_key)?))
+    }
+
+    fn public_key(&self) -> PublicKey {
+        match self {
+            TxSigningKey::Ed25519(key) => PublicKey::ED25519(key.verifying_key().to_bytes()),
+            TxSigningKey::Secp256k1(key) => {
+                let point = key.verifying_key().to_encoded_point(false);
+                let mut bytes = [0u8; 64];
+                bytes.copy_from_slice(&point.as_bytes()[1..]);
+                PublicKey::SECP256K1(bytes)
+            }
+        }
+    }
+
+    /// Sign a 32-byte transaction hash
+    fn sign(&self, hash: &[u8]) -> Result<Signature, Box<dyn std::error::Error>> {
+        match self {
+            TxSigningKey::Ed25519(key) => Ok(Signature::ED25519(key.sign(hash).to_bytes())),
+            TxSigningKey::Secp256k1(key) => {
+                let (signature, recovery_id) = key
+                    .sign_prehash_recoverable(hash)
+                    .map_err(|e| format!("secp256k1 signing failed: {}", e))?;
+                let mut bytes = [0u8; 65];
+                bytes[..64].copy_from_slice(&signature.to_bytes());
+                bytes[64] = recovery_id.to_byte();
+                Ok(Signature::SECP256K1(bytes))
+            }
+        }
+    }
+}
+
+/// Signs a [`TxSigner`]'s transactions. Implement it to keep the private key
+/// out of the worker, e.g. behind a KMS or a remote signing service;
+/// [`LocalSigner`] holds the key in memory.
+pub trait TransactionSigner {
+    /// `ed25519:...` or `secp256k1:...`
+    fn public_key(&self) -> String;
+
+    /// Signature over a 32-byte transaction hash: 64 bytes for ed25519,
+    /// 65 (r || s || recovery id) for secp256k1
+    fn sign(&self, hash: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>>;
+}
+
+/// [`TransactionSigner`] with the private key in memory
+pub struct LocalSigner(TxSigningKey);
+
+impl LocalSigner {
+    /// Parse a private key in any format accepted by [`TxSigner::new`]
+    pub fn new(private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self(TxSigningKey::parse(private_key)?))
+    }
+}
+
+impl TransactionSigner for LocalSigner {
+    fn public_key(&self) -> String {
+        self.0.public_key().to_string()
+    }
+
+    fn sign(&self, hash: &[u8]) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(self.0.sign(hash)?.to_bytes())
+    }
+}
+
+/// Public key (`ed25519:...` or `secp256k1:...`) of a private key in any
+/// format accepted for signing transactions
+pub fn public_key_of(private_key: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(TxSigningKey::parse(private_key)?.public_key().to_string())
+}
+
+// ============================================================================
+// Public API - Universal Functions
+// ============================================================================
+
+/// Universal view function - read-only RPC call
+/// Returns parsed JSON result as string
+pub fn view(
+    rpc: &RpcClient,
+    contract_id: &str,
+    method_name: &str,
+    args: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(String::from_utf8(call_view(rpc, contract_id, method_name, args.as_bytes())?)?)
+}
+
+/// [`view`] with JSON `args`, decoding the JSON result into `T`
+pub fn view_typed<T: serde::de::DeserializeOwned>(
+    rpc: &RpcClient,
+    contract_id: &str,
+    method_name: &str,
+    args: &serde_json::Value,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let result = call_view(rpc, contract_id, method_name, args.to_string().as_bytes())?;
+    serde_json::from_slice(&result)
+        .map_err(|e| format!("Unexpected result from {}.{}: {}", contract_id, method_name, e).into())
+}
+
+/// One view call of a [`view_many`] batch
+#[derive(Debug, Clone)]
+pub struct ViewSpec {
+    pub contract_id: String,
+    pub method_name: String,
+    pub args: serde_json::Value,
+}
+
+impl ViewSpec {
+    pub fn new(contract_id: &str, method_name: &str, args: serde_json::Value) -> Self {
+        Self {
+            contract_id: contract_id.to_string(),
+            method_name: method_name.to_string(),
+            args,
+        }
+    }
+}
+
+/// Outcome of one [`ViewSpec`]; a failed call doesn't fail the batch
+#[derive(Debug, Clone)]
+pub struct ViewResult {
+    spec: ViewSpec,
+    result: Result<Vec<u8>, String>,
+}
+
+impl ViewResult {
+    /// Raw bytes the method returned
+    pub fn raw(&self) -> Result<&[u8], Box<dyn std::error::Error>> {
+        self.result.as_deref().map_err(|e| e.as_str().into())
+    }
+
+    /// JSON result decoded into `T`, like [`view_typed`]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Box<dyn std::error::Error>> {
+        serde_json::from_slice(self.raw()?).map_err(|e| {
+            format!("Unexpected result from {}.{}: {}", self.spec.contract_id, self.spec.method_name, e).into()
+        })
+    }
+}
+
+/// Run several view calls over the shared client, in order. All calls read
+/// the same block (the final block when the first one ran), so e.g. balances
+/// and metadata of different tokens are consistent with each other.
+pub fn view_many(rpc: &RpcClient, specs: Vec<ViewSpec>) -> Vec<ViewResult> {
+    let mut block_hash: Option<String> = None;
+    specs
+        .into_iter()
+        .map(|spec| {
+            let args = spec.args.to_string();
+            let result = call_view_at(rpc, &spec.contract_id, &spec.method_name, args.as_bytes(), block_hash.as_deref())
+                .map(|(result, at)| {
+                    if let Some(at) = at {
+                        block_hash.get_or_insert(at);
+                    }
+                    result
+                })
+                .map_err(|e| e.to_string());
+            ViewResult { spec, result }
+        })
+        .collect()
+}
+
+/// Raw result bytes of a view call
+fn call_view(
+    rpc: &RpcClient,
+    contract_id: &str,
+    method_name: &str,
+    args: &[u8],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(call_view_at(rpc, contract_id, method_name, args, None)?.0)
+}
+
+/// Raw result bytes of a view call at `block_hash` (None: the final block),
+/// with the hash of the block that was read
+fn call_view_at(
+    rpc: &RpcClient,
+    contract_id: &str,
+    method_name: &str,
+    args: &[u8],
+    block_hash: Option<&str>,
+) -> Result<(Vec<u8>, Option<String>), Box<dyn std::error::Error>> {
+    eprintln!("🔍 View call: {}.{}", contract_id, method_name);
+
+    let mut params = serde_json::json!({
+        "request_type": "call_function",
+        "account_id": contract_id,
+        "method_name": method_name,
+        "args_base64": codec::base64_encode(args)
+    });
+    match block_hash {
+        Some(block_hash) => params["block_id"] = block_hash.into(),
+        None => params["finality"] = "final".into(),
+    }
+    let result = query(rpc, params)?;
+
+    let block_hash = result["block_hash"].as_str().map(String::from);
+    let bytes = serde_json::from_value(result.get("result").cloned().ok_or("No result in response")?)?;
+    Ok((bytes, block_hash))
+}
+
+/// Access key as returned by the `view_access_key` query
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccessKeyView {
+    pub nonce: u64,
+    pub permission: AccessKeyPermissionView,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum AccessKeyPermissionView {
+    FullAccess,
+    FunctionCall {
+        /// Remaining allowance in yoctoNEAR (None = unlimited)
+        allowance: Option<String>,
+        receiver_id: String,
+        method_names: Vec<String>,
+    },
+}
+
+impl AccessKeyPermissionView {
+    /// Whether a key with this permission may sign a transaction of `actions`
+    /// to `receiver_id`. `gas_price` is only queried for keys with a limited
+    /// allowance, which must cover the attached gas at that price.
+    fn check(
+        &self,
+        receiver_id: &str,
+        actions: &[Action],
+        gas_price: impl FnOnce() -> Result<u128, Box<dyn std::error::Error>>,
+    ) -> Result<(), String> {
+        let AccessKeyPermissionView::FunctionCall { allowance, receiver_id: allowed_receiver, method_names } = self else {
+            return Ok(());
+        };
+        if receiver_id != allowed_receiver {
+            return Err(format!("function call key only allows calls to {}, not {}", allowed_receiver, receiver_id));
+        }
+        // NEAR refuses anything else from a function call key (RequiresFullAccess)
+        if actions.len() != 1 {
+            return Err(format!("function call key can only sign a single action, not {}", actions.len()));
+        }
+
+        let mut gas: u128 = 0;
+        for action in actions {
+            let Action::FunctionCall(call) = action else {
+                return Err("function call key can only sign function calls".to_string());
+            };
+            if !method_names.is_empty() && !method_names.contains(&call.method_name) {
+                return Err(format!("function call key does not allow method {} (allowed: {})",
+                    call.method_name, method_names.join(", ")));
+            }
+            if call.deposit > 0 {
+                return Err(format!("function call key cannot attach a deposit ({} yoctoNEAR to {})",
+                    call.deposit, call.method_name));
+            }
+            gas += call.gas as u128;
+        }
+
+        let Some(allowance) = allowance else {
+            return Ok(());
+        };
+        let allowance = crate::amount::parse(allowance).map_err(|e| format!("invalid allowance: {}", e))?;
+        let cost = gas * gas_price().map_err(|e| format!("could not check allowance: {}", e))?;
+        if cost > allowance {
+            return Err(format!("remaining allowance {} yoctoNEAR does not cover {} gas ({} yoctoNEAR)",
+                allowance, gas, cost));
+        }
+        Ok(())
+    }
+}
+
+/// Hash of a borsh-serialized, base64-encoded signed transaction (as sent to
+/// `send_tx`), computed locally
+pub fn signed_tx_hash(signed_tx_base64: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = codec::base64_decode(signed_tx_base64)?;
+    borsh::from_slice::<SignedTransaction>(&bytes)?.hash()
+}
+
+/// Look up an access key (`public_key` as "ed25519:...") on `account_id`.
+/// Fails if the key doesn't exist.
+pub fn view_access_key(
+    rpc: &RpcClient,
+    account_id: &str,
+    public_key: &str,
+) -> Result<AccessKeyView, Box<dyn std::error::Error>> {
+    let result = query(
+        rpc,
+        serde_json::json!({
+            "request_type": "view_access_key",
+            "finality": "final",
+            "account_id": account_id,
+            "public_key": public_key
+        }),
+    )?;
+    Ok(serde_json::from_value(result)?)
+}
+
+/// One key of a `view_access_key_list` result
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccessKeyInfoView {
+    pub public_key: String,
+    pub access_key: AccessKeyView,
+}
+
+#[derive(Deserialize)]
+struct AccessKeyList {
+    keys: Vec<AccessKeyInfoView>,
+}
+
+/// All access keys of `account_id`
+pub fn view_access_key_list(
+    rpc: &RpcClient,
+    account_id: &str,
+) -> Result<Vec<AccessKeyInfoView>, Box<dyn std::error::Error>> {
+    let result = query(
+        rpc,
+        serde_json::json!({
+            "request_type": "view_access_key_list",
+            "finality": "final",
+            "account_id": account_id
+        }),
+    )?;
+    Ok(serde_json::from_value::<AccessKeyList>(result)?.keys)
+}
+
+/// Account state as returned by the `view_account` query
+#[derive(Deserialize, Debug, Clone)]
+pub struct AccountView {
+    /// Liquid balance in yoctoNEAR
+    #[serde(deserialize_with = "u128_string")]
+    pub amount: u128,
+    /// Balance locked for staking, in yoctoNEAR
+    #[serde(deserialize_with = "u128_string")]
+    pub locked: u128,
+    /// `11111111111111111111111111111111` if no contract is deployed
+    pub code_hash: String,
+    /// Bytes of state the account pays storage for
+    pub storage_usage: u64,
+}
+
+/// Balance and storage usage of `account_id`
+pub fn view_account(rpc: &RpcClient, account_id: &str) -> Result<AccountView, Box<dyn std::error::Error>> {
+    let result = query(
+        rpc,
+        serde_json::json!({
+            "request_type": "view_account",
+            "finality": "final",
+            "account_id": account_id
+        }),
+    )?;
+    Ok(serde_json::from_value(result)?)
+}
+
+/// Run a `query` RPC request, returning its result
+fn query(rpc: &RpcClient, params: serde_json::Value) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": "dontcare",
+        "method": "query",
+        "params": params
+    });
+
+    let response = rpc.post(&request)?;
+
+    let status = response.status;
+    if status != 200 {
+        return Err(format!("RPC returned status {}", status).into());
+    }
+
+    let mut json: serde_json::Value = serde_json::from_slice(&response.body)?;
+
+    if let Some(error) = json.get("error") {
+        return Err(format!("RPC error: {}", error).into());
+    }
+
+    let result = json.get_mut("result").ok_or("No result in response")?.take();
+    // Older nodes report some failures (e.g. a missing key) as a result with an "error" field
+    if let Some(error) = result.get("error") {
+        return Err(format!("RPC error: {}", error).into());
+    }
+
+    Ok(result)
+}
+
+/// Deserialize an optional u128 sent as a decimal string
+pub(crate) fn option_u128_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<u128>, D::Error> {
+    <Option<String> as Deserialize>::deserialize(deserializer)?
+        .map(|value| crate::amount::parse(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Deserialize a u128 sent as a decimal string
+pub(crate) fn u128_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+    let value = <String as Deserialize>::deserialize(deserializer)?;
+    crate::amount::parse(&value).map_err(serde::de::Error::custom)
+}
+
+/// Universal call function - send transaction with function call
+/// Returns the execution outcome
+#[allow(clippy::too_many_arguments)]
+pub fn call(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    contract_id: &str,
+    method_name: &str,
+    args: &str,
+    gas: u64,
+    deposit: u128,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?
+        .call(contract_id, method_name, args, gas, deposit)
+}
+
+/// One FunctionCall action of a [`call_batch`] transaction
+#[derive(Debug, Clone)]
+pub struct FunctionCallSpec {
+    pub method_name: String,
+    /// Raw arguments, usually JSON bytes
+    pub args: Vec<u8>,
+    pub gas: u64,
+    pub deposit: u128,
+}
+
+impl FunctionCallSpec {
+    /// Call with JSON-serialized `args`
+    pub fn json(method_name: &str, args: &serde_json::Value, gas: u64, deposit: u128) -> Self {
+        Self {
+            method_name: method_name.to_string(),
+            args: args.to_string().into_bytes(),
+            gas,
+            deposit,
+        }
+    }
+
+    fn into_action(self) -> Action {
+        Action::FunctionCall(FunctionCallAction {
+            method_name: self.method_name,
+            args: self.args,
+            gas: self.gas,
+            deposit: self.deposit,
+        })
+    }
+}
+
+/// Several function calls on one contract in a single transaction.
+/// Actions execute in order and atomically: if one fails, none take effect
+/// (attached deposits are refunded). Total gas must fit in one transaction (300 TGas).
+/// Returns the execution outcome
+pub fn call_batch(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    contract_id: &str,
+    calls: Vec<FunctionCallSpec>,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.call_batch(contract_id, calls)
+}
+
+/// Send `amount_yocto` NEAR from the signer to `receiver_id`
+/// Returns the execution outcome
+pub fn transfer_near(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    receiver_id: &str,
+    amount_yocto: u128,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.transfer_near(receiver_id, amount_yocto)
+}
+
+/// Add `public_key` (`ed25519:...`) to the signer's account with `permission`
+/// Returns the execution outcome
+pub fn add_access_key(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    public_key: &str,
+    permission: AccessKeyPermission,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.add_access_key(public_key, permission)
+}
+
+/// Remove `public_key` (`ed25519:...`) from the signer's account. The signing
+/// key may delete itself, so keep another full-access key when rotating.
+/// Returns the execution outcome
+pub fn delete_access_key(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    public_key: &str,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.delete_access_key(public_key)
+}
+
+/// Create `new_account_id` (a sub-account of the signer, or a top-level
+/// account when signed by the registrar) with `public_key` as its full-access
+/// key, funded with `initial_balance` yoctoNEAR
+/// Returns the execution outcome
+pub fn create_account(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    new_account_id: &str,
+    public_key: &str,
+    initial_balance: u128,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.create_account(new_account_id, public_key, initial_balance)
+}
+
+/// Deploy `code` (WASM) to the signer's account, calling `init` in the same
+/// transaction so the contract is never left uninitialized
+/// Returns the execution outcome
+pub fn deploy_contract(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    code: Vec<u8>,
+    init: Option<FunctionCallSpec>,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.deploy_contract(code, init)
+}
+
+/// Poll `tx_status` until the transaction signed by `signer_account_id` has
+/// executed, failing if it executed with an error or `timeout` passes first
+pub fn wait_for_tx(
+    rpc: &RpcClient,
+    tx_hash: &str,
+    signer_account_id: &str,
+    timeout: Duration,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    wait_for_finality(rpc, tx_hash, signer_account_id, TxFinality::ExecutedOptimistic, timeout)
+}
+
+/// Like [`wait_for_tx`], but returns only once the transaction has reached
+/// `finality`. [`TxFinality::Final`] guards against acting on an optimistic
+/// outcome that a re-org could still undo.
+pub fn wait_for_finality(
+    rpc: &RpcClient,
+    tx_hash: &str,
+    signer_account_id: &str,
+    finality: TxFinality,
+    timeout: Duration,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    eprintln!("⏳ Waiting for transaction {} to reach {:?}...", tx_hash, finality);
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        // Unknown until a node has seen the transaction (or, once it is
+        // old, to all but an archival node)
+        let result = with_archival_fallback(rpc, &format!("Transaction {}", tx_hash), |rpc| {
+            query_tx(rpc, "tx", tx_hash, signer_account_id)
+        })?;
+        if let Some(result) = result {
+            let outcome = outcome_from_result(tx_hash.to_string(), result)?;
+            if outcome.finality >= finality {
+                return Ok(outcome);
+            }
+        }
+
+        if Instant::now() + TX_POLL_INTERVAL > deadline {
+            return Err(format!("Transaction {} did not reach {:?} within {:?}", tx_hash, finality, timeout).into());
+        }
+        std::thread::sleep(TX_POLL_INTERVAL);
+    }
+}
+
+thread_local! {
+    static ARCHIVAL_RPC_URL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// RPC to fall back to for historical lookups ([`tx_status`],
+/// [`wait_for_finality`], [`light_client_proof`]); None disables it
+pub fn set_archival_rpc_url(url: Option<String>) {
+    ARCHIVAL_RPC_URL.with(|archival| *archival.borrow_mut() = url);
+}
+
+fn archival_rpc_url() -> Option<String> {
+    ARCHIVAL_RPC_URL.with(|archival| archival.borrow().clone())
+}
+
+/// Run `lookup` on `rpc` and, if that node doesn't have the data, again on
+/// the archival RPC (if one is set and `rpc` isn't already using it)
+fn with_archival_fallback<T>(
+    rpc: &RpcClient,
+    what: &str,
+    lookup: impl Fn(&RpcClient) -> Result<Option<T>, Box<dyn std::error::Error>>,
+) -> Result<Option<T>, Box<dyn std::error::Error>> {
+    if let Some(found) = lookup(rpc)? {
+        return Ok(Some(found));
+    }
+    match archival_rpc_url().filter(|url| !rpc.urls().contains(url)) {
+        Some(archival_rpc_url) => {
+            eprintln!("🗄️  {} unknown to {}, asking archival RPC", what, rpc.urls().join(", "));
+            lookup(&RpcClient::new(&archival_rpc_url))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Look up a past transaction signed by `signer_account_id`, with all its
+/// receipts. Transactions the RPC doesn't know (e.g. older than its
+/// retention) are looked up on the archival RPC, if one is set with
+/// [`set_archival_rpc_url`]. None if neither knows the transaction or it
+/// hasn't executed yet. A failed transaction is still returned; check
+/// [`FinalExecutionOutcomeView::error`].
+pub fn tx_status(
+    rpc: &RpcClient,
+    tx_hash: &str,
+    signer_account_id: &str,
+) -> Result<Option<FinalExecutionOutcomeView>, Box<dyn std::error::Error>> {
+    let result = with_archival_fallback(rpc, &format!("Transaction {}", tx_hash), |rpc| {
+        query_tx(rpc, "EXPERIMENTAL_tx_status", tx_hash, signer_account_id)
+    })?;
+    let Some(result) = result else {
+        return Ok(None);
+    };
+    let executed = result
+        .get("final_execution_status")
+        .and_then(|status| serde_json::from_value::<TxFinality>(status.clone()).ok())
+        .is_none_or(TxFinality::is_executed);
+    if !executed {
+        return Ok(None);
+    }
+
+    serde_json::from_value(result)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse tx_status of {}: {}", tx_hash, e).into())
+}
+
+/// Current gas price in yoctoNEAR per gas unit
+pub fn gas_price(rpc: &RpcClient) -> Result<u128, Box<dyn std::error::Error>> {
+    let result = rpc_result(rpc, "gas_price", serde_json::json!([null]))?;
+    let price = result["gas_price"].as_str().ok_or("No gas_price in response")?;
+    Ok(crate::amount::parse(price)?)
+}
+
+/// Chain parameters from `EXPERIMENTAL_protocol_config` that deposits and
+/// gas amounts depend on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolConfig {
+    pub protocol_version: u32,
+    /// yoctoNEAR locked per byte of account storage
+    pub storage_amount_per_byte: u128,
+    pub min_gas_price: u128,
+    pub max_gas_price: u128,
+}
+
+impl ProtocolConfig {
+    /// Balance that must be locked to store `bytes` bytes
+    pub fn storage_cost(&self, bytes: u64) -> u128 {
+        self.storage_amount_per_byte * bytes as u128
+    }
+}
+
+/// Protocol config of the latest final block
+pub fn protocol_config(rpc: &RpcClient) -> Result<ProtocolConfig, Box<dyn std::error::Error>> {
+    #[derive(Deserialize)]
+    struct RuntimeConfig {
+        #[serde(deserialize_with = "u128_string")]
+        storage_amount_per_byte: u128,
+    }
+    #[derive(Deserialize)]
+    struct ProtocolConfigView {
+        protocol_version: u32,
+        #[serde(deserialize_with = "u128_string")]
+        min_gas_price: u128,
+        #[serde(deserialize_with = "u128_string")]
+        max_gas_price: u128,
+        runtime_config: RuntimeConfig,
+    }
+
+    let result = rpc_result(rpc, "EXPERIMENTAL_protocol_config", serde_json::json!({"finality": "final"}))?;
+    let view: ProtocolConfigView = serde_json::from_value(result)?;
+    Ok(ProtocolConfig {
+        protocol_version: view.protocol_version,
+        storage_amount_per_byte: view.runtime_config.storage_amount_per_byte,
+        min_gas_price: view.min_gas_price,
+        max_gas_price: view.max_gas_price,
+    })
+}
+
+/// Proof that a transaction's outcome is part of the chain, verifiable by a
+/// light client that trusts the block `light_client_head`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightClientProof {
+    pub light_client_head: String,
+    /// `EXPERIMENTAL_light_client_proof` result, kept verbatim so it can be
+    /// handed to a verifier
+    pub proof: serde_json::Value,
+}
+
+/// Inclusion proof of transaction `tx_hash` sent by `sender_id` to
+/// `receiver_id`, anchored at the latest final block. A transaction the RPC
+/// no longer has is proven by the archival RPC, against its own head.
+pub fn light_client_proof(
+    rpc: &RpcClient,
+    tx_hash: &str,
+    sender_id: &str,
+    receiver_id: &str,
+) -> Result<LightClientProof, Box<dyn std::error::Error>> {
+    let proof = with_archival_fallback(rpc, &format!("Transaction {}", tx_hash), |rpc| {
+        let block = rpc_result(rpc, "block", serde_json::json!({"finality": "final"}))?;
+        let light_client_head = block["header"]["hash"]
+            .as_str()
+            .ok_or("No header.hash in block response")?
+            .to_string();
+
+        let proof = rpc_lookup(rpc, "EXPERIMENTAL_light_client_proof", serde_json::json!({
+            "type": "transaction",
+            "transaction_hash": tx_hash,
+            "sender_id": sender_id,
+            "receiver_id": receiver_id,
+            "light_client_head": light_client_head,
+        }))?;
+        Ok(proof.map(|proof| LightClientProof { light_client_head, proof }))
+    })?;
+    proof.ok_or_else(|| format!("No light client proof of {}: transaction unknown to the RPC", tx_hash).into())
+}
+
+/// Result of a JSON-RPC `method` call, failing on RPC errors
+fn rpc_result(
+    rpc: &RpcClient,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let request = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: "dontcare".to_string(),
+        method: method.to_string(),
+        params,
+    };
+
+    let json_response = post_rpc(rpc, &request)?;
+    if let Some(error) = json_response.error {
+        return Err(format!("{} failed: {}", method, error.message).into());
+    }
+    json_response.result.ok_or_else(|| "No result in RPC response".into())
+}
+
+/// Like [`rpc_result`], but None when the node doesn't have the data
+fn rpc_lookup(
+    rpc: &RpcClient,
+    method: &str,
+    params: serde_json::Value,
+) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
+    let request = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: "dontcare".to_string(),
+        method: method.to_string(),
+        params,
+    };
+
+    let json_response = post_rpc(rpc, &request)?;
+    if let Some(error) = json_response.error {
+        if error.is_missing_data() {
+            return Ok(None);
+        }
+        return Err(format!("{} failed: {}", method, error.message).into());
+    }
+    json_response.result.ok_or_else(|| "No result in RPC response".into()).map(Some)
+}
+
+// ============================================================================
+// Convenience Functions (use call/view internally)
+// ============================================================================
+
+/// Gas and attached deposit for the `*_with` convenience calls on
+/// [`TxSigner`]; None keeps the method's default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallOverrides {
+    pub gas: Option<u64>,
+    /// yoctoNEAR
+    pub deposit: Option<u128>,
+}
+
+/// Call storage_deposit on NEAR fungible token contract, attaching the
+/// minimum from its `storage_balance_bounds`
+pub fn storage_deposit(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    token_contract: &str,
+    account_id: Option<&str>,
+    registration_only: bool,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?
+        .storage_deposit(token_contract, account_id, registration_only)
+}
+
+/// NEP-145 `storage_balance_bounds` result
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageBalanceBounds {
+    #[serde(deserialize_with = "u128_string")]
+    pub min: u128,
+    /// None if there is no maximum
+    #[serde(default, deserialize_with = "option_u128_string")]
+    pub max: Option<u128>,
+}
+
+/// Storage deposit bounds of a NEP-145 contract, in yoctoNEAR
+pub fn storage_balance_bounds(
+    rpc: &RpcClient,
+    contract_id: &str,
+) -> Result<StorageBalanceBounds, Box<dyn std::error::Error>> {
+    view_typed(rpc, contract_id, "storage_balance_bounds", &serde_json::json!({}))
+}
+
+/// Deposit needed to register with `token_contract`. Tokens that don't
+/// report bounds get the protocol's cost of [`FT_REGISTRATION_BYTES`], or
+/// [`DEFAULT_STORAGE_DEPOSIT`] if that can't be read either.
+fn storage_minimum(rpc: &RpcClient, token_contract: &str) -> u128 {
+    let e = match storage_balance_bounds(rpc, token_contract) {
+        Ok(bounds) => return bounds.min,
+        Err(e) => e,
+    };
+    let deposit = protocol_config(rpc)
+        .map(|config| config.storage_cost(FT_REGISTRATION_BYTES))
+        .unwrap_or(DEFAULT_STORAGE_DEPOSIT);
+    eprintln!("⚠️  Could not read storage_balance_bounds of {}: {}. Attaching {} yoctoNEAR",
+        token_contract, e, deposit);
+    deposit
+}
+
+/// Call storage_withdraw on a NEP-145 contract, withdrawing `amount`
+/// (None = all) of the signer's storage balance above the minimum
+pub fn storage_withdraw(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    contract_id: &str,
+    amount: Option<u128>,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.storage_withdraw(contract_id, amount)
+}
+
+/// Call storage_unregister on a NEP-145 contract, refunding the signer's
+/// storage deposit. Fails unless the signer's balance is zero or `force`
+/// is set, in which case a fungible token burns the remaining balance.
+pub fn storage_unregister(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    contract_id: &str,
+    force: bool,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?.storage_unregister(contract_id, force)
+}
+
+/// Call ft_transfer_call on NEAR via JSON-RPC
+pub fn ft_transfer_call(
+    rpc: &RpcClient,
+    signer_account_id: &str,
+    signer_private_key: &str,
+    token_contract: &str,
+    receiver_id: &str,
+    amount: &str,
+    msg: &str,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    TxSigner::new(rpc, signer_account_id, signer_private_key)?
+        .ft_transfer_call(token_contract, receiver_id, amount, msg)
+}
+
+// ============================================================================
+// Transaction Signer
+// ============================================================================
+
+/// Signs and sends transactions for one account key.
+///
+/// The free functions above build a fresh signer per call. Keeping one
+/// signer for several transactions saves the access key round trip: the
+/// nonce and block hash are fetched once, and the nonce is then incremented
+/// locally. If the chain rejects a nonce (e.g. another process used the same
+/// key) or the block hash has expired, the cache is refreshed and the
+/// transaction re-signed.
+pub struct TxSigner {
+    rpc: RpcClient,
+    account_id: String,
+    key: Box<dyn TransactionSigner>,
+    public_key: PublicKey,
+    /// Last nonce used by this signer and a recent block hash
+    cached: Cell<Option<(u64, [u8; 32])>>,
+    /// Permission of the signing key, fetched together with the nonce
+    permission: RefCell<Option<AccessKeyPermissionView>>,
+    /// None: broadcast_tx_commit
+    wait_until: Cell<Option<TxFinality>>,
+    last_tx_hash: RefCell<Option<String>>,
+}
+
+impl TxSigner {
+    pub fn new(rpc: &RpcClient, account_id: &str, private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::with_signer(rpc, account_id, LocalSigner::new(private_key)?)
+    }
+
+    /// Signer whose transactions are signed by `key`, e.g. a remote signer
+    /// that never exposes the private key
+    pub fn with_signer(
+        rpc: &RpcClient,
+        account_id: &str,
+        key: impl TransactionSigner + 'static,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            rpc: rpc.clone(),
+            account_id: account_id.to_string(),
+            public_key: key.public_key().parse::<PublicKey>()?,
+            key: Box::new(key),
+            cached: Cell::new(None),
+            permission: RefCell::new(None),
+            wait_until: Cell::new(None),
+            last_tx_hash: RefCell::new(None),
+        })
+    }
+
+    /// Hash of the last transaction this signer signed, known before it is
+    /// broadcast. Lets a caller whose send failed (e.g. the response was lost)
+    /// look the transaction up anyway.
+    pub fn last_tx_hash(&self) -> Option<String> {
+        self.last_tx_hash.borrow().clone()
+    }
+
+    /// Send subsequent transactions with `send_tx`, returning once they reach
+    /// `wait_until` (None restores the default, `broadcast_tx_commit`).
+    /// Below [`TxFinality::ExecutedOptimistic`] the outcome only has a tx hash.
+    pub fn set_wait_until(&self, wait_until: Option<TxFinality>) {
+        self.wait_until.set(wait_until);
+    }
+
+    pub fn account_id(&self) -> &str {
+        &self.account_id
+    }
+
+    /// Signer's public key (`ed25519:...` or `secp256k1:...`)
+    pub fn public_key(&self) -> String {
+        self.public_key.to_string()
+    }
+
+    /// Function call transaction
+    pub fn call(
+        &self,
+        contract_id: &str,
+        method_name: &str,
+        args: &str,
+        gas: u64,
+        deposit: u128,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("📤 Call: {}.{}", contract_id, method_name);
+
+        self.send_actions(
+            contract_id,
+            vec![Action::FunctionCall(FunctionCallAction {
+                method_name: method_name.to_string(),
+                args: args.as_bytes().to_vec(),
+                gas,
+                deposit,
+            })],
+        )
+    }
+
+    /// See [`call_batch`]
+    pub fn call_batch(
+        &self,
+        contract_id: &str,
+        calls: Vec<FunctionCallSpec>,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        if calls.is_empty() {
+            return Err("call_batch needs at least one function call".into());
+        }
+        let methods: Vec<&str> = calls.iter().map(|c| c.method_name.as_str()).collect();
+        eprintln!("📤 Batch call: {}.[{}]", contract_id, methods.join(", "));
+
+        let actions = calls.into_iter().map(FunctionCallSpec::into_action).collect();
+
+        self.send_actions(contract_id, actions)
+    }
+
+    /// Send function calls without waiting for them to execute. The RPC
+    /// still validates the transaction (nonce, balance), but the result is
+    /// only the tx hash; pass it to [`wait_for_tx`] once the outcome matters.
+    pub fn broadcast_async(
+        &self,
+        contract_id: &str,
+        calls: Vec<FunctionCallSpec>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let methods: Vec<&str> = calls.iter().map(|c| c.method_name.as_str()).collect();
+        eprintln!("📤 Async call: {}.[{}]", contract_id, methods.join(", "));
+
+        let actions = calls.into_iter().map(FunctionCallSpec::into_action).collect();
+        Ok(self.send_actions_until(contract_id, actions, Some(TxFinality::None))?.tx_hash)
+    }
+
+    /// See [`transfer_near`]
+    pub fn transfer_near(&self, receiver_id: &str, amount_yocto: u128) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("📤 Transfer: {} yoctoNEAR {} → {}", amount_yocto, self.account_id, receiver_id);
+
+        self.send_actions(receiver_id, vec![Action::Transfer(amount_yocto)])
+    }
+
+    /// See [`add_access_key`]
+    pub fn add_access_key(
+        &self,
+        public_key: &str,
+        permission: AccessKeyPermission,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("🔑 Adding access key {} to {} ({:?})", public_key, self.account_id, permission);
+
+        self.send_actions(
+            &self.account_id,
+            vec![Action::AddKey {
+                public_key: public_key.parse::<PublicKey>()?,
+                access_key: AccessKey { nonce: 0, permission },
+            }],
+        )
+    }
+
+    /// See [`delete_access_key`]
+    pub fn delete_access_key(&self, public_key: &str) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("🔑 Deleting access key {} from {}", public_key, self.account_id);
+
+        self.send_actions(&self.account_id, vec![Action::DeleteKey(public_key.parse::<PublicKey>()?)])
+    }
+
+    /// See [`create_account`]
+    pub fn create_account(
+        &self,
+        new_account_id: &str,
+        public_key: &str,
+        initial_balance: u128,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("🆕 Creating account {} ({} yoctoNEAR) from {}", new_account_id, initial_balance, self.account_id);
+
+        self.send_actions(
+            new_account_id,
+            vec![
+                Action::CreateAccount,
+                Action::Transfer(initial_balance),
+                Action::AddKey {
+                    public_key: public_key.parse::<PublicKey>()?,
+                    access_key: AccessKey { nonce: 0, permission: AccessKeyPermission::FullAccess },
+                },
+            ],
+        )
+    }
+
+    /// See [`deploy_contract`]
+    pub fn deploy_contract(
+        &self,
+        code: Vec<u8>,
+        init: Option<FunctionCallSpec>,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("📦 Deploying {} bytes to {}{}", code.len(), self.account_id,
+            init.as_ref().map(|call| format!(" (init: {})", call.method_name)).unwrap_or_default());
+
+        let mut actions = vec![Action::DeployContract(code)];
+        actions.extend(init.map(FunctionCallSpec::into_action));
+        self.send_actions(&self.account_id, actions)
+    }
+
+    /// See [`storage_deposit`]
+    pub fn storage_deposit(
+        &self,
+        token_contract: &str,
+        account_id: Option<&str>,
+        registration_only: bool,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        self.storage_deposit_with(token_contract, account_id, registration_only, CallOverrides::default())
+    }
+
+    /// [`storage_deposit`](Self::storage_deposit) with non-default gas or
+    /// deposit. By default the token's `storage_balance_bounds` minimum is attached.
+    pub fn storage_deposit_with(
+        &self,
+        token_contract: &str,
+        account_id: Option<&str>,
+        registration_only: bool,
+        overrides: CallOverrides,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let deposit = match overrides.deposit {
+            Some(deposit) => deposit,
+            None => storage_minimum(&self.rpc, token_contract),
+        };
+
+        let args = serde_json::json!({
+            "account_id": account_id,
+            "registration_only": registration_only
+        });
+
+        self.call(
+            token_contract,
+            "storage_deposit",
+            &args.to_string(),
+            overrides.gas.unwrap_or(30_000_000_000_000), // 30 TGas
+            deposit,
+        )
+    }
+
+    /// See [`storage_withdraw`]
+    pub fn storage_withdraw(
+        &self,
+        contract_id: &str,
+        amount: Option<u128>,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let args = serde_json::json!({
+            "amount": amount.map(|a| a.to_string())
+        });
+
+        self.call(
+            contract_id,
+            "storage_withdraw",
+            &args.to_string(),
+            30_000_000_000_000, // 30 TGas
+            1,                  // 1 yoctoNEAR
+        )
+    }
+
+    /// See [`storage_unregister`]. The outcome's return value is `false` if
+    /// the signer wasn't registered.
+    pub fn storage_unregister(&self, contract_id: &str, force: bool) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let args = serde_json::json!({
+            "force": force
+        });
+
+        self.call(
+            contract_id,
+            "storage_unregister",
+            &args.to_string(),
+            30_000_000_000_000, // 30 TGas
+            1,                  // 1 yoctoNEAR
+        )
+    }
+
+    /// See [`ft_transfer_call`]
+    pub fn ft_transfer_call(
+        &self,
+        token_contract: &str,
+        receiver_id: &str,
+        amount: &str,
+        msg: &str,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        self.ft_transfer_call_with(token_contract, receiver_id, amount, msg, CallOverrides::default())
+    }
+
+    /// [`ft_transfer_call`](Self::ft_transfer_call) with non-default gas,
+    /// e.g. for receivers whose `ft_on_transfer` needs less (or more) than 300 TGas.
+    /// NEP-141 requires exactly 1 yoctoNEAR, so a deposit override is rarely useful.
+    pub fn ft_transfer_call_with(
+        &self,
+        token_contract: &str,
+        receiver_id: &str,
+        amount: &str,
+        msg: &str,
+        overrides: CallOverrides,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        eprintln!("🔐 Signing ft_transfer_call transaction...");
+
+        let args = serde_json::json!({
+            "receiver_id": receiver_id,
+            "amount": amount,
+            "msg": msg
+        });
+
+        self.call(
+            token_contract,
+            "ft_transfer_call",
+            &args.to_string(),
+            overrides.gas.unwrap_or(300_000_000_000_000), // 300 TGas
+            overrides.deposit.unwrap_or(1),               // 1 yoctoNEAR
+        )
+    }
+
+    /// Sign and send a transaction with arbitrary actions. A stale nonce or
+    /// expired block hash means the transaction never executed, so it is
+    /// re-signed with fresh access key info, up to [`MAX_TX_ATTEMPTS`] times.
+    fn send_actions(&self, receiver_id: &str, actions: Vec<Action>) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        self.send_actions_until(receiver_id, actions, self.wait_until.get())
+    }
+
+    fn send_actions_until(
+        &self,
+        receiver_id: &str,
+        actions: Vec<Action>,
+        wait_until: Option<TxFinality>,
+    ) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        self.last_tx_hash.replace(None);
+        let mut attempt = 1;
+        loop {
+            let (nonce, block_hash) = self.next_nonce()?;
+            self.check_permission(receiver_id, &actions)?;
+            let signed_tx = self.sign(nonce, block_hash, receiver_id, actions.clone())?;
+            let tx_hash = signed_tx.hash()?;
+            eprintln!("📝 Transaction hash: {}", tx_hash);
+            self.last_tx_hash.replace(Some(tx_hash));
+
+            match send_transaction(&self.rpc, &signed_tx, wait_until) {
+                Err(e)
+                    if attempt < MAX_TX_ATTEMPTS
+                        && e.downcast_ref::<InvalidTxError>().is_some_and(InvalidTxError::is_retryable) =>
+                {
+                    // Another transaction used the key, or the cached block hash got too old
+                    eprintln!("⚠️  {} (attempt {}/{}). Refreshing access key and re-signing...",
+                        e, attempt, MAX_TX_ATTEMPTS);
+                    self.cached.set(None);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Nonce for the next transaction, fetching the access key on first use
+    fn next_nonce(&self) -> Result<(u64, [u8; 32]), Box<dyn std::error::Error>> {
+        let (last_nonce, block_hash) = match self.cached.get() {
+            Some(cached) => cached,
+            None => {
+                let (nonce, block_hash, permission) =
+                    get_access_key_info(&self.rpc, &self.account_id, &self.public_key)?;
+                self.permission.replace(Some(permission));
+                (nonce, block_hash)
+            }
+        };
+        let nonce = last_nonce + 1;
+        self.cached.set(Some((nonce, block_hash)));
+
+        eprintln!("📝 Nonce: {}, Block hash: {}", nonce, hex::encode(block_hash));
+        Ok((nonce, block_hash))
+    }
+
+    /// Fails with a descriptive error if the signing key is a FunctionCall
+    /// key that may not send `actions` to `receiver_id`, rather than letting
+    /// the chain reject the transaction
+    fn check_permission(&self, receiver_id: &str, actions: &[Action]) -> Result<(), Box<dyn std::error::Error>> {
+        let permission = self.permission.borrow();
+        let Some(permission) = permission.as_ref() else {
+            return Ok(());
+        };
+        permission
+            .check(receiver_id, actions, || gas_price(&self.rpc))
+            .map_err(|e| format!("Access key {} of {}: {}", self.public_key, self.account_id, e).into())
+    }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-struct FunctionCallAction {
-    method_name: String,
-    args: Vec<u8>,
-    gas: u64,
-    deposit: u128,
+    fn sign(
+        &self,
+        nonce: u64,
+        block_hash: [u8; 32],
+        receiver_id: &str,
+        actions: Vec<Action>,
+    ) -> Result<SignedTransaction, Box<dyn std::error::Error>> {
+        // Build transaction
+        let transaction = Transaction {
+            signer_id: self.account_id.clone(),
+            public_key: self.public_key.clone(),
+            nonce,
+            receiver_id: receiver_id.to_string(),
+            block_hash,
+            actions,
+        };
+
+        // Serialize and hash transaction
+        let tx_bytes = borsh::to_vec(&transaction)?;
+        let mut hasher = Sha256::new();
+        hasher.update(&tx_bytes);
+        let tx_hash = hasher.finalize();
+
+        // Sign transaction
+        Ok(SignedTransaction {
+            signature: Signature::from_bytes(&self.public_key, &self.key.sign(&tx_hash)?)?,
+            transaction,
+        })
+    }
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-struct SignedTransaction {
-    transaction: Transaction,
-    signature: Signature,
+/// Several keys of one account, handed out round-robin. Each key has its own
+/// [`TxSigner`] and so its own nonce, so consecutive transactions (e.g. the
+/// deposits of a batch) don't wait on or invalidate each other's nonces.
+pub struct KeyPool {
+    signers: Vec<TxSigner>,
+    next: Cell<usize>,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
-enum Signature {
-    ED25519([u8; 64]),
+impl KeyPool {
+    pub fn new(
+        rpc: &RpcClient,
+        account_id: &str,
+        private_keys: &[impl AsRef<str>],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let signers = private_keys
+            .iter()
+            .map(|key| TxSigner::new(rpc, account_id, key.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Self::from_signers(signers)
+    }
+
+    /// Pool of existing signers, e.g. with remote [`TransactionSigner`]s
+    pub fn from_signers(signers: Vec<TxSigner>) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(first) = signers.first() else {
+            return Err("Key pool needs at least one key".into());
+        };
+        if let Some(other) = signers.iter().find(|s| s.account_id != first.account_id) {
+            return Err(format!("Key pool mixes accounts {} and {}", first.account_id, other.account_id).into());
+        }
+        Ok(Self { signers, next: Cell::new(0) })
+    }
+
+    /// Signer for the next transaction
+    pub fn next_signer(&self) -> &TxSigner {
+        let i = self.next.get();
+        self.next.set((i + 1) % self.signers.len());
+        &self.signers[i]
+    }
+
+    pub fn len(&self) -> usize {
+        self.signers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signers.is_empty()
+    }
 }
 
 // ============================================================================
-// Public API - Universal Functions
+// Transaction Builder
 // ============================================================================
 
-/// Universal view function - read-only RPC call
-/// Returns parsed JSON result as string
-pub fn view(
-    rpc_url: &str,
-    contract_id: &str,
-    method_name: &str,
-    args: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    eprintln!("🔍 View call: {}.{}", contract_id, method_name);
+/// Fluent construction of transactions with any mix of actions:
+///
+/// ```no_run
+/// # use intents_ark::near_tx::TransactionBuilder;
+/// # use serde_json::json;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # use intents_ark::near_tx::RpcClient;
+/// # let private_key = "ed25519:...";
+/// # let rpc = RpcClient::new("https://rpc.mainnet.near.org");
+/// let outcome = TransactionBuilder::new("alice.near")
+///     .receiver("wrap.near")
+///     .function_call("near_deposit", &json!({}), 10_000_000_000_000, 1_000_000_000_000_000_000_000_000)
+///     .function_call("ft_transfer", &json!({"receiver_id": "bob.near", "amount": "1"}), 10_000_000_000_000, 1)
+///     .sign(private_key)?
+///     .send(&rpc)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The receiver defaults to the signer. Invalid public keys are reported by
+/// [`sign`](Self::sign).
+pub struct TransactionBuilder {
+    signer_id: String,
+    receiver_id: Option<String>,
+    actions: Vec<Action>,
+    error: Option<String>,
+}
+
+impl TransactionBuilder {
+    pub fn new(signer_id: &str) -> Self {
+        Self {
+            signer_id: signer_id.to_string(),
+            receiver_id: None,
+            actions: Vec::new(),
+            error: None,
+        }
+    }
 
-    let args_base64 = base64::encode(args.as_bytes());
+    pub fn receiver(mut self, receiver_id: &str) -> Self {
+        self.receiver_id = Some(receiver_id.to_string());
+        self
+    }
 
-    let request = serde_json::json!({
-        "jsonrpc": "2.0",
-        "id": "dontcare",
-        "method": "query",
-        "params": {
-            "request_type": "call_function",
-            "finality": "final",
-            "account_id": contract_id,
-            "method_name": method_name,
-            "args_base64": args_base64
+    /// Call `method_name` with JSON `args`
+    pub fn function_call(self, method_name: &str, args: &serde_json::Value, gas: u64, deposit: u128) -> Self {
+        self.function_call_spec(FunctionCallSpec::json(method_name, args, gas, deposit))
+    }
+
+    /// Call with raw (e.g. borsh) args
+    pub fn function_call_spec(mut self, call: FunctionCallSpec) -> Self {
+        self.actions.push(call.into_action());
+        self
+    }
+
+    /// Send `amount_yocto` NEAR to the receiver
+    pub fn transfer(mut self, amount_yocto: u128) -> Self {
+        self.actions.push(Action::Transfer(amount_yocto));
+        self
+    }
+
+    /// Create the receiver account (a sub-account of the signer)
+    pub fn create_account(mut self) -> Self {
+        self.actions.push(Action::CreateAccount);
+        self
+    }
+
+    /// Deploy `code` (wasm) to the receiver
+    pub fn deploy_contract(mut self, code: Vec<u8>) -> Self {
+        self.actions.push(Action::DeployContract(code));
+        self
+    }
+
+    /// Add `public_key` (`ed25519:...` or `secp256k1:...`) to the receiver
+    pub fn add_key(self, public_key: &str, permission: AccessKeyPermission) -> Self {
+        self.with_public_key(public_key, |public_key| Action::AddKey {
+            public_key,
+            access_key: AccessKey { nonce: 0, permission },
+        })
+    }
+
+    /// Remove `public_key` from the receiver
+    pub fn delete_key(self, public_key: &str) -> Self {
+        self.with_public_key(public_key, Action::DeleteKey)
+    }
+
+    /// Delete the receiver account, sending its balance to `beneficiary_id`
+    pub fn delete_account(mut self, beneficiary_id: &str) -> Self {
+        self.actions.push(Action::DeleteAccount(beneficiary_id.to_string()));
+        self
+    }
+
+    /// Relay a NEP-366 signed delegate action (base64 borsh, as returned by
+    /// [`sign_delegate`](Self::sign_delegate)). The receiver must be its sender.
+    pub fn delegate(mut self, signed_delegate_action: &str) -> Self {
+        let decoded = codec::base64_decode(signed_delegate_action)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| borsh::from_slice::<SignedDelegateAction>(&bytes).map_err(|e| e.to_string()));
+        match decoded {
+            Ok(signed) => self.actions.push(Action::Delegate(Box::new(signed))),
+            Err(e) => {
+                self.error.get_or_insert(format!("Invalid signed delegate action: {}", e));
+            }
         }
-    });
+        self
+    }
 
-    let response = Client::new()
-        .post(rpc_url)
-        .header("Content-Type", "application/json")
-        .connect_timeout(Duration::from_secs(10))
-        .body(serde_json::to_string(&request)?.as_bytes())
-        .send()?;
+    /// Sign the actions as a NEP-366 delegate action instead of sending them:
+    /// a relayer submits it with [`delegate`](Self::delegate) and pays the gas.
+    /// `nonce` must be above the key's current nonce, and the relayer must get
+    /// it included by `max_block_height`. Returns the borsh-serialized
+    /// `SignedDelegateAction`, base64-encoded.
+    pub fn sign_delegate(
+        self,
+        key: &dyn TransactionSigner,
+        nonce: u64,
+        max_block_height: u64,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let sender_id = self.signer_id.clone();
+        let (receiver_id, actions) = self.into_parts()?;
+        if actions.iter().any(|action| matches!(action, Action::Delegate(_))) {
+            return Err("Delegate actions cannot be nested".into());
+        }
 
-    let status = response.status();
-    if status != 200 {
-        return Err(format!("RPC returned status {}", status).into());
+        let public_key = key.public_key().parse::<PublicKey>()?;
+        let delegate_action = DelegateAction {
+            sender_id,
+            receiver_id,
+            actions,
+            nonce,
+            max_block_height,
+            public_key,
+        };
+        let hash = crypto::nep366_hash(&borsh::to_vec(&delegate_action)?);
+        let signature = Signature::from_bytes(&delegate_action.public_key, &key.sign(&hash)?)?;
+
+        Ok(codec::base64_encode(borsh::to_vec(&SignedDelegateAction { delegate_action, signature })?))
     }
 
-    let body = response.body()?;
-    let body_str = String::from_utf8(body)?;
-    let json: serde_json::Value = serde_json::from_str(&body_str)?;
+    fn with_public_key(mut self, public_key: &str, action: impl FnOnce(PublicKey) -> Action) -> Self {
+        match public_key.parse::<PublicKey>() {
+            Ok(public_key) => self.actions.push(action(public_key)),
+            Err(e) => {
+                self.error.get_or_insert(e.to_string());
+            }
+        }
+        self
+    }
 
-    if let Some(error) = json.get("error") {
-        return Err(format!("RPC error: {}", error).into());
+    /// Attach the signer's private key. The transaction is signed by
+    /// [`ReadyTransaction::send`], once the nonce and block hash are known.
+    pub fn sign(self, private_key: &str) -> Result<ReadyTransaction, Box<dyn std::error::Error>> {
+        let key = LocalSigner::new(private_key)?;
+        Ok(ReadyTransaction { builder: self, key })
     }
 
-    // Parse result bytes to string
-    if let Some(result) = json.get("result").and_then(|r| r.get("result")) {
-        let result_vec: Vec<u8> = result
-            .as_array()
-            .ok_or("Result should be array")?
-            .iter()
-            .map(|v| v.as_u64().unwrap() as u8)
-            .collect();
+    /// Send through `signer` (reusing its cached nonce) instead of a fresh key
+    pub fn send_with(self, signer: &TxSigner) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        if self.signer_id != signer.account_id {
+            return Err(format!("Transaction for {} sent with signer {}", self.signer_id, signer.account_id).into());
+        }
+        let (receiver_id, actions) = self.into_parts()?;
+        signer.send_actions(&receiver_id, actions)
+    }
 
-        let result_str = String::from_utf8(result_vec)?;
-        Ok(result_str)
-    } else {
-        Err("No result in response".into())
+    fn into_parts(self) -> Result<(String, Vec<Action>), Box<dyn std::error::Error>> {
+        if let Some(error) = self.error {
+            return Err(error.into());
+        }
+        if self.actions.is_empty() {
+            return Err("Transaction has no actions".into());
+        }
+        Ok((self.receiver_id.unwrap_or(self.signer_id), self.actions))
     }
 }
 
-/// Universal call function - send transaction with function call
-/// Returns transaction hash
-pub fn call(
-    rpc_url: &str,
-    signer_account_id: &str,
-    signer_private_key: &str,
-    contract_id: &str,
-    method_name: &str,
-    args: &str,
-    gas: u64,
-    deposit: u128,
-) -> Result<String, Box<dyn std::error::Error>> {
-    eprintln!("📤 Call: {}.{}", contract_id, method_name);
+/// A [`TransactionBuilder`] with its signing key
+pub struct ReadyTransaction {
+    builder: TransactionBuilder,
+    key: LocalSigner,
+}
 
-    send_function_call_transaction(
-        rpc_url,
-        signer_account_id,
-        signer_private_key,
-        contract_id,
-        method_name,
-        args.as_bytes(),
-        gas,
-        deposit,
-    )
+impl ReadyTransaction {
+    /// Sign with the key's current nonce and send
+    pub fn send(self, rpc: &RpcClient) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+        let signer = TxSigner::with_signer(rpc, &self.builder.signer_id, self.key)?;
+        self.builder.send_with(&signer)
+    }
 }
 
 // ============================================================================
-// Convenience Functions (use call/view internally)
+// Transaction Outcome
 // ============================================================================
 
-/// Call storage_deposit on NEAR fungible token contract
-pub fn storage_deposit(
-    rpc_url: &str,
-    signer_account_id: &str,
-    signer_private_key: &str,
-    token_contract: &str,
-    account_id: Option<&str>,
-    registration_only: bool,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let args = serde_json::json!({
-        "account_id": account_id,
-        "registration_only": registration_only
-    });
+/// `wait_until` levels of the `send_tx` RPC, from fastest to most final
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TxFinality {
+    /// Accepted by the RPC node, not yet in a block
+    None,
+    /// Included in a block
+    Included,
+    /// Included in a final block
+    IncludedFinal,
+    /// All non-refund receipts executed, possibly in non-final blocks
+    ExecutedOptimistic,
+    /// Included in a final block, all non-refund receipts executed
+    Executed,
+    /// All receipts executed and every block involved is final
+    Final,
+}
 
-    call(
-        rpc_url,
-        signer_account_id,
-        signer_private_key,
-        token_contract,
-        "storage_deposit",
-        &args.to_string(),
-        30_000_000_000_000, // 30 TGas
-        1250000000000000000000000, // 0.00125 NEAR
-    )
+impl TxFinality {
+    /// The outcome (return value, logs, receipts) is known
+    pub fn is_executed(self) -> bool {
+        self >= TxFinality::ExecutedOptimistic
+    }
 }
 
-/// Call ft_transfer_call on NEAR via JSON-RPC
-pub fn ft_transfer_call(
-    rpc_url: &str,
-    signer_account_id: &str,
-    signer_private_key: &str,
-    token_contract: &str,
-    receiver_id: &str,
-    amount: &str,
-    msg: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
-    eprintln!("🔐 Signing ft_transfer_call transaction...");
+/// Result of a successful transaction
+#[derive(Debug, Clone)]
+pub struct TxOutcome {
+    pub tx_hash: String,
+    /// How far the transaction got before the RPC answered. Below
+    /// [`TxFinality::ExecutedOptimistic`] every other field is empty.
+    pub finality: TxFinality,
+    /// Decoded return value of the transaction (its last function call)
+    pub success_value: Vec<u8>,
+    /// Gas burnt by the transaction and all its receipts
+    pub gas_burnt: u64,
+    /// Logs of the transaction and all its receipts, in execution order
+    pub logs: Vec<String>,
+    pub receipts: Vec<ReceiptOutcome>,
+}
 
-    let args = serde_json::json!({
-        "receiver_id": receiver_id,
-        "amount": amount,
-        "msg": msg
-    });
+/// A NEP-297 event, e.g. `{"standard": "nep245", "event": "mt_mint", ...}`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Nep297Event {
+    pub standard: String,
+    pub version: String,
+    pub event: String,
+    #[serde(default)]
+    pub data: serde_json::Value,
+}
 
-    send_function_call_transaction(
-        rpc_url,
-        signer_account_id,
-        signer_private_key,
-        token_contract,
-        "ft_transfer_call",
-        serde_json::to_vec(&args)?.as_slice(),
-        300_000_000_000_000, // 300 TGas
-        1,                   // 1 yoctoNEAR
-    )
-}
-
-/// Generic function to send any function call transaction
-fn send_function_call_transaction(
-    rpc_url: &str,
-    signer_account_id: &str,
-    signer_private_key: &str,
-    receiver_id: &str,
-    method_name: &str,
-    args: &[u8],
-    gas: u64,
-    deposit: u128,
-) -> Result<String, Box<dyn std::error::Error>> {
-    // Parse private key (remove "ed25519:" prefix if present)
-    let key_str = if signer_private_key.starts_with("ed25519:") {
-        &signer_private_key[8..]
-    } else {
-        signer_private_key
-    };
+/// Execution of one receipt of a transaction
+#[derive(Debug, Clone)]
+pub struct ReceiptOutcome {
+    pub receipt_id: String,
+    pub executor_id: String,
+    pub logs: Vec<String>,
+    pub gas_burnt: u64,
+    /// Decoded return value, or None if the receipt resolved to another receipt
+    pub success_value: Option<Vec<u8>>,
+}
 
-    let key_bytes = bs58::decode(key_str)
-        .into_vec()
-        .map_err(|e| format!("Failed to decode private key: {}", e))?;
+impl TxOutcome {
+    /// Return value parsed as JSON, e.g. the used amount (`"100"`) returned
+    /// by `ft_transfer_call`. None if the call returned nothing.
+    pub fn success_json<T: serde::de::DeserializeOwned>(&self) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        if self.success_value.is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&self.success_value)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse return value of {}: {}", self.tx_hash, e).into())
+    }
 
-    // NEAR private keys in JSON format are 64 bytes (32-byte seed + 32-byte public key)
-    // Extract only the first 32 bytes as the seed
-    if key_bytes.len() != 32 && key_bytes.len() != 64 {
-        return Err(format!("Invalid private key length: {}", key_bytes.len()).into());
+    /// NEP-297 events (`EVENT_JSON:` logs) emitted by any receipt, in order.
+    /// Malformed event logs are skipped.
+    pub fn events(&self) -> Vec<Nep297Event> {
+        self.logs
+            .iter()
+            .filter_map(|log| serde_json::from_str(log.strip_prefix("EVENT_JSON:")?).ok())
+            .collect()
     }
 
-    let mut seed = [0u8; 32];
-    seed.copy_from_slice(&key_bytes[..32]);
-    let signing_key = SigningKey::from_bytes(&seed);
-    let verifying_key = signing_key.verifying_key();
+    fn pending(tx_hash: String, finality: TxFinality) -> Self {
+        Self {
+            tx_hash,
+            finality,
+            success_value: Vec::new(),
+            gas_burnt: 0,
+            logs: Vec::new(),
+            receipts: Vec::new(),
+        }
+    }
 
-    // Get nonce and block hash from RPC
-    let (nonce, block_hash) = get_access_key_info(rpc_url, signer_account_id, &verifying_key)?;
+    fn from_view(
+        tx_hash: String,
+        finality: TxFinality,
+        view: FinalExecutionOutcomeView,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let success_value = view.status.success_value()?.unwrap_or_default();
+
+        let receipts = view
+            .receipts_outcome
+            .into_iter()
+            .map(|receipt| {
+                Ok(ReceiptOutcome {
+                    success_value: receipt.outcome.status.success_value()?,
+                    receipt_id: receipt.id,
+                    executor_id: receipt.outcome.executor_id,
+                    logs: receipt.outcome.logs,
+                    gas_burnt: receipt.outcome.gas_burnt,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            tx_hash,
+            finality,
+            success_value,
+            gas_burnt: view.transaction_outcome.outcome.gas_burnt
+                + receipts.iter().map(|r| r.gas_burnt).sum::<u64>(),
+            logs: view
+                .transaction_outcome
+                .outcome
+                .logs
+                .into_iter()
+                .chain(receipts.iter().flat_map(|r| r.logs.iter().cloned()))
+                .collect(),
+            receipts,
+        })
+    }
+}
 
-    eprintln!("📝 Nonce: {}, Block hash: {}", nonce, hex::encode(&block_hash));
+// ============================================================================
+// RPC Client
+// ============================================================================
 
-    // Build transaction
-    let transaction = Transaction {
-        signer_id: signer_account_id.to_string(),
-        public_key: PublicKey::ED25519(verifying_key.to_bytes()),
-        nonce: nonce + 1,
-        receiver_id: receiver_id.to_string(),
-        block_hash,
-        actions: vec![Action::FunctionCall(FunctionCallAction {
-            method_name: method_name.to_string(),
-            args: args.to_vec(),
-            gas,
-            deposit,
-        })],
-    };
+/// NEAR JSON-RPC endpoints shared by every call in a run, with failover.
+///
+/// Requests go to the first endpoint in configured order that hasn't failed
+/// since its last success; transport errors, HTTP 429 and 5xx move on to the
+/// next endpoint. JSON-RPC errors (unknown account, invalid nonce, ...) are
+/// answers, not endpoint failures. Clones share the same health state.
+#[derive(Clone)]
+pub struct RpcClient {
+    endpoints: Rc<RefCell<Vec<EndpointHealth>>>,
+}
 
-    // Serialize and hash transaction
-    let tx_bytes = borsh::to_vec(&transaction)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&tx_bytes);
-    let tx_hash = hasher.finalize();
+/// Request counters for one endpoint of an [`RpcClient`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointHealth {
+    pub url: String,
+    pub requests: u64,
+    pub failures: u64,
+    /// Failures since the last success; healthy endpoints are tried first
+    pub consecutive_failures: u32,
+    /// Duration of the last successful request
+    pub last_latency: Option<Duration>,
+}
 
-    // Sign transaction
-    let signature = signing_key.sign(&tx_hash);
+impl RpcClient {
+    /// Client for a single endpoint
+    pub fn new(url: &str) -> Self {
+        Self::with_fallbacks(url, &[])
+    }
 
-    let signed_tx = SignedTransaction {
-        transaction,
-        signature: Signature::ED25519(signature.to_bytes()),
-    };
+    /// Client trying `primary` first, then `fallbacks` in order
+    pub fn with_fallbacks(primary: &str, fallbacks: &[String]) -> Self {
+        let endpoints = std::iter::once(primary)
+            .chain(fallbacks.iter().map(String::as_str).filter(|url| *url != primary))
+            .map(|url| EndpointHealth {
+                url: url.to_string(),
+                requests: 0,
+                failures: 0,
+                consecutive_failures: 0,
+                last_latency: None,
+            })
+            .collect();
+        Self { endpoints: Rc::new(RefCell::new(endpoints)) }
+    }
+
+    /// Endpoint URLs in configured order
+    pub fn urls(&self) -> Vec<String> {
+        self.endpoints.borrow().iter().map(|e| e.url.clone()).collect()
+    }
+
+    /// Current counters of every endpoint
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoints.borrow().clone()
+    }
+
+    /// POST a JSON-RPC request, failing over between endpoints
+    fn post<T: Serialize + ?Sized>(&self, request: &T) -> Result<http::Response, Box<dyn std::error::Error>> {
+        // Stable sort: configured order among equally healthy endpoints
+        let mut order: Vec<usize> = (0..self.endpoints.borrow().len()).collect();
+        order.sort_by_key(|&i| self.endpoints.borrow()[i].consecutive_failures);
+
+        let mut last_error: Option<Box<dyn std::error::Error>> = None;
+        for i in order {
+            let url = self.endpoints.borrow()[i].url.clone();
+            if let Some(e) = &last_error {
+                eprintln!("⚠️  RPC request failed ({}), trying {}", e, url);
+            }
+
+            let started = Instant::now();
+            let result = http::post_json(Endpoint::Rpc, &url, request);
+            let mut endpoints = self.endpoints.borrow_mut();
+            let health = &mut endpoints[i];
+            health.requests += 1;
+            match result {
+                Ok(response) if response.status != 429 && response.status < 500 => {
+                    health.consecutive_failures = 0;
+                    health.last_latency = Some(started.elapsed());
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    health.failures += 1;
+                    health.consecutive_failures += 1;
+                    last_error = Some(format!("{} returned status {}", url, response.status).into());
+                }
+                Err(e) => {
+                    health.failures += 1;
+                    health.consecutive_failures += 1;
+                    last_error = Some(e);
+                }
+            }
+        }
 
-    // Send transaction via RPC
-    send_transaction(rpc_url, &signed_tx)
+        Err(last_error.unwrap_or_else(|| "RpcClient has no endpoints".into()))
+    }
 }
 
 // ============================================================================
@@ -316,22 +1915,60 @@ struct JsonRpcResponse<T> {
 #[derive(Deserialize, Debug)]
 struct JsonRpcError {
     message: String,
+    /// e.g. `{"name": "UNKNOWN_TRANSACTION", "info": {...}}`
+    cause: Option<serde_json::Value>,
+    /// Structured error, e.g. `{"TxExecutionError": {"InvalidTxError": ...}}`
+    data: Option<serde_json::Value>,
 }
 
-#[derive(Deserialize)]
-struct AccessKeyQueryResult {
-    nonce: u64,
-    block_hash: String,
+impl JsonRpcError {
+    /// The node doesn't have the transaction or block: not seen yet, or too
+    /// old for it
+    fn is_missing_data(&self) -> bool {
+        let cause = self.cause.as_ref().and_then(|c| c["name"].as_str());
+        matches!(cause, Some("UNKNOWN_TRANSACTION" | "GARBAGE_COLLECTED_BLOCK" | "UNKNOWN_BLOCK"))
+    }
+}
+
+/// Transaction rejected before execution (stale nonce, expired block hash, ...).
+/// Holds the `InvalidTxError` value from the RPC, e.g. `{"InvalidNonce": {...}}`
+#[derive(Debug)]
+pub struct InvalidTxError(pub serde_json::Value);
+
+impl InvalidTxError {
+    /// The access key nonce had already been used
+    pub fn is_invalid_nonce(&self) -> bool {
+        self.0.get("InvalidNonce").is_some()
+    }
+
+    /// The block hash the transaction referenced is too old
+    pub fn is_expired(&self) -> bool {
+        self.0 == "Expired"
+    }
+
+    /// Rejected only because of stale access key info; re-signing may succeed
+    pub fn is_retryable(&self) -> bool {
+        self.is_invalid_nonce() || self.is_expired()
+    }
 }
 
+impl std::fmt::Display for InvalidTxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid transaction: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidTxError {}
+
 // ============================================================================
 // Transaction Outcome Structures (compatible with near-primitives)
 // ============================================================================
+// Fields mirror the RPC response; see [`tx_status`].
 
 /// TxExecutionError can be ActionError or InvalidTxError
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
-enum TxExecutionError {
+pub enum TxExecutionError {
     ActionError {
         #[serde(rename = "ActionError")]
         action_error: ActionError,
@@ -343,14 +1980,14 @@ enum TxExecutionError {
 }
 
 #[derive(Deserialize, Debug)]
-struct ActionError {
-    index: Option<u64>,
-    kind: ActionErrorKind,
+pub struct ActionError {
+    pub index: Option<u64>,
+    pub kind: ActionErrorKind,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
-enum ActionErrorKind {
+pub enum ActionErrorKind {
     FunctionCallError {
         #[serde(rename = "FunctionCallError")]
         function_call_error: FunctionCallErrorKind,
@@ -360,7 +1997,7 @@ enum ActionErrorKind {
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
-enum FunctionCallErrorKind {
+pub enum FunctionCallErrorKind {
     ExecutionError {
         #[serde(rename = "ExecutionError")]
         execution_error: String,
@@ -371,7 +2008,7 @@ enum FunctionCallErrorKind {
 /// ExecutionStatusView represents the execution status of a transaction or receipt
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
-enum ExecutionStatusView {
+pub enum ExecutionStatusView {
     Failure {
         #[serde(rename = "Failure")]
         failure: TxExecutionError,
@@ -389,7 +2026,7 @@ enum ExecutionStatusView {
 /// FinalExecutionStatus represents the overall transaction status
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
-enum FinalExecutionStatus {
+pub enum FinalExecutionStatus {
     Failure {
         #[serde(rename = "Failure")]
         failure: TxExecutionError,
@@ -403,37 +2040,88 @@ enum FinalExecutionStatus {
 }
 
 #[derive(Deserialize, Debug)]
-struct ExecutionOutcomeView {
-    logs: Vec<String>,
-    receipt_ids: Vec<String>,
-    gas_burnt: u64,
-    tokens_burnt: String,
-    executor_id: String,
-    status: ExecutionStatusView,
+pub struct ExecutionOutcomeView {
+    pub logs: Vec<String>,
+    pub receipt_ids: Vec<String>,
+    pub gas_burnt: u64,
+    pub tokens_burnt: String,
+    pub executor_id: String,
+    pub status: ExecutionStatusView,
 }
 
 #[derive(Deserialize, Debug)]
-struct ExecutionOutcomeWithIdView {
+pub struct ExecutionOutcomeWithIdView {
     // proof: MerklePath, // Skip proof parsing
-    block_hash: String,
-    id: String,
-    outcome: ExecutionOutcomeView,
+    pub block_hash: String,
+    pub id: String,
+    pub outcome: ExecutionOutcomeView,
 }
 
 #[derive(Deserialize, Debug)]
-struct FinalExecutionOutcomeView {
-    status: FinalExecutionStatus,
+pub struct FinalExecutionOutcomeView {
+    pub status: FinalExecutionStatus,
     // transaction: SignedTransactionView, // We don't need the full transaction back
-    transaction_outcome: ExecutionOutcomeWithIdView,
-    receipts_outcome: Vec<ExecutionOutcomeWithIdView>,
+    pub transaction_outcome: ExecutionOutcomeWithIdView,
+    pub receipts_outcome: Vec<ExecutionOutcomeWithIdView>,
+    /// Full receipts (only from `EXPERIMENTAL_tx_status`), as returned by the RPC
+    #[serde(default)]
+    pub receipts: Vec<serde_json::Value>,
+}
+
+/// Decode a base64 `SuccessValue`
+fn decode_success_value(value: &str) -> Result<Vec<u8>, String> {
+    codec::base64_decode(value).map_err(|e| format!("Failed to decode SuccessValue: {}", e))
+}
+
+impl ExecutionStatusView {
+    /// Decoded return value; None unless the status is `SuccessValue`
+    pub fn success_value(&self) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            ExecutionStatusView::SuccessValue { success_value } => decode_success_value(success_value).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl FinalExecutionStatus {
+    /// Decoded return value of the transaction; None unless it succeeded
+    pub fn success_value(&self) -> Result<Option<Vec<u8>>, String> {
+        match self {
+            FinalExecutionStatus::SuccessValue { success_value } => decode_success_value(success_value).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl FinalExecutionOutcomeView {
+    /// Return value parsed as JSON. None if the transaction failed or returned nothing.
+    pub fn success_json<T: serde::de::DeserializeOwned>(&self) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        match self.status.success_value()? {
+            Some(value) if !value.is_empty() => Ok(Some(serde_json::from_slice(&value)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// First failure of the transaction or any of its receipts, if any
+    pub fn error(&self) -> Option<String> {
+        if let FinalExecutionStatus::Failure { failure } = &self.status {
+            return Some(format_tx_error(failure));
+        }
+        std::iter::once(&self.transaction_outcome)
+            .chain(&self.receipts_outcome)
+            .find_map(|o| match &o.outcome.status {
+                ExecutionStatusView::Failure { failure } => Some(format_tx_error(failure)),
+                _ => None,
+            })
+    }
 }
 
 fn get_access_key_info(
-    rpc_url: &str,
+    rpc: &RpcClient,
     account_id: &str,
-    public_key: &VerifyingKey,
-) -> Result<(u64, [u8; 32]), Box<dyn std::error::Error>> {
-    let public_key_str = format!("ed25519:{}", bs58::encode(public_key.to_bytes()).into_string());
+    public_key: &PublicKey,
+) -> Result<(u64, [u8; 32], AccessKeyPermissionView), Box<dyn std::error::Error>> {
+    let public_key_str = public_key.to_string();
 
     let request = JsonRpcRequest {
         jsonrpc: "2.0".to_string(),
@@ -447,23 +2135,18 @@ fn get_access_key_info(
         }),
     };
 
-    let response = Client::new()
-        .post(rpc_url)
-        .header("Content-Type", "application/json")
-        .connect_timeout(Duration::from_secs(10))
-        .body(serde_json::to_string(&request)?.as_bytes())
-        .send()?;
+    let response = rpc.post(&request)?;
 
-    let status = response.status();
+    let status = response.status;
     if status != 200 {
         return Err(format!("RPC returned status {}", status).into());
     }
 
-    let body = response.body()?;
+    let body = response.body;
     let body_str = String::from_utf8(body.clone())
         .unwrap_or_else(|_| format!("{:?}", body));
 
-    eprintln!("📥 RPC Response (first 500 chars): {}", &body_str.chars().take(500).collect::<String>());
+    logging::debug_body("📥 RPC Response", &body_str, 500);
 
     // Parse as generic JSON first to handle nested structure
     let json_value: serde_json::Value = serde_json::from_slice(&body)
@@ -486,82 +2169,146 @@ fn get_access_key_info(
         .and_then(|b| b.as_str())
         .ok_or("Missing or invalid 'block_hash' field in result")?;
 
-    // Parse block hash from base58
-    let block_hash_bytes = bs58::decode(block_hash_str)
-        .into_vec()
+    let block_hash = codec::base58_decode_array(block_hash_str)
         .map_err(|e| format!("Failed to decode block hash: {}", e))?;
 
-    if block_hash_bytes.len() != 32 {
-        return Err(format!("Invalid block hash length: {} bytes", block_hash_bytes.len()).into());
-    }
-
-    let mut block_hash = [0u8; 32];
-    block_hash.copy_from_slice(&block_hash_bytes);
+    let permission = result.get("permission")
+        .ok_or("Missing 'permission' field in result")
+        .and_then(|p| serde_json::from_value(p.clone()).map_err(|_| "Invalid 'permission' field in result"))?;
 
-    Ok((nonce, block_hash))
+    Ok((nonce, block_hash, permission))
 }
 
 fn send_transaction(
-    rpc_url: &str,
+    rpc: &RpcClient,
     signed_tx: &SignedTransaction,
-) -> Result<String, Box<dyn std::error::Error>> {
+    wait_until: Option<TxFinality>,
+) -> Result<TxOutcome, Box<dyn std::error::Error>> {
     // Serialize transaction with borsh
     let tx_bytes = borsh::to_vec(signed_tx)?;
-    let tx_base64 = base64::encode(&tx_bytes);
+    let tx_base64 = codec::base64_encode(&tx_bytes);
+
+    let request = match wait_until {
+        Some(wait_until) => JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "dontcare".to_string(),
+            method: "send_tx".to_string(),
+            params: serde_json::json!({
+                "signed_tx_base64": tx_base64,
+                "wait_until": wait_until,
+            }),
+        },
+        None => JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: "dontcare".to_string(),
+            method: "broadcast_tx_commit".to_string(),
+            params: serde_json::json!([tx_base64]),
+        },
+    };
+
+    eprintln!("📡 Sending transaction to NEAR RPC...");
+
+    let json_response = post_rpc(rpc, &request)?;
+    if let Some(error) = json_response.error {
+        if let Some(invalid_tx) = error
+            .data
+            .as_ref()
+            .and_then(|data| data.get("TxExecutionError")?.get("InvalidTxError"))
+        {
+            return Err(InvalidTxError(invalid_tx.clone()).into());
+        }
+        return Err(format!("Transaction failed: {}", error.message).into());
+    }
+
+    let result = json_response.result.ok_or("No result in RPC response")?;
+    // Results before execution carry no transaction, so the hash may have to be computed
+    let tx_hash = match result["transaction"]["hash"].as_str() {
+        Some(hash) => hash.to_string(),
+        None => signed_tx.hash()?,
+    };
+    eprintln!("📋 Transaction broadcast: {}", tx_hash);
+
+    outcome_from_result(tx_hash, result)
+}
 
+/// Current `method` (`tx` or `EXPERIMENTAL_tx_status`) result for a
+/// transaction, None if the RPC doesn't know it (yet) or no longer has it
+fn query_tx(
+    rpc: &RpcClient,
+    method: &str,
+    tx_hash: &str,
+    signer_account_id: &str,
+) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error>> {
     let request = JsonRpcRequest {
         jsonrpc: "2.0".to_string(),
         id: "dontcare".to_string(),
-        method: "broadcast_tx_commit".to_string(),
-        params: vec![tx_base64],
+        method: method.to_string(),
+        params: serde_json::json!({
+            "tx_hash": tx_hash,
+            "sender_account_id": signer_account_id,
+            "wait_until": TxFinality::None,
+        }),
     };
 
-    eprintln!("📡 Sending transaction to NEAR RPC...");
+    let json_response = post_rpc(rpc, &request)?;
+    if let Some(error) = json_response.error {
+        if error.is_missing_data() {
+            return Ok(None);
+        }
+        return Err(format!("tx_status failed: {}", error.message).into());
+    }
+
+    json_response.result.ok_or_else(|| "No result in RPC response".into()).map(Some)
+}
 
-    let response = Client::new()
-        .post(rpc_url)
-        .header("Content-Type", "application/json")
-        .connect_timeout(Duration::from_secs(60)) // Longer timeout for tx commit
-        .body(serde_json::to_string(&request)?.as_bytes())
-        .send()?;
+/// POST a JSON-RPC request and parse the response envelope
+fn post_rpc<T: Serialize>(
+    rpc: &RpcClient,
+    request: &JsonRpcRequest<T>,
+) -> Result<JsonRpcResponse<serde_json::Value>, Box<dyn std::error::Error>> {
+    let response = rpc.post(request)?;
 
-    let status = response.status();
+    let status = response.status;
     if status != 200 {
-        let body = response.body().unwrap_or_default();
-        let error_text = String::from_utf8_lossy(&body);
-        return Err(format!("RPC returned status {}: {}", status, error_text).into());
+        let error_text = String::from_utf8_lossy(&response.body);
+        return Err(format!("RPC returned status {}: {}", status, logging::redact(&error_text)).into());
     }
 
-    let body = response.body()?;
+    let body = response.body;
 
     // Debug: print response for analysis
     let body_str = String::from_utf8_lossy(&body);
-    eprintln!("📥 Transaction response (first 2000 chars): {}", &body_str.chars().take(2000).collect::<String>());
+    logging::debug_body("📥 Transaction response", &body_str, 2000);
 
-    let json_response: JsonRpcResponse<serde_json::Value> = serde_json::from_slice(&body)?;
+    Ok(serde_json::from_slice(&body)?)
+}
 
-    if let Some(error) = json_response.error {
-        return Err(format!("Transaction failed: {}", error.message).into());
+/// Check a send_tx / broadcast_tx_commit / tx result for failures
+fn outcome_from_result(tx_hash: String, result: serde_json::Value) -> Result<TxOutcome, Box<dyn std::error::Error>> {
+    // broadcast_tx_commit predates final_execution_status and waits for execution
+    let finality = match result.get("final_execution_status") {
+        Some(status) => serde_json::from_value(status.clone())
+            .map_err(|e| format!("Unknown final_execution_status {}: {}", status, e))?,
+        None => TxFinality::ExecutedOptimistic,
+    };
+    if !finality.is_executed() {
+        // Not executed yet, so there is no outcome to check
+        eprintln!("📋 Transaction {} reached {:?}", tx_hash, finality);
+        return Ok(TxOutcome::pending(tx_hash, finality));
     }
 
-    let result = json_response.result.ok_or("No result in RPC response")?;
-
-    // Extract transaction hash first (for logging)
-    let tx_hash = result
-        .get("transaction")
-        .and_then(|tx| tx.get("hash"))
-        .and_then(|h| h.as_str())
-        .ok_or("No transaction hash in response")?
-        .to_string();
-
-    eprintln!("📋 Transaction broadcast: {}", tx_hash);
-
     // Parse the full execution outcome to check for failures
     let outcome: FinalExecutionOutcomeView = serde_json::from_value(result.clone())
         .map_err(|e| format!("Failed to parse FinalExecutionOutcomeView: {}", e))?;
 
     // Check top-level status
     match &outcome.status {
+        FinalExecutionStatus::Failure {
+            failure: TxExecutionError::InvalidTxError { invalid_tx_error },
+        } => {
+            eprintln!("❌ Transaction rejected: {}", invalid_tx_error);
+            return Err(InvalidTxError(invalid_tx_error.clone()).into());
+        }
         FinalExecutionStatus::Failure { failure: err } => {
             let error_msg = format_tx_error(err);
             eprintln!("❌ Transaction FAILED (top-level): {}", error_msg);
@@ -607,7 +2354,7 @@ fn send_transaction(
 
     eprintln!("✅ Transaction successful: {}", tx_hash);
 
-    Ok(tx_hash)
+    TxOutcome::from_view(tx_hash, finality, outcome)
 }
 
 /// Format TxExecutionError for user-friendly error messages
@@ -636,3 +2383,332 @@ fn format_tx_error(err: &TxExecutionError) -> String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secp256k1_signature_recovers_signer_public_key() {
+        let private_key = format!("secp256k1:{}", codec::base58_encode([7u8; 32]));
+        let signing_key = TxSigningKey::parse(&private_key).unwrap();
+        let hash = Sha256::digest(b"transaction bytes");
+
+        let Signature::SECP256K1(signature) = signing_key.sign(&hash).unwrap() else {
+            panic!("expected a secp256k1 signature");
+        };
+        let recovered = k256::ecdsa::VerifyingKey::recover_from_prehash(
+            &hash,
+            &k256::ecdsa::Signature::from_slice(&signature[..64]).unwrap(),
+            k256::ecdsa::RecoveryId::from_byte(signature[64]).unwrap(),
+        )
+        .unwrap();
+
+        let public_key = public_key_of(&private_key).unwrap();
+        assert!(public_key.starts_with("secp256k1:"));
+        let TxSigningKey::Secp256k1(key) = &signing_key else { unreachable!() };
+        assert_eq!(&recovered, key.verifying_key());
+        assert_eq!(public_key.parse::<PublicKey>().unwrap(), signing_key.public_key());
+    }
+
+    #[test]
+    fn test_outcome_decodes_return_values_and_sums_gas() {
+        let view: FinalExecutionOutcomeView = serde_json::from_value(serde_json::json!({
+            "status": {"SuccessValue": "IjEwMDAwIg=="},
+            "transaction_outcome": {"block_hash": "", "id": "tx", "outcome": {
+                "logs": [], "receipt_ids": ["r1"], "gas_burnt": 1, "tokens_burnt": "0",
+                "executor_id": "alice.near", "status": {"SuccessReceiptId": "r1"}
+            }},
+            "receipts_outcome": [
+                {"block_hash": "", "id": "r1", "outcome": {
+                    "logs": [r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer"}"#, "EVENT_JSON:{"],
+                    "receipt_ids": ["r2"], "gas_burnt": 10, "tokens_burnt": "0",
+                    "executor_id": "usdc.near", "status": {"SuccessReceiptId": "r2"}
+                }},
+                {"block_hash": "", "id": "r2", "outcome": {
+                    "logs": ["refund 0"], "receipt_ids": [], "gas_burnt": 100, "tokens_burnt": "0",
+                    "executor_id": "usdc.near", "status": {"SuccessValue": "IjEwMDAwIg=="}
+                }}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(view.error(), None);
+
+        assert_eq!(view.success_json::<String>().unwrap().as_deref(), Some("10000"));
+
+        let outcome = TxOutcome::from_view("tx".to_string(), TxFinality::Final, view).unwrap();
+        assert_eq!(outcome.success_json::<String>().unwrap().as_deref(), Some("10000"));
+        assert_eq!(outcome.gas_burnt, 111);
+        assert_eq!(outcome.logs, [r#"EVENT_JSON:{"standard":"nep141","version":"1.0.0","event":"ft_transfer"}"#, "EVENT_JSON:{", "refund 0"]);
+        let events = outcome.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].standard.as_str(), events[0].event.as_str()), ("nep141", "ft_transfer"));
+        assert_eq!(outcome.receipts[0].success_value, None);
+        assert_eq!(outcome.receipts[1].success_value.as_deref(), Some(&b"\"10000\""[..]));
+
+        let failed: FinalExecutionOutcomeView = serde_json::from_value(serde_json::json!({
+            "status": {"SuccessValue": ""},
+            "transaction_outcome": {"block_hash": "", "id": "tx", "outcome": {
+                "logs": [], "receipt_ids": [], "gas_burnt": 1, "tokens_burnt": "0",
+                "executor_id": "alice.near", "status": {"SuccessReceiptId": "r1"}
+            }},
+            "receipts_outcome": [{"block_hash": "", "id": "r1", "outcome": {
+                "logs": [], "receipt_ids": [], "gas_burnt": 1, "tokens_burnt": "0", "executor_id": "usdc.near",
+                "status": {"Failure": {"ActionError": {"index": 0, "kind": {"FunctionCallError": {"ExecutionError": "boom"}}}}}
+            }}]
+        }))
+        .unwrap();
+        assert_eq!(failed.error().as_deref(), Some("action 0: Smart contract panicked: boom"));
+    }
+
+    #[test]
+    fn test_delegate_action_is_signed_with_nep366_prefix() {
+        use ed25519_dalek::Verifier;
+
+        let key = LocalSigner::new(&codec::base58_encode([5u8; 32])).unwrap();
+        let signed = TransactionBuilder::new("alice.near")
+            .receiver("wrap.near")
+            .transfer(1)
+            .sign_delegate(&key, 8, 1_000)
+            .unwrap();
+
+        let SignedDelegateAction { delegate_action, signature } =
+            borsh::from_slice(&codec::base64_decode(&signed).unwrap()).unwrap();
+        assert_eq!((delegate_action.sender_id.as_str(), delegate_action.receiver_id.as_str()), ("alice.near", "wrap.near"));
+        assert_eq!((delegate_action.nonce, delegate_action.max_block_height), (8, 1_000));
+        let (PublicKey::ED25519(public_key), Signature::ED25519(signature)) = (&delegate_action.public_key, signature) else {
+            panic!("expected an ed25519 key");
+        };
+        let hash = crypto::nep366_hash(&borsh::to_vec(&delegate_action).unwrap());
+        ed25519_dalek::VerifyingKey::from_bytes(public_key)
+            .unwrap()
+            .verify(&hash, &ed25519_dalek::Signature::from_bytes(&signature))
+            .unwrap();
+
+        // The relayer wraps it in an Action::Delegate (borsh variant 8) addressed to the sender
+        let (receiver_id, actions) = TransactionBuilder::new("relayer.near")
+            .receiver("alice.near")
+            .delegate(&signed)
+            .into_parts()
+            .unwrap();
+        assert_eq!(receiver_id, "alice.near");
+        assert_eq!(borsh::to_vec(&actions[0]).unwrap()[0], 8);
+        let nested = TransactionBuilder::new("alice.near").delegate(&signed).sign_delegate(&key, 9, 1_000);
+        assert!(nested.unwrap_err().to_string().contains("cannot be nested"));
+        assert!(TransactionBuilder::new("relayer.near").delegate("not base64").into_parts().is_err());
+    }
+
+    #[test]
+    fn test_key_pool_rotates_keys_of_one_account() {
+        let rpc = RpcClient::new("https://rpc.example");
+        let keys: Vec<String> = (1..=3u8).map(|i| codec::base58_encode([i; 32])).collect();
+        let pool = KeyPool::new(&rpc, "alice.near", &keys).unwrap();
+
+        let used: Vec<String> = (0..4).map(|_| pool.next_signer().public_key()).collect();
+        assert_eq!(used[3], used[0]);
+        assert!(used[0] != used[1] && used[1] != used[2] && used[0] != used[2]);
+
+        let bob = TxSigner::new(&rpc, "bob.near", &keys[0]).unwrap();
+        let alice = TxSigner::new(&rpc, "alice.near", &keys[1]).unwrap();
+        assert!(KeyPool::from_signers(vec![alice, bob]).is_err());
+        assert!(KeyPool::new(&rpc, "alice.near", &[] as &[String]).is_err());
+    }
+
+    #[test]
+    fn test_function_call_key_permission_is_checked_before_signing() {
+        let permission: AccessKeyPermissionView = serde_json::from_value(serde_json::json!({
+            "FunctionCall": {"allowance": "1000000", "receiver_id": "intents.near", "method_names": ["execute_intents"]}
+        }))
+        .unwrap();
+        let call = |method_name: &str, gas: u64, deposit: u128| Action::FunctionCall(FunctionCallAction {
+            method_name: method_name.to_string(),
+            args: vec![],
+            gas,
+            deposit,
+        });
+        let gas_price = || Ok(100);
+
+        assert!(permission.check("intents.near", &[call("execute_intents", 10_000, 0)], gas_price).is_ok());
+        let errors = [
+            permission.check("wrap.near", &[call("execute_intents", 1, 0)], gas_price),
+            permission.check("intents.near", &[call("add_public_key", 1, 0)], gas_price),
+            permission.check("intents.near", &[call("execute_intents", 1, 1)], gas_price),
+            permission.check("intents.near", &[Action::Transfer(1)], gas_price),
+            permission.check("intents.near", &[call("execute_intents", 10_001, 0)], gas_price),
+            permission.check("intents.near", &[call("execute_intents", 1, 0), call("execute_intents", 1, 0)], gas_price),
+        ];
+        assert!(errors[0].as_ref().unwrap_err().contains("only allows calls to intents.near"));
+        assert!(errors[1].as_ref().unwrap_err().contains("does not allow method add_public_key"));
+        assert!(errors[2].as_ref().unwrap_err().contains("cannot attach a deposit"));
+        assert!(errors[3].as_ref().unwrap_err().contains("can only sign function calls"));
+        assert!(errors[4].as_ref().unwrap_err().contains("does not cover 10001 gas"));
+        assert!(errors[5].as_ref().unwrap_err().contains("can only sign a single action, not 2"));
+        assert!(AccessKeyPermissionView::FullAccess.check("wrap.near", &[Action::Transfer(1)], || unreachable!()).is_ok());
+    }
+
+    #[cfg(feature = "record-replay")]
+    #[test]
+    fn test_signer_reuses_nonce_and_retries_stale_access_key() {
+        use crate::replay::{self, Exchange};
+        use serde_json::json;
+
+        let rpc = "https://rpc.example";
+        let exchange = |method: &str, response: serde_json::Value| Exchange {
+            url: rpc.to_string(),
+            request: json!({"method": method, "params": {"request_type": "view_access_key"}}),
+            status: 200,
+            response,
+        };
+        let access_key = json!({"result": {
+            "nonce": 42, "block_hash": "11111111111111111111111111111111", "permission": "FullAccess"
+        }});
+        let success = json!({"result": {
+            "status": {"SuccessValue": ""},
+            "transaction": {"hash": "tx"},
+            "transaction_outcome": {"block_hash": "", "id": "tx", "outcome": {
+                "logs": [], "receipt_ids": [], "gas_burnt": 0, "tokens_burnt": "0",
+                "executor_id": "alice.near", "status": {"SuccessValue": ""}
+            }},
+            "receipts_outcome": []
+        }});
+        let invalid_nonce = json!({"error": {
+            "message": "Server error",
+            "data": {"TxExecutionError": {"InvalidTxError": {"InvalidNonce": {"tx_nonce": 44, "ak_nonce": 50}}}}
+        }});
+        let expired = json!({"error": {
+            "message": "Server error",
+            "data": {"TxExecutionError": {"InvalidTxError": "Expired"}}
+        }});
+        let broadcast = |response: &serde_json::Value| Exchange {
+            request: json!({"method": "broadcast_tx_commit"}),
+            ..exchange("", response.clone())
+        };
+
+        // The access key is only queried again after the nonce is rejected
+        replay::start(vec![
+            exchange("query", access_key.clone()),
+            broadcast(&success),
+            broadcast(&invalid_nonce),
+            exchange("query", access_key.clone()),
+            broadcast(&expired),
+            exchange("query", access_key.clone()),
+            broadcast(&success),
+            // Gives up after MAX_TX_ATTEMPTS
+            broadcast(&invalid_nonce),
+            exchange("query", access_key.clone()),
+            broadcast(&invalid_nonce),
+            exchange("query", access_key),
+            broadcast(&invalid_nonce),
+            Exchange {
+                request: json!({"method": "send_tx"}),
+                ..exchange("", json!({"result": {"final_execution_status": "INCLUDED"}}))
+            },
+        ]);
+
+        let private_key = format!("ed25519:{}", codec::base58_encode([1u8; 64]));
+        let signer = TxSigner::new(&RpcClient::new(rpc), "alice.near", &private_key).unwrap();
+        assert_eq!(signer.transfer_near("bob.near", 1).unwrap().tx_hash, "tx");
+        assert_eq!(signer.transfer_near("bob.near", 1).unwrap().tx_hash, "tx");
+        let err = signer.transfer_near("bob.near", 1).unwrap_err();
+        assert!(err.downcast_ref::<InvalidTxError>().is_some_and(InvalidTxError::is_invalid_nonce));
+
+        // send_tx answers before execution; the hash is computed locally
+        signer.set_wait_until(Some(TxFinality::Included));
+        let outcome = signer.transfer_near("bob.near", 1).unwrap();
+        assert_eq!(outcome.finality, TxFinality::Included);
+        assert_eq!(codec::base58_decode(&outcome.tx_hash).unwrap().len(), 32);
+        assert!(replay::finish().is_empty());
+    }
+
+    #[cfg(feature = "record-replay")]
+    #[test]
+    fn test_wait_for_tx_polls_until_executed_and_fails_over() {
+        use crate::replay::{self, Exchange};
+        use serde_json::json;
+
+        let tx = |response: serde_json::Value| Exchange {
+            url: "https://rpc.example".to_string(),
+            request: json!({"method": "tx"}),
+            status: 200,
+            response,
+        };
+        replay::start(vec![
+            Exchange {
+                url: "https://down.example".to_string(),
+                status: 503,
+                ..tx(json!("Service Unavailable"))
+            },
+            tx(json!({"error": {"message": "Server error", "cause": {"name": "UNKNOWN_TRANSACTION"}}})),
+            tx(json!({"result": {
+                "final_execution_status": "FINAL",
+                "status": {"SuccessValue": ""},
+                "transaction_outcome": {"block_hash": "", "id": "tx", "outcome": {
+                    "logs": [], "receipt_ids": [], "gas_burnt": 5, "tokens_burnt": "0",
+                    "executor_id": "alice.near", "status": {"SuccessValue": ""}
+                }},
+                "receipts_outcome": []
+            }})),
+        ]);
+
+        // The failing endpoint is skipped once it has failed
+        let rpc = RpcClient::with_fallbacks("https://down.example", &["https://rpc.example".to_string()]);
+        let outcome = wait_for_tx(&rpc, "tx", "alice.near", Duration::from_secs(5)).unwrap();
+        assert_eq!((outcome.finality, outcome.gas_burnt), (TxFinality::Final, 5));
+        let health = rpc.health();
+        assert_eq!((health[0].requests, health[0].consecutive_failures), (1, 1));
+        assert_eq!((health[1].requests, health[1].failures), (2, 0));
+        assert!(replay::finish().is_empty());
+    }
+
+    #[cfg(feature = "record-replay")]
+    #[test]
+    fn test_old_transactions_are_looked_up_on_archival_rpc() {
+        use crate::replay::{self, Exchange};
+        use serde_json::json;
+
+        let exchange = |url: &str, method: &str, response: serde_json::Value| Exchange {
+            url: url.to_string(),
+            request: json!({"method": method}),
+            status: 200,
+            response,
+        };
+        let gc = json!({"error": {"message": "Server error", "cause": {"name": "GARBAGE_COLLECTED_BLOCK"}}});
+        let unknown = json!({"error": {"message": "Server error", "cause": {"name": "UNKNOWN_TRANSACTION"}}});
+        replay::start(vec![
+            exchange("https://rpc.example", "tx", gc),
+            exchange("https://archival.example", "tx", json!({"result": {
+                "final_execution_status": "FINAL",
+                "status": {"SuccessValue": ""},
+                "transaction_outcome": {"block_hash": "", "id": "tx", "outcome": {
+                    "logs": [], "receipt_ids": [], "gas_burnt": 5, "tokens_burnt": "0",
+                    "executor_id": "alice.near", "status": {"SuccessValue": ""}
+                }},
+                "receipts_outcome": []
+            }})),
+            exchange("https://rpc.example", "block", json!({"result": {"header": {"hash": "head"}}})),
+            exchange("https://rpc.example", "EXPERIMENTAL_light_client_proof", unknown.clone()),
+            exchange("https://archival.example", "block", json!({"result": {"header": {"hash": "archival-head"}}})),
+            exchange("https://archival.example", "EXPERIMENTAL_light_client_proof", json!({"result": {"block_proof": []}})),
+            exchange("https://rpc.example", "block", json!({"result": {"header": {"hash": "head"}}})),
+            exchange("https://rpc.example", "EXPERIMENTAL_light_client_proof", unknown.clone()),
+            exchange("https://archival.example", "block", json!({"result": {"header": {"hash": "archival-head"}}})),
+            exchange("https://archival.example", "EXPERIMENTAL_light_client_proof", unknown),
+        ]);
+        set_archival_rpc_url(Some("https://archival.example".to_string()));
+
+        let rpc = RpcClient::new("https://rpc.example");
+        let outcome = wait_for_tx(&rpc, "tx", "alice.near", Duration::from_secs(5)).unwrap();
+        assert_eq!((outcome.finality, outcome.gas_burnt), (TxFinality::Final, 5));
+
+        // Proven against the head of the node that has the transaction
+        let proof = light_client_proof(&rpc, "tx", "alice.near", "bob.near").unwrap();
+        assert_eq!(proof.light_client_head, "archival-head");
+        assert_eq!(proof.proof, json!({"block_proof": []}));
+
+        let error = light_client_proof(&rpc, "tx", "alice.near", "bob.near").unwrap_err();
+        assert!(error.to_string().contains("transaction unknown to the RPC"), "{}", error);
+
+        set_archival_rpc_url(None);
+        assert!(replay::finish().is_empty());
+    }
+}
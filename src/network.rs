@@ -0,0 +1,105 @@
+//! Mainnet/testnet endpoints in one place
+//!
+//! `NEAR_NETWORK=testnet` switches every default below to its testnet
+//! counterpart so the whole pipeline can be rehearsed there. Individual values
+//! can still be overridden with `NEAR_RPC_URL`, `NEAR_RPC_FALLBACK_URLS`, `NEAR_ARCHIVAL_RPC_URL`,
+//! `INTENTS_API_URL` and `INTENTS_CONTRACT`.
+
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+/// Endpoints and accounts for one network
+#[derive(Debug, Clone)]
+pub struct NetworkConfig {
+    pub network: Network,
+    /// NEAR Intents solver relay JSON-RPC endpoint
+    pub relay_url: String,
+    /// Verifier contract holding deposits and executing intents
+    pub intents_contract: String,
+    pub rpc_url: String,
+    /// Tried in order when `rpc_url` fails (`NEAR_RPC_FALLBACK_URLS`, comma-separated)
+    pub rpc_fallback_urls: Vec<String>,
+    /// RPC keeping full history, for transactions older than regular nodes
+    /// retain (a few epochs). Empty `NEAR_ARCHIVAL_RPC_URL` disables it.
+    pub archival_rpc_url: Option<String>,
+    /// Block explorer base URL (transactions are at `{explorer_url}/txns/{hash}`)
+    pub explorer_url: String,
+}
+
+impl Network {
+    /// Network from `NEAR_NETWORK` (`mainnet` by default)
+    pub fn from_env() -> Result<Self, String> {
+        match env::var("NEAR_NETWORK").map(|v| v.to_lowercase()).as_deref() {
+            Err(_) | Ok("") | Ok("mainnet") => Ok(Network::Mainnet),
+            Ok("testnet") => Ok(Network::Testnet),
+            Ok(other) => Err(format!("Unknown NEAR_NETWORK: {} (expected mainnet or testnet)", other)),
+        }
+    }
+
+    /// Built-in defaults, without env overrides
+    pub fn defaults(self) -> NetworkConfig {
+        let (relay_url, intents_contract, rpc_url, archival_rpc_url, explorer_url) = match self {
+            Network::Mainnet => (
+                "https://solver-relay-v2.chaindefuser.com/rpc",
+                "intents.near",
+                "https://rpc.mainnet.near.org",
+                "https://archival-rpc.mainnet.near.org",
+                "https://nearblocks.io",
+            ),
+            // The public relay only quotes mainnet assets; point INTENTS_API_URL
+            // at a testnet relay for end-to-end rehearsals.
+            Network::Testnet => (
+                "https://solver-relay-v2.chaindefuser.com/rpc",
+                "intents.testnet",
+                "https://rpc.testnet.near.org",
+                "https://archival-rpc.testnet.near.org",
+                "https://testnet.nearblocks.io",
+            ),
+        };
+
+        NetworkConfig {
+            network: self,
+            relay_url: relay_url.to_string(),
+            intents_contract: intents_contract.to_string(),
+            rpc_url: rpc_url.to_string(),
+            rpc_fallback_urls: Vec::new(),
+            archival_rpc_url: Some(archival_rpc_url.to_string()),
+            explorer_url: explorer_url.to_string(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Config for `NEAR_NETWORK` with per-value env overrides applied
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = Network::from_env()?.defaults();
+
+        if let Ok(url) = env::var("INTENTS_API_URL") {
+            config.relay_url = url;
+        }
+        if let Ok(contract) = env::var("INTENTS_CONTRACT") {
+            config.intents_contract = contract;
+        }
+        if let Ok(url) = env::var("NEAR_RPC_URL") {
+            config.rpc_url = url;
+        }
+        if let Ok(urls) = env::var("NEAR_RPC_FALLBACK_URLS") {
+            config.rpc_fallback_urls =
+                urls.split(',').map(str::trim).filter(|url| !url.is_empty()).map(String::from).collect();
+        }
+        if let Ok(url) = env::var("NEAR_ARCHIVAL_RPC_URL") {
+            config.archival_rpc_url = Some(url).filter(|url| !url.is_empty());
+        }
+
+        Ok(config)
+    }
+
+    pub fn tx_url(&self, tx_hash: &str) -> String {
+        format!("{}/txns/{}", self.explorer_url, tx_hash)
+    }
+}
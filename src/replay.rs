@@ -0,0 +1,180 @@
+//! Record/replay of relay and RPC traffic for offline tests
+//!
+//! With the `record-replay` feature, [`crate::http::post_json`] routes every
+//! request through this module:
+//!
+//! - `HTTP_RECORD_FILE=path` sends requests as usual and writes each exchange
+//!   to a JSON fixture at `path` (rewritten after every response, so the file
+//!   is complete even if the worker exits early).
+//! - `HTTP_REPLAY_FILE=path` answers requests from such a fixture without
+//!   touching the network. Tests can load one directly with [`start`].
+//!
+//! Exchanges are replayed in order. Requests carry nonces, signatures and
+//! deadlines that change on every run, so a request only has to match the
+//! recorded one on URL and [`request_key`] (the JSON-RPC method, plus the
+//! query type and contract method for NEAR `query` calls).
+//!
+//! State is thread-local (the WASI worker is single-threaded), so parallel
+//! tests each replay their own fixture.
+
+pub use crate::http::request_key;
+
+use crate::http::Response;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::env;
+
+/// One request and the response it got
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub url: String,
+    /// Request body. Only the fields used by [`request_key`] are needed when
+    /// writing fixtures by hand.
+    pub request: serde_json::Value,
+    pub status: u16,
+    /// Response body: parsed JSON, or a string holding a non-JSON body as is
+    pub response: serde_json::Value,
+}
+
+enum Mode {
+    Record { path: String, exchanges: Vec<Exchange> },
+    Replay { exchanges: VecDeque<Exchange> },
+}
+
+thread_local! {
+    static MODE: RefCell<Option<Mode>> = RefCell::new(mode_from_env());
+}
+
+fn mode_from_env() -> Option<Mode> {
+    if let Ok(path) = env::var("HTTP_REPLAY_FILE") {
+        let fixture = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read HTTP_REPLAY_FILE {}: {}", path, e));
+        let exchanges = serde_json::from_str(&fixture)
+            .unwrap_or_else(|e| panic!("Invalid HTTP_REPLAY_FILE {}: {}", path, e));
+        eprintln!("📼 Replaying HTTP traffic from {}", path);
+        return Some(Mode::Replay { exchanges });
+    }
+
+    env::var("HTTP_RECORD_FILE").ok().map(|path| {
+        eprintln!("📼 Recording HTTP traffic to {}", path);
+        Mode::Record { path, exchanges: Vec::new() }
+    })
+}
+
+/// Replay `exchanges` on this thread, replacing any env-configured mode
+pub fn start(exchanges: Vec<Exchange>) {
+    MODE.with(|m| *m.borrow_mut() = Some(Mode::Replay { exchanges: exchanges.into() }));
+}
+
+/// Stop replaying on this thread, returning the exchanges that were never requested
+pub fn finish() -> Vec<Exchange> {
+    match MODE.with(|m| m.borrow_mut().take()) {
+        Some(Mode::Replay { exchanges }) => exchanges.into(),
+        _ => Vec::new(),
+    }
+}
+
+/// The recorded response to `body`, if replaying. Errors when the request
+/// doesn't match the next recorded one, or the fixture has run out.
+pub(crate) fn replay(url: &str, body: &[u8]) -> Result<Option<Response>, Box<dyn std::error::Error>> {
+    MODE.with(|m| {
+        let mut mode = m.borrow_mut();
+        let Some(Mode::Replay { exchanges }) = mode.as_mut() else {
+            return Ok(None);
+        };
+
+        let request: serde_json::Value = serde_json::from_slice(body)?;
+        let key = request_key(&request);
+        let exchange = exchanges
+            .pop_front()
+            .ok_or_else(|| format!("Replay fixture exhausted at {} {}", url, key))?;
+
+        let expected_key = request_key(&exchange.request);
+        if exchange.url != url || expected_key != key {
+            return Err(format!(
+                "Replay mismatch: expected {} {}, got {} {}",
+                exchange.url, expected_key, url, key
+            )
+            .into());
+        }
+
+        let body = match exchange.response {
+            serde_json::Value::String(raw) => raw.into_bytes(),
+            json => serde_json::to_vec(&json)?,
+        };
+        Ok(Some(Response { status: exchange.status, body }))
+    })
+}
+
+/// Append an exchange to the fixture, if recording
+pub(crate) fn record(url: &str, body: &[u8], response: &Response) -> Result<(), Box<dyn std::error::Error>> {
+    MODE.with(|m| {
+        let mut mode = m.borrow_mut();
+        let Some(Mode::Record { path, exchanges }) = mode.as_mut() else {
+            return Ok(());
+        };
+
+        exchanges.push(Exchange {
+            url: url.to_string(),
+            request: serde_json::from_slice(body)?,
+            status: response.status,
+            response: serde_json::from_slice(&response.body).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(&response.body).into_owned())
+            }),
+        });
+        std::fs::write(path, serde_json::to_string_pretty(exchanges)?)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_replay_rejects_mismatched_request() {
+        start(vec![Exchange {
+            url: "https://relay.example".to_string(),
+            request: json!({"method": "quote"}),
+            status: 200,
+            response: json!({}),
+        }]);
+
+        let err = replay("https://relay.example", br#"{"method":"publish_intent"}"#).unwrap_err();
+        assert!(err.to_string().contains("expected https://relay.example quote"));
+        finish();
+    }
+
+    #[test]
+    fn test_replay_serves_exchanges_in_order() {
+        start(vec![
+            Exchange {
+                url: "https://rpc.example".to_string(),
+                request: json!({"method": "query", "params": {"request_type": "call_function", "method_name": "ft_balance_of"}}),
+                status: 200,
+                response: json!({"result": "1"}),
+            },
+            Exchange {
+                url: "https://relay.example".to_string(),
+                request: json!({"method": "quote"}),
+                status: 502,
+                response: json!("Bad Gateway"),
+            },
+        ]);
+
+        // Params that aren't part of the key (args, nonces) may differ
+        let balance = br#"{"method":"query","params":{"request_type":"call_function","method_name":"ft_balance_of","args_base64":"e30="}}"#;
+        let response = replay("https://rpc.example", balance).unwrap().unwrap();
+        assert_eq!((response.status, response.body), (200, br#"{"result":"1"}"#.to_vec()));
+
+        // Non-JSON bodies are replayed verbatim
+        let response = replay("https://relay.example", br#"{"method":"quote"}"#).unwrap().unwrap();
+        assert_eq!((response.status, response.body), (502, b"Bad Gateway".to_vec()));
+
+        assert!(replay("https://relay.example", br#"{"method":"quote"}"#).is_err(), "fixture exhausted");
+        assert!(finish().is_empty());
+        assert!(replay("https://relay.example", br#"{"method":"quote"}"#).unwrap().is_none());
+    }
+}
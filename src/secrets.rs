@@ -0,0 +1,55 @@
+//! Worker secrets from an inline stdin envelope or environment variables
+//!
+//! OutLayer can either export secrets as env vars or pass them inline with the
+//! request as `{"secrets": {...}, "request": {...}}`. Inline values take
+//! precedence. Every secret is held in [`Zeroizing`] so it is wiped from
+//! memory when dropped.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use zeroize::Zeroizing;
+
+#[derive(Deserialize, Default)]
+#[serde(transparent)]
+pub struct Secrets {
+    inline: HashMap<String, Zeroizing<String>>,
+}
+
+impl Secrets {
+    /// Secret `name`, from the inline envelope first, then the environment
+    pub fn get(&self, name: &str) -> Option<Zeroizing<String>> {
+        self.inline
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok().map(Zeroizing::new))
+    }
+
+    /// Whether any secrets were provided inline
+    pub fn is_inline(&self) -> bool {
+        !self.inline.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Names only, never values
+        f.debug_set().entries(self.inline.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_secrets_are_read_but_never_printed() {
+        let secrets: Secrets =
+            serde_json::from_str(r#"{"INLINE_ONLY_TEST_KEY":"ed25519:secret"}"#).unwrap();
+
+        assert!(secrets.is_inline());
+        assert_eq!(secrets.get("INLINE_ONLY_TEST_KEY").as_deref().map(String::as_str), Some("ed25519:secret"));
+        assert!(secrets.get("INLINE_ONLY_MISSING_KEY").is_none());
+        assert!(!format!("{:?}", secrets).contains("ed25519:secret"));
+    }
+}
@@ -0,0 +1,29 @@
+//! Optional operator webhook for progress events
+//!
+//! When `PROGRESS_WEBHOOK_URL` is set, the worker POSTs a JSON event to it as
+//! each stage of a swap completes or fails, so long-running swaps can be
+//! followed live rather than only through the final Output. Delivery is best
+//! effort: each event is sent once, and a failed delivery is logged without
+//! affecting the swap.
+
+use crate::http::{self, Endpoint};
+use serde::Serialize;
+use std::env;
+
+/// Webhook URL from `PROGRESS_WEBHOOK_URL` (None if unset or empty)
+pub fn url() -> Option<String> {
+    env::var("PROGRESS_WEBHOOK_URL").ok().filter(|url| !url.is_empty())
+}
+
+/// POST `event` to the webhook, if one is configured
+pub fn notify<T: Serialize>(event: &T) {
+    let Some(url) = url() else {
+        return;
+    };
+
+    match http::post_json(Endpoint::Webhook, &url, event) {
+        Ok(response) if (200..300).contains(&response.status) => {}
+        Ok(response) => eprintln!("⚠️  Progress webhook returned status {}", response.status),
+        Err(e) => eprintln!("⚠️  Progress webhook failed: {}", e),
+    }
+}
@@ -0,0 +1,66 @@
+"""Reference NEP-413 vectors for tests/nep413_vectors.rs.
+
+Independent of the Rust code: the borsh layout is written out by hand from
+the NEP-413 spec and signatures come from the `cryptography` package.
+
+    python3 tests/fixtures/nep413_vectors.py > tests/fixtures/nep413_vectors.json
+"""
+
+import base64
+import hashlib
+import json
+import struct
+
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+from cryptography.hazmat.primitives.serialization import Encoding, PublicFormat
+
+ALPHABET = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz"
+
+
+def b58(data):
+    n = int.from_bytes(data, "big")
+    out = ""
+    while n:
+        n, r = divmod(n, 58)
+        out = ALPHABET[r] + out
+    return "1" * (len(data) - len(data.lstrip(b"\0"))) + out
+
+
+def borsh_string(s):
+    raw = s.encode()
+    return struct.pack("<I", len(raw)) + raw
+
+
+def nep413_hash(message, nonce, recipient, callback_url):
+    nonce = base64.b64decode(nonce).ljust(32, b"\0")
+    payload = borsh_string(message) + nonce + borsh_string(recipient)
+    payload += b"\0" if callback_url is None else b"\1" + borsh_string(callback_url)
+    return hashlib.sha256(struct.pack("<I", 2**31 + 413) + payload).digest()
+
+
+CASES = [
+    ("zero nonce", bytes([1] * 32), '{"signer_id":"test.near","intents":[]}', bytes(32), "intents.near", None),
+    ("short nonce is right-padded", bytes([2] * 32), '{"signer_id":"alice.near","intents":[]}', b"\xab\xcd", "intents.near", None),
+    ("callback url", bytes([3] * 32), '{"signer_id":"bob.near","intents":[]}', bytes(range(32)), "intents.near", "https://wallet.example/done"),
+    ("non-ascii message", bytes(range(32)), '{"memo":"swap → été"}', bytes([255] * 32), "intents.testnet", None),
+]
+
+vectors = []
+for name, seed, message, nonce, recipient, callback_url in CASES:
+    key = Ed25519PrivateKey.from_private_bytes(seed)
+    public = key.public_key().public_bytes(Encoding.Raw, PublicFormat.Raw)
+    nonce = base64.b64encode(nonce).decode()
+    digest = nep413_hash(message, nonce, recipient, callback_url)
+    vectors.append({
+        "name": name,
+        "private_key": "ed25519:" + b58(seed + public),
+        "message": message,
+        "nonce": nonce,
+        "recipient": recipient,
+        "callback_url": callback_url,
+        "hash": digest.hex(),
+        "signature": "ed25519:" + b58(key.sign(digest)),
+        "public_key": "ed25519:" + b58(public),
+    })
+
+print(json.dumps(vectors, indent=2, ensure_ascii=False))
@@ -1,11 +1,12 @@
-/// Integration tests for NEAR transaction signing and RPC
-/// Run with: cargo test --test near_tx_integration -- --nocapture
-///
-/// Required env vars:
-/// - TEST_ACCOUNT_ID (e.g., test-swap.testnet)
-/// - TEST_PRIVATE_KEY (ed25519:... or base58)
-/// - TEST_TOKEN_CONTRACT (e.g., wrap.testnet)
-
+//! Integration tests for NEAR transaction signing and RPC
+//! Run with: cargo test --test near_tx_integration -- --nocapture
+//!
+//! Required env vars:
+//! - TEST_ACCOUNT_ID (e.g., test-swap.testnet)
+//! - TEST_PRIVATE_KEY (ed25519:... or base58)
+//! - TEST_TOKEN_CONTRACT (e.g., wrap.testnet)
+
+use base64::Engine;
 use std::env;
 
 #[test]
@@ -21,11 +22,7 @@ fn test_get_access_key_testnet() {
     println!("📍 RPC: {}", rpc_url);
 
     // Parse private key
-    let key_str = if private_key.starts_with("ed25519:") {
-        &private_key[8..]
-    } else {
-        &private_key
-    };
+    let key_str = private_key.strip_prefix("ed25519:").unwrap_or(&private_key);
 
     let key_bytes = bs58::decode(key_str)
         .into_vec()
@@ -148,7 +145,7 @@ fn test_ft_balance_of_testnet() {
     let args = serde_json::json!({
         "account_id": account_id
     });
-    let args_base64 = base64::encode(args.to_string().as_bytes());
+    let args_base64 = base64::engine::general_purpose::STANDARD.encode(args.to_string().as_bytes());
 
     let request = serde_json::json!({
         "jsonrpc": "2.0",
@@ -206,7 +203,7 @@ fn test_storage_deposit_testnet() {
     let rpc_url = "https://rpc.testnet.near.org";
     let account_id = env::var("TEST_ACCOUNT_ID")
         .expect("Set TEST_ACCOUNT_ID env var");
-    let private_key = env::var("TEST_PRIVATE_KEY")
+    let _private_key = env::var("TEST_PRIVATE_KEY")
         .expect("Set TEST_PRIVATE_KEY env var");
     let token_contract = "wrap.testnet";
 
@@ -247,7 +244,7 @@ fn check_ft_balance(rpc_url: &str, account_id: &str, token_contract: &str) -> St
     let args = serde_json::json!({
         "account_id": account_id
     });
-    let args_base64 = base64::encode(args.to_string().as_bytes());
+    let args_base64 = base64::engine::general_purpose::STANDARD.encode(args.to_string().as_bytes());
 
     let request = serde_json::json!({
         "jsonrpc": "2.0",